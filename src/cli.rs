@@ -32,6 +32,7 @@ pub fn cli() -> Command {
                 .long("csv")
                 .help("Format output as CSV"),
         )
+        .arg(arg_columns())
         .arg(arg_older_than_date("List only releases older than DATE"))
         .arg(arg_newer_than_date("List only releases newer than DATE"))
         .arg(
@@ -67,6 +68,7 @@ pub fn cli() -> Command {
             The following environment variables can be passed to butido:
 
                 RUST_LOG - to enable logging, for exact usage see the Rust cookbook
+                           (takes precedence over --verbose and --quiet)
         "#))
 
         .arg(Arg::new("version")
@@ -84,6 +86,37 @@ pub fn cli() -> Command {
             .help("Generate a Chrome compatible trace file (trace-*.json)")
         )
 
+        .arg(Arg::new("verbose")
+            .action(ArgAction::Count)
+            .required(false)
+            .short('v')
+            .long("verbose")
+            .help("Increase the log verbosity (-v: info, -vv: debug, -vvv: trace)")
+            .long_help(indoc::indoc!(r#"
+                Increase the log verbosity. Can be passed multiple times:
+
+                    -v   - info
+                    -vv  - debug
+                    -vvv - trace
+
+                The default log level is "warn". If 'RUST_LOG' is set, it takes precedence over this flag.
+            "#))
+            .conflicts_with("quiet")
+        )
+
+        .arg(Arg::new("quiet")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .short('q')
+            .long("quiet")
+            .help("Only log errors")
+            .long_help(indoc::indoc!(r#"
+                Only log errors (sets the log level to "error").
+                If 'RUST_LOG' is set, it takes precedence over this flag.
+            "#))
+            .conflicts_with("verbose")
+        )
+
         .arg(Arg::new("hide_bars")
             .action(ArgAction::SetTrue)
             .required(false)
@@ -91,6 +124,61 @@ pub fn cli() -> Command {
             .help("Hide all progress bars")
         )
 
+        .arg(Arg::new("color")
+            .required(false)
+            .global(true)
+            .long("color")
+            .value_name("WHEN")
+            .value_parser(["auto", "always", "never"])
+            .default_value("auto")
+            .help("Whether to use colors in the output")
+            .long_help(indoc::indoc!(r#"
+                Whether to use colors in the output:
+
+                    auto   - only if stdout is a terminal (and neither 'NO_COLOR' nor 'CLICOLOR=0' is set)
+                    always - e.g., when piping into a pager that can display colors
+                    never
+            "#))
+        )
+
+        .arg(Arg::new("config")
+            .required(false)
+            .long("config")
+            .value_name("PATH")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Load the configuration from PATH as well")
+            .long_help(indoc::indoc!(r#"
+                Load the configuration from PATH in addition to the 'config.toml' in the repository and the one in
+                the XDG configuration directory. The settings from PATH take precedence over the settings from these
+                files, but can still be overridden via environment variables ('BUTIDO_*').
+            "#))
+        )
+
+        .arg(Arg::new("strict")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .long("strict")
+            .help("Treat warnings about the configuration as errors")
+            .long_help(indoc::indoc!(r#"
+                Treat warnings about the configuration as errors, e.g., to make CI jobs fail if the configuration
+                needs attention. Without this flag, the warnings are printed and butido continues.
+                Can also be enabled via environment variable 'BUTIDO_STRICT' (any value except "0" and "false").
+            "#))
+        )
+
+        .arg(Arg::new("profile")
+            .required(false)
+            .long("profile")
+            .value_name("NAME")
+            .help("Use the configuration profile NAME")
+            .long_help(indoc::indoc!(r#"
+                Use the configuration profile NAME, i.e., the settings from the table "profiles.NAME" of the
+                configuration. The settings of the profile take precedence over the settings from the configuration
+                files, but can still be overridden via environment variables ('BUTIDO_*').
+                Can also be set via environment variable 'BUTIDO_PROFILE', but this setting has precedence.
+            "#))
+        )
+
         .arg(Arg::new("database_host")
             .required(false)
             .long("db-url")
@@ -154,6 +242,39 @@ pub fn cli() -> Command {
             "#))
             .value_parser(clap::value_parser!(u16))
         )
+        .arg(Arg::new("database_pool_max_size")
+            .required(false)
+            .long("db-pool-max-size")
+            .value_name("N")
+            .help("Override the maximum number of database connections")
+            .long_help(indoc::indoc!(r#"
+                Override the maximum number of connections in the database connection pool set via configuration.
+                Can also be overridden via environment 'BUTIDO_DATABASE_POOL_MAX_SIZE', but this setting has precedence.
+            "#))
+            .value_parser(clap::value_parser!(u32))
+        )
+        .arg(Arg::new("database_pool_min_idle")
+            .required(false)
+            .long("db-pool-min-idle")
+            .value_name("N")
+            .help("Override the minimum number of idle database connections")
+            .long_help(indoc::indoc!(r#"
+                Override the minimum number of idle connections in the database connection pool set via configuration.
+                Can also be overridden via environment 'BUTIDO_DATABASE_POOL_MIN_IDLE', but this setting has precedence.
+            "#))
+            .value_parser(clap::value_parser!(u32))
+        )
+        .arg(Arg::new("database_pool_timeout")
+            .required(false)
+            .long("db-pool-timeout")
+            .value_name("TIMEOUT")
+            .help("Override the timeout for getting a database connection from the pool (in seconds)")
+            .long_help(indoc::indoc!(r#"
+                Override the timeout for getting a connection from the database connection pool set via configuration.
+                Can also be overridden via environment 'BUTIDO_DATABASE_POOL_TIMEOUT', but this setting has precedence.
+            "#))
+            .value_parser(clap::value_parser!(u16))
+        )
 
         .subcommand(Command::new("generate-completions")
             .about("Generate and print commandline completions")
@@ -165,6 +286,31 @@ pub fn cli() -> Command {
             )
         )
 
+        .subcommand(Command::new("list-highlight-themes")
+            .about("List the themes that can be used for highlighting scripts (script_highlight_theme)")
+        )
+
+        .subcommand(Command::new("config")
+            .about("Work with the butido configuration")
+            .subcommand(Command::new("show")
+                .about("Show the effective configuration")
+                .long_about(indoc::indoc!(r#"
+                    Show the effective configuration, i.e., the configuration files, the selected profile and the
+                    environment variables ('BUTIDO_*') merged and validated, with the database settings from the
+                    commandline ('--db-*') applied. Secrets (the database password and the values of HTTP headers) are
+                    masked.
+                "#))
+                .arg(Arg::new("format")
+                    .required(false)
+                    .long("format")
+                    .value_name("FORMAT")
+                    .value_parser(["toml", "json"])
+                    .default_value("toml")
+                    .help("The output format")
+                )
+            )
+        )
+
         .subcommand(Command::new("db")
             .about("Database CLI interface")
             .subcommand(Command::new("cli")
@@ -174,17 +320,39 @@ pub fn cli() -> Command {
 
                         - psql
                         - pgcli
+                        - usql
 
                     if installed.
+
+                    Additional arguments can be passed to the program after a "--", e.g.:
+
+                        butido db cli --tool psql -- --no-psqlrc
                 "#))
 
                 .arg(Arg::new("tool")
                     .required(false)
                     .long("tool")
                     .value_name("TOOL")
-                    .value_parser(["psql", "pgcli"])
+                    .value_parser(["psql", "pgcli", "usql"])
                     .help("Use a specific tool")
                 )
+
+                .arg(Arg::new("print_url")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("print-url")
+                    .help("Only print the database connection URL (password masked) instead of starting a database CLI")
+                    .conflicts_with_all(["tool", "tool_args"])
+                )
+
+                .arg(Arg::new("tool_args")
+                    .required(false)
+                    .num_args(1..)
+                    .last(true)
+                    .value_name("ARGS")
+                    .allow_hyphen_values(true)
+                    .help("Additional arguments that are passed to the database CLI program")
+                )
             )
 
             .subcommand(Command::new("setup")
@@ -202,6 +370,7 @@ pub fn cli() -> Command {
                     .long("csv")
                     .help("Format output as CSV")
                 )
+                .arg(arg_columns())
                 .arg(Arg::new("job_uuid")
                     .required(false)
                     .long("job")
@@ -210,6 +379,20 @@ pub fn cli() -> Command {
                     .help("Print only artifacts for a certain job")
                     .value_parser(uuid::Uuid::parse_str)
                 )
+                .arg(Arg::new("package")
+                    .required(false)
+                    .long("package")
+                    .short('p')
+                    .value_name("PKG")
+                    .help("Print only artifacts of PKG")
+                )
+                .arg(Arg::new("package_version")
+                    .required(false)
+                    .long("version")
+                    .value_name("VERSION")
+                    .requires("package")
+                    .help("Print only artifacts of this version of PKG (string match)")
+                )
                 .arg(Arg::new("limit")
                     .required(false)
                     .long("limit")
@@ -218,6 +401,38 @@ pub fn cli() -> Command {
                     .help("List newest LIMIT artifacts (0=unlimited)")
                     .value_parser(clap::value_parser!(usize))
                 )
+                .arg(Arg::new("orphaned")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("orphaned")
+                    .help("List only artifacts whose file is missing in the staging or release store")
+                    .long_help(indoc::indoc!(r#"
+                        List only artifacts whose file is missing.
+
+                        The file of a released artifact is expected in the release store it was
+                        released to, the file of an artifact that was not released is expected in
+                        the staging directory of its submit.
+                        If LIMIT is given, only the newest LIMIT artifacts are checked.
+                    "#))
+                )
+                .arg(Arg::new("order")
+                    .required(false)
+                    .long("order")
+                    .value_name("ORDER")
+                    .value_parser(["id", "date", "path"])
+                    .default_value("id")
+                    .help("The order in which the artifacts are listed")
+                    .long_help(indoc::indoc!(r#"
+                        The order in which the artifacts are listed:
+
+                            id   - the order in which the artifacts were recorded, the newest artifact last
+                            date - the submit time of the job that built the artifact, the newest artifact last
+                            path - the path of the artifact, alphabetically
+
+                        With --limit, the newest LIMIT artifacts are listed (the first LIMIT artifacts when ordering
+                        by path).
+                    "#))
+                )
             )
 
             .subcommand(Command::new("envvars")
@@ -238,6 +453,12 @@ pub fn cli() -> Command {
                     .long("csv")
                     .help("Format output as CSV")
                 )
+                .arg(Arg::new("with_counts")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("with-counts")
+                    .help("Show how many jobs used each image and the submit time of the most recent one")
+                )
             )
 
             .subcommand(Command::new("submit")
@@ -259,6 +480,7 @@ pub fn cli() -> Command {
                     .long("csv")
                     .help("Format output as CSV")
                 )
+                .arg(arg_columns())
                 .arg(Arg::new("with_pkg")
                     .required(false)
                     .long("with-pkg")
@@ -294,6 +516,12 @@ pub fn cli() -> Command {
                     .value_name("IMAGE")
                     .help("Limit listed submits to submits on IMAGE")
                 )
+                .arg(Arg::new("failed")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("failed")
+                    .help("Only list submits with at least one failed job")
+                )
             )
 
             .subcommand(Command::new("jobs")
@@ -304,6 +532,7 @@ pub fn cli() -> Command {
                     .long("csv")
                     .help("Format output as CSV")
                 )
+                .arg(arg_columns())
 
                 .arg(Arg::new("submit_uuid")
                     .required(false)
@@ -385,6 +614,30 @@ pub fn cli() -> Command {
                     .help("Show the log")
                 )
 
+                .arg(Arg::new("timestamps")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("timestamps")
+                    .short('t')
+                    .help("Prefix each line of the log with the time it was received (with --log, requires record_log_times in the configuration)")
+                )
+                .arg(Arg::new("grep")
+                    .required(false)
+                    .long("grep")
+                    .value_name("REGEX")
+                    .requires("show_log")
+                    .help("Only show the lines of the log that match REGEX (with --log)")
+                )
+                .arg(Arg::new("context")
+                    .required(false)
+                    .long("context")
+                    .short('C')
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(usize))
+                    .requires("grep")
+                    .help("Show N lines before and after each matching line (with --grep)")
+                )
+
                 .arg(Arg::new("show_script")
                     .action(ArgAction::SetTrue)
                     .required(false)
@@ -393,6 +646,29 @@ pub fn cli() -> Command {
                     .help("Show the script")
                 )
 
+                .arg(Arg::new("theme")
+                    .required(false)
+                    .long("theme")
+                    .value_name("THEME")
+                    .value_parser(highlight_theme_validator)
+                    .requires("show_script")
+                    .help("Highlight the script with THEME instead of script_highlight_theme (see 'list-highlight-themes')")
+                )
+
+                .arg(Arg::new("output")
+                    .required(false)
+                    .long("output")
+                    .short('o')
+                    .value_name("FILE")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .requires("show_script")
+                    .help("Write the script to FILE instead of printing it")
+                    .long_help(indoc::indoc!(r#"
+                        Write the script to FILE instead of printing it.
+                        The script is only highlighted in FILE with '--color always'.
+                    "#))
+                )
+
                 .arg(Arg::new("show_env")
                     .action(ArgAction::SetTrue)
                     .required(false)
@@ -415,6 +691,70 @@ pub fn cli() -> Command {
                     .help("The job to print the log of")
                     .value_parser(uuid::Uuid::parse_str)
                 )
+                .arg(Arg::new("follow")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("follow")
+                    .short('f')
+                    .help("Follow the log of a running job until the job is finished")
+                )
+                .arg(Arg::new("follow_timeout")
+                    .required(false)
+                    .long("follow-timeout")
+                    .value_name("SECONDS")
+                    .value_parser(clap::value_parser!(u64))
+                    .default_value("600")
+                    .requires("follow")
+                    .help("Stop following the log if the job did not log anything for SECONDS (the job may have been aborted)")
+                )
+                .arg(Arg::new("timestamps")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("timestamps")
+                    .short('t')
+                    .help("Prefix each line of the log with the time it was received (requires record_log_times in the configuration)")
+                )
+                .arg(Arg::new("grep")
+                    .required(false)
+                    .long("grep")
+                    .value_name("REGEX")
+                    .conflicts_with("follow")
+                    .help("Only show the lines of the log that match REGEX")
+                )
+                .arg(Arg::new("context")
+                    .required(false)
+                    .long("context")
+                    .short('C')
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(usize))
+                    .requires("grep")
+                    .help("Show N lines before and after each matching line (with --grep)")
+                )
+            )
+            .subcommand(Command::new("requeue")
+                .about("Run a (failed) job again with its recorded script and environment")
+                .long_about(indoc::indoc!(r#"
+                    Run a job again, e.g., if it failed because of a transient issue.
+
+                    The job is run with the script and the environment that were recorded for it in
+                    the database, on the same image, as the only job of a new submit. The dependency
+                    DAG is not built again, the artifacts of the dependencies are searched in the
+                    staging directory of the submit of the job and in the release stores.
+                    Secrets (build --secret) are not recorded and therefore not available to the job.
+                "#))
+                .arg(Arg::new("job_uuid")
+                    .required(true)
+                    .index(1)
+                    .value_name("UUID")
+                    .help("The job to run again")
+                    .value_parser(uuid::Uuid::parse_str)
+                )
+                .arg(Arg::new("keep-failed-containers")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("keep-failed-containers")
+                    .help("Keep the container if the job fails (for debugging)")
+                )
             )
             .subcommand(releases_list_command.clone())
         )
@@ -423,15 +763,37 @@ pub fn cli() -> Command {
             .about("Build packages in containers")
 
             .arg(Arg::new("package_name")
-                .required(true)
+                .required_unless_present("from_stdin")
                 .index(1)
                 .value_name("NAME")
             )
             .arg(Arg::new("package_version")
                 .required(false)
                 .index(2)
-                .value_name("VERSION")
-                .help("Exact package version to build (string match)")
+                .value_name("VERSION_CONSTRAINT")
+                .help("Package version (constraint) to build, the highest matching version is built")
+                .long_help(indoc::indoc!(r#"
+                    The version of the package to build.
+                    If a package with exactly this version exists, it is built. Otherwise, a version
+                    without a comparator is matched as SemVer, e.g. "1" matches all 1.x.y versions and
+                    the highest matching version is built. Use "=VERSION" to only build exactly this
+                    version (string match).
+                "#))
+            )
+            .arg(Arg::new("from_stdin")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("from-stdin")
+                .conflicts_with_all(["package_name", "package_version", "resume", "watch"])
+                .help("Read the packages to build from stdin and build them together")
+                .long_help(indoc::indoc!(r#"
+                    Read the packages to build from stdin, one package per line as "NAME [VERSION_CONSTRAINT]".
+                    Empty lines and lines starting with '#' are ignored.
+
+                    All packages are built in one submit, with one dependency DAG, so that dependencies that
+                    are shared by the packages are only built once. The first package is recorded as the
+                    package of the submit in the database.
+                "#))
             )
 
             .arg(Arg::new("no_verification")
@@ -460,12 +822,55 @@ pub fn cli() -> Command {
                 .value_parser(dir_exists_validator)
                 .help("Do not throw dice on staging directory name, but hardcode for this run.")
             )
+            .arg(Arg::new("resume")
+                .required(false)
+                .long("resume")
+                .value_name("SUBMIT")
+                .value_parser(uuid::Uuid::parse_str)
+                .conflicts_with("staging_dir")
+                .help("Resume an interrupted submit")
+                .long_help(indoc::indoc!(r#"
+                    Resume the interrupted submit SUBMIT, which has to be for the same package.
+                    The artifacts of the jobs that finished successfully in the submit are reused (if the
+                    script and environment of the job are still the same), only the other jobs are run.
+                    The staging directory of the submit is used for this run.
+
+                    A build is interrupted with SIGTERM or SIGINT (Ctrl-C): No new jobs are started, but the running
+                    jobs are finished, so that their artifacts are reused when the submit is resumed. On the second
+                    signal, the containers of the running jobs are killed.
+                "#))
+            )
 
             .arg(Arg::new("shebang")
                 .required(false)
                 .long("shebang")
                 .value_name("BANG")
-                .help("Overwrite the configured shebang line")
+                .help("Overwrite the configured shebang line (of the image)")
+            )
+
+            .arg(Arg::new("phases")
+                .required(false)
+                .long("phases")
+                .value_name("PHASES")
+                .value_delimiter(',')
+                .action(ArgAction::Append)
+                .conflicts_with("skip-phases")
+                .help("Only run these phases of the scripts (comma-separated)")
+                .long_help(indoc::indoc!(r#"
+                    Only run these phases of the package scripts, e.g. `--phases unpack,build` to skip the tests.
+                    The phases are run in the order of available_phases from the configuration, the order given
+                    here does not matter. Each phase must be one of the available_phases, packages that don't
+                    define a phase just don't run it.
+                    By default, all phases are run.
+                "#))
+            )
+            .arg(Arg::new("skip-phases")
+                .required(false)
+                .long("skip-phases")
+                .value_name("PHASES")
+                .value_delimiter(',')
+                .action(ArgAction::Append)
+                .help("Do not run these phases of the scripts (comma-separated)")
             )
 
             .arg(Arg::new("env")
@@ -480,13 +885,199 @@ pub fn cli() -> Command {
                     This argument expects \"key=value\" or name of variable available in ENV
                 "#))
             )
+            .arg(Arg::new("env_from_job")
+                .required(false)
+                .long("env-from-job")
+                .value_name("UUID")
+                .value_parser(uuid::Uuid::parse_str)
+                .help("Pass the environment of the job UUID to all build jobs (overridden by --env)")
+                .long_help(indoc::indoc!(r#"
+                    Pass the environment variables the job UUID ran with (as recorded in the database) to each build
+                    job, e.g. to reproduce a build. The environment of the package of the job and the git author and
+                    commit hash variables are left out, they are set by the build anyways.
+                    Variables passed with --env override the ones of the job.
+                "#))
+            )
+
+            .arg(Arg::new("secret")
+                .required(false)
+                .action(ArgAction::Append)
+                .long("secret")
+                .value_name("NAME=PATH")
+                .help("Make the file at PATH available to all build jobs as secret NAME")
+                .long_help(indoc::indoc!(r#"
+                    Make the content of the file at PATH available to each build job at "/run/secrets/NAME", e.g. a token
+                    for fetching a private dependency. The file is removed from the container after the script finished
+                    (containers that cannot be started or whose secrets cannot be removed are removed completely).
+                    Unlike variables passed with --env, secrets are neither stored in the database nor logged.
+                "#))
+            )
 
             .arg(Arg::new("image")
-                .required(true)
+                .required(false)
                 .value_name("IMAGE NAME")
                 .short('I')
                 .long("image")
-                .help("Name of the Docker image to use")
+                .help("Name of the Docker image to use (defaults to docker.default_image)")
+            )
+
+            .arg(Arg::new("arch")
+                .required(false)
+                .long("arch")
+                .value_name("ARCH")
+                .help("The architecture to build the packages for")
+                .long_help(indoc::indoc!(r#"
+                    The architecture (or generic target) to build the packages for.
+                    The architecture is passed to the build containers as BUTIDO_ARCH and stored with the jobs.
+                    Artifacts are only reused if they were built for the same architecture (or also without an
+                    architecture, if this is not passed).
+                "#))
+            )
+
+            .arg(Arg::new("max-log-lines")
+                .required(false)
+                .long("max-log-lines")
+                .value_name("LINES")
+                .value_parser(clap::value_parser!(usize))
+                .help("Kill jobs that print more than LINES log lines (overrides max_log_lines from the configuration)")
+                .long_help(indoc::indoc!(r#"
+                    Kill the container of a job that prints more than LINES log lines and fail the job. The rest of the
+                    log is dropped, so that a misbehaving build cannot fill the database.
+                    Overrides max_log_lines from the configuration (unlimited if neither is set).
+                "#))
+            )
+
+            .arg(Arg::new("keep-failed-containers")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("keep-failed-containers")
+                .help("Do not stop the containers of failed jobs")
+                .long_help(indoc::indoc!(r#"
+                    Do not stop the containers of jobs where the script failed, so that they can be
+                    inspected with 'docker exec'. The ID of each kept container is printed.
+
+                    The containers of successful jobs are stopped anyways.
+                    Kept containers have to be cleaned up manually, e.g. with
+                    'butido endpoint containers stop' and 'butido endpoint containers prune'.
+                "#))
+            )
+
+            .arg(Arg::new("output-dir")
+                .required(false)
+                .long("output-dir")
+                .value_name("PATH")
+                .help("Copy the packages that were built to PATH after a successful build")
+            )
+            .arg(Arg::new("overwrite")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("overwrite")
+                .requires("output-dir")
+                .help("Overwrite existing files in the output directory")
+            )
+
+            .arg(Arg::new("no-reuse")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("no-reuse")
+                .conflicts_with("resume")
+                .help("Build all packages, do not reuse artifacts of earlier builds")
+                .long_help(indoc::indoc!(r#"
+                    Build all packages of the dependency tree, even if there are artifacts of an earlier build
+                    (in the staging or release stores) that could be reused.
+                    This is useful to verify that all packages can (still) be built from source.
+                "#))
+            )
+
+            .arg(Arg::new("fail-fast")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("fail-fast")
+                .overrides_with("no-fail-fast")
+                .help("Cancel all jobs as soon as one job fails")
+                .long_help(indoc::indoc!(r#"
+                    Cancel all jobs as soon as one job fails: Jobs that wait for their dependencies or for a
+                    free endpoint are not started anymore and the containers of running jobs are killed (the
+                    killed jobs are recorded as failed in the database).
+
+                    By default (--no-fail-fast), only the jobs that depend on a failed job are skipped and all
+                    independent jobs are finished before the failures are reported.
+                "#))
+            )
+            .arg(Arg::new("no-fail-fast")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("no-fail-fast")
+                .overrides_with("fail-fast")
+                .help("Finish all jobs that do not depend on a failed job (default)")
+            )
+
+            .arg(Arg::new("no-summary")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("no-summary")
+                .help("Do not print a summary after the build")
+            )
+
+            .arg(Arg::new("json")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("json")
+                .conflicts_with("no-summary")
+                .help("Print the summary after the build as JSON")
+                .long_help(indoc::indoc!(r#"
+                    Print the summary after the build as JSON, for further processing.
+                    The JSON contains the number of built, reused, failed and skipped jobs,
+                    the wall time and all jobs with their outcome and duration (in seconds).
+                    The JSON is the only output on stdout, all other output of the build is written to stderr.
+                "#))
+            )
+
+            .arg(Arg::new("events-file")
+                .required(false)
+                .long("events-file")
+                .value_name("PATH")
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with_all(["events-fd", "dry_run"])
+                .help("Write the events of the build to PATH, as JSON Lines")
+                .long_help(indoc::indoc!(r#"
+                    Write the events of the build to PATH while the build is running, one JSON object per line.
+                    This is meant for tools that follow the progress of a build (e.g. a CI system), instead of
+                    parsing the progress bars. The events are written with --hide-bars as well.
+
+                    Each event has an "event" field with its name and a "time" field:
+                      job_started     the container of a job was started (with the endpoint)
+                      phase_changed   the script of a job entered a phase
+                      job_reused      the artifacts of an earlier build were reused for a job
+                      job_finished    a job was built, failed (with the error) or skipped
+                      submit_finished all jobs are done (with the number of jobs per outcome)
+                "#))
+            )
+            .arg(Arg::new("events-fd")
+                .required(false)
+                .long("events-fd")
+                .value_name("FD")
+                .value_parser(clap::value_parser!(u32))
+                .conflicts_with("dry_run")
+                .help("Write the events of the build to the open file descriptor FD, as JSON Lines")
+                .long_help(indoc::indoc!(r#"
+                    Like --events-file, but write the events to the file descriptor FD, which must be opened
+                    (for writing) by the caller, e.g. with `butido build ... --events-fd 3 3>&1 >/dev/null`.
+                "#))
+            )
+
+            .arg(Arg::new("stats")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("stats")
+                .conflicts_with_all(["json", "dry_run"])
+                .help("Print how the jobs were distributed over the endpoints after the build")
+                .long_help(indoc::indoc!(r#"
+                    Print the number of jobs that were scheduled on each endpoint and the average and maximum
+                    time the jobs waited for a free endpoint after the build.
+                    A job that was moved to another endpoint because it could not be started is counted for
+                    each endpoint it was scheduled on.
+                "#))
             )
 
             .arg(Arg::new("write-log-file")
@@ -502,6 +1093,50 @@ pub fn cli() -> Command {
                     The log of a build is written to `<log_dir>/<build id>.log`.
                 "#))
             )
+
+            .arg(Arg::new("dry_run")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("dry-run")
+                .help("Only show which jobs would be built or reused, without building anything")
+                .long_help(indoc::indoc!(r#"
+                    Do not build anything, but print the plan for the build: Which jobs would be built, which jobs
+                    would reuse artifacts from earlier builds, and on which endpoints the jobs could be scheduled.
+
+                    Butido still connects to the endpoints and the database for this, but no containers are created
+                    and nothing is written to the database.
+                "#))
+            )
+
+            .arg(Arg::new("watch")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("watch")
+                .conflicts_with("dry_run")
+                .help("Build again whenever a package script or a source of the package changes")
+                .long_help(indoc::indoc!(r#"
+                    Build the package, then wait until a package script (pkg.toml) in the repository or a
+                    source file of the package changes and build again. Changes are collected until no
+                    file changed for a second, so that saving multiple files results in a single build.
+
+                    A short PASS/FAIL line is printed after each build. Press Ctrl-C to exit, a running build is
+                    shut down like without --watch (see --resume).
+                "#))
+            )
+
+            .arg(Arg::new("network_mode")
+                .required(false)
+                .long("network-mode")
+                .value_name("MODE")
+                .value_parser(network_mode_validator)
+                .help("Overwrite the configured network mode of the endpoints for this build")
+                .long_help(indoc::indoc!(r#"
+                    Use this network mode for all containers of this build instead of the network mode that is
+                    configured for the endpoints (`network_mode`).
+
+                    MODE must be one of "bridge", "host", "none", "default", or "container:<name|id>".
+                "#))
+            )
         )
 
         .subcommand(Command::new("what-depends")
@@ -527,6 +1162,21 @@ pub fn cli() -> Command {
                 ])
                 .help("Specify which dependency types are to be checked. By default, all are checked")
             )
+            .arg(Arg::new("exclude")
+                .required(false)
+                .action(ArgAction::Append)
+                .long("exclude")
+                .value_name("REGEX")
+                .help("Exclude packages with a name matching REGEX from the output (can be passed multiple times)")
+                .long_help(indoc::indoc!(r#"
+                    Exclude packages with a name matching REGEX from the output. Can be passed multiple times.
+
+                    Packages that depend on the package but match REGEX are not listed and the dependencies on
+                    packages matching REGEX are removed from the listed dependencies. The removed dependencies
+                    are printed after each package and the excluded packages are printed at the end, so that it
+                    is clear that the list is not complete.
+                "#))
+            )
         )
         .subcommand(Command::new("dependencies-of")
             .alias("depsof")
@@ -559,6 +1209,20 @@ pub fn cli() -> Command {
                 ])
                 .help("Specify which dependency types are to be printed. By default, all are checked")
             )
+            .arg(Arg::new("exclude")
+                .required(false)
+                .action(ArgAction::Append)
+                .long("exclude")
+                .value_name("REGEX")
+                .help("Exclude packages with a name matching REGEX from the output (can be passed multiple times)")
+                .long_help(indoc::indoc!(r#"
+                    Exclude packages with a name matching REGEX from the output. Can be passed multiple times.
+
+                    The dependencies on packages matching REGEX are removed from the listed dependencies. The
+                    removed dependencies are printed after each package, so that it is clear that the list is
+                    not complete.
+                "#))
+            )
         )
         .subcommand(Command::new("versions-of")
             .alias("versions")
@@ -569,6 +1233,29 @@ pub fn cli() -> Command {
                 .value_name("PACKAGE_NAME")
                 .help("The name of the package")
             )
+            .arg(Arg::new("image")
+                .required(false)
+                .value_name("IMAGE NAME")
+                .short('I')
+                .long("image")
+                .help("Only list the versions that can be built on this Docker image")
+                .long_help(indoc::indoc!(r#"
+                    Only list the versions that can be built on this Docker image.
+
+                    A version is listed if all dependencies that are required on this image (and with the
+                    passed env, considering the conditions of the dependencies) are available in the repository
+                    and if all packages in the dependency tree are allowed on this image.
+                "#))
+            )
+            .arg(Arg::new("env")
+                .required(false)
+                .action(ArgAction::Append)
+                .short('E')
+                .long("env")
+                .requires("image")
+                .value_parser(env_pass_validator)
+                .help("Additional env to be passed when building packages (requires --image)")
+            )
         )
         .subcommand(Command::new("env-of")
             .alias("env")
@@ -585,12 +1272,35 @@ pub fn cli() -> Command {
                 .value_name("PACKAGE_VERSION")
                 .help("The version of the package")
             )
+            .arg(Arg::new("effective")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("effective")
+                .help("Show the environment as it would be set in the build container")
+                .long_help(indoc::indoc!(r#"
+                    Show the environment as it would be set in the build container.
+
+                    This includes the git author and git commit hash variables (if configured)
+                    and the variables passed with --env. The source of each variable is shown
+                    next to it. If a variable is set more than once, only the value that takes
+                    effect is shown.
+                "#))
+            )
+            .arg(Arg::new("env")
+                .required(false)
+                .action(ArgAction::Append)
+                .short('E')
+                .long("env")
+                .requires("effective")
+                .value_parser(env_pass_validator)
+                .help("Additional env to be passed when building packages (requires --effective)")
+            )
         )
 
         .subcommand(Command::new("find-artifact")
             .about("Find artifacts for packages")
             .arg(Arg::new("package_name_regex")
-                .required(true)
+                .required_unless_present("reindex")
                 .index(1)
                 .value_name("REGEX")
                 .help("The regex to match the package name against")
@@ -631,15 +1341,41 @@ pub fn cli() -> Command {
                 .value_name("IMAGE")
                 .help("Only list artifacts that were built on IMAGE")
             )
+            .arg(Arg::new("arch")
+                .required(false)
+                .long("arch")
+                .value_name("ARCH")
+                .help("Only list artifacts that were built for ARCH (default: artifacts that were built without an architecture)")
+            )
+            .arg(Arg::new("ignore_env")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("ignore-env")
+                .conflicts_with("env_filter")
+                .help("Don't check for environment equality. Can return artifacts that were built with a different environment.")
+            )
+            .arg(Arg::new("reindex")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("reindex")
+                .help("Rebuild the artifact index (see \"artifact_index\" in the configuration) from scratch")
+            )
+            .arg(Arg::new("no_index")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("no-index")
+                .conflicts_with("reindex")
+                .help("Don't use the artifact index, search the database instead")
+            )
         )
 
         .subcommand(Command::new("find-pkg")
             .about("Find a package by regex")
             .arg(Arg::new("package_name_regex")
-                .required(true)
+                .required_unless_present("changed_since")
                 .index(1)
                 .value_name("REGEX")
-                .help("The regex to match the package name against")
+                .help("The regex to match the package name against (or a glob pattern, with --glob)")
             )
             .arg(Arg::new("package_version_constraint")
                 .required(false)
@@ -648,6 +1384,19 @@ pub fn cli() -> Command {
                 .help("A version constraint to match the package version against (optional), e.g., '=1.0.0'")
             )
 
+            .arg(Arg::new("glob")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("glob")
+                .short('g')
+                .help("Interpret REGEX as a glob pattern that must match the whole name, e.g., 'foo-*'")
+                .long_help(indoc::indoc!(r#"
+                    Interpret REGEX as a glob pattern instead of a regex. The pattern must match the whole package
+                    name: '*' matches any characters, '?' matches one character, and '[abc]' (or '[!abc]') matches
+                    one of (or none of) the listed characters, e.g., 'python3-*' or 'lib?'.
+                "#))
+            )
+
             .arg(Arg::new("terse")
                 .action(ArgAction::SetTrue)
                 .required(false)
@@ -656,6 +1405,25 @@ pub fn cli() -> Command {
                 .help("Do not use the fancy format, but simply <name> <version>")
             )
 
+            .arg(Arg::new("changed_since")
+                .required(false)
+                .long("changed-since")
+                .alias("since-commit")
+                .value_name("GITREF")
+                .help("Only find the packages whose files changed since GITREF (implies --terse)")
+                .long_help(indoc::indoc!(r#"
+                    Only find the packages whose files changed between the git revision GITREF (e.g. a commit,
+                    branch or tag) and the working directory (including uncommitted and untracked files).
+
+                    A package changed if one of its pkg.toml files changed (including the ones in parent
+                    directories that it inherits from), one of its patches changed, or any other file in its
+                    directory changed.
+
+                    The packages are printed as "<name> <version>" (like with --terse), so that the output can
+                    be passed to "butido build --from-stdin".
+                "#))
+            )
+
             .arg(Arg::new("show_all")
                 .action(ArgAction::SetTrue)
                 .required(false)
@@ -750,6 +1518,21 @@ pub fn cli() -> Command {
                 .short('s')
                 .help("Show the script of the package")
             )
+
+            .arg(Arg::new("show_inherited")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("show-inherited")
+                .help("Show the pkg.toml file each field of the package was loaded from")
+                .long_help(indoc::indoc!(r#"
+                    Show the pkg.toml file each field of the package was loaded from.
+
+                    A pkg.toml file in a parent directory provides defaults for all packages beneath it:
+                    Values of a child override the values of its parents, tables (e.g. "sources" or
+                    "environment") are merged key by key, and arrays (e.g. "patches") of a child replace
+                    the arrays of its parents.
+                "#))
+            )
             .arg(script_arg_line_numbers())
             .arg(script_arg_no_line_numbers())
             .arg(script_arg_highlight())
@@ -780,6 +1563,81 @@ pub fn cli() -> Command {
                     .help("Verify all packages where the package name matches REGEX")
                 )
 
+                .arg(Arg::new("redownload")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("redownload")
+                    .visible_alias("fix")
+                    .help("Download missing sources and sources that fail the hash check again")
+                    .long_help(indoc::indoc!(r#"
+                        Download the sources that are missing or that fail the hash check again and verify them afterwards.
+                        Sources that have to be downloaded manually are not downloaded, they are reported instead.
+                    "#))
+                )
+
+                .arg(Arg::new("print_hash")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("print-hash")
+                    .conflicts_with("redownload")
+                    .help("Print the expected and the actual hash of each source")
+                    .long_help(indoc::indoc!(r#"
+                        Print the expected and the actual hash of each source.
+                        This is useful to update the package definitions if the upstream sources changed.
+                    "#))
+                )
+
+                .arg(Arg::new("parallel")
+                    .required(false)
+                    .long("parallel")
+                    .short('j')
+                    .value_name("N")
+                    .value_parser(clap::value_parser!(std::num::NonZeroUsize))
+                    .help("Verify at most N sources concurrently (default: number of CPUs)")
+                    .long_help(indoc::indoc!(r#"
+                        Verify at most N sources concurrently.
+                        Defaults to the number of CPUs. Lower it if the source cache is on slow
+                        storage (e.g. spinning disks), where many concurrent reads thrash the disk.
+                    "#))
+                )
+
+                .arg(Arg::new("recursive")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("recursive")
+                    .short('r')
+                    .help("Verify the sources of all (transitive) dependencies as well")
+                    .long_help(indoc::indoc!(r#"
+                        Verify the sources of all (transitive) dependencies of the packages as well, i.e., all sources
+                        that are required for building the packages.
+                        The dependencies depend on the image and the environment because of conditions on
+                        dependencies, see --image and --env.
+                    "#))
+                )
+
+                .arg(Arg::new("image")
+                    .required(false)
+                    .value_name("IMAGE NAME")
+                    .short('I')
+                    .long("image")
+                    .requires("recursive")
+                    .help("Name of the Docker image to use for resolving the dependencies (with --recursive)")
+                    .long_help(indoc::indoc!(r#"
+                        Name of the Docker image to use for resolving the dependencies (with --recursive).
+                        Defaults to the configured docker.default_image (if set).
+                    "#))
+                )
+
+                .arg(Arg::new("env")
+                    .required(false)
+                    .action(ArgAction::Append)
+                    .short('E')
+                    .long("env")
+                    .requires("recursive")
+                    .value_parser(env_pass_validator)
+                    .help("Additional env to use for resolving the dependencies (with --recursive)")
+                )
+
                 .group(ArgGroup::new("verify-one-or-many")
                     .args(["package_name", "matching"])
                     .required(true)
@@ -843,6 +1701,89 @@ pub fn cli() -> Command {
                     .help("Set timeout for download in seconds")
                     .value_parser(clap::value_parser!(u64))
                 )
+
+                .arg(Arg::new("noninteractive")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("non-interactive")
+                    .help("Don't wait for sources that have to be downloaded manually")
+                    .long_help(indoc::indoc!(r#"
+                        By default, butido prints the instructions for missing sources that have to be downloaded manually
+                        and waits until the file was placed at its destination.
+                        With this flag, butido only prints the instructions and fails.
+                    "#))
+                )
+
+                .arg(Arg::new("check_only")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("check-only")
+                    .help("Only print the sources that would be downloaded and their sizes")
+                    .long_help(indoc::indoc!(r#"
+                        Only print the sources that would be downloaded (the missing ones, or all with --force) and
+                        their sizes, as reported by the servers (with HEAD requests). Nothing is downloaded.
+                    "#))
+                )
+
+                .arg(Arg::new("recursive")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("recursive")
+                    .short('r')
+                    .help("Download the sources of all (transitive) dependencies as well")
+                    .long_help(indoc::indoc!(r#"
+                        Download the sources of all (transitive) dependencies of the packages as well, i.e., all
+                        sources that are required for building the packages (see --runtime-only).
+                        The dependencies depend on the image and the environment because of conditions on
+                        dependencies, see --image and --env.
+                    "#))
+                )
+
+                .arg(Arg::new("runtime_only")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("runtime-only")
+                    .requires("recursive")
+                    .overrides_with("include_build_deps")
+                    .help("Only download the sources of the runtime dependencies (with --recursive)")
+                    .long_help(indoc::indoc!(r#"
+                        Only download the sources of the (transitive) runtime dependencies (with --recursive).
+                        The build time dependencies are left out, with all their dependencies (unless they are
+                        runtime dependencies as well).
+                    "#))
+                )
+
+                .arg(Arg::new("include_build_deps")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("include-build-deps")
+                    .requires("recursive")
+                    .overrides_with("runtime_only")
+                    .help("Download the sources of the build time dependencies as well (with --recursive, the default)")
+                )
+
+                .arg(Arg::new("image")
+                    .required(false)
+                    .value_name("IMAGE NAME")
+                    .short('I')
+                    .long("image")
+                    .requires("recursive")
+                    .help("Name of the Docker image to use for resolving the dependencies (with --recursive)")
+                    .long_help(indoc::indoc!(r#"
+                        Name of the Docker image to use for resolving the dependencies (with --recursive).
+                        Defaults to the configured docker.default_image (if set).
+                    "#))
+                )
+
+                .arg(Arg::new("env")
+                    .required(false)
+                    .action(ArgAction::Append)
+                    .short('E')
+                    .long("env")
+                    .requires("recursive")
+                    .value_parser(env_pass_validator)
+                    .help("Additional env to use for resolving the dependencies (with --recursive)")
+                )
             )
             .subcommand(Command::new("of")
                 .about("Get the paths of the sources of a package")
@@ -868,9 +1809,9 @@ pub fn cli() -> Command {
                 .about("Remove release artifacts")
                 .long_about(indoc::indoc!(r#"
                     Removes a released artifact from the release store and deletes the according database entry.
+                    With --older-than, all releases in the release store that are older than the date are removed.
 
-                    This command asks interactively whether you want to delete data.
-                    This can't be turned off.
+                    This command asks interactively whether you want to delete data, unless --yes is passed.
                 "#))
                 .arg(Arg::new("release_store_name")
                     .required(true)
@@ -880,7 +1821,7 @@ pub fn cli() -> Command {
                 )
 
                 .arg(Arg::new("package_name")
-                    .required(true)
+                    .required_unless_present("older_than")
                     .index(1)
                     .value_name("PKG")
                     .help("The name of the package")
@@ -888,12 +1829,70 @@ pub fn cli() -> Command {
                 )
 
                 .arg(Arg::new("package_version")
-                    .required(true)
+                    .required_unless_present("older_than")
                     .index(2)
                     .value_name("VERSION")
                     .help("The exact version of the package (string match)")
                     .requires("package_name")
                 )
+
+                .arg(arg_older_than_date("Remove all releases from the release store that are older than DATE")
+                    .conflicts_with("package_name")
+                )
+
+                .arg(Arg::new("noninteractive")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("yes")
+                    .alias("non-interactive")
+                    .short('y')
+                    .help("Remove the release without asking for confirmation")
+                )
+
+                .arg(Arg::new("dry_run")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("dry-run")
+                    .help("Only show what would be removed, without removing anything")
+                    .conflicts_with("noninteractive")
+                )
+            )
+
+            .subcommand(Command::new("verify")
+                .about("Verify that the released artifacts in the database match the files in the release stores")
+                .long_about(indoc::indoc!(r#"
+                    Checks that the file of each release in the database exists in its release store and reports
+                    files in the release stores that are not known to the database.
+                "#))
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .help("Format output as CSV")
+                )
+                .arg(Arg::new("release_store_name")
+                    .required(false)
+                    .long("store")
+                    .value_name("RELEASE_STORE_NAME")
+                    .help("Verify only this release store (default: all release stores)")
+                )
+                .arg(Arg::new("check_content")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("check-content")
+                    .help("Also compare the hash of each released file with the artifact in the staging store")
+                    .long_help(indoc::indoc!(r#"
+                        Also compare the SHA256 hash of each released file with the hash of the artifact in the
+                        staging directory of its submit.
+                        Releases whose artifact is not available in the staging directory anymore cannot be checked.
+                    "#))
+                )
+                .arg(Arg::new("check_signatures")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("signatures")
+                    .help("Also verify the signatures of the released files with release_signing.verify_command")
+                )
             )
 
             .subcommand(Command::new("new")
@@ -906,15 +1905,26 @@ pub fn cli() -> Command {
                     .value_parser(uuid::Uuid::parse_str)
                 )
                 .arg(Arg::new("release_store_name")
-                    .required(true)
+                    .required_unless_present("release_to_all_stores")
+                    .action(ArgAction::Append)
                     .long("to")
                     .value_name("RELEASE_STORE_NAME")
-                    .help("Release store name to release to")
+                    .help("Release store name to release to (can be passed multiple times)")
                     .long_help(indoc::indoc!(r#"
                         Butido can release to different release stores, based on this CLI flag.
                         The release stores that are available must be listed in the configuration.
+                        Pass this flag multiple times to release to multiple release stores at once.
+                        If releasing to a store fails, the other stores are still released to. The result of each
+                        store is printed and the command fails if releasing to any store failed.
                     "#))
                 )
+                .arg(Arg::new("release_to_all_stores")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("to-all")
+                    .help("Release to all release stores that are listed in the configuration")
+                    .conflicts_with("release_store_name")
+                )
                 .arg(Arg::new("package_name")
                     .required(false)
                     .index(2)
@@ -951,6 +1961,33 @@ pub fn cli() -> Command {
 
         )
 
+        .subcommand(Command::new("staging")
+            .about("Handle the staging directories")
+            .subcommand(Command::new("gc")
+                .about("Remove old staging directories that are not needed anymore")
+                .long_about(indoc::indoc!(r#"
+                    Remove old staging directories that are not needed anymore.
+
+                    A staging directory is not needed anymore if all artifacts of its submit were
+                    released, if its submit has no artifacts or if there is no such submit in the
+                    database. The directories are listed and only removed after a confirmation.
+                "#))
+                .arg(arg_older_than_date("Remove only staging directories older than DATE (default: the configured staging_retention)"))
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .help("Format output as CSV")
+                )
+                .arg(Arg::new("dry_run")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("dry-run")
+                    .help("Only list the staging directories that would be removed")
+                )
+            )
+        )
+
         .subcommand(Command::new("lint")
             .about("Lint the package script of one or multiple packages")
             .arg(Arg::new("package_name")
@@ -965,6 +2002,18 @@ pub fn cli() -> Command {
                 .value_name("VERSION_CONSTRAINT")
                 .help("A version constraint to match the package version against (optional), e.g., '=1.0.0'")
             )
+            .arg(Arg::new("builtin")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("builtin")
+                .help("Run the built-in lints instead of the configured linter")
+                .long_help(indoc::indoc!(r#"
+                    Run the built-in lints instead of the configured linter (script_linter).
+                    These check the structure of the packages: Whether all configured phases are defined,
+                    whether scripts are empty, whether all sources are used by the scripts, and whether the
+                    shebangs in the scripts match the configured ones.
+                "#))
+            )
         )
 
         .subcommand(Command::new("tree-of")
@@ -992,6 +2041,7 @@ pub fn cli() -> Command {
 
                     Required because tree might look different on different images because of
                     conditions on dependencies.
+                    Defaults to the configured docker.default_image (if set).
                 "#))
             )
             .arg(Arg::new("env")
@@ -1015,6 +2065,19 @@ pub fn cli() -> Command {
                 .help("Output the dependency DAG in the Graphviz DOT format")
                 .conflicts_with("serial-buildorder")
             )
+            .arg(Arg::new("json")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("json")
+                .conflicts_with_all(["dot", "serial-buildorder"])
+                .help("Output the dependency trees as JSON")
+                .long_help(indoc::indoc!(r#"
+                    Output the dependency trees as a JSON array with one object per matching package.
+                    Each package is an object with the fields "name", "version", "build_dep" (whether it is a
+                    build time dependency of its parent), "children" (the dependencies, with the same
+                    structure) and "truncated" (whether dependencies were left out because of --depth).
+                "#))
+            )
             .arg(Arg::new("serial-buildorder")
                 .action(ArgAction::SetTrue)
                 .required(false)
@@ -1032,10 +2095,49 @@ pub fn cli() -> Command {
                 "#))
                 .conflicts_with("dot")
             )
+            .arg(Arg::new("depth")
+                .required(false)
+                .long("depth")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Only descend N levels into the dependency tree (0 prints only the package itself)")
+                .long_help(indoc::indoc!(r#"
+                    Only descend N levels into the dependency tree.
+                    Packages that have dependencies below that level are marked with "..." in
+                    the tree output. The --dot output contains only the packages up to that
+                    level. A depth of 0 prints only the package itself.
+                "#))
+                .conflicts_with("serial-buildorder")
+            )
+            .arg(Arg::new("no_color")
+                .action(ArgAction::SetTrue)
+                .required(false)
+                .long("no-color")
+                .help("Do not highlight build time dependencies with a color")
+                .long_help(indoc::indoc!(r#"
+                    Do not highlight build time dependencies with a color.
+                    Build time dependencies are always marked with a "*". They are additionally
+                    highlighted with a color if stdout is a terminal, unless this flag is passed.
+                "#))
+            )
         )
 
         .subcommand(Command::new("metrics")
             .about("Print metrics about butido")
+            .arg(Arg::new("format")
+                .required(false)
+                .long("format")
+                .value_name("FORMAT")
+                .value_parser(["human", "prometheus"])
+                .default_value("human")
+                .help("The output format")
+                .long_help(indoc::indoc!(r#"
+                    The output format.
+                    "prometheus" prints the metrics in the Prometheus text exposition format, e.g.
+                    for the textfile collector of the node exporter. It also contains the number of
+                    jobs by their result, which requires parsing the logs of all jobs.
+                "#))
+            )
         )
 
         .subcommand(Command::new("endpoint")
@@ -1066,6 +2168,18 @@ pub fn cli() -> Command {
                     .help("How long to sleep between pings")
                     .value_parser(clap::value_parser!(u64))
                 )
+                .arg(Arg::new("json")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("json")
+                    .help("Print the results (latencies, packet loss, errors) as JSON")
+                    .long_help(indoc::indoc!(r#"
+                        Print the results as JSON: For each endpoint whether all pings succeeded, the round-trip
+                        times of the successful pings (in milliseconds), the fraction of failed pings and the last
+                        error. All pings are sent, even if some fail, and endpoints that cannot be reached are
+                        included with an error.
+                    "#))
+                )
             )
             .subcommand(Command::new("stats")
                 .about("Get stats for the endpoint(s)")
@@ -1075,6 +2189,28 @@ pub fn cli() -> Command {
                     .long("csv")
                     .help("Format output as CSV")
                 )
+                .arg(Arg::new("json")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("json")
+                    .help("Print the stats as JSON, endpoints that cannot be reached are included with an error")
+                    .conflicts_with("csv")
+                )
+            )
+            .subcommand(Command::new("summary")
+                .about("Show the utilization and reachability of the endpoint(s)")
+                .long_about(indoc::indoc!(r#"
+                    Show the utilization and reachability of the endpoint(s).
+
+                    The running jobs are counted by the running containers on the endpoint.
+                    Endpoints that cannot be reached are marked as down.
+                "#))
+                .arg(Arg::new("csv")
+                    .action(ArgAction::SetTrue)
+                    .required(false)
+                    .long("csv")
+                    .help("Format output as CSV")
+                )
             )
             .subcommand(Command::new("containers")
                 .about("Work with the containers of the endpoint(s)")
@@ -1082,6 +2218,21 @@ pub fn cli() -> Command {
                     .about("Remove exited containers")
                     .arg(arg_older_than_date("Prune only containers older than DATE"))
                     .arg(arg_newer_than_date("Prune only containers newer than DATE"))
+                    .arg(arg_since_date("Prune only containers created at or after DATE"))
+                    .arg(arg_until_date("Prune only containers created at or before DATE"))
+                    .arg(Arg::new("filter_image")
+                        .required(false)
+                        .short('I')
+                        .long("image")
+                        .value_name("IMAGE")
+                        .help("Prune only containers of IMAGE")
+                    )
+                    .arg(Arg::new("dry_run")
+                        .action(ArgAction::SetTrue)
+                        .required(false)
+                        .long("dry-run")
+                        .help("Only list the containers that would be removed")
+                    )
                 )
                 .subcommand(Command::new("stop")
                     .about("Stop running containers")
@@ -1119,25 +2270,66 @@ pub fn cli() -> Command {
                         .value_name("IMAGE")
                         .help("List only containers of IMAGE")
                     )
+                    .arg(Arg::new("submit")
+                        .required(false)
+                        .long("submit")
+                        .value_name("UUID")
+                        .value_parser(uuid::Uuid::parse_str)
+                        .help("List only containers of the submit UUID")
+                        .long_help(indoc::indoc!(r#"
+                            List only the containers of the jobs of the submit UUID, e.g. to clean up after an aborted
+                            submit. The containers are found by their "butido.submit" label, containers that were created
+                            by older versions of butido are not labeled.
+                            Use --list-stopped to list the containers that are not running anymore as well.
+                        "#))
+                    )
 
                     .arg(arg_older_than_date("List only containers older than DATE"))
                     .arg(arg_newer_than_date("List only containers newer than DATE"))
+                    .arg(arg_since_date("List only containers created at or after DATE"))
+                    .arg(arg_until_date("List only containers created at or before DATE"))
                 )
                 .subcommand(Command::new("top")
-                    .about("List the processes of all containers")
+                    .about("List the running containers by their resource usage")
+                    .long_about(indoc::indoc!(r#"
+                        List the running containers with the summed up CPU and memory usage of their processes,
+                        the busiest container first.
+
+                        With --raw, the processes of each container are listed instead.
+                    "#))
                     .arg(Arg::new("csv")
                         .action(ArgAction::SetTrue)
                         .required(false)
                         .long("csv")
                         .help("List top output as CSV")
                     )
+                    .arg(Arg::new("sort")
+                        .required(false)
+                        .long("sort")
+                        .value_name("RESOURCE")
+                        .value_parser(["cpu", "mem"])
+                        .default_value("cpu")
+                        .conflicts_with("raw")
+                        .help("Sort the containers by their CPU or memory usage")
+                    )
                     .arg(Arg::new("limit")
                         .required(false)
                         .long("limit")
                         .value_name("LIMIT")
-                        .help("Only list LIMIT processes for each container")
+                        .help("Only list the LIMIT busiest containers (with --raw: only list LIMIT processes for each container)")
                         .value_parser(clap::value_parser!(usize))
                     )
+                    .arg(Arg::new("raw")
+                        .action(ArgAction::SetTrue)
+                        .required(false)
+                        .long("raw")
+                        .help("List the processes of each container instead of the resource usage of the containers")
+                    )
+
+                    .arg(arg_older_than_date("List only containers older than DATE"))
+                    .arg(arg_newer_than_date("List only containers newer than DATE"))
+                    .arg(arg_since_date("List only containers created at or after DATE"))
+                    .arg(arg_until_date("List only containers created at or before DATE"))
                 )
             )
             .subcommand(Command::new("container")
@@ -1201,7 +2393,13 @@ pub fn cli() -> Command {
 
                 .subcommand(Command::new("inspect")
                     .about("Display details about the container")
-                    .long_about("Display details about the container. Do not assume the output format to be stable.")
+                    .long_about("Display details about the container. Do not assume the output format to be stable, use --json for tooling.")
+                    .arg(Arg::new("json")
+                        .action(ArgAction::SetTrue)
+                        .required(false)
+                        .long("json")
+                        .help("Print the details as returned by the Docker API, as JSON")
+                    )
                 )
             )
             .subcommand(Command::new("images")
@@ -1223,6 +2421,12 @@ pub fn cli() -> Command {
                         .long("csv")
                         .help("List top output as CSV")
                     )
+                    .arg(Arg::new("refresh")
+                        .action(ArgAction::SetTrue)
+                        .required(false)
+                        .long("refresh")
+                        .help("Pull the configured images on the endpoint(s) first, so that stale images are updated")
+                    )
                 )
             )
         )
@@ -1303,6 +2507,47 @@ fn dir_exists_validator(s: &str) -> Result<String, String> {
     }
 }
 
+/// Check whether 's' is a network mode that can be used for containers
+fn network_mode_validator(s: &str) -> Result<String, String> {
+    match s {
+        "bridge" | "host" | "none" | "default" => Ok(s.to_owned()),
+        _ => match s.strip_prefix("container:") {
+            Some(container) if !container.is_empty() => Ok(s.to_owned()),
+            _ => Err(format!(
+                "Unknown network mode '{s}', expected \"bridge\", \"host\", \"none\", \"default\", or \"container:<name|id>\""
+            )),
+        },
+    }
+}
+
+/// Check whether 's' is the name of a theme that is available for highlighting scripts
+fn highlight_theme_validator(s: &str) -> Result<String, String> {
+    let themes = crate::package::highlight_themes();
+    if themes.iter().any(|theme| theme == s) {
+        Ok(s.to_owned())
+    } else {
+        Err(format!(
+            "Theme not known: {s}, the available themes are: {}",
+            themes.join(", ")
+        ))
+    }
+}
+
+fn arg_columns() -> Arg {
+    Arg::new("columns")
+        .required(false)
+        .long("columns")
+        .value_name("COLUMNS")
+        .value_delimiter(',')
+        .action(ArgAction::Append)
+        .help("Only print these columns, in this order (comma-separated)")
+        .long_help(indoc::indoc!(r#"
+            Only print these columns, in the given order (e.g. for --csv). The columns are named by their
+            header, in lowercase and with '_' between the words (e.g. "package_version" for "Package Version").
+            By default, all columns are printed.
+        "#))
+}
+
 fn arg_older_than_date(about: &str) -> Arg {
     Arg::new("older_than")
         .required(false)
@@ -1363,6 +2608,22 @@ fn arg_newer_than_date(about: &str) -> Arg {
         .value_parser(parse_date_from_string)
 }
 
+/// Lower bound of a time window, the same as `arg_newer_than_date()` but inclusive
+fn arg_since_date(about: &str) -> Arg {
+    arg_newer_than_date(about)
+        .id("since")
+        .long("since")
+        .conflicts_with("newer_than")
+}
+
+/// Upper bound of a time window, the same as `arg_older_than_date()` but inclusive
+fn arg_until_date(about: &str) -> Arg {
+    arg_older_than_date(about)
+        .id("until")
+        .long("until")
+        .conflicts_with("older_than")
+}
+
 fn parse_date_from_string(s: &str) -> std::result::Result<String, String> {
     humantime::parse_duration(s)
         .map_err(|e| e.to_string())
@@ -1384,6 +2645,8 @@ fn parse_date_from_string(s: &str) -> std::result::Result<String, String> {
 #[cfg(test)]
 mod tests {
     use super::env_pass_validator;
+    use super::highlight_theme_validator;
+    use super::network_mode_validator;
 
     #[test]
     fn test_env_pass_validator_1() {
@@ -1459,4 +2722,24 @@ mod tests {
     fn test_env_pass_validator_15() {
         assert!(env_pass_validator("123").is_err());
     }
+
+    #[test]
+    fn test_network_mode_validator_valid() {
+        for mode in ["bridge", "host", "none", "default", "container:foo"] {
+            assert!(network_mode_validator(mode).is_ok(), "{mode}");
+        }
+    }
+
+    #[test]
+    fn test_network_mode_validator_invalid() {
+        for mode in ["", "Host", "nonexistent", "container:", "container"] {
+            assert!(network_mode_validator(mode).is_err(), "{mode}");
+        }
+    }
+
+    #[test]
+    fn test_highlight_theme_validator() {
+        assert!(highlight_theme_validator("base16-ocean.dark").is_ok());
+        assert!(highlight_theme_validator("no-such-theme").is_err());
+    }
 }
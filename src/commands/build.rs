@@ -10,6 +10,7 @@
 
 //! Implementation of the 'build' subcommand
 
+use std::io::BufRead;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
@@ -25,6 +26,7 @@ use colored::Colorize;
 use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::Pool;
 use diesel::ExpressionMethods;
+use diesel::OptionalExtension;
 use diesel::PgConnection;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
@@ -38,24 +40,36 @@ use uuid::Uuid;
 use crate::config::*;
 use crate::db::models::{EnvVar, GitHash, Image, Job, Package, Submit};
 use crate::filestore::path::StoreRoot;
+use crate::filestore::ArtifactPath;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
+use crate::job::BuildSecret;
 use crate::job::JobResource;
 use crate::log::LogItem;
+use crate::orchestrator::BuildPlan;
+use crate::orchestrator::BuildPlanner;
+use crate::orchestrator::BuildSummary;
+use crate::orchestrator::EventSink;
+use crate::orchestrator::JobOutcome;
 use crate::orchestrator::OrchestratorSetup;
+use crate::orchestrator::Shutdown;
 use crate::package::condition::ConditionData;
 use crate::package::Dag;
 use crate::package::PackageName;
 use crate::package::PackageVersion;
+use crate::package::PackageVersionConstraint;
+use crate::package::PhaseName;
 use crate::package::Shebang;
 use crate::repository::Repository;
 use crate::schema;
 use crate::source::SourceCache;
-use crate::util::docker::ImageNameLookup;
 use crate::util::progress::ProgressBars;
 use crate::util::EnvironmentVariableName;
 
 /// Implementation of the "build" subcommand
+///
+/// If no `shutdown` is passed, SIGTERM and SIGINT are handled while the jobs run. Otherwise the
+/// caller handles them (see `build_watch()`).
 #[allow(clippy::too_many_arguments)]
 pub async fn build(
     repo_root: &Path,
@@ -65,6 +79,7 @@ pub async fn build(
     config: &Configuration,
     repo: Repository,
     repo_path: &Path,
+    shutdown: Option<Shutdown>,
 ) -> Result<()> {
     let command_span = tracing::debug_span!("command-build");
 
@@ -77,95 +92,102 @@ pub async fn build(
 
     let now = chrono::offset::Local::now().naive_local();
 
+    let image_name = crate::commands::util::get_image_name(matches, config)?.ok_or_else(|| {
+        anyhow!("No image given, pass --image or set docker.default_image in the configuration")
+    })?;
+
+    // The shebang passed on the commandline overrides the one configured for the image (or the
+    // global one)
     let shebang = Shebang::from({
         matches
             .get_one::<String>("shebang")
             .map(|s| s.to_owned())
-            .unwrap_or_else(|| config.shebang().clone())
+            .unwrap_or_else(|| config.shebang_for_image(&image_name).clone())
     });
-
-    let image_name_lookup = ImageNameLookup::create(config.docker().images())?;
-    let image_name = matches
-        .get_one::<String>("image")
-        .map(|s| image_name_lookup.expand(s))
-        .unwrap()?; // safe by clap
+    shebang
+        .validate()
+        .with_context(|| anyhow!("Invalid shebang for image {}", image_name))?;
 
     debug!("Getting repository HEAD");
     let hash_str = crate::util::git::get_repo_head_commit_hash(&git_repo)?;
     trace!("Repository HEAD = {}", hash_str);
-    let phases = config.available_phases();
 
-    let mut endpoint_configurations = config
-        .docker()
-        .endpoints()
-        .iter()
-        .map(|(ep_name, ep_cfg)| {
-            crate::endpoint::EndpointConfiguration::builder()
-                .endpoint_name(ep_name.clone())
-                .endpoint(ep_cfg.clone())
-                .required_images(
-                    config
-                        .docker()
-                        .images()
-                        .iter()
-                        .map(|img| img.name.clone())
-                        .collect::<Vec<_>>(),
-                )
-                .required_docker_versions(config.docker().docker_versions().clone())
-                .required_docker_api_versions(config.docker().docker_api_versions().clone())
-                .build()
-        })
-        .collect::<Vec<_>>();
-    {
-        // Because we're loading always sequentially, to have a bit more spread over the endpoints,
-        // shuffle the endpoints here. Not a perfect solution, but a working one.
-        use rand::seq::SliceRandom;
-        let mut rng = rand::thread_rng();
-        endpoint_configurations.shuffle(&mut rng);
-    }
+    let network_mode = matches.get_one::<String>("network_mode");
+    let arch = matches.get_one::<String>("arch").cloned();
+
+    let endpoint_configurations =
+        crate::commands::util::get_endpoint_configurations(config, network_mode);
     info!("Endpoint config build");
 
-    let pname = matches
-        .get_one::<String>("package_name")
-        .map(|s| s.to_owned())
-        .map(PackageName::from)
-        .unwrap(); // safe by clap
+    let requested_packages = if matches.get_flag("from_stdin") {
+        let requested_packages = parse_package_list(std::io::stdin().lock())
+            .context("Reading the packages to build from stdin")?;
+        if requested_packages.is_empty() {
+            return Err(anyhow!("No packages to build were passed on stdin"));
+        }
+        requested_packages
+    } else {
+        let pname = matches
+            .get_one::<String>("package_name")
+            .map(|s| s.to_owned())
+            .map(PackageName::from)
+            .unwrap(); // safe by clap
 
-    let pvers = matches
-        .get_one::<String>("package_version")
-        .map(|s| s.to_owned())
-        .map(PackageVersion::from);
-    info!("We want {} ({:?})", pname, pvers);
+        let pvers = matches
+            .get_one::<String>("package_version")
+            .map(|s| PackageVersionConstraint::try_from(s.as_str()))
+            .transpose()?;
+        vec![(pname, pvers)]
+    };
+    for (pname, pvers) in requested_packages.iter() {
+        info!("We want {} ({:?})", pname, pvers);
+    }
 
-    let additional_env = matches
+    let commandline_env = matches
         .get_many::<String>("env")
         .unwrap_or_default()
         .map(|s| crate::util::env::parse_to_env(s.as_ref()))
         .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?;
-
-    let packages = if let Some(pvers) = pvers {
-        debug!(
-            "Searching for package with version: '{}' '{}'",
-            pname, pvers
-        );
-        repo.find(&pname, &pvers)
-    } else {
-        debug!("Searching for package by name: '{}'", pname);
-        repo.find_by_name(&pname)
+    let mut additional_env = match matches.get_one::<Uuid>("env_from_job") {
+        Some(job_uuid) => {
+            load_env_of_job(&mut database_pool.get().unwrap(), config, &repo, job_uuid)?
+        }
+        None => vec![],
     };
-    debug!("Found {} relevant packages", packages.len());
-
-    // We only support building one package per call.
-    // Everything else is invalid
-    if packages.len() > 1 {
-        return Err(anyhow!(
-            "Found multiple packages ({}). Cannot decide which one to build",
-            packages.len()
-        ));
+    // The variables passed with --env override the ones of the job (--env-from-job)
+    for (name, value) in commandline_env {
+        additional_env.retain(|(n, _)| *n != name);
+        additional_env.push((name, value));
     }
-    let package = *packages
-        .first()
-        .ok_or_else(|| anyhow!("Found no package."))?;
+    additional_env
+        .iter()
+        .try_for_each(|(name, _)| config.containers().check_env_name(name))
+        .context("Checking the environment passed on the commandline")?;
+
+    let secrets = matches
+        .get_many::<String>("secret")
+        .unwrap_or_default()
+        .map(|s| BuildSecret::load(s))
+        .collect::<Result<Vec<_>>>()
+        .context("Loading the secrets passed on the commandline")?;
+
+    let phases = select_phases(
+        config.available_phases(),
+        matches
+            .get_many::<String>("phases")
+            .map(|names| names.map(String::as_str).collect()),
+        matches
+            .get_many::<String>("skip-phases")
+            .map(|names| names.map(String::as_str).collect()),
+    )?;
+
+    let packages = requested_packages
+        .iter()
+        .map(|(pname, pvers)| find_package(&repo, pname, pvers.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    // With multiple packages (--from-stdin), the first one is recorded as the requested package of
+    // the submit
+    let package = packages[0];
 
     let release_stores = config
         .release_stores()
@@ -190,10 +212,19 @@ pub async fn build(
 
     drop(loading_span_guard);
 
-    let (staging_store, staging_dir, submit_id) = {
+    let dry_run = matches.get_flag("dry_run");
+    let (staging_store, staging_dir, submit_id, staging_dir_created) = {
         let bar_staging_loading = progressbars.bar()?;
 
-        let (submit_id, p) = if let Some(staging_dir) =
+        let (submit_id, p) = if let Some(submit_id) = matches.get_one::<Uuid>("resume") {
+            let staging_dir = load_resumed_submit(
+                &mut database_pool.get().unwrap(),
+                config,
+                submit_id,
+                package,
+            )?;
+            (*submit_id, staging_dir)
+        } else if let Some(staging_dir) =
             matches.get_one::<String>("staging_dir").map(PathBuf::from)
         {
             info!(
@@ -223,7 +254,8 @@ pub async fn build(
             (submit_id, staging_dir)
         };
 
-        if !p.is_dir() {
+        let staging_dir_created = !p.is_dir();
+        if staging_dir_created {
             tokio::fs::create_dir_all(&p)
                 .instrument(
                     tracing::trace_span!(parent: &loading_span, "Creating directories", path = ?p),
@@ -240,7 +272,7 @@ pub async fn build(
         }
         r.map(RwLock::new)
             .map(Arc::new)
-            .map(|store| (store, p, submit_id))?
+            .map(|store| (store, p, submit_id, staging_dir_created))?
     };
 
     let dag = {
@@ -250,8 +282,8 @@ pub async fn build(
             env: &additional_env,
         };
 
-        let dag = Dag::for_root_package(
-            package.clone(),
+        let dag = Dag::for_root_packages(
+            packages.iter().map(|p| (*p).clone()).collect(),
             &repo,
             Some(&bar_tree_building),
             &condition_data,
@@ -260,7 +292,10 @@ pub async fn build(
         dag
     };
 
-    let source_cache = SourceCache::new(config.source_cache_root().clone());
+    let source_cache = SourceCache::new(
+        config.source_cache_root().clone(),
+        config.source_mirror_base().clone(),
+    );
 
     if matches.get_flag("no_verification") {
         warn!(parent: &loading_span, "No hash verification will be performed");
@@ -269,6 +304,8 @@ pub async fn build(
             dag.all_packages().into_iter(),
             &source_cache,
             &progressbars,
+            false,
+            crate::commands::source::default_parallel_verifications(),
         )
         .instrument(tracing::trace_span!(parent: &loading_span, "verify source hashes"))
         .await?;
@@ -291,34 +328,46 @@ pub async fn build(
 
     dag.all_packages()
         .into_iter()
-        .map(|pkg| {
-            if let Some(allowlist) = pkg.allowed_images() {
-                if !allowlist.contains(&image_name) {
-                    return Err(anyhow!(
-                        "Package {} {} is only allowed on: {}",
-                        pkg.name(),
-                        pkg.version(),
-                        allowlist.iter().join(", ")
-                    ));
-                }
-            }
-
-            if let Some(deniedlist) = pkg.denied_images() {
-                if deniedlist.iter().any(|denied| image_name == *denied) {
-                    return Err(anyhow!(
-                        "Package {} {} is not allowed to be built on {}",
-                        pkg.name(),
-                        pkg.version(),
-                        image_name
-                    ));
-                }
-            }
-
-            Ok(())
-        })
+        .map(|pkg| pkg.check_image(&image_name))
         .collect::<Result<Vec<()>>>()?;
 
     drop(loading_span);
+
+    if dry_run {
+        let resources: Vec<JobResource> =
+            additional_env.into_iter().map(JobResource::from).collect();
+        let jobdag = crate::job::Dag::from_package_dag(
+            dag,
+            shebang,
+            image_name,
+            arch,
+            phases.clone(),
+            resources,
+        );
+
+        let plan = BuildPlanner::builder()
+            .endpoint_config(endpoint_configurations)
+            .staging_store(staging_store)
+            .release_stores(release_stores)
+            .jobdag(jobdag)
+            .database(database_pool)
+            .reuse_artifacts(!matches.get_flag("no-reuse"))
+            .config(config)
+            .repository(git_repo)
+            .build()
+            .plan()
+            .instrument(tracing::debug_span!(parent: &command_span, "plan"))
+            .await?;
+
+        if staging_dir_created {
+            tokio::fs::remove_dir_all(&staging_dir)
+                .await
+                .with_context(|| anyhow!("Removing {}", staging_dir.display()))?;
+        }
+
+        return print_build_plan(&plan);
+    }
+
     let submit_span = tracing::debug_span!(parent: &command_span, "submit");
 
     trace!(parent: &submit_span, "Setting up database jobs for Package, GitHash, Image");
@@ -355,6 +404,7 @@ pub async fn build(
         &db_image,
         &db_package,
         &db_githash,
+        env!("VERGEN_GIT_DESCRIBE"),
     )?;
     trace!(
         parent: &submit_span,
@@ -362,42 +412,76 @@ pub async fn build(
         submit
     );
 
+    // With --json, stdout is reserved for the JSON summary
+    let json_summary = matches.get_flag("json");
     {
-        let out = std::io::stdout();
-        let mut outlock = out.lock();
+        let mut outlock = human_output(json_summary);
 
         #[inline]
         fn mkgreen<T: ToString>(t: &T) -> colored::ColoredString {
             t.to_string().green()
         }
 
-        writeln!(outlock, "Starting submit: {}", mkgreen(&submit_id))?;
+        if matches.contains_id("resume") {
+            writeln!(outlock, "Resuming submit: {}", mkgreen(&submit_id))?;
+        } else {
+            writeln!(outlock, "Starting submit: {}", mkgreen(&submit_id))?;
+        }
         writeln!(outlock, "Started at:      {}", mkgreen(&now))?;
         writeln!(outlock, "On Image:        {}", mkgreen(&db_image.name))?;
-        writeln!(
-            outlock,
-            "For Package:     {p} {v}",
-            p = mkgreen(&db_package.name),
-            v = mkgreen(&db_package.version)
-        )?;
+        if let Some(arch) = arch.as_ref() {
+            writeln!(outlock, "For arch:        {}", mkgreen(arch))?;
+        }
+        for package in packages.iter() {
+            writeln!(
+                outlock,
+                "For Package:     {p} {v}",
+                p = mkgreen(package.name()),
+                v = mkgreen(package.version())
+            )?;
+        }
         writeln!(outlock, "On repo hash:    {}", mkgreen(&db_githash.hash))?;
     }
 
     trace!(parent: &submit_span, "Setting up job sets");
     let resources: Vec<JobResource> = additional_env.into_iter().map(JobResource::from).collect();
-    let jobdag =
-        crate::job::Dag::from_package_dag(dag, shebang, image_name, phases.clone(), resources);
+    let jobdag = crate::job::Dag::from_package_dag(
+        dag,
+        shebang,
+        image_name,
+        arch,
+        phases.clone(),
+        resources,
+    );
     trace!(parent: &submit_span, "Setting up job sets finished successfully");
     drop(submit_span);
 
     let build_span = tracing::debug_span!(parent: &command_span, "build");
 
+    let events = match (
+        matches.get_one::<PathBuf>("events-file"),
+        matches.get_one::<u32>("events-fd"),
+    ) {
+        (Some(path), _) => EventSink::to_file(path)?,
+        (None, Some(fd)) => EventSink::to_fd(*fd)?,
+        (None, None) => EventSink::default(),
+    };
+
+    let (shutdown, signal_handler) = match shutdown {
+        Some(shutdown) => (shutdown, None),
+        None => {
+            let shutdown = Shutdown::default();
+            let signal_handler = shutdown.install()?;
+            (shutdown, Some(signal_handler))
+        }
+    };
+
     trace!(parent: &build_span, "Setting up Orchestrator");
     let orch = OrchestratorSetup::builder()
         .progress_generator(progressbars)
         .endpoint_config(endpoint_configurations)
-        .staging_store(staging_store)
-        .release_stores(release_stores)
+        .staging_store(staging_store.clone())
+        .release_stores(release_stores.clone())
         .database(database_pool.clone())
         .source_cache(source_cache)
         .submit(submit)
@@ -406,6 +490,24 @@ pub async fn build(
         } else {
             None
         })
+        .keep_failed_containers(matches.get_flag("keep-failed-containers"))
+        .max_log_lines(
+            matches
+                .get_one::<usize>("max-log-lines")
+                .copied()
+                .or(*config.max_log_lines()),
+        )
+        .artifact_verifier(
+            config
+                .artifact_verifier()
+                .as_ref()
+                .map(|verifier| repo_root.join(verifier)),
+        )
+        .reuse_artifacts(!matches.get_flag("no-reuse"))
+        .fail_fast(matches.get_flag("fail-fast"))
+        .secrets(secrets)
+        .events(events)
+        .shutdown(shutdown.clone())
         .jobdag(jobdag)
         .config(config)
         .repository(git_repo)
@@ -416,9 +518,39 @@ pub async fn build(
 
     info!(parent: &build_span, "Running orchestrator...");
     let mut artifacts = vec![];
-    let errors = orch.run(&mut artifacts).instrument(build_span).await?;
-    let out = std::io::stdout();
-    let mut outlock = out.lock();
+    let (errors, summary) = orch.run(&mut artifacts).instrument(build_span).await?;
+    // All jobs are done, a signal exits butido immediately from now on
+    drop(signal_handler);
+
+    // The submit is resumed with the same UUID, so the state is reset if the resumed build finishes
+    Submit::set_interrupted(
+        &mut *database_pool.get()?,
+        &submit_id,
+        shutdown.is_requested(),
+    )?;
+
+    let copied_artifacts = match matches.get_one::<String>("output-dir").map(PathBuf::from) {
+        Some(output_dir) if errors.is_empty() => {
+            copy_to_output_dir(
+                &artifacts,
+                &*staging_store.read().await,
+                &release_stores,
+                &output_dir,
+                matches.get_flag("overwrite"),
+            )
+            .await?
+        }
+        Some(output_dir) => {
+            warn!(
+                "Not copying the artifacts to {}, the build failed",
+                output_dir.display()
+            );
+            vec![]
+        }
+        None => vec![],
+    };
+
+    let mut outlock = human_output(json_summary);
 
     if !artifacts.is_empty() {
         writeln!(outlock, "Packages created:")?;
@@ -427,17 +559,33 @@ pub async fn build(
         writeln!(outlock, "{}", staging_dir.join(artifact_path).display()).map_err(Error::from)
     })?;
 
+    if !copied_artifacts.is_empty() {
+        writeln!(outlock, "Packages copied:")?;
+    }
+    copied_artifacts
+        .into_iter()
+        .try_for_each(|path| writeln!(outlock, "{}", path.display()).map_err(Error::from))?;
+
     let mut had_error = false;
     for (job_uuid, error) in errors {
         had_error = true;
-        for cause in error.chain() {
-            writeln!(outlock, "{}: {}", "[ERROR]".red(), cause)?;
-        }
+        write_error_chain(&mut outlock, &job_uuid, &error)?;
 
+        // The job is not in the database if it failed before the container was run. The error
+        // chain was printed already, so don't fail here (which would hide the remaining errors).
         let data = schema::jobs::table
             .filter(schema::jobs::dsl::uuid.eq(job_uuid))
             .inner_join(schema::packages::table)
-            .first::<(Job, Package)>(&mut *database_pool.get().unwrap())?;
+            .first::<(Job, Package)>(&mut *database_pool.get().unwrap())
+            .optional()?;
+        let Some(data) = data else {
+            writeln!(
+                outlock,
+                "No log available, Job {} was not recorded in the database\n\n",
+                job_uuid.to_string().red()
+            )?;
+            continue;
+        };
 
         let number_log_lines = *config.build_error_lines();
         writeln!(
@@ -502,9 +650,583 @@ pub async fn build(
         }
     }
 
+    if !matches.get_flag("no-summary") {
+        if json_summary {
+            print_build_summary(&mut std::io::stdout(), &summary, true)?;
+        } else {
+            print_build_summary(&mut outlock, &summary, false)?;
+        }
+    }
+    if matches.get_flag("stats") {
+        print_scheduling_stats(&mut outlock, &summary)?;
+    }
+
+    if shutdown.is_requested() {
+        // The staging directory is kept, the artifacts of the finished jobs are reused then
+        writeln!(
+            outlock,
+            "The build was interrupted, resume it with: --resume {submit_id}"
+        )?;
+        return Err(anyhow!("The build was interrupted"));
+    }
+
     if had_error {
         Err(anyhow!("One or multiple errors during build"))
     } else {
         Ok(())
     }
 }
+
+/// Copy the `artifacts` of a build to the directory `output_dir` and return the paths of the copies
+///
+/// The artifacts are copied from the staging store or, if they were reused from an earlier build,
+/// from the release stores.
+/// Fails if a file with the same name already exists in `output_dir`, unless `overwrite` is set.
+async fn copy_to_output_dir(
+    artifacts: &[ArtifactPath],
+    staging_store: &StagingStore,
+    release_stores: &[Arc<ReleaseStore>],
+    output_dir: &Path,
+    overwrite: bool,
+) -> Result<Vec<PathBuf>> {
+    tokio::fs::create_dir_all(output_dir)
+        .await
+        .with_context(|| anyhow!("Creating {}", output_dir.display()))?;
+
+    let mut copied = Vec::with_capacity(artifacts.len());
+    for artifact in artifacts {
+        let source = std::iter::once(staging_store.root_path())
+            .chain(release_stores.iter().map(|rs| rs.root_path()))
+            .map(|root| root.join(artifact))
+            .find_map(|full_path| full_path.transpose())
+            .transpose()?
+            .ok_or_else(|| anyhow!("Artifact not found in the stores: {}", artifact.display()))?
+            .joined();
+
+        let file_name = artifact
+            .file_name()
+            .ok_or_else(|| anyhow!("Artifact has no file name: {}", artifact.display()))?;
+        let dest = output_dir.join(file_name);
+        if !overwrite && tokio::fs::try_exists(&dest).await? {
+            return Err(anyhow!(
+                "Cannot copy {}, {} exists already (use --overwrite to replace it)",
+                source.display(),
+                dest.display()
+            ));
+        }
+
+        trace!("Copying {} to {}", source.display(), dest.display());
+        tokio::fs::copy(&source, &dest)
+            .await
+            .with_context(|| anyhow!("Copying {} to {}", source.display(), dest.display()))?;
+        copied.push(dest);
+    }
+
+    Ok(copied)
+}
+
+/// Find the package with the name `pname` that matches the version constraint `pvers` (if any)
+///
+/// If there are multiple versions of the package and no constraint is given (or the constraint is
+/// an exact version that is not unique), the package to build can't be decided.
+fn find_package<'a>(
+    repo: &'a Repository,
+    pname: &PackageName,
+    pvers: Option<&PackageVersionConstraint>,
+) -> Result<&'a crate::package::Package> {
+    let packages = if let Some(exact_version) = pvers.and_then(|c| c.exact_version()) {
+        debug!(
+            "Searching for package with version: '{}' '{}'",
+            pname, exact_version
+        );
+        repo.find(pname, exact_version)
+    } else if let Some(pvers) = pvers {
+        debug!(
+            "Searching for best matching package version: '{}' '{}'",
+            pname, pvers
+        );
+        repo.find_best_match(pname, pvers)?.into_iter().collect()
+    } else {
+        debug!("Searching for package by name: '{}'", pname);
+        repo.find_by_name(pname)
+    };
+    debug!("Found {} relevant packages", packages.len());
+
+    // We only support building one version of a package per call.
+    // Everything else is invalid
+    if packages.len() > 1 {
+        return Err(anyhow!(
+            "Found multiple packages ({}) for {}. Cannot decide which one to build",
+            packages.len(),
+            pname
+        ));
+    }
+    packages
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow!("Found no package: {}", pname))
+}
+
+/// Parse the list of packages to build (`--from-stdin`)
+///
+/// Each line contains the name of a package and optionally a version constraint, separated by
+/// whitespace. Empty lines and lines starting with '#' are ignored.
+fn parse_package_list(
+    reader: impl BufRead,
+) -> Result<Vec<(PackageName, Option<PackageVersionConstraint>)>> {
+    let mut packages = Vec::new();
+    for (lineno, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let pname = fields
+            .next()
+            .map(|s| PackageName::from(s.to_string()))
+            .unwrap(); // line is not empty
+        let pvers = fields
+            .next()
+            .map(PackageVersionConstraint::try_from)
+            .transpose()
+            .with_context(|| anyhow!("Line {}: {}", lineno + 1, line))?;
+        if fields.next().is_some() {
+            return Err(anyhow!(
+                "Line {}: Expected 'NAME [VERSION_CONSTRAINT]': {}",
+                lineno + 1,
+                line
+            ));
+        }
+        packages.push((pname, pvers));
+    }
+    Ok(packages)
+}
+
+/// Load the environment of the job `job_uuid` from the database (`--env-from-job`)
+///
+/// The variables that are set by the build anyways are left out: the environment of the package
+/// of the job and the variables for the git author and commit hash.
+fn load_env_of_job(
+    database_connection: &mut PgConnection,
+    config: &Configuration,
+    repo: &Repository,
+    job_uuid: &Uuid,
+) -> Result<Vec<(EnvironmentVariableName, String)>> {
+    let (job, package) = schema::jobs::table
+        .filter(schema::jobs::dsl::uuid.eq(job_uuid))
+        .inner_join(schema::packages::table)
+        .first::<(Job, Package)>(database_connection)
+        .optional()?
+        .ok_or_else(|| anyhow!("Cannot load the environment of job {}, not found", job_uuid))?;
+
+    let package_env = repo
+        .find(
+            &PackageName::from(package.name),
+            &PackageVersion::from(package.version),
+        )
+        .into_iter()
+        .filter_map(|p| p.environment().as_ref())
+        .flat_map(|env| env.keys())
+        .cloned()
+        .collect::<Vec<_>>();
+    let git_env = [
+        config.containers().git_author(),
+        config.containers().git_commit_hash(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+
+    let env = job
+        .env(database_connection)?
+        .into_iter()
+        .map(|envvar| {
+            (
+                EnvironmentVariableName::from(envvar.name.as_str()),
+                envvar.value,
+            )
+        })
+        .filter(|(name, _)| !package_env.contains(name) && !git_env.contains(&name))
+        .collect::<Vec<_>>();
+    for (name, value) in env.iter() {
+        debug!("Environment of job {}: {}={}", job_uuid, name, value);
+    }
+    Ok(env)
+}
+
+/// Get the phases of the scripts, as selected with --phases or --skip-phases
+///
+/// The phases are run in the order of `available_phases` (all of them by default). Each selected
+/// (or skipped) phase must be one of the `available_phases`, packages that don't have a selected
+/// phase just don't run it.
+fn select_phases(
+    available_phases: &[PhaseName],
+    selected: Option<Vec<&str>>,
+    skipped: Option<Vec<&str>>,
+) -> Result<Vec<PhaseName>> {
+    if let Some(name) = selected
+        .iter()
+        .chain(skipped.iter())
+        .flatten()
+        .find(|name| {
+            !available_phases
+                .iter()
+                .any(|phase| phase.as_str() == **name)
+        })
+    {
+        return Err(anyhow!(
+            "Unknown phase '{}', available phases: {}",
+            name,
+            available_phases
+                .iter()
+                .map(|phase| phase.as_str())
+                .join(", ")
+        ));
+    }
+
+    Ok(available_phases
+        .iter()
+        .filter(|phase| {
+            selected
+                .as_ref()
+                .map(|names| names.contains(&phase.as_str()))
+                .unwrap_or(true)
+        })
+        .filter(|phase| {
+            skipped
+                .as_ref()
+                .map(|names| !names.contains(&phase.as_str()))
+                .unwrap_or(true)
+        })
+        .cloned()
+        .collect())
+}
+
+/// Load the submit `submit_id` that is resumed (`--resume`) and return its staging directory
+///
+/// The jobs of the submit are not scheduled again by the orchestrator if their artifacts can be
+/// found in the staging directory (and they still have the same script and environment).
+fn load_resumed_submit(
+    database_connection: &mut PgConnection,
+    config: &Configuration,
+    submit_id: &Uuid,
+    package: &crate::package::Package,
+) -> Result<PathBuf> {
+    let submit = Submit::with_id(database_connection, submit_id)
+        .with_context(|| anyhow!("Cannot resume submit {}", submit_id))?;
+
+    let requested_package = Package::fetch_by_id(database_connection, submit.requested_package_id)?
+        .ok_or_else(|| anyhow!("Package of submit {} not found", submit_id))?;
+    if requested_package.name != package.name().as_ref() as &str
+        || requested_package.version != package.version().as_ref() as &str
+    {
+        return Err(anyhow!(
+            "Cannot resume submit {} for {} {}, it was for {} {}",
+            submit_id,
+            package.name(),
+            package.version(),
+            requested_package.name,
+            requested_package.version
+        ));
+    }
+
+    let staging_dir = config
+        .staging_directory()
+        .join(submit_id.hyphenated().to_string());
+    if !staging_dir.is_dir() {
+        return Err(anyhow!(
+            "Cannot resume submit {}, its staging directory does not exist anymore: {}",
+            submit_id,
+            staging_dir.display()
+        ));
+    }
+
+    let successful_jobs = Job::successful_for_submit(database_connection, &submit)?;
+    info!(
+        "Resuming {}submit {}, {} jobs finished successfully before",
+        if submit.interrupted {
+            "interrupted "
+        } else {
+            ""
+        },
+        submit_id,
+        successful_jobs.len()
+    );
+    for job in successful_jobs {
+        debug!("Job {} finished successfully before", job.uuid);
+    }
+
+    Ok(staging_dir)
+}
+
+/// Write the error of a failed job with its complete chain of causes, one cause per line
+fn write_error_chain(out: &mut impl Write, job_uuid: &Uuid, error: &Error) -> Result<()> {
+    writeln!(out, "{}: Job {} failed", "[ERROR]".red(), job_uuid)?;
+    for cause in error.chain() {
+        writeln!(out, "{}: {}", "[ERROR]".red(), cause)?;
+    }
+    Ok(())
+}
+
+/// The output for the messages of a build that are meant to be read by humans
+///
+/// If the summary is printed as JSON (`--json`), the messages are written to stderr, so that
+/// stdout only contains the JSON.
+fn human_output(json_summary: bool) -> Box<dyn Write> {
+    if json_summary {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    }
+}
+
+/// Print the summary of a build
+///
+/// If `json` is `true`, the summary is printed as JSON, including all jobs.
+fn print_build_summary(out: &mut impl Write, summary: &BuildSummary, json: bool) -> Result<()> {
+    /// The number of jobs that are listed as the slowest jobs
+    const SLOWEST_JOBS: usize = 5;
+
+    if json {
+        writeln!(out, "{}", serde_json::to_string_pretty(summary)?)?;
+        return Ok(());
+    }
+
+    // Durations are rounded to seconds, more precision is just noise here
+    let fmt_duration = |d: std::time::Duration| {
+        humantime::format_duration(std::time::Duration::from_secs(d.as_secs())).to_string()
+    };
+
+    writeln!(out, "Build summary:")?;
+    writeln!(out, "Built:     {}", summary.count(JobOutcome::Built))?;
+    writeln!(out, "Reused:    {}", summary.count(JobOutcome::Reused))?;
+    writeln!(out, "Failed:    {}", summary.count(JobOutcome::Failed))?;
+    writeln!(out, "Skipped:   {}", summary.count(JobOutcome::Skipped))?;
+    writeln!(out, "Wall time: {}", fmt_duration(summary.wall_time()))?;
+
+    let slowest = summary.slowest(SLOWEST_JOBS);
+    if !slowest.is_empty() {
+        writeln!(out, "Slowest jobs:")?;
+        for job in slowest {
+            let duration = job.duration().map(fmt_duration).unwrap_or_default();
+            let line = format!(
+                "{:>12}  {} {} ({})",
+                duration,
+                job.package_name(),
+                job.package_version(),
+                job.uuid()
+            );
+            if job.outcome() == JobOutcome::Failed {
+                writeln!(out, "{}", line.red())?;
+            } else {
+                writeln!(out, "{line}")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print how many jobs were scheduled on each endpoint and how long they waited for a free
+/// endpoint (see `--stats`)
+fn print_scheduling_stats(out: &mut impl Write, summary: &BuildSummary) -> Result<()> {
+    // Waiting times are rounded to milliseconds
+    let fmt_duration = |d: std::time::Duration| {
+        humantime::format_duration(std::time::Duration::from_millis(d.as_millis() as u64))
+            .to_string()
+    };
+
+    let stats = summary.scheduling_stats();
+    let width = stats
+        .iter()
+        .map(|s| s.endpoint_name().as_ref().len())
+        .chain(std::iter::once("Endpoint".len()))
+        .max()
+        .unwrap_or_default();
+
+    writeln!(out, "Scheduling statistics:")?;
+    writeln!(
+        out,
+        "{:<width$}  {:>6}  {:>12}  {:>12}",
+        "Endpoint", "Jobs", "Avg wait", "Max wait"
+    )?;
+    for s in stats {
+        writeln!(
+            out,
+            "{:<width$}  {:>6}  {:>12}  {:>12}",
+            s.endpoint_name().as_ref(),
+            s.jobs(),
+            fmt_duration(s.avg_wait_time()),
+            fmt_duration(s.max_wait_time())
+        )?;
+    }
+    Ok(())
+}
+
+/// Print the plan of a build (see `--dry-run`)
+fn print_build_plan(plan: &BuildPlan) -> Result<()> {
+    let endpoints = plan.endpoints().iter().join(", ");
+    let data = plan
+        .jobs()
+        .iter()
+        .map(|job| {
+            let (action, endpoints) = if job.reuse() {
+                ("reuse".white(), String::from("-"))
+            } else {
+                ("build".yellow(), endpoints.clone())
+            };
+
+            vec![
+                job.uuid().to_string().normal(),
+                job.package_name().to_string().normal(),
+                job.package_version().to_string().normal(),
+                action,
+                endpoints.normal(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let hdrs =
+        crate::commands::util::mk_header(vec!["Job", "Package", "Version", "Action", "Endpoints"]);
+    crate::commands::util::display_data(hdrs, data, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_error_chain() {
+        // The error is built like the error of a job whose script failed (see `JobHandle::run()`)
+        let job_uuid = Uuid::new_v4();
+        let error = Err::<(), _>(anyhow!("Error during container run: 'make failed'"))
+            .with_context(|| anyhow!("Error during running job on '{}'", "testendpoint"))
+            .with_context(|| {
+                crate::endpoint::JobHandle::create_job_run_error(
+                    &job_uuid,
+                    "foo",
+                    "1.0",
+                    "http://localhost:2375",
+                    "0123456789ab",
+                    "/bin/bash",
+                )
+            })
+            .unwrap_err();
+
+        let mut out = Vec::new();
+        write_error_chain(&mut out, &job_uuid, &error).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains(&format!("Job {job_uuid} failed")));
+        assert!(out.contains("Error while running job for"));
+        assert!(out.contains("docker --host http://localhost:2375 exec -it 0123456789ab /bin/bash"));
+        assert!(out.contains("butido db log-of"));
+        assert!(out.contains("Error during running job on 'testendpoint'"));
+        assert!(out.contains("Error during container run: 'make failed'"));
+    }
+
+    #[test]
+    fn test_print_build_summary_with_failed_job() {
+        use crate::orchestrator::JobSummary;
+        use crate::package::tests::pname;
+        use crate::package::tests::pversion;
+        use std::time::Duration;
+
+        let failed_uuid = Uuid::new_v4();
+        let summary = BuildSummary::new(
+            vec![
+                JobSummary::new(
+                    Uuid::new_v4(),
+                    pname("a"),
+                    pversion("1"),
+                    JobOutcome::Built,
+                    Some(Duration::from_secs(10)),
+                ),
+                JobSummary::new(
+                    failed_uuid,
+                    pname("b"),
+                    pversion("2"),
+                    JobOutcome::Failed,
+                    Some(Duration::from_secs(5)),
+                ),
+                JobSummary::new(
+                    Uuid::new_v4(),
+                    pname("c"),
+                    pversion("3"),
+                    JobOutcome::Skipped,
+                    None,
+                ),
+            ],
+            Duration::from_secs(20),
+            vec![],
+        );
+
+        let mut out = Vec::new();
+        print_build_summary(&mut out, &summary, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Built:     1"));
+        assert!(out.contains("Failed:    1"));
+        assert!(out.contains("Skipped:   1"));
+        assert!(out.contains(&format!("b 2 ({failed_uuid})")));
+
+        let mut out = Vec::new();
+        print_build_summary(&mut out, &summary, true).unwrap();
+        let json = serde_json::from_slice::<serde_json::Value>(&out).unwrap();
+        assert_eq!(json["failed"], 1);
+        assert_eq!(json["jobs"][1]["uuid"], failed_uuid.to_string());
+        assert_eq!(json["jobs"][1]["outcome"], "failed");
+        assert_eq!(json["jobs"][2]["outcome"], "skipped");
+    }
+
+    #[test]
+    fn test_parse_package_list() {
+        let input = "a 1.0\n\n# comment\n  b  \nc =2\n";
+        let packages = parse_package_list(input.as_bytes()).unwrap();
+        let packages = packages
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.as_ref().map(|v| v.to_string())))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            packages,
+            vec![
+                (String::from("a"), Some(String::from("1.0"))),
+                (String::from("b"), None),
+                (String::from("c"), Some(String::from("=2"))),
+            ]
+        );
+
+        assert!(parse_package_list("a 1 2\n".as_bytes()).is_err());
+        assert!(parse_package_list("".as_bytes()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_select_phases() {
+        let phase = |name: &str| PhaseName::from(name.to_string());
+        let available = vec![
+            phase("unpack"),
+            phase("build"),
+            phase("test"),
+            phase("install"),
+        ];
+        let all = select_phases(&available, None, None).unwrap();
+        assert_eq!(all, available);
+
+        let selected = select_phases(&available, Some(vec!["install", "build"]), None).unwrap();
+        assert_eq!(selected, vec![phase("build"), phase("install")]);
+
+        let skipped = select_phases(&available, None, Some(vec!["unpack"])).unwrap();
+        assert_eq!(
+            skipped,
+            vec![phase("build"), phase("test"), phase("install")]
+        );
+
+        let err = select_phases(&available, Some(vec!["build", "check"]), None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown phase 'check', available phases: unpack, build, test, install"
+        );
+        assert!(select_phases(&available, None, Some(vec!["foo"])).is_err());
+    }
+}
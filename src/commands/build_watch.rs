@@ -0,0 +1,322 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the "build --watch" mode
+//!
+//! The package is built again each time a package script (`pkg.toml`) in the repository or a
+//! source file of the package changes.
+
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use colored::Colorize;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::Pool;
+use diesel::PgConnection;
+use itertools::Itertools;
+use notify::RecursiveMode;
+use notify::Watcher;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::{debug, trace};
+
+use crate::config::Configuration;
+use crate::orchestrator::Shutdown;
+use crate::package::PackageName;
+use crate::repository::Repository;
+use crate::source::SourceCache;
+use crate::util::progress::ProgressBars;
+
+/// A build is only started once the watched files did not change for this long, so that saving
+/// multiple files (or a file in multiple steps) results in a single build
+const DEBOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Implementation of the "build --watch" mode
+///
+/// Runs the build, waits until a watched file changes and repeats, until Ctrl-C is pressed (or
+/// SIGTERM is received). A running build is shut down like a build without `--watch`, i.e. the
+/// running jobs are finished first.
+/// The repository is loaded again (with `load_repo`) before each build, so that changed scripts
+/// are used.
+#[allow(clippy::too_many_arguments)]
+pub async fn build_watch<L>(
+    repo_root: &Path,
+    matches: &ArgMatches,
+    progressbars: ProgressBars,
+    database_pool: Pool<ConnectionManager<PgConnection>>,
+    config: &Configuration,
+    load_repo: L,
+    repo_path: &Path,
+) -> Result<()>
+where
+    L: Fn() -> Result<Repository>,
+{
+    let pname = matches
+        .get_one::<String>("package_name")
+        .map(|s| s.to_owned())
+        .map(PackageName::from)
+        .unwrap(); // safe by clap
+    let source_cache = SourceCache::new(
+        config.source_cache_root().clone(),
+        config.source_mirror_base().clone(),
+    );
+
+    let git_repo = git2::Repository::open(repo_path)
+        .with_context(|| anyhow!("Opening repository at {}", repo_path.display()))?;
+    // Files in the repository that are ignored by git (e.g. build outputs) don't trigger a build
+    let is_ignored = |path: &Path| {
+        path.strip_prefix(repo_root)
+            .ok()
+            .and_then(|path| git_repo.is_path_ignored(path).ok())
+            .unwrap_or(false)
+    };
+    let mut watcher = ChangeWatcher::new(repo_root)?;
+
+    // The signals are handled for the whole session, not only while the jobs of a build run
+    let shutdown = Shutdown::default();
+    let _signal_handler = shutdown.install()?;
+
+    for iteration in 1.. {
+        let start = Instant::now();
+        let (result, source_files) = match load_repo() {
+            Ok(repo) => {
+                // If a source URL cannot be resolved, the build reports the error
+                let source_files = repo
+                    .find_by_name(&pname)
+                    .into_iter()
+                    .flat_map(|p| source_cache.sources_for(p).unwrap_or_default())
+                    .map(|source| source.path())
+                    .collect::<Vec<_>>();
+
+                let result = crate::commands::build(
+                    repo_root,
+                    matches,
+                    progressbars.clone(),
+                    database_pool.clone(),
+                    config,
+                    repo,
+                    repo_path,
+                    Some(shutdown.clone()),
+                )
+                .await;
+                (result, source_files)
+            }
+            Err(e) => (Err(e), vec![]),
+        };
+
+        let out = std::io::stdout();
+        let mut outlock = out.lock();
+        let elapsed = humantime::format_duration(Duration::from_secs(start.elapsed().as_secs()));
+        match result {
+            Ok(()) => writeln!(
+                outlock,
+                "{} Build #{} of {} passed after {}",
+                "[PASS]".green(),
+                iteration,
+                pname,
+                elapsed
+            )?,
+            Err(e) => writeln!(
+                outlock,
+                "{} Build #{} of {} failed after {}: {:#}",
+                "[FAIL]".red(),
+                iteration,
+                pname,
+                elapsed,
+                e
+            )?,
+        }
+        if shutdown.is_requested() {
+            return Ok(());
+        }
+        writeln!(outlock, "Waiting for changes (press Ctrl-C to exit)...")?;
+        drop(outlock);
+
+        watcher.watch_sources(&source_files)?;
+        tokio::select! {
+            changed = watcher.wait_for_changes(&source_files, is_ignored) => {
+                for path in changed? {
+                    debug!("Changed: {}", path.display());
+                }
+            },
+            _ = shutdown.requested() => return Ok(()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a change of the file at `path` triggers a new build: If it is one of the
+/// `source_files` or a package script that is neither in a `.git` directory nor ignored
+fn is_watched<I>(path: &Path, source_files: &[PathBuf], is_ignored: I) -> bool
+where
+    I: Fn(&Path) -> bool,
+{
+    let is_package_script = path
+        .file_name()
+        .map(|name| name == "pkg.toml")
+        .unwrap_or(false);
+
+    source_files.iter().any(|source| source == path)
+        || (is_package_script
+            && !path.components().any(|c| c.as_os_str() == ".git")
+            && !is_ignored(path))
+}
+
+/// The filesystem watcher of the package scripts in the repository and the source files of the
+/// package
+///
+/// The watcher is kept for the whole session, so that changes during a build are noticed as well.
+struct ChangeWatcher {
+    watcher: notify::RecommendedWatcher,
+    events: UnboundedReceiver<notify::Result<notify::Event>>,
+    source_dirs: Vec<PathBuf>,
+}
+
+impl ChangeWatcher {
+    /// Create the watcher and watch the repository at `repo_root`
+    fn new(repo_root: &Path) -> Result<Self> {
+        let (sender, events) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // Sending only fails once the watcher is dropped
+            let _ = sender.send(event);
+        })
+        .context("Creating the filesystem watcher")?;
+
+        watcher
+            .watch(repo_root, RecursiveMode::Recursive)
+            .with_context(|| anyhow!("Watching {}", repo_root.display()))?;
+
+        Ok(ChangeWatcher {
+            watcher,
+            events,
+            source_dirs: vec![],
+        })
+    }
+
+    /// Watch the directories of the `source_files` (instead of the ones of the previous build)
+    ///
+    /// The directories are watched, so that sources that are downloaded (again) are noticed.
+    fn watch_sources(&mut self, source_files: &[PathBuf]) -> Result<()> {
+        let source_dirs = source_files
+            .iter()
+            .filter_map(|source| source.parent())
+            .filter(|dir| dir.is_dir())
+            .unique()
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+
+        for dir in self.source_dirs.iter().filter(|d| !source_dirs.contains(d)) {
+            if let Err(e) = self.watcher.unwatch(dir) {
+                // The directory may have been removed in the meantime
+                debug!("Could not stop watching {}: {}", dir.display(), e);
+            }
+        }
+        for dir in source_dirs.iter().filter(|d| !self.source_dirs.contains(d)) {
+            self.watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .with_context(|| anyhow!("Watching {}", dir.display()))?;
+        }
+        trace!("Watching {} source directories", source_dirs.len());
+        self.source_dirs = source_dirs;
+        Ok(())
+    }
+
+    /// Wait until a watched file (see `is_watched()`) changes and return the changed files
+    ///
+    /// Changes since the last call are returned as well. After a change, this waits until there
+    /// were no more changes for the `DEBOUNCE_INTERVAL`.
+    async fn wait_for_changes<I>(
+        &mut self,
+        source_files: &[PathBuf],
+        is_ignored: I,
+    ) -> Result<Vec<PathBuf>>
+    where
+        I: Fn(&Path) -> bool,
+    {
+        let mut changed: Vec<PathBuf> = vec![];
+        let mut deadline = None;
+        loop {
+            let event = match deadline {
+                None => self.events.recv().await,
+                Some(deadline) => match tokio::time::timeout_at(deadline, self.events.recv()).await
+                {
+                    Ok(event) => event,
+                    Err(_) => return Ok(changed), // No more changes since the last change
+                },
+            };
+            let event = event
+                .ok_or_else(|| anyhow!("The filesystem watcher stopped"))?
+                .context("Watching for changes")?;
+            if event.kind.is_access() {
+                continue;
+            }
+
+            for path in event.paths {
+                if is_watched(&path, source_files, &is_ignored) {
+                    trace!("Change of {}: {:?}", path.display(), event.kind);
+                    deadline = Some(tokio::time::Instant::now() + DEBOUNCE_INTERVAL);
+                    if !changed.contains(&path) {
+                        changed.push(path);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_watched() {
+        let p = PathBuf::from;
+        let sources = vec![p("/cache/a-1/src-123.source")];
+        let not_ignored = |_: &Path| false;
+        let is_ignored = |path: &Path| path.starts_with("/repo/target");
+
+        assert!(is_watched(&p("/repo/a/pkg.toml"), &sources, not_ignored));
+        assert!(is_watched(&p("/repo/pkg.toml"), &sources, not_ignored));
+        assert!(is_watched(
+            &p("/cache/a-1/src-123.source"),
+            &sources,
+            not_ignored
+        ));
+        assert!(!is_watched(
+            &p("/repo/a/pkg.toml.swp"),
+            &sources,
+            not_ignored
+        ));
+        assert!(!is_watched(&p("/repo/a/README"), &sources, not_ignored));
+        assert!(!is_watched(
+            &p("/cache/a-1/src-456.source"),
+            &sources,
+            not_ignored
+        ));
+        assert!(!is_watched(
+            &p("/repo/.git/pkg.toml"),
+            &sources,
+            not_ignored
+        ));
+        assert!(!is_watched(
+            &p("/repo/target/pkg.toml"),
+            &sources,
+            is_ignored
+        ));
+        assert!(is_watched(&p("/repo/a/pkg.toml"), &sources, is_ignored));
+    }
+}
@@ -0,0 +1,84 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'config' subcommand
+
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+
+use crate::config::Configuration;
+use crate::db::DbConnectionConfig;
+
+/// Implementation of the "config" subcommand
+pub fn config(
+    matches: &ArgMatches,
+    config: &Configuration,
+    db_connection_config: DbConnectionConfig<'_>,
+) -> Result<()> {
+    match matches.subcommand() {
+        Some(("show", matches)) => show(matches, config, db_connection_config),
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("No subcommand")),
+    }
+}
+
+/// Implementation of the "config show" subcommand
+///
+/// Prints the merged and validated configuration, with the database settings from the CLI
+/// (`--db-*`) applied. Secrets are masked.
+fn show(
+    matches: &ArgMatches,
+    config: &Configuration,
+    db_connection_config: DbConnectionConfig<'_>,
+) -> Result<()> {
+    let mut value = toml::Table::try_from(config).context("Serializing the configuration")?;
+
+    // The database password stays masked:
+    let db = &db_connection_config;
+    for (key, setting) in [
+        ("database_host", toml::Value::from(*db.database_host())),
+        (
+            "database_port",
+            toml::Value::from(i64::from(*db.database_port())),
+        ),
+        ("database_user", toml::Value::from(*db.database_user())),
+        ("database_name", toml::Value::from(*db.database_name())),
+        (
+            "database_connection_timeout",
+            toml::Value::from(i64::from(*db.database_connection_timeout())),
+        ),
+        (
+            "database_pool_max_size",
+            toml::Value::from(*db.database_pool_max_size()),
+        ),
+        (
+            "database_pool_min_idle",
+            toml::Value::from(*db.database_pool_min_idle()),
+        ),
+        (
+            "database_pool_timeout",
+            toml::Value::from(i64::from(*db.database_pool_timeout())),
+        ),
+    ] {
+        value.insert(String::from(key), setting);
+    }
+
+    let output = match matches.get_one::<String>("format").map(String::as_str) {
+        Some("json") => serde_json::to_string_pretty(&value)?,
+        _ => toml::to_string_pretty(&value)?,
+    };
+
+    let mut out = std::io::stdout().lock();
+    writeln!(out, "{}", output.trim_end()).map_err(anyhow::Error::from)
+}
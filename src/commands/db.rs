@@ -11,9 +11,11 @@
 //! Implementation of the 'db' subcommand
 
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
 use std::str::FromStr;
+use std::sync::Arc;
 
 use anyhow::anyhow;
 use anyhow::Context;
@@ -24,6 +26,9 @@ use colored::Colorize;
 use diesel::BelongingToDsl;
 use diesel::ExpressionMethods;
 use diesel::JoinOnDsl;
+use diesel::NullableExpressionMethods;
+use diesel::OptionalExtension;
+use diesel::PgConnection;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 use diesel_migrations::embed_migrations;
@@ -31,31 +36,57 @@ use diesel_migrations::EmbeddedMigrations;
 use diesel_migrations::HarnessWithOutput;
 use diesel_migrations::MigrationHarness;
 use itertools::Itertools;
-use tracing::{debug, info, trace};
+use tokio::sync::RwLock;
+use tracing::{debug, info, trace, warn};
 
 use crate::commands::util::get_date_filter;
 use crate::config::Configuration;
 use crate::db::models;
 use crate::db::DbConnectionConfig;
+use crate::endpoint::EndpointScheduler;
+use crate::filestore::path::StoreRoot;
+use crate::filestore::ReleaseStore;
+use crate::filestore::StagingStore;
+use crate::job::JobResource;
+use crate::job::RunnableJob;
 use crate::log::JobResult;
+use crate::log::LogItem;
+use crate::log::TimedLogItem;
+use crate::package::condition::ConditionData;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
 use crate::package::Script;
+use crate::package::Shebang;
+use crate::repository::Repository;
 use crate::schema;
+use crate::source::SourceCache;
+use crate::util::docker::ImageName;
 use crate::util::docker::ImageNameLookup;
+use crate::util::progress::ProgressBars;
+use crate::util::EnvironmentVariableName;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
 /// Implementation of the "db" subcommand
-pub fn db(
+pub async fn db<L>(
     db_connection_config: DbConnectionConfig<'_>,
     config: &Configuration,
     matches: &ArgMatches,
-) -> Result<()> {
+    progressbars: ProgressBars,
+    load_repo: L,
+    repo_path: &Path,
+) -> Result<()>
+where
+    L: FnOnce() -> Result<Repository>,
+{
     let default_limit = config.database_default_query_limit();
 
     match matches.subcommand() {
         Some(("cli", matches)) => cli(db_connection_config, matches),
         Some(("setup", _matches)) => setup(db_connection_config),
-        Some(("artifacts", matches)) => artifacts(db_connection_config, matches, default_limit),
+        Some(("artifacts", matches)) => {
+            artifacts(db_connection_config, config, matches, default_limit)
+        }
         Some(("envvars", matches)) => envvars(db_connection_config, matches),
         Some(("images", matches)) => images(db_connection_config, matches),
         Some(("submit", matches)) => submit(db_connection_config, config, matches),
@@ -63,6 +94,17 @@ pub fn db(
         Some(("jobs", matches)) => jobs(db_connection_config, config, matches, default_limit),
         Some(("job", matches)) => job(db_connection_config, config, matches),
         Some(("log-of", matches)) => log_of(db_connection_config, matches),
+        Some(("requeue", matches)) => {
+            requeue(
+                db_connection_config,
+                config,
+                matches,
+                progressbars,
+                load_repo,
+                repo_path,
+            )
+            .await
+        }
         Some(("releases", matches)) => {
             releases(db_connection_config, config, matches, default_limit)
         }
@@ -74,85 +116,104 @@ pub fn db(
 /// Implementation of the "db cli" subcommand
 fn cli(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     trait PgCliCommand {
-        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>) -> Result<()>;
+        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>, args: &[String]) -> Result<()>;
+    }
+
+    /// Helper to run the database CLI program and check its exit status
+    fn run_pg_cli_command(mut cmd: Command, program: &str) -> Result<()> {
+        cmd.stdin(std::process::Stdio::inherit())
+            .stdout(std::process::Stdio::inherit())
+            .stderr(std::process::Stdio::inherit())
+            .output()
+            .map_err(Error::from)
+            .and_then(|out| {
+                if out.status.success() {
+                    info!("{} exited successfully", program);
+                    Ok(())
+                } else {
+                    Err(anyhow!("{} did not exit successfully", program)).with_context(|| {
+                        match String::from_utf8(out.stderr) {
+                            Ok(log) => anyhow!("{}", log),
+                            Err(e) => anyhow!("Cannot parse log into valid UTF-8: {}", e),
+                        }
+                    })
+                }
+            })
     }
 
     struct Psql(PathBuf);
     impl PgCliCommand for Psql {
-        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>) -> Result<()> {
-            Command::new(&self.0)
-                .arg(format!("--dbname={}", dbcc.database_name()))
+        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>, args: &[String]) -> Result<()> {
+            let mut cmd = Command::new(&self.0);
+            cmd.arg(format!("--dbname={}", dbcc.database_name()))
                 .arg(format!("--host={}", dbcc.database_host()))
                 .arg(format!("--port={}", dbcc.database_port()))
                 .arg(format!("--username={}", dbcc.database_user()))
-                .stdin(std::process::Stdio::inherit())
-                .stdout(std::process::Stdio::inherit())
-                .stderr(std::process::Stdio::inherit())
-                .output()
-                .map_err(Error::from)
-                .and_then(|out| {
-                    if out.status.success() {
-                        info!("psql exited successfully");
-                        Ok(())
-                    } else {
-                        Err(anyhow!("psql did not exit successfully")).with_context(|| {
-                            match String::from_utf8(out.stderr) {
-                                Ok(log) => anyhow!("{}", log),
-                                Err(e) => anyhow!("Cannot parse log into valid UTF-8: {}", e),
-                            }
-                        })
-                    }
-                })
+                .args(args);
+            run_pg_cli_command(cmd, "psql")
         }
     }
 
     struct PgCli(PathBuf);
     impl PgCliCommand for PgCli {
-        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>) -> Result<()> {
-            Command::new(&self.0)
-                .arg("--host")
+        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>, args: &[String]) -> Result<()> {
+            let mut cmd = Command::new(&self.0);
+            cmd.arg("--host")
                 .arg(dbcc.database_host())
                 .arg("--port")
                 .arg(dbcc.database_port().to_string())
                 .arg("--username")
                 .arg(dbcc.database_user())
-                .arg(dbcc.database_name())
-                .stdin(std::process::Stdio::inherit())
-                .stdout(std::process::Stdio::inherit())
-                .stderr(std::process::Stdio::inherit())
-                .output()
-                .map_err(Error::from)
-                .and_then(|out| {
-                    if out.status.success() {
-                        info!("pgcli exited successfully");
-                        Ok(())
-                    } else {
-                        Err(anyhow!("pgcli did not exit successfully")).with_context(|| {
-                            match String::from_utf8(out.stderr) {
-                                Ok(log) => anyhow!("{}", log),
-                                Err(e) => anyhow!("Cannot parse log into valid UTF-8: {}", e),
-                            }
-                        })
-                    }
-                })
+                .args(args)
+                .arg(dbcc.database_name());
+            run_pg_cli_command(cmd, "pgcli")
+        }
+    }
+
+    struct Usql(PathBuf);
+    impl PgCliCommand for Usql {
+        fn run_for_uri(&self, dbcc: DbConnectionConfig<'_>, args: &[String]) -> Result<()> {
+            // usql only accepts a connection URL, the password is requested interactively:
+            let mut cmd = Command::new(&self.0);
+            cmd.args(args).arg(format!(
+                "postgres://{user}@{host}:{port}/{name}",
+                user = dbcc.database_user(),
+                host = dbcc.database_host(),
+                port = dbcc.database_port(),
+                name = dbcc.database_name(),
+            ));
+            run_pg_cli_command(cmd, "usql")
         }
     }
 
+    if matches.get_flag("print_url") {
+        // The Debug implementation of DbConnectionConfig masks the password:
+        println!("{db_connection_config:?}");
+        return Ok(());
+    }
+
+    let tool_args = matches
+        .get_many::<String>("tool_args")
+        .unwrap_or_default()
+        .cloned()
+        .collect::<Vec<String>>();
+
     matches
         .get_one::<String>("tool")
         .map(|s| vec![s.as_str()])
-        .unwrap_or_else(|| vec!["psql", "pgcli"])
+        .unwrap_or_else(|| vec!["psql", "pgcli", "usql"])
         .into_iter()
         .filter_map(|s| which::which(s).ok().map(|path| (path, s)))
         .map(|(path, s)| match s {
             "psql" => Ok(Box::new(Psql(path)) as Box<dyn PgCliCommand>),
             "pgcli" => Ok(Box::new(PgCli(path)) as Box<dyn PgCliCommand>),
+            "usql" => Ok(Box::new(Usql(path)) as Box<dyn PgCliCommand>),
             prog => Err(anyhow!("Unsupported pg CLI program: {}", prog)),
         })
         .next()
         .transpose()?
         .ok_or_else(|| anyhow!("No Program found"))?
-        .run_for_uri(db_connection_config)
+        .run_for_uri(db_connection_config, &tool_args)
 }
 
 fn setup(conn_cfg: DbConnectionConfig<'_>) -> Result<()> {
@@ -176,48 +237,158 @@ fn get_limit(matches: &ArgMatches, default_limit: &usize) -> Result<i64> {
 /// Implementation of the "db artifacts" subcommand
 fn artifacts(
     conn_cfg: DbConnectionConfig<'_>,
+    config: &Configuration,
     matches: &ArgMatches,
     default_limit: &usize,
 ) -> Result<()> {
     use crate::schema::artifacts::dsl;
 
     let csv = matches.get_flag("csv");
+    let orphaned = matches.get_flag("orphaned");
     let job_uuid = matches.get_one::<uuid::Uuid>("job_uuid");
+    let package_name = matches.get_one::<String>("package");
+    let package_version = matches.get_one::<String>("package_version");
     let limit = get_limit(matches, default_limit)?;
-
-    let hdrs = crate::commands::util::mk_header(vec!["Path", "Released", "Job"]);
+    let order = matches
+        .get_one::<String>("order")
+        .map(String::as_str)
+        .unwrap_or("id");
+
+    let columns = crate::commands::util::Columns::select(
+        if orphaned {
+            vec!["Path", "Released", "Job", "Missing file"]
+        } else {
+            vec!["Path", "Released", "Job"]
+        },
+        matches,
+    )?;
     let mut conn = conn_cfg.establish_connection()?;
+
+    // Filtering for a job of another package would silently yield nothing
+    if let (Some(job_uuid), Some(package_name)) = (job_uuid, package_name) {
+        let package = schema::jobs::table
+            .inner_join(schema::packages::table)
+            .filter(schema::jobs::dsl::uuid.eq(job_uuid))
+            .select(schema::packages::all_columns)
+            .first::<models::Package>(&mut conn)
+            .optional()?;
+
+        if let Some(package) = package {
+            if package.name != *package_name
+                || package_version.is_some_and(|v| package.version != *v)
+            {
+                return Err(anyhow!(
+                    "Job {} is a job of package {} {}, not of the requested package",
+                    job_uuid,
+                    package.name,
+                    package.version
+                ));
+            }
+        }
+    }
+
     let mut query = dsl::artifacts
-        .order_by(schema::artifacts::id.desc()) // required for the --limit implementation
-        .inner_join(schema::jobs::table)
-        .left_join(schema::releases::table)
+        .inner_join(
+            schema::jobs::table
+                .inner_join(schema::submits::table)
+                .inner_join(schema::packages::table),
+        )
+        .left_join(schema::releases::table.inner_join(schema::release_stores::table))
         .into_boxed()
         .limit(limit);
+
+    // The order is required for the --limit implementation: The newest LIMIT artifacts are listed
+    // (the first LIMIT artifacts when ordering by path)
+    query = match order {
+        "date" => query.order_by((
+            schema::submits::submit_time.desc(),
+            schema::artifacts::id.desc(),
+        )),
+        "path" => query.order_by(schema::artifacts::path.asc()),
+        _ => query.order_by(schema::artifacts::id.desc()),
+    };
     if let Some(job_uuid) = job_uuid {
         query = query.filter(schema::jobs::dsl::uuid.eq(job_uuid))
     };
+    if let Some(package_name) = package_name {
+        query = query.filter(schema::packages::dsl::name.eq(package_name))
+    };
+    if let Some(package_version) = package_version {
+        query = query.filter(schema::packages::dsl::version.eq(package_version))
+    };
 
-    let data = query
-        .load::<(models::Artifact, models::Job, Option<models::Release>)>(&mut conn)?
+    let mut artifacts = query.load::<(
+        models::Artifact,
+        (models::Job, models::Submit, models::Package),
+        Option<(models::Release, models::ReleaseStore)>,
+    )>(&mut conn)?;
+    if order != "path" {
+        artifacts.reverse(); // We want the newest artifacts at the bottom (reverse the order for --limit)
+    }
+
+    let data = artifacts
         .into_iter()
-        .rev() // We want the newest artifacts at the bottom (reverse the order for --limit)
-        .map(|(artifact, job, rel)| {
+        .map(|(artifact, (job, submit, _), rel)| {
+            let missing_path = if orphaned {
+                let path = artifact_file_path(config, &artifact, &submit, rel.as_ref())?;
+                if path.is_file() {
+                    return Ok(None);
+                }
+                Some(path.display().to_string())
+            } else {
+                None
+            };
+
             let rel = rel
-                .map(|r| r.release_date.to_string())
+                .map(|(r, _)| r.release_date.to_string())
                 .unwrap_or_else(|| String::from("no"));
-            vec![artifact.path, rel, job.uuid.to_string()]
+            let mut row = vec![artifact.path, rel, job.uuid.to_string()];
+            row.extend(missing_path);
+            Ok(Some(row))
         })
-        .collect::<Vec<_>>();
+        .filter_map_ok(|row| row)
+        .collect::<Result<Vec<_>>>()?;
 
     if data.is_empty() {
-        info!("No artifacts in database");
+        if orphaned {
+            info!("No orphaned artifacts in database");
+        } else {
+            info!("No artifacts in database");
+        }
     } else {
-        crate::commands::util::display_data(hdrs, data, csv)?;
+        columns.display(data, csv)?;
     }
 
     Ok(())
 }
 
+/// Get the path where the file of an artifact is expected to be
+///
+/// This is the path in the release store if the artifact was released and the path in the staging
+/// directory of the submit otherwise.
+fn artifact_file_path(
+    config: &Configuration,
+    artifact: &models::Artifact,
+    submit: &models::Submit,
+    release: Option<&(models::Release, models::ReleaseStore)>,
+) -> Result<PathBuf> {
+    let path = if let Some((release, store)) = release {
+        let released_path =
+            crate::filestore::ArtifactPath::new(PathBuf::from(release.released_path(artifact)))?;
+        config
+            .releases_directory()
+            .join(&store.store_name)
+            .join(released_path)
+    } else {
+        let artifact_path = crate::filestore::ArtifactPath::new(PathBuf::from(&artifact.path))?;
+        config
+            .staging_directory()
+            .join(submit.uuid.to_string())
+            .join(artifact_path)
+    };
+    Ok(path)
+}
+
 /// Implementation of the "db envvars" subcommand
 fn envvars(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     use crate::schema::envvars::dsl;
@@ -245,8 +416,38 @@ fn images(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
     use crate::schema::images::dsl;
 
     let csv = matches.get_flag("csv");
-    let hdrs = crate::commands::util::mk_header(vec!["Name"]);
     let mut conn = conn_cfg.establish_connection()?;
+
+    if matches.get_flag("with_counts") {
+        let hdrs = crate::commands::util::mk_header(vec!["Name", "Jobs", "Last job"]);
+        let data = schema::images::table
+            .left_join(schema::jobs::table.inner_join(schema::submits::table))
+            .group_by(schema::images::id)
+            .select((
+                schema::images::name,
+                diesel::dsl::count(schema::jobs::id.nullable()),
+                diesel::dsl::max(schema::submits::submit_time.nullable()),
+            ))
+            .order_by(schema::images::name)
+            .load::<(String, i64, Option<chrono::NaiveDateTime>)>(&mut conn)?
+            .into_iter()
+            .map(|(name, jobs, last_job)| {
+                let last_job = last_job
+                    .map(|dt| dt.to_string())
+                    .unwrap_or_else(|| String::from("-"));
+                vec![name, jobs.to_string(), last_job]
+            })
+            .collect::<Vec<_>>();
+
+        if data.is_empty() {
+            info!("No images in database");
+        } else {
+            crate::commands::util::display_data(hdrs, data, csv)?;
+        }
+        return Ok(());
+    }
+
+    let hdrs = crate::commands::util::mk_header(vec!["Name"]);
     let data = dsl::images
         .load::<models::Image>(&mut conn)?
         .into_iter()
@@ -291,7 +492,7 @@ fn submit(
         let mut err = 0;
 
         for j in jobs.iter() {
-            match crate::log::ParsedLog::from_str(&j.log_text)?.is_successfull() {
+            match job_result(j)? {
                 JobResult::Unknown => unkn += 1,
                 JobResult::Success => succ += 1,
                 JobResult::Errored => err += 1,
@@ -310,6 +511,7 @@ fn submit(
             Submit   {submit_id}
             Date:    {submit_dt}
             Commit:  {submit_commit}
+            Butido:  {submit_butido_version}
             Jobs:    {n_jobs}
             Success: {n_jobs_success}
             Unknown: {n_jobs_unknown}
@@ -319,6 +521,7 @@ fn submit(
         submit_id = submit.uuid.to_string().cyan(),
         submit_dt = submit.submit_time.to_string().cyan(),
         submit_commit = githash.hash.cyan(),
+        submit_butido_version = submit.butido_version.as_deref().unwrap_or("unknown").cyan(),
         n_jobs = n_jobs.to_string().cyan(),
         n_jobs_success = jobs_success.to_string().green(),
         n_jobs_unknown = jobs_unknown.to_string().red(),
@@ -376,12 +579,10 @@ fn submits(
 ) -> Result<()> {
     let csv = matches.get_flag("csv");
     let limit = get_limit(matches, default_limit)?;
-    let hdrs = crate::commands::util::mk_header(vec![
-        "Time",
-        "UUID",
-        "For Package",
-        "For Package Version",
-    ]);
+    let columns = crate::commands::util::Columns::select(
+        vec!["Time", "UUID", "For Package", "For Package Version"],
+        matches,
+    )?;
     let mut conn = conn_cfg.establish_connection()?;
 
     let query = schema::submits::table
@@ -406,6 +607,21 @@ fn submits(
         query
     };
 
+    let query = if matches.get_flag("failed") {
+        // Jobs that were recorded before the success state was stored need their logs parsed
+        // once:
+        let backfilled = models::Job::backfill_success(&mut conn)?;
+        debug!("Stored the success state of {} jobs", backfilled);
+
+        query.filter(diesel::dsl::exists(
+            schema::jobs::table
+                .filter(schema::jobs::submit_id.eq(schema::submits::id))
+                .filter(schema::jobs::success.eq(false)),
+        ))
+    } else {
+        query
+    };
+
     let submits = if let Some(pkgname) = matches.get_one::<String>("with_pkg") {
         // In the case of a with_pkg command, we must execute two queries on the database, as the
         // diesel framework does not yet support aliases for queries (see
@@ -476,7 +692,7 @@ fn submits(
     if data.is_empty() {
         info!("No submits in database");
     } else {
-        crate::commands::util::display_data(hdrs, data, csv)?;
+        columns.display(data, csv)?;
     }
 
     Ok(())
@@ -490,9 +706,12 @@ fn jobs(
     default_limit: &usize,
 ) -> Result<()> {
     let csv = matches.get_flag("csv");
-    let hdrs = crate::commands::util::mk_header(vec![
-        "Submit", "Job", "Time", "Host", "Ok?", "Package", "Version", "Distro", "Type",
-    ]);
+    let columns = crate::commands::util::Columns::select(
+        vec![
+            "Submit", "Job", "Time", "Host", "Ok?", "Package", "Version", "Distro", "Type",
+        ],
+        matches,
+    )?;
     let mut conn = conn_cfg.establish_connection()?;
     let older_than_filter = get_date_filter("older_than", matches)?;
     let newer_than_filter = get_date_filter("newer_than", matches)?;
@@ -612,7 +831,7 @@ fn jobs(
     if data.is_empty() {
         info!("No submits in database");
     } else {
-        crate::commands::util::display_data(hdrs, data, csv)?;
+        columns.display(data, csv)?;
     }
 
     Ok(())
@@ -626,8 +845,13 @@ fn job(
 ) -> Result<()> {
     let script_highlight = !matches.get_flag("no_script_highlight");
     let script_line_numbers = !matches.get_flag("no_script_line_numbers");
-    let configured_theme = config.script_highlight_theme();
+    let configured_theme = matches
+        .get_one::<String>("theme")
+        .or(config.script_highlight_theme().as_ref());
+    let script_output = matches.get_one::<PathBuf>("output");
     let show_log = matches.get_flag("show_log");
+    let show_timestamps = matches.get_flag("timestamps");
+    let log_grep = LogGrep::from_matches(matches)?;
     let show_script = matches.get_flag("show_script");
     let csv = matches.get_flag("csv");
     let mut conn = conn_cfg.establish_connection()?;
@@ -650,8 +874,8 @@ fn job(
     trace!("Parsing log");
     let parsed_log = crate::log::ParsedLog::from_str(&data.0.log_text)?;
     trace!("Parsed log = {:?}", parsed_log);
-    let success = parsed_log.is_successfull();
-    trace!("log successful = {:?}", success);
+    let success = job_result(&data.0)?;
+    trace!("job successful = {:?}", success);
 
     if csv {
         let hdrs = crate::commands::util::mk_header(vec![
@@ -662,6 +886,9 @@ fn job(
             "Ran on",
             "Image Name",
             "Container",
+            "Peak Memory (bytes)",
+            "CPU Time (ns)",
+            "Memory Is Peak",
         ]);
 
         let data = vec![vec![
@@ -676,6 +903,18 @@ fn job(
             data.2.name.to_string(),
             data.4.name.to_string(),
             data.0.container_hash,
+            data.0
+                .peak_memory_bytes
+                .map(|b| b.to_string())
+                .unwrap_or_default(),
+            data.0
+                .cpu_time_ns
+                .map(|t| t.to_string())
+                .unwrap_or_default(),
+            data.0
+                .peak_memory_bytes
+                .map(|_| data.0.memory_is_peak.unwrap_or(true).to_string())
+                .unwrap_or_default(),
         ]];
         crate::commands::util::display_data(hdrs, data, csv)
     } else {
@@ -704,7 +943,10 @@ fn job(
 
                 Ran on:     {endpoint_name}
                 Image:      {image_name}
+                Arch:       {arch}
                 Container:  {container_hash}
+                Memory:     {memory}
+                CPU time:   {cpu_time}
 
                 Script:     {script_len} lines
                 Log:        {log_len} lines
@@ -725,7 +967,31 @@ fn job(
             package_version = data.3.version.cyan(),
             endpoint_name = data.2.name.cyan(),
             image_name = data.4.name.cyan(),
+            arch = data.0.arch.as_deref().unwrap_or("none").cyan(),
             container_hash = data.0.container_hash.cyan(),
+            memory = data
+                .0
+                .peak_memory_bytes
+                .map(|b| {
+                    let kind = if data.0.memory_is_peak.unwrap_or(true) {
+                        "peak"
+                    } else {
+                        "current usage when the script finished, the peak is not available"
+                    };
+                    format!("{} ({kind})", bytesize::ByteSize::b(b as u64))
+                })
+                .unwrap_or_else(|| String::from("unknown"))
+                .cyan(),
+            cpu_time = data
+                .0
+                .cpu_time_ns
+                .map(|t| {
+                    // Nanoseconds are too precise to be helpful here
+                    let millis = t as u64 / 1_000_000;
+                    humantime::format_duration(std::time::Duration::from_millis(millis)).to_string()
+                })
+                .unwrap_or_else(|| String::from("unknown"))
+                .cyan(),
             script_len = format!("{:<4}", data.0.script_text.lines().count()).cyan(),
             log_len = format!("{:<4}", data.0.log_text.lines().count()).cyan(),
         );
@@ -745,36 +1011,36 @@ fn job(
         }
 
         if show_script {
-            let theme = configured_theme.as_ref().ok_or_else(|| {
+            let theme = configured_theme.ok_or_else(|| {
                 anyhow!("Highlighting for script enabled, but no theme configured")
             })?;
             let script = Script::from(data.0.script_text);
-            let script = crate::ui::script_to_printable(
-                &script,
-                script_highlight,
-                theme,
-                script_line_numbers,
-            )?;
-
-            let s = indoc::formatdoc!(
-                r#"
+            // A file gets the plain script, unless colors are requested explicitly
+            let highlight = script_highlight
+                && (script_output.is_none()
+                    || matches.get_one::<String>("color").map(String::as_str) == Some("always"));
+            let script =
+                crate::ui::script_to_printable(&script, highlight, theme, script_line_numbers)?;
+
+            if let Some(path) = script_output {
+                std::fs::write(path, script)
+                    .with_context(|| anyhow!("Writing the script to {}", path.display()))?;
+            } else {
+                let s = indoc::formatdoc!(
+                    r#"
                 ---
 
                 {script}
 
             "#,
-                script = script
-            );
-            writeln!(out, "{s}")?;
+                    script = script
+                );
+                writeln!(out, "{s}")?;
+            }
         }
 
         if show_log {
-            let log = parsed_log
-                .into_iter()
-                .map(|line_item| line_item.display().map(|d| d.to_string()))
-                .collect::<Result<Vec<_>>>()?
-                .into_iter() // ugly, but hey... not important right now.
-                .join("\n");
+            let log = display_log(parsed_log, show_timestamps, log_grep.as_ref())?.join("\n");
 
             let s = indoc::formatdoc!(
                 r#"
@@ -792,6 +1058,268 @@ fn job(
     }
 }
 
+/// Implementation of the subcommand "db requeue"
+///
+/// Runs a job again with the script and the environment that were recorded for it in the
+/// database, as the only job of a new submit, without building the job DAG again.
+/// The artifacts of the dependencies of the package are searched like for a resumed build (`build
+/// --resume`), in the staging directory of the submit of the job and in the release stores. The
+/// ones from the staging directory are copied to the staging directory of the new submit.
+/// Secrets (`build --secret`) are not recorded, so they are not available to the job.
+async fn requeue<L>(
+    conn_cfg: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+    progressbars: ProgressBars,
+    load_repo: L,
+    repo_path: &Path,
+) -> Result<()>
+where
+    L: FnOnce() -> Result<Repository>,
+{
+    let job_uuid = matches.get_one::<uuid::Uuid>("job_uuid").unwrap();
+    let database_pool = conn_cfg.establish_pool()?;
+    let mut conn = database_pool.get()?;
+
+    let (job, submit, package, image) = schema::jobs::table
+        .filter(schema::jobs::dsl::uuid.eq(job_uuid))
+        .inner_join(schema::submits::table)
+        .inner_join(schema::packages::table)
+        .inner_join(schema::images::table)
+        .first::<(models::Job, models::Submit, models::Package, models::Image)>(&mut conn)
+        .optional()?
+        .ok_or_else(|| anyhow!("Job {} not found", job_uuid))?;
+
+    if is_job_successfull(&job)? == Some(true) {
+        warn!(
+            "Job {} finished successfully, running it again anyways",
+            job_uuid
+        );
+    }
+
+    // The package definition (sources, patches) of the current repository is used, so the new
+    // submit is recorded with the current commit
+    let old_githash = models::GitHash::with_id(&mut conn, submit.repo_hash_id)?;
+    let git_repo = git2::Repository::open(repo_path)
+        .with_context(|| anyhow!("Opening repository at {}", repo_path.display()))?;
+    let head = crate::util::git::get_repo_head_commit_hash(&git_repo)?;
+    if head != old_githash.hash {
+        warn!(
+            "The job was built from commit {}, the repository is at {}, the package definition (sources, patches) of the repository is used",
+            old_githash.hash, head
+        );
+    }
+    let githash = models::GitHash::create_or_fetch(&mut conn, &head)?;
+
+    let env = job
+        .env(&mut conn)?
+        .into_iter()
+        .map(|envvar| {
+            (
+                EnvironmentVariableName::from(envvar.name.as_str()),
+                envvar.value,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let repo = load_repo()?;
+    let pname = PackageName::from(package.name.clone());
+    let pversion = PackageVersion::from(package.version.clone());
+    let repo_package = repo
+        .find(&pname, &pversion)
+        .into_iter()
+        .next()
+        .cloned()
+        .ok_or_else(|| anyhow!("Package {} {} not found in the repository", pname, pversion))?;
+    let image_name = ImageName::from(image.name.clone());
+
+    let staging_dir_of_job = config
+        .staging_directory()
+        .join(submit.uuid.hyphenated().to_string());
+    if !staging_dir_of_job.is_dir() {
+        return Err(anyhow!(
+            "Cannot requeue job {}, the staging directory of its submit does not exist anymore: {}",
+            job_uuid,
+            staging_dir_of_job.display()
+        ));
+    }
+    let bar = progressbars.bar()?;
+    let staging_store_of_job = StagingStore::load(StoreRoot::new(staging_dir_of_job)?, &bar)?;
+    bar.finish_with_message("Loaded staging successfully");
+
+    let release_stores = config
+        .release_stores()
+        .iter()
+        .map(|storename| {
+            let bar = progressbars.bar()?;
+            let p = config.releases_directory().join(storename);
+            let store = ReleaseStore::load(StoreRoot::new(p)?, &bar)?;
+            bar.finish_with_message(format!("Loaded releases in {storename} successfully"));
+            Ok(Arc::new(store))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // The dependencies were built with the environment of the job without the environment of the
+    // package (i.e., the environment from the commandline and the git environment)
+    let dependency_env = env
+        .iter()
+        .filter(|(name, value)| {
+            repo_package
+                .environment()
+                .as_ref()
+                .and_then(|package_env| package_env.get(name))
+                != Some(value)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    let condition_data = ConditionData {
+        image_name: Some(&image_name),
+        env: &dependency_env,
+    };
+    let dag =
+        crate::package::Dag::for_root_package(repo_package.clone(), &repo, None, &condition_data)?;
+    let jobdag = crate::job::Dag::from_package_dag(
+        dag,
+        Shebang::from(config.shebang_for_image(&image_name).clone()),
+        image_name.clone(),
+        job.arch.clone(),
+        config.available_phases().clone(),
+        dependency_env.into_iter().map(JobResource::from).collect(),
+    );
+
+    let mut dependency_artifacts = vec![];
+    for jobdef in jobdag.iter() {
+        let dependency = jobdef.job.package();
+        if dependency.name() == repo_package.name()
+            && dependency.version() == repo_package.version()
+        {
+            continue;
+        }
+
+        let artifacts = crate::orchestrator::find_replacement_artifacts(
+            jobdef.job,
+            config,
+            database_pool.clone(),
+            &staging_store_of_job,
+            &release_stores,
+            None,
+            None,
+            None,
+        )?;
+        if artifacts.is_empty() {
+            return Err(anyhow!(
+                "No artifacts found for the dependency {} {} of job {}, it has to be built again",
+                dependency.name(),
+                dependency.version(),
+                job_uuid
+            ));
+        }
+        dependency_artifacts.extend(artifacts);
+    }
+
+    let submit_id = uuid::Uuid::new_v4();
+    let staging_dir = config
+        .staging_directory()
+        .join(submit_id.hyphenated().to_string());
+    tokio::fs::create_dir_all(&staging_dir).await?;
+    for artifact in dependency_artifacts.iter() {
+        if let Some(source) = staging_store_of_job.root_path().join(artifact)? {
+            let destination = staging_dir.join(artifact);
+            if let Some(parent) = destination.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::copy(source.joined(), &destination)
+                .await
+                .with_context(|| {
+                    anyhow!("Copying {} to {}", source.display(), destination.display())
+                })?;
+        }
+    }
+    let bar = progressbars.bar()?;
+    let staging_store = StagingStore::load(StoreRoot::new(staging_dir.clone())?, &bar)?;
+    bar.finish_with_message("Loaded staging successfully");
+
+    let now = chrono::offset::Local::now().naive_local();
+    let new_submit = models::Submit::create(
+        &mut conn,
+        &now,
+        &submit_id,
+        &image,
+        &package,
+        &githash,
+        env!("VERGEN_GIT_DESCRIBE"),
+    )?;
+    drop(conn);
+
+    let source_cache = SourceCache::new(
+        config.source_cache_root().clone(),
+        config.source_mirror_base().clone(),
+    );
+    let runnable = RunnableJob::from_recorded_job(
+        repo_package,
+        image_name,
+        job.arch.clone(),
+        Script::from(job.script_text.clone()),
+        env,
+        &source_cache,
+        config,
+        dependency_artifacts,
+    )?;
+    let new_job_uuid = *runnable.uuid();
+
+    {
+        let out = std::io::stdout();
+        let mut outlock = out.lock();
+        writeln!(outlock, "Requeueing job:  {}", job_uuid.to_string().green())?;
+        writeln!(
+            outlock,
+            "Starting submit: {}",
+            submit_id.to_string().green()
+        )?;
+        writeln!(
+            outlock,
+            "New job:         {}",
+            new_job_uuid.to_string().green()
+        )?;
+    }
+
+    let scheduler = EndpointScheduler::setup(
+        crate::commands::util::get_endpoint_configurations(config, None),
+        Arc::new(RwLock::new(staging_store)),
+        release_stores,
+        database_pool,
+        new_submit,
+        None,
+        config.docker().endpoint_retry_attempts(),
+        matches.get_flag("keep-failed-containers"),
+        config
+            .artifact_verifier()
+            .as_ref()
+            .map(|verifier| repo_path.join(verifier)),
+        *config.max_log_lines(),
+        *config.record_log_times(),
+        crate::orchestrator::EventSink::default(),
+        crate::orchestrator::Shutdown::default(),
+    )
+    .await?;
+
+    let artifacts = scheduler
+        .schedule_job(runnable, progressbars.bar()?)
+        .await?
+        .run(&|| false)
+        .await?
+        .with_context(|| anyhow!("Requeued job {} failed", new_job_uuid))?;
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    if !artifacts.is_empty() {
+        writeln!(outlock, "Packages created:")?;
+    }
+    artifacts.into_iter().try_for_each(|artifact_path| {
+        writeln!(outlock, "{}", staging_dir.join(artifact_path).display()).map_err(Error::from)
+    })
+}
+
 /// Implementation of the subcommand "db log-of"
 fn log_of(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     let mut conn = conn_cfg.establish_connection()?;
@@ -799,19 +1327,186 @@ fn log_of(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
     let out = std::io::stdout();
     let mut lock = out.lock();
 
+    let timestamps = matches.get_flag("timestamps");
+    let log_grep = LogGrep::from_matches(matches)?;
+
+    if matches.get_flag("follow") {
+        let timeout = matches
+            .get_one::<u64>("follow_timeout")
+            .map(|s| std::time::Duration::from_secs(*s))
+            .unwrap(); // safe by clap default value
+        return follow_log_of(&mut conn, job_uuid, timestamps, timeout, &mut lock);
+    }
+
     schema::jobs::table
         .filter(schema::jobs::dsl::uuid.eq(job_uuid))
         .select(schema::jobs::dsl::log_text)
         .first::<String>(&mut conn)
         .map_err(Error::from)
-        .and_then(|s| crate::log::ParsedLog::from_str(&s))?
+        .and_then(|s| print_log(&mut lock, &s, timestamps, log_grep.as_ref()))
+}
+
+/// Print the log of a job while it is running, until it is finished
+///
+/// The log of a running job is read from the partial log chunks that are written while the job
+/// runs. Once the job is finished, the remaining lines are taken from the complete log.
+///
+/// The partial log is only written every few seconds, so a job that neither finished nor logged
+/// anything yet is waited for. If the job did not log anything for `timeout`, an error is returned:
+/// The job may not exist, or the build that ran it was aborted (in which case the job will never
+/// finish).
+fn follow_log_of(
+    conn: &mut PgConnection,
+    job_uuid: &uuid::Uuid,
+    timestamps: bool,
+    timeout: std::time::Duration,
+    out: &mut impl Write,
+) -> Result<()> {
+    let poll_interval = std::time::Duration::from_secs(1);
+    let mut last_chunk_id = 0;
+    let mut printed_lines = 0;
+    let mut last_activity = std::time::Instant::now();
+
+    loop {
+        let log = schema::jobs::table
+            .filter(schema::jobs::dsl::uuid.eq(job_uuid))
+            .select(schema::jobs::dsl::log_text)
+            .first::<String>(conn)
+            .optional()?;
+
+        if let Some(log) = log {
+            let rest = log.split('\n').skip(printed_lines).join("\n");
+            return print_log(out, &rest, timestamps, None);
+        }
+
+        let chunks = models::JobLogChunk::load_after(conn, job_uuid, last_chunk_id)?;
+        if chunks.is_empty() {
+            if last_activity.elapsed() > timeout && last_chunk_id == 0 {
+                return Err(anyhow!(
+                    "Job {} not found, it did not finish and did not log anything for {}s",
+                    job_uuid,
+                    timeout.as_secs()
+                ));
+            }
+            if last_activity.elapsed() > timeout {
+                return Err(anyhow!(
+                    "Job {} did not log anything for {}s, stopping to follow the log (the build may have been aborted)",
+                    job_uuid,
+                    timeout.as_secs()
+                ));
+            }
+        } else {
+            last_activity = std::time::Instant::now();
+        }
+
+        for chunk in chunks {
+            print_log(out, &chunk.log_text, timestamps, None)?;
+            printed_lines += chunk.log_text.split('\n').count();
+            last_chunk_id = chunk.id;
+        }
+
+        out.flush()?;
+        std::thread::sleep(poll_interval);
+    }
+}
+
+/// Parse a (partial) job log and print it
+fn print_log(
+    out: &mut impl Write,
+    log: &str,
+    timestamps: bool,
+    grep: Option<&LogGrep>,
+) -> Result<()> {
+    display_log(crate::log::ParsedLog::from_str(log)?, timestamps, grep)?
         .into_iter()
-        .map(|line| {
-            line.display()
-                .and_then(|d| writeln!(lock, "{d}").map_err(Error::from))
-        })
-        .collect::<Result<Vec<()>>>()
-        .map(|_| ())
+        .try_for_each(|line| writeln!(out, "{line}").map_err(Error::from))
+}
+
+/// Get the lines of a job log for displaying them, only the lines selected by `grep` (if any)
+fn display_log(
+    log: crate::log::ParsedLog,
+    timestamps: bool,
+    grep: Option<&LogGrep>,
+) -> Result<Vec<String>> {
+    let items = log.into_timed_iter().collect::<Vec<_>>();
+    match grep {
+        Some(grep) => grep
+            .select(&items)
+            .into_iter()
+            .map(|item| match item {
+                Some(item) => display_log_item(item, timestamps),
+                None => Ok(String::from("--")),
+            })
+            .collect(),
+        None => items
+            .iter()
+            .map(|item| display_log_item(item, timestamps))
+            .collect(),
+    }
+}
+
+/// A filter for the lines of a job log (`--grep` and `--context`)
+struct LogGrep {
+    regex: regex::Regex,
+
+    /// The number of lines to show before and after each matching line
+    context: usize,
+}
+
+impl LogGrep {
+    fn from_matches(matches: &ArgMatches) -> Result<Option<Self>> {
+        matches
+            .get_one::<String>("grep")
+            .map(|regex| {
+                let regex = regex::Regex::new(regex)
+                    .with_context(|| anyhow!("Invalid regex: {}", regex))?;
+                let context = matches.get_one::<usize>("context").copied().unwrap_or(0);
+                Ok(LogGrep { regex, context })
+            })
+            .transpose()
+    }
+
+    /// Select the lines of the log that match the regex and the lines around them
+    ///
+    /// Only the text of the log lines is matched, not the phase and state markers.
+    /// `None` is returned in place of skipped lines between two groups of selected lines (like the
+    /// "--" separator of grep).
+    fn select<'a>(&self, items: &'a [TimedLogItem]) -> Vec<Option<&'a TimedLogItem>> {
+        let matching = items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| match item.item() {
+                LogItem::Line(line) => self.regex.is_match(&String::from_utf8_lossy(line)),
+                _ => false,
+            })
+            .map(|(i, _)| i);
+
+        let mut selected = vec![false; items.len()];
+        for i in matching {
+            let end = (i + self.context + 1).min(items.len());
+            selected[i.saturating_sub(self.context)..end].fill(true);
+        }
+
+        let mut result = vec![];
+        for (i, item) in items.iter().enumerate() {
+            if !selected[i] {
+                continue;
+            }
+            if i > 0 && !selected[i - 1] && !result.is_empty() {
+                result.push(None);
+            }
+            result.push(Some(item));
+        }
+        result
+    }
+}
+
+fn display_log_item(item: &TimedLogItem, timestamps: bool) -> Result<String> {
+    if timestamps {
+        item.display_with_time()
+    } else {
+        item.item().display().map(|d| d.to_string())
+    }
 }
 
 /// Implementation of the "db releases" subcommand
@@ -824,7 +1519,10 @@ pub fn releases(
     let csv = matches.get_flag("csv");
     let mut conn = conn_cfg.establish_connection()?;
     let limit = get_limit(matches, default_limit)?;
-    let header = crate::commands::util::mk_header(["Package", "Version", "Date", "Path"].to_vec());
+    let columns = crate::commands::util::Columns::select(
+        vec!["Package", "Version", "Date", "Path"],
+        matches,
+    )?;
     let mut query = schema::jobs::table
         .inner_join(schema::packages::table)
         .inner_join(schema::artifacts::table)
@@ -871,10 +1569,11 @@ pub fn releases(
         )>(&mut conn)?
         .into_iter()
         .map(|(art, pack, rel, rstore)| {
+            let release_path = rel.released_path(&art);
             let p = config
                 .releases_directory()
                 .join(&rstore.store_name)
-                .join(&art.path);
+                .join(release_path);
 
             vec![
                 pack.name,
@@ -883,19 +1582,80 @@ pub fn releases(
                 if p.is_file() {
                     p.display().to_string()
                 } else {
-                    let relative_path = PathBuf::from(rstore.store_name).join(art.path);
+                    let relative_path = PathBuf::from(&rstore.store_name).join(release_path);
                     format!("{} is not available locally", relative_path.display())
                 },
             ]
         })
         .collect::<Vec<Vec<_>>>();
 
-    crate::commands::util::display_data(header, data, csv)
+    columns.display(data, csv)
+}
+
+/// Get the result of a job
+///
+/// Uses the stored success state of the job and falls back to parsing the log for jobs that were
+/// recorded before it was stored.
+fn job_result(job: &models::Job) -> Result<JobResult> {
+    match job.success {
+        Some(true) => Ok(JobResult::Success),
+        Some(false) => Ok(JobResult::Errored),
+        None => crate::log::ParsedLog::from_str(&job.log_text).map(|pl| pl.is_successfull()),
+    }
 }
 
 /// Check if a job is successful
 ///
 /// Returns Ok(None) if cannot be decided
 fn is_job_successfull(job: &models::Job) -> Result<Option<bool>> {
-    crate::log::ParsedLog::from_str(&job.log_text).map(|pl| pl.is_successfull().to_bool())
+    job_result(job).map(|result| result.to_bool())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(lines: &[&str]) -> Vec<TimedLogItem> {
+        lines
+            .iter()
+            .map(|l| TimedLogItem::new(None, LogItem::Line(l.as_bytes().to_vec())))
+            .collect()
+    }
+
+    fn grep(regex: &str, context: usize, items: &[TimedLogItem]) -> Vec<String> {
+        LogGrep {
+            regex: regex::Regex::new(regex).unwrap(),
+            context,
+        }
+        .select(items)
+        .into_iter()
+        .map(|item| match item.map(TimedLogItem::item) {
+            Some(LogItem::Line(l)) => String::from_utf8(l.clone()).unwrap(),
+            Some(other) => panic!("Unexpected item: {other:?}"),
+            None => String::from("--"),
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_log_grep() {
+        let items = log(&["a", "error 1", "b", "c", "d", "e", "error 2", "f"]);
+        assert_eq!(grep("error", 0, &items), vec!["error 1", "--", "error 2"]);
+        assert_eq!(
+            grep("error", 1, &items),
+            vec!["a", "error 1", "b", "--", "e", "error 2", "f"]
+        );
+        assert_eq!(grep("error", 2, &items).len(), items.len());
+        assert!(grep("warning", 1, &items).is_empty());
+    }
+
+    #[test]
+    fn test_log_grep_ignores_markers() {
+        let mut items = log(&["building"]);
+        items.push(TimedLogItem::new(
+            None,
+            LogItem::CurrentPhase(String::from("build")),
+        ));
+        assert_eq!(grep("build", 0, &items), vec!["building"]);
+    }
 }
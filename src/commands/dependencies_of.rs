@@ -17,10 +17,12 @@ use anyhow::Result;
 use clap::ArgMatches;
 use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
+use itertools::Itertools;
 use tracing::trace;
 
 use crate::commands::util::getbool;
 use crate::config::*;
+use crate::package::Package;
 use crate::package::PackageName;
 use crate::package::PackageVersionConstraint;
 use crate::repository::Repository;
@@ -32,6 +34,7 @@ pub async fn dependencies_of(
     config: &Configuration,
     repo: Repository,
 ) -> Result<()> {
+    use filters::failable::filter::FailableFilter;
     use filters::filter::Filter;
 
     let package_filter = {
@@ -52,11 +55,17 @@ pub async fn dependencies_of(
             version_constraint
         );
 
-        crate::util::filters::build_package_filter_by_name(name).and(
-            crate::util::filters::build_package_filter_by_version_constraint(version_constraint),
-        )
+        let name_filter = crate::util::filters::build_package_filter_by_name(name);
+        let version_filter =
+            crate::util::filters::build_package_filter_by_version_constraint(version_constraint);
+        move |package: &Package| -> Result<bool> {
+            Ok(name_filter.filter(package) && version_filter.filter(package)?)
+        }
     };
 
+    let excludes = crate::commands::util::get_exclude_regexes(matches)?;
+    let is_excluded = |name: &PackageName| excludes.iter().any(|re| re.is_match(name));
+
     let format = config.package_print_format();
     let hb = crate::ui::handlebars_for_package_printing(format)?;
     let stdout = std::io::stdout();
@@ -93,21 +102,32 @@ pub async fn dependencies_of(
         print_denied_images: false,
         print_phases: false,
         print_script: false,
+        print_field_origins: false,
         script_line_numbers: false,
         script_highlighting: false,
     };
 
     let iter = repo
         .packages()
-        .filter(|package| package_filter.filter(package))
+        .map(|package| package_filter(package).map(|b| (b, package)))
+        .filter_ok(|(b, _)| *b)
+        .map_ok(|tpl| tpl.1)
         .inspect(|pkg| trace!("Found package: {:?}", pkg))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
         .enumerate()
-        .map(|(i, p)| p.prepare_print(config, &flags, &hb, i));
+        .map(|(i, p)| {
+            let mut p = p.clone();
+            let pruned = p.remove_dependencies(is_excluded);
+            (p.prepare_print(config, &flags, &hb, i), pruned)
+        });
 
     tokio_stream::iter(iter)
-        .map(|pp| pp.into_displayable())
-        .try_for_each(|p| {
-            let r = writeln!(&mut outlock, "{p}").map_err(anyhow::Error::from);
+        .map(|(pp, pruned)| pp.into_displayable().map(|p| (p, pruned)))
+        .try_for_each(|(p, pruned)| {
+            let r = writeln!(&mut outlock, "{p}")
+                .and_then(|_| crate::commands::util::write_pruned(&mut outlock, &pruned))
+                .map_err(anyhow::Error::from);
             futures::future::ready(r)
         })
         .await
@@ -22,11 +22,12 @@ use anyhow::Result;
 use clap::ArgMatches;
 use itertools::Itertools;
 use tokio_stream::StreamExt;
-use tracing::{debug, info, trace};
+use tracing::{debug, info, trace, warn};
 
 use crate::config::Configuration;
 use crate::config::EndpointName;
 use crate::endpoint::Endpoint;
+use crate::endpoint::EndpointConfiguration;
 use crate::util::docker::ImageNameLookup;
 use crate::util::progress::ProgressBars;
 
@@ -55,6 +56,7 @@ pub async fn endpoint(
         Some(("stats", matches)) => {
             stats(endpoint_names, matches, config, progress_generator).await
         }
+        Some(("summary", matches)) => summary(endpoint_names, matches, config).await,
         Some(("container", matches)) => {
             crate::commands::endpoint_container::container(endpoint_names, matches, config).await
         }
@@ -73,6 +75,10 @@ async fn ping(
 ) -> Result<()> {
     let n_pings = *matches.get_one::<u64>("ping_n").unwrap(); // safe by clap
     let sleep = *matches.get_one::<u64>("ping_sleep").unwrap(); // safe by clap
+    if matches.get_flag("json") {
+        return ping_json(endpoint_names, config, n_pings, sleep).await;
+    }
+
     let endpoints = connect_to_endpoints(config, &endpoint_names).await?;
     let multibar = Arc::new({
         let mp = indicatif::MultiProgress::new();
@@ -82,7 +88,7 @@ async fn ping(
         mp
     });
 
-    endpoints
+    let results = endpoints
         .iter()
         .map(|endpoint| {
             let bar = progress_generator.bar().inspect(|bar| {
@@ -93,25 +99,199 @@ async fn ping(
 
             async move {
                 let bar = bar?;
+                let mut latencies_ms = Vec::new();
                 for i in 1..(n_pings + 1) {
                     debug!("Pinging {} for the {} time", endpoint.name(), i);
+                    let start = std::time::Instant::now();
                     let r = endpoint.ping().await;
                     bar.inc(1);
-                    if let Err(e) = r {
-                        bar.finish_with_message(format!("Pinging {} failed", endpoint.name()));
-                        return Err(e);
+                    match r {
+                        Ok(_) => latencies_ms.push(start.elapsed().as_secs_f64() * 1000.0),
+                        Err(e) => warn!("Pinging {} failed: {:#}", endpoint.name(), e),
+                    }
+
+                    if i < n_pings {
+                        tokio::time::sleep(tokio::time::Duration::from_secs(sleep)).await;
+                    }
+                }
+
+                if latencies_ms.len() as u64 == n_pings {
+                    bar.finish_with_message(format!("Pinging {} successful", endpoint.name()));
+                } else {
+                    bar.finish_with_message(format!("Pinging {} failed", endpoint.name()));
+                }
+                Ok((endpoint.name().clone(), latencies_ms))
+            }
+        })
+        .collect::<futures::stream::FuturesUnordered<_>>()
+        .collect::<Result<Vec<_>>>()
+        .await?
+        .into_iter()
+        .sorted_by(|a, b| a.0.cmp(&b.0))
+        .collect::<Vec<_>>();
+
+    let hdr = crate::commands::util::mk_header(
+        [
+            "Endpoint", "Sent", "Received", "Success", "Min", "Avg", "Max", "Stddev",
+        ]
+        .to_vec(),
+    );
+    let fmt_ms = |ms: f64| format!("{ms:.1} ms");
+    let data = results
+        .iter()
+        .map(|(ep_name, latencies_ms)| {
+            let success_rate = if n_pings == 0 {
+                100.0
+            } else {
+                latencies_ms.len() as f64 / n_pings as f64 * 100.0
+            };
+            let mut row = vec![
+                ep_name.to_string(),
+                n_pings.to_string(),
+                latencies_ms.len().to_string(),
+                format!("{success_rate:.1}%"),
+            ];
+            match LatencyStats::from_samples(latencies_ms) {
+                Some(stats) => row.extend([
+                    fmt_ms(stats.min),
+                    fmt_ms(stats.avg),
+                    fmt_ms(stats.max),
+                    fmt_ms(stats.stddev),
+                ]),
+                None => row.extend(vec![String::from("-"); 4]),
+            }
+            row
+        })
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data(hdr, data, false)?;
+
+    let failed = results
+        .iter()
+        .filter(|(_, latencies_ms)| latencies_ms.len() as u64 != n_pings)
+        .map(|(ep_name, _)| ep_name)
+        .join(", ");
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Pinging failed for endpoints: {}", failed))
+    }
+}
+
+/// Latency statistics of the successful pings to an endpoint (in milliseconds)
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct LatencyStats {
+    min: f64,
+    avg: f64,
+    max: f64,
+    /// The (population) standard deviation
+    stddev: f64,
+}
+
+impl LatencyStats {
+    /// Calculate the statistics of the `samples`, returns None if there are no samples
+    fn from_samples(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let n = samples.len() as f64;
+        let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let avg = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|s| (s - avg).powi(2)).sum::<f64>() / n;
+        Some(LatencyStats {
+            min,
+            avg,
+            max,
+            stddev: variance.sqrt(),
+        })
+    }
+}
+
+/// The result of "endpoint ping --json" for one endpoint
+#[derive(serde::Serialize)]
+struct PingResult {
+    endpoint: EndpointName,
+    success: bool,
+    /// The round-trip times of the successful pings in milliseconds
+    latencies_ms: Vec<f64>,
+    latency: Option<LatencyStats>,
+    /// The fraction of the pings that failed (0.0 - 1.0)
+    packet_loss: f64,
+    error: Option<String>,
+}
+
+/// Implementation of "endpoint ping --json"
+///
+/// Unlike the human output, this doesn't stop at the first failed ping and includes endpoints
+/// that cannot be reached (with an error) instead of failing.
+async fn ping_json(
+    endpoint_names: Vec<EndpointName>,
+    config: &Configuration,
+    n_pings: u64,
+    sleep: u64,
+) -> Result<()> {
+    let endpoint_configurations = endpoint_configurations(config, &endpoint_names);
+    let results = crate::endpoint::util::try_setup_endpoints(endpoint_configurations)
+        .await
+        .into_iter()
+        .map(|(ep_name, ep)| async move {
+            let ep = match ep {
+                Ok(ep) => ep,
+                Err(e) => {
+                    return PingResult {
+                        endpoint: ep_name,
+                        success: false,
+                        latencies_ms: Vec::new(),
+                        latency: None,
+                        packet_loss: 1.0,
+                        error: Some(format!("{e:#}")),
                     }
+                }
+            };
+
+            let mut latencies_ms = Vec::new();
+            let mut error = None;
+            for i in 1..(n_pings + 1) {
+                debug!("Pinging {} for the {} time", ep_name, i);
+                let start = std::time::Instant::now();
+                match ep.health_check().await {
+                    Ok(()) => latencies_ms.push(start.elapsed().as_secs_f64() * 1000.0),
+                    Err(e) => error = Some(format!("{e:#}")),
+                }
 
+                if i < n_pings {
                     tokio::time::sleep(tokio::time::Duration::from_secs(sleep)).await;
                 }
+            }
 
-                bar.finish_with_message(format!("Pinging {} successful", endpoint.name()));
-                Ok(())
+            let failed = n_pings - latencies_ms.len() as u64;
+            PingResult {
+                endpoint: ep_name,
+                success: failed == 0,
+                latency: LatencyStats::from_samples(&latencies_ms),
+                latencies_ms,
+                packet_loss: if n_pings == 0 {
+                    0.0
+                } else {
+                    failed as f64 / n_pings as f64
+                },
+                error,
             }
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
-        .collect::<Result<()>>()
+        .collect::<Vec<_>>()
         .await
+        .into_iter()
+        .sorted_by(|a, b| a.endpoint.cmp(&b.endpoint))
+        .collect::<Vec<_>>();
+
+    writeln!(
+        std::io::stdout(),
+        "{}",
+        serde_json::to_string_pretty(&results)?
+    )?;
+    Ok(())
 }
 
 async fn stats(
@@ -120,6 +300,10 @@ async fn stats(
     config: &Configuration,
     progress_generator: ProgressBars,
 ) -> Result<()> {
+    if matches.get_flag("json") {
+        return stats_json(endpoint_names, config).await;
+    }
+
     let csv = matches.get_flag("csv");
     let endpoints = connect_to_endpoints(config, &endpoint_names).await?;
     let bar = progress_generator.bar()?;
@@ -179,6 +363,138 @@ async fn stats(
     crate::commands::util::display_data(hdr, data, csv)
 }
 
+/// The result of "endpoint stats --json" for one endpoint
+#[derive(serde::Serialize)]
+struct StatsResult {
+    endpoint: EndpointName,
+    #[serde(flatten)]
+    stats: Option<crate::endpoint::EndpointStats>,
+    error: Option<String>,
+}
+
+/// Implementation of "endpoint stats --json"
+///
+/// Endpoints that cannot be reached are included with an error instead of failing.
+async fn stats_json(endpoint_names: Vec<EndpointName>, config: &Configuration) -> Result<()> {
+    let endpoint_configurations = endpoint_configurations(config, &endpoint_names);
+    let results = crate::endpoint::util::try_setup_endpoints(endpoint_configurations)
+        .await
+        .into_iter()
+        .map(|(ep_name, ep)| async move {
+            let stats = match ep {
+                Ok(ep) => ep.stats().await,
+                Err(e) => Err(e),
+            };
+            match stats {
+                Ok(stats) => StatsResult {
+                    endpoint: ep_name,
+                    stats: Some(stats),
+                    error: None,
+                },
+                Err(e) => StatsResult {
+                    endpoint: ep_name,
+                    stats: None,
+                    error: Some(format!("{e:#}")),
+                },
+            }
+        })
+        .collect::<futures::stream::FuturesUnordered<_>>()
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .sorted_by(|a, b| a.endpoint.cmp(&b.endpoint))
+        .collect::<Vec<_>>();
+
+    writeln!(
+        std::io::stdout(),
+        "{}",
+        serde_json::to_string_pretty(&results)?
+    )?;
+    Ok(())
+}
+
+async fn summary(
+    endpoint_names: Vec<EndpointName>,
+    matches: &ArgMatches,
+    config: &Configuration,
+) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let hdr = crate::commands::util::mk_header(
+        [
+            "Endpoint",
+            "Status",
+            "Running jobs",
+            "Max jobs",
+            "Utilization",
+        ]
+        .to_vec(),
+    );
+
+    let max_jobs = config
+        .docker()
+        .endpoints()
+        .iter()
+        .map(|(ep_name, ep_cfg)| (ep_name.clone(), ep_cfg.maxjobs()))
+        .collect::<HashMap<EndpointName, usize>>();
+    let max_jobs = &max_jobs;
+
+    let endpoint_configurations = endpoint_configurations(config, &endpoint_names);
+    let data = crate::endpoint::util::try_setup_endpoints(endpoint_configurations)
+        .await
+        .into_iter()
+        .map(|(ep_name, ep)| async move {
+            // Unreachable endpoints are listed as down instead of failing the command
+            let running_jobs = match ep {
+                Ok(ep) => {
+                    let (health, stats) = tokio::join!(ep.health_check(), ep.container_stats());
+                    health
+                        .and(stats)
+                        .map(|stats| {
+                            // Each job runs in its own container
+                            let running =
+                                stats.iter().filter(|stat| stat.state == "running").count();
+                            (running, ep.utilization_with(running))
+                        })
+                        .inspect_err(|e| debug!("Endpoint {} is down: {:#}", ep_name, e))
+                        .ok()
+                }
+                Err(e) => {
+                    debug!("Endpoint {} is down: {:#}", ep_name, e);
+                    None
+                }
+            };
+
+            let max_jobs = max_jobs
+                .get(&ep_name)
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            match running_jobs {
+                Some((running, utilization)) => vec![
+                    ep_name.to_string(),
+                    String::from("up"),
+                    running.to_string(),
+                    max_jobs,
+                    format!("{utilization:.1}%"),
+                ],
+                None => vec![
+                    ep_name.to_string(),
+                    String::from("down"),
+                    String::from("-"),
+                    max_jobs,
+                    String::from("-"),
+                ],
+            }
+        })
+        .collect::<futures::stream::FuturesUnordered<_>>()
+        .collect::<Vec<Vec<String>>>()
+        .await
+        .into_iter()
+        .sorted()
+        .collect::<Vec<Vec<String>>>();
+
+    crate::commands::util::display_data(hdr, data, csv)
+}
+
 async fn containers(
     endpoint_names: Vec<EndpointName>,
     matches: &ArgMatches,
@@ -200,6 +516,7 @@ async fn containers_list(
     config: &Configuration,
 ) -> Result<()> {
     let list_stopped = matches.get_flag("list_stopped");
+    let filter_submit = matches.get_one::<uuid::Uuid>("submit").copied();
     let filter_image = if let Some(image) = matches.get_one::<String>("filter_image") {
         let image_name_lookup = ImageNameLookup::create(config.docker().images())?;
         Some(image_name_lookup.expand(image)?.as_ref().to_string())
@@ -208,6 +525,8 @@ async fn containers_list(
     };
     let older_than_filter = crate::commands::util::get_date_filter("older_than", matches)?;
     let newer_than_filter = crate::commands::util::get_date_filter("newer_than", matches)?;
+    let since_filter = crate::commands::util::get_date_filter("since", matches)?;
+    let until_filter = crate::commands::util::get_date_filter("until", matches)?;
     let csv = matches.get_flag("csv");
     let hdr = crate::commands::util::mk_header(
         [
@@ -225,9 +544,11 @@ async fn containers_list(
         .await?
         .into_iter()
         .map(|ep| async move {
-            ep.container_stats()
-                .await
-                .map(|stats| (ep.name().clone(), stats))
+            let stats = match filter_submit {
+                Some(submit_uuid) => ep.container_stats_of_submit(&submit_uuid).await,
+                None => ep.container_stats().await,
+            };
+            stats.map(|stats| (ep.name().clone(), stats))
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
         .collect::<Result<Vec<(_, _)>>>()
@@ -256,6 +577,7 @@ async fn containers_list(
                         .map(|time| time < &stat.created)
                         .unwrap_or(true)
                 })
+                .filter(|stat| stat.created_between(since_filter.as_ref(), until_filter.as_ref()))
                 .map(|stat| {
                     // TODO: The output can become too wide (we should, e.g., try to shorten the IDs):
                     vec![
@@ -279,60 +601,131 @@ async fn containers_prune(
     matches: &ArgMatches,
     config: &Configuration,
 ) -> Result<()> {
+    let filter_image = if let Some(image) = matches.get_one::<String>("filter_image") {
+        let image_name_lookup = ImageNameLookup::create(config.docker().images())?;
+        Some(image_name_lookup.expand(image)?.as_ref().to_string())
+    } else {
+        None
+    };
     let older_than_filter = crate::commands::util::get_date_filter("older_than", matches)?;
     let newer_than_filter = crate::commands::util::get_date_filter("newer_than", matches)?;
+    let since_filter = crate::commands::util::get_date_filter("since", matches)?;
+    let until_filter = crate::commands::util::get_date_filter("until", matches)?;
+    let dry_run = matches.get_flag("dry_run");
 
     let stats = connect_to_endpoints(config, &endpoint_names)
         .await?
         .into_iter()
-        .map(move |ep| async move {
-            let stats = ep
-                .container_stats()
-                .await?
-                .into_iter()
-                .filter(|stat| stat.state == "exited")
-                .filter(|stat| {
-                    older_than_filter
-                        .as_ref()
-                        .map(|time| time > &stat.created)
-                        .unwrap_or(true)
-                })
-                .filter(|stat| {
-                    newer_than_filter
-                        .as_ref()
-                        .map(|time| time < &stat.created)
-                        .unwrap_or(true)
-                })
-                .map(|stat| (ep.clone(), stat))
-                .collect::<Vec<(_, _)>>();
-            Ok(stats)
+        .map(|ep| {
+            let filter_image = filter_image.as_ref();
+            async move {
+                let stats = ep
+                    .container_stats()
+                    .await?
+                    .into_iter()
+                    .filter(|stat| stat.state == "exited")
+                    .filter(|stat| filter_image.map(|fim| *fim == stat.image).unwrap_or(true))
+                    .filter(|stat| {
+                        older_than_filter
+                            .as_ref()
+                            .map(|time| time > &stat.created)
+                            .unwrap_or(true)
+                    })
+                    .filter(|stat| {
+                        newer_than_filter
+                            .as_ref()
+                            .map(|time| time < &stat.created)
+                            .unwrap_or(true)
+                    })
+                    .filter(|stat| {
+                        stat.created_between(since_filter.as_ref(), until_filter.as_ref())
+                    })
+                    .collect::<Vec<_>>();
+                Ok((ep, stats))
+            }
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
         .collect::<Result<Vec<_>>>()
         .await?;
 
+    if dry_run {
+        let hdr = crate::commands::util::mk_header(
+            ["Endpoint", "Container id", "Image", "Created"].to_vec(),
+        );
+        let data = stats
+            .into_iter()
+            .sorted_by(|(ep1, _), (ep2, _)| ep1.name().cmp(ep2.name()))
+            .flat_map(|(ep, stats)| {
+                stats.into_iter().map(move |stat| {
+                    vec![
+                        ep.name().as_ref().to_owned(),
+                        stat.id,
+                        stat.image,
+                        stat.created.to_string(),
+                    ]
+                })
+            })
+            .collect::<Vec<Vec<String>>>();
+        return crate::commands::util::display_data(hdr, data, false);
+    }
+
     let prompt = format!(
         "Really delete {} Containers?",
-        stats.iter().flatten().count()
+        stats.iter().map(|(_, stats)| stats.len()).sum::<usize>()
     );
     if !dialoguer::Confirm::new().with_prompt(prompt).interact()? {
         return Ok(());
     }
 
-    stats
+    let results = stats
         .into_iter()
-        .flat_map(Vec::into_iter)
-        .map(|(ep, stat)| async move {
-            ep.get_container_by_id(&stat.id)
-                .await?
-                .ok_or_else(|| anyhow!("Failed to find existing container {}", stat.id))?
-                .delete()
-                .await
-                .map_err(Error::from)
+        .map(|(ep, stats)| async move {
+            let matched = stats.len();
+            let results = stats
+                .into_iter()
+                .map(|stat| {
+                    let ep = ep.clone();
+                    async move {
+                        ep.get_container_by_id(&stat.id)
+                            .await?
+                            .ok_or_else(|| {
+                                anyhow!("Failed to find existing container {}", stat.id)
+                            })?
+                            .delete()
+                            .await
+                            .map_err(Error::from)
+                    }
+                })
+                .collect::<futures::stream::FuturesUnordered<_>>()
+                .collect::<Vec<Result<()>>>()
+                .await;
+            (ep, matched, results)
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
-        .collect::<Result<()>>()
-        .await
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut errors = vec![];
+    let mut out = std::io::stdout().lock();
+    for (ep, matched, results) in results
+        .into_iter()
+        .sorted_by(|(ep1, _, _), (ep2, _, _)| ep1.name().cmp(ep2.name()))
+    {
+        let (removed, failed): (Vec<_>, Vec<_>) = results.into_iter().partition(Result::is_ok);
+        writeln!(
+            out,
+            "{}: {} containers matched, {} removed",
+            ep.name(),
+            matched,
+            removed.len()
+        )?;
+        errors.extend(failed.into_iter().filter_map(Result::err));
+    }
+
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
 }
 
 async fn containers_top(
@@ -340,12 +733,30 @@ async fn containers_top(
     matches: &ArgMatches,
     config: &Configuration,
 ) -> Result<()> {
-    let limit = matches.get_one::<usize>("limit");
+    if matches.get_flag("raw") {
+        let tops = fetch_container_tops(endpoint_names, matches, config, None).await?;
+        containers_top_raw(tops, matches)
+    } else {
+        // "aux" adds the CPU and memory usage of the processes, which is needed for sorting
+        let tops = fetch_container_tops(endpoint_names, matches, config, Some("aux")).await?;
+        containers_top_aggregated(tops, matches)
+    }
+}
+
+/// Get the `top` output of the running containers on the endpoints
+///
+/// `ps_args` are passed to `ps` in the containers (Docker uses "-ef" if they are `None`).
+async fn fetch_container_tops(
+    endpoint_names: Vec<EndpointName>,
+    matches: &ArgMatches,
+    config: &Configuration,
+    ps_args: Option<&str>,
+) -> Result<Vec<(String, shiplift::rep::Top)>> {
     let older_than_filter = crate::commands::util::get_date_filter("older_than", matches)?;
     let newer_than_filter = crate::commands::util::get_date_filter("newer_than", matches)?;
-    let csv = matches.get_flag("csv");
-
-    let data = connect_to_endpoints(config, &endpoint_names)
+    let since_filter = crate::commands::util::get_date_filter("since", matches)?;
+    let until_filter = crate::commands::util::get_date_filter("until", matches)?;
+    connect_to_endpoints(config, &endpoint_names)
         .await?
         .into_iter()
         .inspect(|ep| trace!("Fetching stats for endpoint: {}", ep.name()))
@@ -368,6 +779,7 @@ async fn containers_top(
                         .map(|time| time < &stat.created)
                         .unwrap_or(true)
                 })
+                .filter(|stat| stat.created_between(since_filter.as_ref(), until_filter.as_ref()))
                 .map(|stat| (ep.clone(), stat))
                 .collect::<Vec<(_, _)>>();
             Ok(stats)
@@ -382,14 +794,22 @@ async fn containers_top(
             ep.get_container_by_id(&stat.id)
                 .await?
                 .ok_or_else(|| anyhow!("Failed to find existing container {}", stat.id))?
-                .top(None)
+                .top(ps_args)
                 .await
                 .with_context(|| anyhow!("Fetching 'top' for {}", stat.id))
                 .map(|top| (stat.id, top))
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
         .collect::<Result<Vec<_>>>()
-        .await?
+        .await
+}
+
+/// Implementation of "endpoint containers top --raw": List the processes of each container
+fn containers_top_raw(tops: Vec<(String, shiplift::rep::Top)>, matches: &ArgMatches) -> Result<()> {
+    let limit = matches.get_one::<usize>("limit");
+    let csv = matches.get_flag("csv");
+
+    let data = tops
         .into_iter()
         .inspect(|(cid, _top)| trace!("Processing top of container: {}", cid))
         .map(|(container_id, top)| {
@@ -442,6 +862,94 @@ async fn containers_top(
     crate::commands::util::display_data(hdr, data, csv)
 }
 
+/// Implementation of "endpoint containers top": List the containers sorted by their resource usage
+fn containers_top_aggregated(
+    tops: Vec<(String, shiplift::rep::Top)>,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let limit = matches.get_one::<usize>("limit");
+    let csv = matches.get_flag("csv");
+    let sort_by_mem = matches.get_one::<String>("sort").map(String::as_str) == Some("mem");
+
+    let mut usages = tops
+        .iter()
+        .map(|(container_id, top)| ContainerUsage::from_top(container_id, top))
+        .collect::<Result<Vec<_>>>()?;
+    if sort_by_mem {
+        usages.sort_by(|u1, u2| u2.mem.total_cmp(&u1.mem));
+    } else {
+        usages.sort_by(|u1, u2| u2.cpu.total_cmp(&u1.cpu));
+    }
+    if let Some(limit) = limit {
+        usages.truncate(*limit);
+    }
+
+    let hdr = crate::commands::util::mk_header(vec!["Container ID", "Processes", "CPU %", "MEM %"]);
+    let data = usages
+        .into_iter()
+        .map(|usage| {
+            vec![
+                usage.container_id.to_string(),
+                usage.processes.to_string(),
+                format!("{:.1}", usage.cpu),
+                format!("{:.1}", usage.mem),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    crate::commands::util::display_data(hdr, data, csv)
+}
+
+/// The resource usage of all processes of a container
+#[derive(Debug, PartialEq)]
+struct ContainerUsage<'a> {
+    container_id: &'a str,
+    processes: usize,
+
+    /// The sum of the CPU usage of the processes in percent (of one CPU)
+    cpu: f64,
+
+    /// The sum of the memory usage of the processes in percent
+    mem: f64,
+}
+
+impl<'a> ContainerUsage<'a> {
+    /// Sum up the usage from the `top` output of a container (with the "aux" ps arguments)
+    fn from_top(container_id: &'a str, top: &shiplift::rep::Top) -> Result<Self> {
+        let column = |title: &str| {
+            top.titles.iter().position(|t| t == title).ok_or_else(|| {
+                anyhow!(
+                    "The top output of container {} has no {} column",
+                    container_id,
+                    title
+                )
+            })
+        };
+        let sum = |column: usize| {
+            top.processes
+                .iter()
+                .map(|process| {
+                    let value = process.get(column).map(String::as_str).unwrap_or_default();
+                    value.parse::<f64>().with_context(|| {
+                        anyhow!(
+                            "Parsing '{}' in the top output of container {}",
+                            value,
+                            container_id
+                        )
+                    })
+                })
+                .sum::<Result<f64>>()
+        };
+
+        Ok(ContainerUsage {
+            container_id,
+            processes: top.processes.len(),
+            cpu: sum(column("%CPU")?)?,
+            mem: sum(column("%MEM")?)?,
+        })
+    }
+}
+
 async fn containers_stop(
     endpoint_names: Vec<EndpointName>,
     matches: &ArgMatches,
@@ -554,13 +1062,35 @@ async fn images_list(
 
 async fn images_present(
     endpoint_names: Vec<EndpointName>,
-    _matches: &ArgMatches,
+    matches: &ArgMatches,
     config: &Configuration,
 ) -> Result<()> {
     use crate::util::docker::ImageName;
 
     let eps = connect_to_endpoints(config, &endpoint_names).await?;
 
+    if matches.get_flag("refresh") {
+        eps.iter()
+            .flat_map(|ep| {
+                config
+                    .docker()
+                    .images()
+                    .iter()
+                    .filter(|img| ep.can_run_image(&img.name))
+                    .map(move |img| (ep, &img.name))
+            })
+            .map(|(ep, img_name)| async move {
+                info!("Pulling {} on {}", img_name, ep.name());
+                // A failed pull is only reported, the image is listed as missing (or stale)
+                if let Err(e) = ep.pull_image(img_name).await {
+                    warn!("{:#}", e);
+                }
+            })
+            .collect::<futures::stream::FuturesUnordered<_>>()
+            .collect::<Vec<()>>()
+            .await;
+    }
+
     let ep_names_to_images = eps
         .iter()
         .map(|ep| async move {
@@ -607,13 +1137,34 @@ pub(super) async fn connect_to_endpoints(
     config: &Configuration,
     endpoint_names: &[EndpointName],
 ) -> Result<Vec<Arc<Endpoint>>> {
-    let endpoint_configurations = config
+    let endpoint_configurations = endpoint_configurations(config, endpoint_names);
+
+    info!("Endpoint config build");
+    info!(
+        "Connecting to {n} endpoints: {eps}",
+        n = endpoint_configurations.len(),
+        eps = endpoint_configurations
+            .iter()
+            .map(|epc| epc.endpoint_name())
+            .join(", ")
+    );
+
+    crate::endpoint::util::setup_endpoints(endpoint_configurations).await
+}
+
+/// Helper function to build the configurations of all endpoints from the configuration, that
+/// appear (by name) in the `endpoint_names` list
+fn endpoint_configurations(
+    config: &Configuration,
+    endpoint_names: &[EndpointName],
+) -> Vec<EndpointConfiguration> {
+    config
         .docker()
         .endpoints()
         .iter()
         .filter(|(ep_name, _)| endpoint_names.contains(ep_name))
         .map(|(ep_name, ep_cfg)| {
-            crate::endpoint::EndpointConfiguration::builder()
+            EndpointConfiguration::builder()
                 .endpoint_name(ep_name.clone())
                 .endpoint(ep_cfg.clone())
                 .required_images(
@@ -628,17 +1179,53 @@ pub(super) async fn connect_to_endpoints(
                 .required_docker_api_versions(config.docker().docker_api_versions().clone())
                 .build()
         })
-        .collect::<Vec<_>>();
+        .collect()
+}
 
-    info!("Endpoint config build");
-    info!(
-        "Connecting to {n} endpoints: {eps}",
-        n = endpoint_configurations.len(),
-        eps = endpoint_configurations
-            .iter()
-            .map(|epc| epc.endpoint_name())
-            .join(", ")
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    crate::endpoint::util::setup_endpoints(endpoint_configurations).await
+    #[test]
+    fn test_latency_stats() {
+        assert_eq!(LatencyStats::from_samples(&[]), None);
+
+        let stats = LatencyStats::from_samples(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]).unwrap();
+        assert_eq!(
+            stats,
+            LatencyStats {
+                min: 2.0,
+                avg: 5.0,
+                max: 9.0,
+                stddev: 2.0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_container_usage_from_top() {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let top = shiplift::rep::Top {
+            titles: strings(&["USER", "PID", "%CPU", "%MEM", "COMMAND"]),
+            processes: vec![
+                strings(&["root", "1", "0.5", "1.0", "/bin/bash"]),
+                strings(&["root", "42", "99.5", "10.5", "make -j4"]),
+            ],
+        };
+        assert_eq!(
+            ContainerUsage::from_top("abc", &top).unwrap(),
+            ContainerUsage {
+                container_id: "abc",
+                processes: 2,
+                cpu: 100.0,
+                mem: 11.5,
+            }
+        );
+
+        let top = shiplift::rep::Top {
+            titles: strings(&["UID", "PID", "CMD"]),
+            processes: vec![strings(&["root", "1", "/bin/bash"])],
+        };
+        assert!(ContainerUsage::from_top("abc", &top).is_err());
+    }
 }
@@ -118,7 +118,13 @@ pub async fn container(
                 Ok(())
             }
         }
-        Some(("inspect", _)) => inspect(container).await,
+        Some(("inspect", matches)) => {
+            if matches.get_flag("json") {
+                inspect_json(container).await
+            } else {
+                inspect(container).await
+            }
+        }
         Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
         None => Err(anyhow!("No subcommand")),
     }
@@ -190,6 +196,23 @@ async fn exec(matches: &ArgMatches, container: Container<'_>) -> Result<()> {
         .await
 }
 
+/// Print the inspect details about the container as JSON
+///
+/// The details are converted to a `serde_json::Value` first, so that the keys of all objects are
+/// sorted and the output is deterministic.
+async fn inspect_json(container: Container<'_>) -> Result<()> {
+    use std::io::Write;
+
+    let d = container.inspect().await?;
+    let value = serde_json::to_value(&d)?;
+    writeln!(
+        std::io::stdout(),
+        "{}",
+        serde_json::to_string_pretty(&value)?
+    )?;
+    Ok(())
+}
+
 // Print inspect details about the container
 //
 //
@@ -10,16 +10,26 @@
 
 //! Implementation of the 'env-of' subcommand
 
+use std::path::Path;
+
 use anyhow::Result;
 use clap::ArgMatches;
 use tracing::trace;
 
+use crate::config::Configuration;
+use crate::package::Package;
 use crate::package::PackageName;
 use crate::package::PackageVersion;
 use crate::repository::Repository;
+use crate::util::EnvironmentVariableName;
 
 /// Implementation of the "env_of" subcommand
-pub async fn env_of(matches: &ArgMatches, repo: Repository) -> Result<()> {
+pub async fn env_of(
+    matches: &ArgMatches,
+    config: &Configuration,
+    repo_path: &Path,
+    repo: Repository,
+) -> Result<()> {
     use filters::filter::Filter;
     use std::io::Write;
 
@@ -45,12 +55,43 @@ pub async fn env_of(matches: &ArgMatches, repo: Repository) -> Result<()> {
         )
     };
 
+    let effective_env = if matches.get_flag("effective") {
+        let additional_env = matches
+            .get_many::<String>("env")
+            .unwrap_or_default()
+            .map(AsRef::as_ref)
+            .map(crate::util::env::parse_to_env)
+            .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?;
+
+        let git_repo = git2::Repository::open(repo_path)?;
+        let (git_author_env, git_commit_env) = crate::orchestrator::git_env(config, &git_repo)?;
+
+        // The same order as in RunnableJob::build_from_job() and RunnableJob::environment()
+        let env = additional_env
+            .into_iter()
+            .map(|(k, v)| (k, v, "commandline"))
+            .chain(git_author_env.map(|(k, v)| (k, v, "git author")))
+            .chain(git_commit_env.map(|(k, v)| (k, v, "git commit hash")))
+            .collect::<Vec<_>>();
+        Some(env)
+    } else {
+        None
+    };
+
     let mut stdout = std::io::stdout();
     repo.packages()
         .filter(|package| package_filter.filter(package))
         .inspect(|pkg| trace!("Found package: {:?}", pkg))
         .try_for_each(|pkg| {
-            if let Some(hm) = pkg.environment() {
+            if let Some(env) = effective_env.as_ref() {
+                let merged = merge_environment(env, pkg);
+                if merged.is_empty() {
+                    writeln!(stdout, "No environment")?;
+                }
+                for (key, value, source) in merged {
+                    writeln!(stdout, "{key} = '{value}' ({source})")?;
+                }
+            } else if let Some(hm) = pkg.environment() {
                 for (key, value) in hm {
                     writeln!(stdout, "{key} = '{value}'")?;
                 }
@@ -61,3 +102,30 @@ pub async fn env_of(matches: &ArgMatches, repo: Repository) -> Result<()> {
             Ok(())
         })
 }
+
+/// Merge the job environment with the environment of the package, the way the variables end up
+/// in the container
+///
+/// Variables that are set more than once are only listed with the value that takes effect, which
+/// is the last one.
+fn merge_environment<'a>(
+    job_env: &'a [(EnvironmentVariableName, String, &'static str)],
+    package: &'a Package,
+) -> Vec<(&'a EnvironmentVariableName, &'a String, &'static str)> {
+    let mut merged: Vec<(&EnvironmentVariableName, &String, &'static str)> = Vec::new();
+    let package_env = package
+        .environment()
+        .iter()
+        .flat_map(|hm| hm.iter())
+        .map(|(k, v)| (k, v, "package"));
+
+    for (key, value, source) in job_env
+        .iter()
+        .map(|(k, v, source)| (k, v, *source))
+        .chain(package_env)
+    {
+        merged.retain(|(k, _, _)| *k != key);
+        merged.push((key, value, source));
+    }
+    merged
+}
@@ -14,6 +14,7 @@ use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
@@ -22,9 +23,10 @@ use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::Pool;
 use diesel::PgConnection;
 use itertools::Itertools;
-use tracing::{debug, trace};
+use tracing::{debug, info, trace, warn};
 
 use crate::config::Configuration;
+use crate::db::ArtifactIndex;
 use crate::filestore::path::StoreRoot;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
@@ -41,9 +43,33 @@ pub async fn find_artifact(
     repo: Repository,
     database_pool: Pool<ConnectionManager<PgConnection>>,
 ) -> Result<()> {
-    let package_name_regex = crate::commands::util::mk_package_name_regex({
-        matches.get_one::<String>("package_name_regex").unwrap() // safe by clap
-    })?;
+    let index = if matches.get_flag("reindex") {
+        let path = config.artifact_index().as_ref().ok_or_else(|| {
+            anyhow!("Cannot rebuild the artifact index, artifact_index is not configured")
+        })?;
+        let index = ArtifactIndex::rebuild(path, &mut *database_pool.get()?)?;
+        info!(
+            "Rebuilt the artifact index with {} artifacts: {}",
+            index.artifact_count(),
+            path.display()
+        );
+        Some(index)
+    } else if matches.get_flag("no_index") {
+        None
+    } else {
+        match config.artifact_index().as_ref() {
+            Some(path) => Some(ArtifactIndex::load_updated(
+                path,
+                &mut *database_pool.get()?,
+            )?),
+            None => None,
+        }
+    };
+
+    let Some(package_name_regex) = matches.get_one::<String>("package_name_regex") else {
+        return Ok(()); // only --reindex was passed
+    };
+    let package_name_regex = crate::commands::util::mk_package_name_regex(package_name_regex)?;
 
     let package_version_constraint = matches
         .get_one::<String>("package_version_constraint")
@@ -67,6 +93,12 @@ pub async fn find_artifact(
         .get_one::<String>("image")
         .map(|s| image_name_lookup.expand(s))
         .transpose()?;
+    let arch = matches.get_one::<String>("arch").map(String::as_str);
+
+    let ignore_env = matches.get_flag("ignore_env");
+    if ignore_env {
+        warn!("Ignoring the environment, the found artifacts might have been built with a different environment");
+    }
 
     debug!(
         "Finding artifacts for '{:?}' '{:?}'",
@@ -117,14 +149,18 @@ pub async fn find_artifact(
 
     repo.packages()
         .filter(|p| package_name_regex.captures(p.name()).is_some())
-        .filter(|p| {
+        .map(|p| {
             package_version_constraint
                 .as_ref()
                 .map(|v| v.matches(p.version()))
-                .unwrap_or(true)
+                .unwrap_or(Ok(true))
+                .map(|b| (b, p))
         })
+        .filter_ok(|(b, _)| *b)
+        .map_ok(|tpl| tpl.1)
         .inspect(|pkg| trace!("Found package: {:?}", pkg))
         .map(|pkg| {
+            let pkg = pkg?;
             let script_filter = !matches.get_flag("no_script_filter");
             let pathes = crate::db::FindArtifacts::builder()
                 .config(config)
@@ -133,8 +169,11 @@ pub async fn find_artifact(
                 .database_pool(database_pool.clone())
                 .env_filter(&env_filter)
                 .script_filter(script_filter)
+                .ignore_env(ignore_env)
                 .image_name(image_name.as_ref())
+                .arch(arch)
                 .package(pkg)
+                .index(index.as_ref())
                 .build()
                 .run()?;
 
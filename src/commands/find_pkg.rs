@@ -10,12 +10,16 @@
 
 //! Implementation of the 'find-pkg' subcommand
 
+use std::path::Path;
+
+use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use clap::ArgMatches;
 use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
-use tracing::trace;
+use itertools::Itertools;
+use tracing::{trace, warn};
 
 use crate::config::Configuration;
 use crate::package::PackageVersionConstraint;
@@ -24,15 +28,21 @@ use crate::ui::*;
 
 /// Implementation of the "find_pkg" subcommand
 pub async fn find_pkg(
+    repo_path: &Path,
     matches: &ArgMatches,
     config: &Configuration,
     repo: Repository,
 ) -> Result<()> {
     use std::io::Write;
 
-    let package_name_regex = crate::commands::util::mk_package_name_regex({
-        matches.get_one::<String>("package_name_regex").unwrap() // safe by clap
-    })?;
+    let package_name_pattern = matches.get_one::<String>("package_name_regex");
+    let package_name_regex = match package_name_pattern {
+        Some(glob) if matches.get_flag("glob") => {
+            crate::commands::util::mk_package_name_glob(glob)?
+        }
+        Some(regex) => crate::commands::util::mk_package_name_regex(regex)?,
+        None => crate::commands::util::mk_package_name_regex(".*")?, // All packages if only --changed-since is passed
+    };
 
     let package_version_constraint = matches
         .get_one::<String>("package_version_constraint")
@@ -41,20 +51,42 @@ pub async fn find_pkg(
         .transpose()
         .context("Parsing package version constraint")?;
 
-    let iter = repo
-        .packages()
+    let changed_since = matches.get_one::<String>("changed_since");
+    let packages = match changed_since {
+        Some(rev) => {
+            let git_repo = git2::Repository::open(repo_path)
+                .with_context(|| anyhow!("Opening repository at {}", repo_path.display()))?;
+            let changed_files = crate::util::git::get_changed_files_since(&git_repo, rev)?;
+            repo.packages_affected_by(&changed_files)?
+        }
+        None => repo.packages().collect(),
+    };
+
+    let found = packages
+        .into_iter()
         .filter(|p| package_name_regex.captures(p.name()).is_some())
-        .filter(|p| {
+        .map(|p| {
             package_version_constraint
                 .as_ref()
                 .map(|v| v.matches(p.version()))
-                .unwrap_or(true)
+                .unwrap_or(Ok(true))
+                .map(|b| (b, p))
         })
-        .inspect(|pkg| trace!("Found package: {:?}", pkg));
+        .filter_ok(|(b, _)| *b)
+        .map_ok(|tpl| tpl.1)
+        .inspect(|pkg| trace!("Found package: {:?}", pkg))
+        .collect::<Result<Vec<_>>>()?;
+
+    if found.is_empty() {
+        if let Some(pattern) = package_name_pattern {
+            warn!("No package matches '{}'", pattern);
+        }
+    }
+    let iter = found.into_iter();
 
     let out = std::io::stdout();
     let mut outlock = out.lock();
-    if matches.get_flag("terse") {
+    if matches.get_flag("terse") || changed_since.is_some() {
         for p in iter {
             writeln!(outlock, "{} {}", p.name(), p.version())?;
         }
@@ -81,6 +113,7 @@ pub async fn find_pkg(
             print_denied_images: matches.get_flag("show_denied_images"),
             print_phases: matches.get_flag("show_phases"),
             print_script: matches.get_flag("show_script"),
+            print_field_origins: matches.get_flag("show_inherited"),
             script_line_numbers: !matches.get_flag("no_script_line_numbers"),
             script_highlighting: !matches.get_flag("no_script_highlight"),
         };
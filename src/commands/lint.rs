@@ -10,15 +10,22 @@
 
 //! Implementation of the 'lint' subcommand
 
+use std::io::Write;
 use std::path::Path;
+use std::path::PathBuf;
 
 use anyhow::anyhow;
 use anyhow::Result;
 use clap::ArgMatches;
+use colored::Colorize;
+use itertools::Itertools;
 
 use crate::config::*;
+use crate::package::Package;
 use crate::package::PackageName;
 use crate::package::PackageVersionConstraint;
+use crate::package::Phase;
+use crate::package::PhaseName;
 use crate::repository::Repository;
 use crate::util::progress::ProgressBars;
 
@@ -30,8 +37,6 @@ pub async fn lint(
     config: &Configuration,
     repo: Repository,
 ) -> Result<()> {
-    let linter = crate::ui::find_linter_command(repo_path, config)?
-        .ok_or_else(|| anyhow!("No linter command found"))?;
     let pname = matches
         .get_one::<String>("package_name")
         .map(|s| s.to_owned())
@@ -42,18 +47,323 @@ pub async fn lint(
         .map(PackageVersionConstraint::try_from)
         .transpose()?;
 
-    let bar = progressbars.bar()?;
-    bar.set_message("Linting package scripts...");
-
-    let iter = repo
+    let packages = repo
         .packages()
         .filter(|p| pname.as_ref().map(|n| p.name() == n).unwrap_or(true))
-        .filter(|p| {
+        .map(|p| {
             pvers
                 .as_ref()
                 .map(|v| v.matches(p.version()))
-                .unwrap_or(true)
-        });
+                .unwrap_or(Ok(true))
+                .map(|b| (b, p))
+        })
+        .filter_ok(|(b, _)| *b)
+        .map_ok(|tpl| tpl.1)
+        .collect::<Result<Vec<_>>>()?;
+    let iter = packages.into_iter();
+
+    if matches.get_flag("builtin") {
+        return builtin_lint(iter, config);
+    }
+
+    let linter = crate::ui::find_linter_command(repo_path, config)?
+        .ok_or_else(|| anyhow!("No linter command found"))?;
+    let bar = progressbars.bar()?;
+    bar.set_message("Linting package scripts...");
 
     crate::commands::util::lint_packages(iter, &linter, config, bar).await
 }
+
+/// The severity of a [Finding] of the built-in lints
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Severity {
+    /// The package can be built, but probably not as intended
+    Warning,
+
+    /// The package cannot be built
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => "warning".yellow().fmt(f),
+            Severity::Error => "error".red().fmt(f),
+        }
+    }
+}
+
+/// A problem of a package that was found by the built-in lints
+#[derive(Debug)]
+struct Finding {
+    severity: Severity,
+
+    /// The `pkg.toml` file that defines the problematic field (if known)
+    file: Option<PathBuf>,
+
+    message: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, file: Option<&PathBuf>, message: String) -> Self {
+        Finding {
+            severity,
+            file: file.cloned(),
+            message,
+        }
+    }
+}
+
+/// Implementation of "lint --builtin"
+///
+/// Runs the built-in lints on the packages and prints the findings, fails if there are findings
+/// with the severity "error".
+fn builtin_lint<'a, I>(packages: I, config: &Configuration) -> Result<()>
+where
+    I: Iterator<Item = &'a Package>,
+{
+    // The package scripts are run with the global shebang or the shebang of the image
+    let shebangs = std::iter::once(config.shebang())
+        .chain(
+            config
+                .docker()
+                .images()
+                .iter()
+                .filter_map(|i| i.shebang.as_ref()),
+        )
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    let mut n_errors = 0;
+    for package in packages {
+        for finding in builtin_lints(package, config.available_phases(), &shebangs) {
+            if finding.severity == Severity::Error {
+                n_errors += 1;
+            }
+            writeln!(
+                outlock,
+                "{}: {} {}{}: {}",
+                finding.severity,
+                package.name(),
+                package.version(),
+                finding
+                    .file
+                    .map(|file| format!(" ({})", file.display()))
+                    .unwrap_or_default(),
+                finding.message
+            )?;
+        }
+    }
+
+    if n_errors > 0 {
+        Err(anyhow!("The built-in lints found {} errors", n_errors))
+    } else {
+        Ok(())
+    }
+}
+
+/// Run the built-in lints on the `package`
+///
+/// * Only `available_phases` can be defined, a warning is emitted for each of them that is not
+///   defined (packages may skip phases intentionally, so this is not an error)
+/// * Scripts must not be empty and must not be loaded from a file (not supported yet)
+/// * Each source should be used by a script (as `/inputs/<name>.source`)
+/// * If a script starts with a shebang, it has to be one of the `shebangs` the scripts are run
+///   with, as the line is only a comment otherwise
+fn builtin_lints(
+    package: &Package,
+    available_phases: &[PhaseName],
+    shebangs: &[&str],
+) -> Vec<Finding> {
+    // The file that defines a field (or any field in the table) `field`
+    let origin = |field: &str| {
+        let table_prefix = format!("{field}.");
+        package
+            .field_origins()
+            .iter()
+            .find(|(name, _)| *name == field || name.starts_with(&table_prefix))
+            .map(|(_, file)| file)
+    };
+    let package_file = origin("version").or_else(|| origin("name"));
+    let phase_file = |name: &PhaseName| origin(&format!("phases.{}", name.as_str()));
+
+    let mut findings = vec![];
+    for name in available_phases {
+        if !package.phases().contains_key(name) {
+            findings.push(Finding::new(
+                Severity::Warning,
+                package_file,
+                format!("Phase '{}' is not defined", name.as_str()),
+            ));
+        }
+    }
+
+    let mut phases = package.phases().iter().collect::<Vec<_>>();
+    phases.sort_by(|(n1, _), (n2, _)| n1.as_str().cmp(n2.as_str()));
+    for (name, phase) in phases.iter() {
+        if !available_phases.contains(name) {
+            findings.push(Finding::new(
+                Severity::Error,
+                phase_file(name),
+                format!("Phase '{}' is not configured", name.as_str()),
+            ));
+        }
+
+        match phase {
+            Phase::Path(path) => findings.push(Finding::new(
+                Severity::Error,
+                phase_file(name),
+                format!(
+                    "Phase '{}' is loaded from the file {}, which is not supported",
+                    name.as_str(),
+                    path.display()
+                ),
+            )),
+            Phase::Text(text) if text.trim().is_empty() => findings.push(Finding::new(
+                Severity::Warning,
+                phase_file(name),
+                format!("Phase '{}' has an empty script", name.as_str()),
+            )),
+            Phase::Text(text) => {
+                let first_line = text.trim_start().lines().next().unwrap_or_default().trim();
+                if first_line.starts_with("#!") && !shebangs.contains(&first_line) {
+                    findings.push(Finding::new(
+                        Severity::Warning,
+                        phase_file(name),
+                        format!(
+                            "Phase '{}' starts with the shebang '{}', but the scripts are run with: {}",
+                            name.as_str(),
+                            first_line,
+                            shebangs.join(", ")
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    let mut sources = package.sources().keys().collect::<Vec<_>>();
+    sources.sort();
+    for source in sources {
+        let file_name = format!("{source}.source");
+        let used = phases.iter().any(|(_, phase)| match phase {
+            Phase::Text(text) => text.contains(&file_name),
+            Phase::Path(_) => false,
+        });
+        if !used {
+            findings.push(Finding::new(
+                Severity::Warning,
+                origin(&format!("sources.{source}")),
+                format!(
+                    "Source '{}' is not used by any script (as /inputs/{})",
+                    source, file_name
+                ),
+            ));
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::package::tests::package;
+
+    fn phase_names(names: &[&str]) -> Vec<PhaseName> {
+        names
+            .iter()
+            .map(|n| PhaseName::from(n.to_string()))
+            .collect()
+    }
+
+    fn package_with_phases(phases: &[(&str, Phase)]) -> Package {
+        let mut p = package("a", "1", "https://rust-lang.org", "123");
+        p.set_phases(
+            phases
+                .iter()
+                .map(|(name, phase)| (PhaseName::from(name.to_string()), phase.clone()))
+                .collect::<HashMap<_, _>>(),
+        );
+        p
+    }
+
+    fn messages(findings: &[Finding], severity: Severity) -> Vec<&str> {
+        findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .map(|f| f.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_package() {
+        let p = package_with_phases(&[
+            (
+                "unpack",
+                Phase::Text("tar xf /inputs/src.source".to_string()),
+            ),
+            ("build", Phase::Text("#!/bin/bash\nmake".to_string())),
+        ]);
+        let findings = builtin_lints(&p, &phase_names(&["unpack", "build"]), &["#!/bin/bash"]);
+        assert!(findings.is_empty(), "{findings:?}");
+    }
+
+    #[test]
+    fn test_phases() {
+        let p = package_with_phases(&[
+            (
+                "unpack",
+                Phase::Text("tar xf /inputs/src.source".to_string()),
+            ),
+            ("build", Phase::Text("  \n ".to_string())),
+            ("extra", Phase::Path(PathBuf::from("extra.sh"))),
+        ]);
+        let findings = builtin_lints(&p, &phase_names(&["unpack", "build", "install"]), &[]);
+        assert_eq!(
+            messages(&findings, Severity::Error),
+            vec![
+                "Phase 'extra' is not configured",
+                "Phase 'extra' is loaded from the file extra.sh, which is not supported",
+            ]
+        );
+        assert_eq!(
+            messages(&findings, Severity::Warning),
+            vec![
+                "Phase 'install' is not defined",
+                "Phase 'build' has an empty script",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unused_source() {
+        let mut p = package_with_phases(&[("build", Phase::Text("make".to_string()))]);
+        p.set_field_origins(BTreeMap::from([
+            ("sources.src.url".to_string(), PathBuf::from("a/pkg.toml")),
+            ("version".to_string(), PathBuf::from("a/1/pkg.toml")),
+        ]));
+        let findings = builtin_lints(&p, &phase_names(&["build"]), &[]);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert_eq!(findings[0].file, Some(PathBuf::from("a/pkg.toml")));
+        assert!(findings[0].message.contains("/inputs/src.source"));
+    }
+
+    #[test]
+    fn test_shebang_mismatch() {
+        let p = package_with_phases(&[(
+            "build",
+            Phase::Text("\n    #!/bin/sh\n    make /inputs/src.source".to_string()),
+        )]);
+        let findings = builtin_lints(&p, &phase_names(&["build"]), &["#!/bin/bash"]);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert!(findings[0].message.contains("'#!/bin/sh'"));
+    }
+}
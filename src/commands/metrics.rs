@@ -12,21 +12,26 @@
 
 use std::io::Write;
 use std::path::Path;
+use std::str::FromStr;
 
 use anyhow::Error;
 use anyhow::Result;
+use clap::ArgMatches;
 use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::Pool;
+use diesel::ExpressionMethods;
 use diesel::PgConnection;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 use walkdir::WalkDir;
 
 use crate::config::Configuration;
+use crate::log::JobResult;
 use crate::repository::Repository;
 
 pub async fn metrics(
     repo_path: &Path,
+    matches: &ArgMatches,
     config: &Configuration,
     repo: Repository,
     pool: Pool<ConnectionManager<PgConnection>>,
@@ -121,6 +126,93 @@ pub async fn metrics(
         n_submits
     )?;
 
+    if matches.get_one::<String>("format").map(String::as_str) == Some("prometheus") {
+        let (mut jobs_success, mut jobs_error, mut jobs_unknown) = (0, 0, 0);
+        let states = crate::schema::jobs::table
+            .select(crate::schema::jobs::success)
+            .load::<Option<bool>>(&mut pool.get().unwrap())?;
+        for state in states {
+            match state {
+                Some(true) => jobs_success += 1,
+                Some(false) => jobs_error += 1,
+                None => {}
+            }
+        }
+
+        // Only the logs of jobs without a stored success state have to be parsed:
+        let logs = crate::schema::jobs::table
+            .filter(crate::schema::jobs::success.is_null())
+            .select(crate::schema::jobs::log_text)
+            .load::<String>(&mut pool.get().unwrap())?;
+        for log in logs {
+            match crate::log::ParsedLog::from_str(&log)?.is_successfull() {
+                JobResult::Success => jobs_success += 1,
+                JobResult::Errored => jobs_error += 1,
+                JobResult::Unknown => jobs_unknown += 1,
+            }
+        }
+
+        let metrics = [
+            (
+                "butido_info",
+                "gauge",
+                "Information about the butido release",
+                vec![(format!("release=\"{}\"", clap::crate_version!()), 1)],
+            ),
+            (
+                "butido_repository_packages",
+                "gauge",
+                "Number of packages in the repository",
+                vec![(String::new(), repo.packages().count() as i64)],
+            ),
+            (
+                "butido_configured_endpoints",
+                "gauge",
+                "Number of configured endpoints",
+                vec![(String::new(), config.docker().endpoints().len() as i64)],
+            ),
+            (
+                "butido_db_packages",
+                "gauge",
+                "Number of packages in the database",
+                vec![(String::new(), n_packages)],
+            ),
+            (
+                "butido_submits_total",
+                "counter",
+                "Number of submits",
+                vec![(String::new(), n_submits)],
+            ),
+            (
+                "butido_jobs_total",
+                "counter",
+                "Number of jobs by their result",
+                vec![
+                    (String::from("result=\"success\""), jobs_success),
+                    (String::from("result=\"error\""), jobs_error),
+                    (String::from("result=\"unknown\""), jobs_unknown),
+                ],
+            ),
+            (
+                "butido_artifacts",
+                "gauge",
+                "Number of artifacts",
+                vec![(String::new(), n_artifacts)],
+            ),
+            (
+                "butido_releases",
+                "gauge",
+                "Number of releases",
+                vec![(String::new(), n_releases)],
+            ),
+        ];
+
+        for (name, metric_type, help, samples) in metrics {
+            write_prometheus_metric(&mut out, name, metric_type, help, &samples)?;
+        }
+        return Ok(());
+    }
+
     write!(
         out,
         "{}",
@@ -171,3 +263,53 @@ pub async fn metrics(
     )
     .map_err(Error::from)
 }
+
+/// Write a metric in the Prometheus text exposition format
+///
+/// Each sample consists of the labels of the sample (e.g. `result="success"`, empty for no labels)
+/// and its value.
+fn write_prometheus_metric(
+    out: &mut impl Write,
+    name: &str,
+    metric_type: &str,
+    help: &str,
+    samples: &[(String, i64)],
+) -> Result<()> {
+    writeln!(out, "# HELP {name} {help}")?;
+    writeln!(out, "# TYPE {name} {metric_type}")?;
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            writeln!(out, "{name} {value}")?;
+        } else {
+            writeln!(out, "{name}{{{labels}}} {value}")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_prometheus_metric() {
+        let mut out = Vec::new();
+        let samples = [
+            (String::from("result=\"success\""), 3),
+            (String::from("result=\"error\""), 1),
+        ];
+        write_prometheus_metric(&mut out, "butido_jobs_total", "counter", "Jobs", &samples)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            indoc::indoc!(
+                r#"
+                # HELP butido_jobs_total Jobs
+                # TYPE butido_jobs_total counter
+                butido_jobs_total{result="success"} 3
+                butido_jobs_total{result="error"} 1
+                "#
+            )
+        );
+    }
+}
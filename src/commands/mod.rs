@@ -11,6 +11,12 @@
 mod build;
 pub use build::build;
 
+mod build_watch;
+pub use build_watch::build_watch;
+
+mod config;
+pub use config::config;
+
 mod db;
 pub use db::db;
 
@@ -42,6 +48,9 @@ pub use release::release;
 mod source;
 pub use source::source;
 
+mod staging;
+pub use staging::staging;
+
 mod versions_of;
 pub use versions_of::versions_of;
 
@@ -10,22 +10,39 @@
 
 //! Implementation of the 'release' subcommand
 
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::io::Write;
+use std::path::Component;
+use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
+use chrono::NaiveDateTime;
 use clap::ArgMatches;
 use diesel::prelude::*;
+use handlebars::Handlebars;
+use itertools::Itertools;
 use resiter::AndThen;
 use tokio_stream::StreamExt;
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
 use crate::config::Configuration;
+use crate::config::ReleaseSigningConfig;
+use crate::config::SIGNING_ARTIFACT_PLACEHOLDER;
+use crate::config::SIGNING_SIGNATURE_PLACEHOLDER;
 use crate::db::models as dbmodels;
 use crate::db::DbConnectionConfig;
+use crate::filestore::path::StoreRoot;
+use crate::filestore::sha256;
+use crate::filestore::ReleaseStore;
+
+/// An artifact that is about to be released, with the name and version of its package and the
+/// name of the image it was built with
+type ArtifactToRelease = (dbmodels::Artifact, String, String, String, Option<String>);
 
 /// Implementation of the "release" subcommand
 pub async fn release(
@@ -40,6 +57,7 @@ pub async fn release(
         }
         Some(("new", matches)) => new_release(db_connection_config, config, matches).await,
         Some(("rm", matches)) => rm_release(db_connection_config, config, matches).await,
+        Some(("verify", matches)) => verify_release(db_connection_config, config, matches).await,
         Some((other, _matches)) => Err(anyhow!("Unknown subcommand: {}", other)),
         None => Err(anyhow!("Missing subcommand")),
     }
@@ -51,13 +69,28 @@ async fn new_release(
     matches: &ArgMatches,
 ) -> Result<()> {
     let print_released_file_pathes = !matches.get_flag("quiet");
-    let release_store_name = matches.get_one::<String>("release_store_name").unwrap(); // safe by clap
+    let release_store_names = if matches.get_flag("release_to_all_stores") {
+        config.release_stores().clone()
+    } else {
+        matches
+            .get_many::<String>("release_store_name")
+            .unwrap() // safe by clap
+            .unique()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
     if !(config.releases_directory().exists() && config.releases_directory().is_dir()) {
         return Err(anyhow!(
             "Release directory does not exist or does not point to directory: {}",
             config.releases_directory().display()
         ));
     }
+    if let Some(unknown) = release_store_names
+        .iter()
+        .find(|name| !config.release_stores().contains(name))
+    {
+        return Err(anyhow!("Unknown release store name: {}", unknown));
+    }
 
     let pname = matches.get_one::<String>("package_name");
 
@@ -76,10 +109,20 @@ async fn new_release(
 
     let arts = {
         let sel = crate::schema::artifacts::dsl::artifacts
-            .inner_join(crate::schema::jobs::table.inner_join(crate::schema::packages::table))
+            .inner_join(
+                crate::schema::jobs::table
+                    .inner_join(crate::schema::packages::table)
+                    .inner_join(crate::schema::images::table),
+            )
             .filter(crate::schema::jobs::submit_id.eq(submit.id))
             .left_outer_join(crate::schema::releases::table) // not released
-            .select(crate::schema::artifacts::all_columns);
+            .select((
+                crate::schema::artifacts::all_columns,
+                crate::schema::packages::name,
+                crate::schema::packages::version,
+                crate::schema::images::name,
+                crate::schema::jobs::arch,
+            ));
 
         match (pname, pvers) {
             (Some(name), Some(vers)) => {
@@ -90,7 +133,7 @@ async fn new_release(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&query)
                 );
-                query.load::<dbmodels::Artifact>(&mut pool.get().unwrap())?
+                query.load::<ArtifactToRelease>(&mut pool.get().unwrap())?
             }
             (Some(name), None) => {
                 let query = sel.filter(crate::schema::packages::name.eq(name));
@@ -98,7 +141,7 @@ async fn new_release(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&query)
                 );
-                query.load::<dbmodels::Artifact>(&mut pool.get().unwrap())?
+                query.load::<ArtifactToRelease>(&mut pool.get().unwrap())?
             }
             (None, Some(vers)) => {
                 let query = sel.filter(crate::schema::packages::version.like(vers));
@@ -106,14 +149,14 @@ async fn new_release(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&query)
                 );
-                query.load::<dbmodels::Artifact>(&mut pool.get().unwrap())?
+                query.load::<ArtifactToRelease>(&mut pool.get().unwrap())?
             }
             (None, None) => {
                 debug!(
                     "Query: {:?}",
                     diesel::debug_query::<diesel::pg::Pg, _>(&sel)
                 );
-                sel.load::<dbmodels::Artifact>(&mut pool.get().unwrap())?
+                sel.load::<ArtifactToRelease>(&mut pool.get().unwrap())?
             }
         }
     };
@@ -123,124 +166,329 @@ async fn new_release(
         return Err(anyhow!("No matching artifacts found to release"));
     }
 
-    arts.iter()
-        .filter_map(|art| {
-            art.path_buf()
-                .parent()
-                .map(|p| config.releases_directory().join(release_store_name).join(p))
-        })
-        .map(|p| async {
-            debug!("mkdir {:?}", p);
-            tokio::fs::create_dir_all(p).await.map_err(Error::from)
+    let now = chrono::offset::Local::now().naive_local();
+    let hb = handlebars_for_release_path(config.release_path_template())?;
+    let arts = arts
+        .into_iter()
+        .map(|(art, name, version, image, arch)| {
+            let release_path =
+                render_release_path(&hb, &art, &name, &version, &image, arch.as_deref(), &now)?;
+            Ok((art, release_path))
         })
-        .collect::<futures::stream::FuturesUnordered<_>>()
-        .collect::<Result<()>>()
-        .await?;
+        .collect::<Result<Vec<_>>>()?;
 
     let staging_base: &PathBuf = &config.staging_directory().join(submit.uuid.to_string());
-
-    let release_store =
-        crate::db::models::ReleaseStore::create(&mut pool.get().unwrap(), release_store_name)?;
     let do_update = matches.get_flag("package_do_update");
     let interactive = !matches.get_flag("noninteractive");
 
-    let now = chrono::offset::Local::now().naive_local();
-    let any_err = arts
-        .into_iter()
-        .map(|art| async {
-            #[allow(clippy::redundant_locals)]
-            let art = art; // ensure it is moved
-            let art_path = staging_base.join(&art.path);
-            let dest_path = config
-                .releases_directory()
-                .join(release_store_name)
-                .join(&art.path);
-            debug!(
-                "Trying to release {} to {}",
-                art_path.display(),
-                dest_path.display()
-            );
+    // Release to one store after the other, so that a failing store does not keep the artifacts
+    // from being released to the other stores
+    let mut results = Vec::with_capacity(release_store_names.len());
+    for release_store_name in release_store_names.iter() {
+        let result: Result<()> = async {
+            arts.iter()
+                .filter_map(|(_, release_path)| {
+                    Path::new(release_path)
+                        .parent()
+                        .map(|p| config.releases_directory().join(release_store_name).join(p))
+                })
+                .map(|p| async {
+                    debug!("mkdir {:?}", p);
+                    tokio::fs::create_dir_all(p).await.map_err(Error::from)
+                })
+                .collect::<futures::stream::FuturesUnordered<_>>()
+                .collect::<Result<()>>()
+                .await?;
 
-            if !art_path.is_file() {
-                trace!(
-                    "Artifact does not exist as file, cannot release it: {:?}",
-                    art
-                );
-                Err(anyhow!("Not a file: {}", art_path.display()))
+            let release_store = crate::db::models::ReleaseStore::create(
+                &mut pool.get().unwrap(),
+                release_store_name,
+            )?;
+
+            // Only files in the same release store are candidates for deduplication, every
+            // release store has to stay usable on its own
+            let dedup_store = if *config.dedup_releases() {
+                let root = StoreRoot::new(config.releases_directory().join(release_store_name))?;
+                Some(ReleaseStore::load(root, &indicatif::ProgressBar::hidden())?)
             } else {
-                if dest_path.exists() && !do_update {
-                    return Err(anyhow!("Does already exist: {}", dest_path.display()));
-                } else if dest_path.exists() && do_update {
-                    writeln!(
-                        std::io::stderr(),
-                        "Going to update: {}",
-                        dest_path.display()
-                    )?;
-                    if interactive
-                        && !dialoguer::Confirm::new()
-                            .with_prompt("Continue?")
-                            .interact()?
-                    {
-                        return Err(anyhow!(
-                            "Does already exist: {} and update was denied",
-                            dest_path.display()
-                        ));
-                    }
-                }
+                None
+            };
 
-                if dest_path.exists() {
+            // The futures below only borrow these
+            let pool = &pool;
+            let release_store = &release_store;
+            let dedup_store = dedup_store.as_ref();
+
+            let any_err = arts
+                .iter()
+                .map(|(art, release_path)| async move {
+                    let art_path = staging_base.join(&art.path);
+                    let dest_path = config
+                        .releases_directory()
+                        .join(release_store_name)
+                        .join(release_path);
                     debug!(
-                        "Removing {} before writing new file to this path",
+                        "Trying to release {} to {}",
+                        art_path.display(),
                         dest_path.display()
                     );
-                    tokio::fs::remove_file(&dest_path).await.with_context(|| {
-                        anyhow!(
-                            "Removing {} before writing new file to this path",
-                            dest_path.display()
-                        )
-                    })?;
-                }
 
-                // else !dest_path.exists()
-                tokio::fs::copy(&art_path, &dest_path)
-                    .await
-                    .with_context(|| {
-                        anyhow!("Copying {} to {}", art_path.display(), dest_path.display())
-                    })
-                    .and_then(|_| {
+                    if !art_path.is_file() {
+                        trace!(
+                            "Artifact does not exist as file, cannot release it: {:?}",
+                            art
+                        );
+                        Err(anyhow!("Not a file: {}", art_path.display()))
+                    } else {
+                        if dest_path.exists() && !do_update {
+                            return Err(anyhow!("Does already exist: {}", dest_path.display()));
+                        } else if dest_path.exists() && do_update {
+                            writeln!(
+                                std::io::stderr(),
+                                "Going to update: {}",
+                                dest_path.display()
+                            )?;
+                            if interactive
+                                && !dialoguer::Confirm::new()
+                                    .with_prompt("Continue?")
+                                    .interact()?
+                            {
+                                return Err(anyhow!(
+                                    "Does already exist: {} and update was denied",
+                                    dest_path.display()
+                                ));
+                            }
+                        }
+
+                        if dest_path.exists() {
+                            debug!(
+                                "Removing {} before writing new file to this path",
+                                dest_path.display()
+                            );
+                            tokio::fs::remove_file(&dest_path).await.with_context(|| {
+                                anyhow!(
+                                    "Removing {} before writing new file to this path",
+                                    dest_path.display()
+                                )
+                            })?;
+                        }
+
+                        // else !dest_path.exists()
+                        copy_to_release_store(&art_path, &dest_path, dedup_store).await?;
+
+                        let signature_path = match config.release_signing() {
+                            Some(signing) => {
+                                let store_root =
+                                    config.releases_directory().join(release_store_name);
+                                Some(sign_release(signing, &store_root, release_path).await?)
+                            }
+                            None => None,
+                        };
+
                         debug!("Updating {:?} to set released = true", art);
                         let rel = crate::db::models::Release::create(
                             &mut pool.get().unwrap(),
-                            &art,
+                            art,
                             &now,
-                            &release_store,
+                            release_store,
+                            Some(release_path.as_str()).filter(|p| *p != art.path),
+                            signature_path.as_deref(),
                         )?;
                         debug!("Release object = {:?}", rel);
                         Ok(dest_path)
-                    })
-            }
-        })
-        .collect::<futures::stream::FuturesUnordered<_>>()
-        .collect::<Vec<Result<_>>>()
-        .await
-        .into_iter()
-        .and_then_ok(|dest_path| {
-            if print_released_file_pathes {
-                writeln!(std::io::stdout(), "{}", dest_path.display()).map_err(Error::from)
+                    }
+                })
+                .collect::<futures::stream::FuturesUnordered<_>>()
+                .collect::<Vec<Result<_>>>()
+                .await
+                .into_iter()
+                .and_then_ok(|dest_path| {
+                    if print_released_file_pathes {
+                        writeln!(std::io::stdout(), "{}", dest_path.display()).map_err(Error::from)
+                    } else {
+                        Ok(())
+                    }
+                })
+                .filter_map(Result::err)
+                .inspect(|err| error!("Error: {}", err.to_string()))
+                .last()
+                .is_some(); // consume iterator completely, if not empty, there was an error
+
+            if any_err {
+                Err(anyhow!("Releasing one or more artifacts failed"))
             } else {
                 Ok(())
             }
-        })
-        .filter_map(Result::err)
-        .inspect(|err| error!("Error: {}", err.to_string()))
-        .last()
-        .is_some(); // consume iterator completely, if not empty, there was an error
+        }
+        .await;
+
+        results.push((release_store_name, result));
+    }
+
+    // Report the result of every release store (stdout is kept for the released file paths)
+    let mut failed_stores = Vec::new();
+    {
+        let mut errlock = std::io::stderr().lock();
+        for (release_store_name, result) in results {
+            match result {
+                Ok(()) => writeln!(
+                    errlock,
+                    "Released {} artifact(s) to release store {}",
+                    arts.len(),
+                    release_store_name
+                )?,
+                Err(e) => {
+                    writeln!(
+                        errlock,
+                        "Releasing to release store {} failed: {:#}",
+                        release_store_name, e
+                    )?;
+                    failed_stores.push(release_store_name.as_str());
+                }
+            }
+        }
+    }
 
-    if any_err {
-        Err(anyhow!("Releasing one or more artifacts failed"))
+    // Keep the artifact index up to date, so that the next search does not have to update it:
+    if let Some(index_path) = config.artifact_index() {
+        let index = pool
+            .get()
+            .map_err(Error::from)
+            .and_then(|mut conn| crate::db::ArtifactIndex::load_updated(index_path, &mut conn));
+        if let Err(e) = index {
+            warn!("Updating the artifact index failed: {:#}", e);
+        }
+    }
+
+    if failed_stores.is_empty() {
+        Ok(())
     } else {
+        Err(anyhow!(
+            "Releasing to {} of {} release stores failed: {}",
+            failed_stores.len(),
+            release_store_names.len(),
+            failed_stores.join(", ")
+        ))
+    }
+}
+
+/// Sign the released file at `release_path` inside the release store at `store_root`
+///
+/// Returns the path of the detached signature inside the release store. If signing fails, the
+/// released file is removed again, so that it is not left in the release store without a database
+/// entry.
+async fn sign_release(
+    signing: &ReleaseSigningConfig,
+    store_root: &Path,
+    release_path: &str,
+) -> Result<String> {
+    let signature_path = format!("{}.{}", release_path, signing.signature_extension());
+    let dest_path = store_root.join(release_path);
+    let dest_signature = store_root.join(&signature_path);
+
+    let result = async {
+        if dest_signature.exists() {
+            debug!("Removing old signature {}", dest_signature.display());
+            tokio::fs::remove_file(&dest_signature).await?;
+        }
+        run_signing_command(signing.sign_command(), &dest_path, &dest_signature).await?;
+        if !dest_signature.is_file() {
+            return Err(anyhow!(
+                "The signing command did not create the signature: {}",
+                dest_signature.display()
+            ));
+        }
         Ok(())
     }
+    .await
+    .with_context(|| anyhow!("Signing {}", dest_path.display()));
+
+    if let Err(e) = result {
+        if let Err(rm_err) = tokio::fs::remove_file(&dest_path).await {
+            warn!(
+                "Removing {} after signing failed: {}",
+                dest_path.display(),
+                rm_err
+            );
+        }
+        return Err(e);
+    }
+    debug!("Signed {}", dest_path.display());
+    Ok(signature_path)
+}
+
+/// Run a command of the release signing configuration for the released file at `artifact` and its
+/// detached signature at `signature`
+async fn run_signing_command(command: &[String], artifact: &Path, signature: &Path) -> Result<()> {
+    let artifact = artifact.to_string_lossy();
+    let signature = signature.to_string_lossy();
+    let args = command
+        .iter()
+        .map(|arg| {
+            arg.replace(SIGNING_ARTIFACT_PLACEHOLDER, &artifact)
+                .replace(SIGNING_SIGNATURE_PLACEHOLDER, &signature)
+        })
+        .collect::<Vec<_>>();
+    let (program, args) = args
+        .split_first()
+        .ok_or_else(|| anyhow!("The signing command is empty"))?;
+
+    trace!("Running {} {:?}", program, args);
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .with_context(|| anyhow!("Running {}", program))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} failed ({}): {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Copy the artifact at `art_path` to `dest_path` in a release store
+///
+/// If the release store is passed as `dedup_store`, `dest_path` is hardlinked to a file with
+/// identical content in the store instead, if there is one.
+async fn copy_to_release_store(
+    art_path: &Path,
+    dest_path: &Path,
+    dedup_store: Option<&ReleaseStore>,
+) -> Result<()> {
+    let identical = match dedup_store {
+        Some(store) => store.find_identical(art_path, dest_path).await?,
+        None => None,
+    };
+    if let Some(identical) = identical {
+        match tokio::fs::hard_link(&identical, dest_path).await {
+            Ok(()) => {
+                debug!(
+                    "Linked {} to identical file {}",
+                    dest_path.display(),
+                    identical.display()
+                );
+                return Ok(());
+            }
+            Err(e) => warn!(
+                "Linking {} to identical file {} failed, copying instead: {}",
+                dest_path.display(),
+                identical.display(),
+                e
+            ),
+        }
+    }
+
+    tokio::fs::copy(art_path, dest_path)
+        .await
+        .with_context(|| anyhow!("Copying {} to {}", art_path.display(), dest_path.display()))?;
+    Ok(())
 }
 
 pub async fn rm_release(
@@ -262,12 +510,15 @@ pub async fn rm_release(
         ));
     }
 
+    let mut conn = db_connection_config.establish_connection()?;
+    if let Some(older_than) = crate::commands::util::get_date_filter("older_than", matches)? {
+        return rm_releases_older_than(&mut conn, config, matches, release_store_name, older_than);
+    }
+
     let pname = matches.get_one::<String>("package_name").unwrap(); // safe by clap
     let pvers = matches.get_one::<String>("package_version").unwrap(); // safe by clap
     debug!("Remove Release called for: {:?} {:?}", pname, pvers);
 
-    let mut conn = db_connection_config.establish_connection()?;
-
     let (release, artifact) =
         crate::schema::jobs::table
             .inner_join(crate::schema::packages::table)
@@ -295,7 +546,7 @@ pub async fn rm_release(
     let artifact_path = config
         .releases_directory()
         .join(release_store_name)
-        .join(&artifact.path);
+        .join(release.released_path(&artifact));
     if !artifact_path.is_file() {
         return Err(anyhow!("Not a file: {}", artifact_path.display()));
     }
@@ -311,18 +562,459 @@ pub async fn rm_release(
         release.id,
         release.release_date
     )?;
-    if !dialoguer::Confirm::new()
-        .with_prompt("Continue?")
-        .interact()?
+    if matches.get_flag("dry_run") {
+        return Ok(());
+    }
+    if !matches.get_flag("noninteractive")
+        && !dialoguer::Confirm::new()
+            .with_prompt("Continue?")
+            .interact()?
     {
         return Ok(());
     }
 
     tokio::fs::remove_file(&artifact_path).await?;
     info!("File removed");
+    if let Some(signature_path) = release.signature_path.as_ref() {
+        let signature_path = config
+            .releases_directory()
+            .join(release_store_name)
+            .join(signature_path);
+        if signature_path.exists() {
+            tokio::fs::remove_file(&signature_path).await?;
+            info!("Signature removed");
+        }
+    }
 
     diesel::delete(&release).execute(&mut conn)?;
     info!("Release deleted from database");
 
     Ok(())
 }
+
+/// Implementation of "release rm --older-than": Remove all releases from the release store that
+/// are older than `older_than`
+///
+/// The database entries are deleted in a single transaction. The files are only removed after the
+/// transaction was committed, and only if no other release in the release store references them
+/// anymore. Files that don't exist (anymore) are skipped.
+fn rm_releases_older_than(
+    conn: &mut PgConnection,
+    config: &Configuration,
+    matches: &ArgMatches,
+    release_store_name: &str,
+    older_than: chrono::DateTime<chrono::Local>,
+) -> Result<()> {
+    debug!(
+        "Remove releases from {} older than {}",
+        release_store_name, older_than
+    );
+    let releases = dbmodels::Release::older_than(conn, release_store_name, &older_than)?;
+    if releases.is_empty() {
+        info!(
+            "No releases in {} older than {}",
+            release_store_name, older_than
+        );
+        return Ok(());
+    }
+
+    let store_path = config.releases_directory().join(release_store_name);
+    let hdr = crate::commands::util::mk_header(["Package", "Version", "Date", "Path"].to_vec());
+    let data = releases
+        .iter()
+        .map(|(release, artifact, name, version)| {
+            vec![
+                name.clone(),
+                version.clone(),
+                release.release_date.to_string(),
+                store_path
+                    .join(release.released_path(artifact))
+                    .display()
+                    .to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data(hdr, data, false)?;
+
+    if matches.get_flag("dry_run") {
+        return Ok(());
+    }
+    let prompt = format!(
+        "Really remove {} releases from {}?",
+        releases.len(),
+        release_store_name
+    );
+    if !matches.get_flag("noninteractive")
+        && !dialoguer::Confirm::new().with_prompt(prompt).interact()?
+    {
+        return Ok(());
+    }
+
+    // A file can be referenced by more than one release (e.g., if it was released again to the
+    // same path), it is only removed if none of the remaining releases references it
+    let (files, kept_files) = conn.transaction::<_, Error, _>(|conn| {
+        for (release, _, _, _) in releases.iter() {
+            diesel::delete(release).execute(conn)?;
+        }
+
+        let mut files: Vec<(&str, Option<&str>)> = Vec::with_capacity(releases.len());
+        let mut kept_files = 0;
+        for (release, artifact, _, _) in releases.iter() {
+            let released_path = release.released_path(artifact);
+            if files.iter().any(|(path, _)| *path == released_path) {
+                continue;
+            }
+
+            if dbmodels::Release::is_released(conn, release_store_name, released_path)? {
+                info!("Keeping {}, it is still released", released_path);
+                kept_files += 1;
+                continue;
+            }
+            files.push((released_path, release.signature_path.as_deref()));
+        }
+        Ok((files, kept_files))
+    })?;
+
+    let mut missing_files = 0;
+    for (released_path, signature_path) in files {
+        let path = store_path.join(released_path);
+        match std::fs::remove_file(&path) {
+            Ok(()) => trace!("Removed {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!("Released file does not exist: {}", path.display());
+                missing_files += 1;
+            }
+            Err(e) => {
+                return Err(Error::from(e))
+                    .with_context(|| anyhow!("Removing file: {}", path.display()))
+            }
+        }
+
+        if let Some(signature_path) = signature_path {
+            let path = store_path.join(signature_path);
+            match std::fs::remove_file(&path) {
+                Ok(()) => trace!("Removed {}", path.display()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    warn!("Signature does not exist: {}", path.display());
+                }
+                Err(e) => {
+                    return Err(Error::from(e))
+                        .with_context(|| anyhow!("Removing file: {}", path.display()))
+                }
+            }
+        }
+    }
+
+    writeln!(
+        std::io::stdout(),
+        "Removed {} releases from {} ({} files did not exist, {} files are still released)",
+        releases.len(),
+        release_store_name,
+        missing_files,
+        kept_files
+    )?;
+    Ok(())
+}
+
+/// Implementation of the "release verify" subcommand
+async fn verify_release(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let check_content = matches.get_flag("check_content");
+    let verify_command = if matches.get_flag("check_signatures") {
+        let command = config
+            .release_signing()
+            .as_ref()
+            .and_then(|signing| signing.verify_command().as_ref())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Cannot check the signatures, release_signing.verify_command is not configured"
+                )
+            })?;
+        Some(command)
+    } else {
+        None
+    };
+    let release_store_names = match matches.get_one::<String>("release_store_name") {
+        Some(name) if !config.release_stores().contains(name) => {
+            return Err(anyhow!("Unknown release store name: {}", name));
+        }
+        Some(name) => vec![name.clone()],
+        None => config.release_stores().clone(),
+    };
+
+    let mut conn = db_connection_config.establish_connection()?;
+    let releases = crate::schema::releases::table
+        .inner_join(
+            crate::schema::artifacts::table.inner_join(
+                crate::schema::jobs::table
+                    .inner_join(crate::schema::packages::table)
+                    .inner_join(crate::schema::submits::table),
+            ),
+        )
+        .inner_join(crate::schema::release_stores::table)
+        .filter(crate::schema::release_stores::store_name.eq_any(&release_store_names))
+        .order(crate::schema::releases::id)
+        .select((
+            crate::schema::releases::all_columns,
+            crate::schema::artifacts::all_columns,
+            crate::schema::release_stores::store_name,
+            crate::schema::packages::name,
+            crate::schema::packages::version,
+            crate::schema::submits::uuid,
+        ))
+        .load::<(
+            dbmodels::Release,
+            dbmodels::Artifact,
+            String,
+            String,
+            String,
+            uuid::Uuid,
+        )>(&mut conn)?;
+
+    // Paths of the released files, relative to the releases directory
+    let mut released_files = HashSet::with_capacity(releases.len());
+    let mut problems = Vec::new();
+    for (release, artifact, store_name, package_name, package_version, submit_uuid) in
+        releases.iter()
+    {
+        let release_path = release.released_path(artifact);
+        let path = config
+            .releases_directory()
+            .join(store_name)
+            .join(release_path);
+        trace!("Verifying release {} at {}", release.id, path.display());
+
+        let problem = if !path.is_file() {
+            Some("Released file is missing")
+        } else if check_content {
+            let staging_path = config
+                .staging_directory()
+                .join(submit_uuid.to_string())
+                .join(&artifact.path);
+
+            if !staging_path.is_file() {
+                warn!(
+                    "Cannot check the content of {}, the artifact is not available in the staging directory anymore",
+                    path.display()
+                );
+                None
+            } else if sha256(&path).await? != sha256(&staging_path).await? {
+                Some("Content differs from the staged artifact")
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        if let Some(problem) = problem {
+            problems.push(vec![
+                store_name.clone(),
+                release_path.to_string(),
+                package_name.clone(),
+                package_version.clone(),
+                problem.to_string(),
+            ]);
+        }
+        released_files.insert(PathBuf::from(store_name).join(release_path));
+
+        if let Some(signature_path) = release.signature_path.as_ref() {
+            let signature = config
+                .releases_directory()
+                .join(store_name)
+                .join(signature_path);
+            let problem = if !signature.is_file() {
+                Some("Signature is missing")
+            } else if let Some(verify_command) = verify_command.filter(|_| path.is_file()) {
+                match run_signing_command(verify_command, &path, &signature).await {
+                    Ok(()) => None,
+                    Err(e) => {
+                        debug!("Verifying {} failed: {:#}", signature.display(), e);
+                        Some("Signature verification failed")
+                    }
+                }
+            } else {
+                None
+            };
+
+            if let Some(problem) = problem {
+                problems.push(vec![
+                    store_name.clone(),
+                    signature_path.clone(),
+                    package_name.clone(),
+                    package_version.clone(),
+                    problem.to_string(),
+                ]);
+            }
+            released_files.insert(PathBuf::from(store_name).join(signature_path));
+        }
+    }
+
+    for store_name in release_store_names.iter() {
+        let store_root = config.releases_directory().join(store_name);
+        if !store_root.is_dir() {
+            warn!(
+                "Release store directory does not exist: {}",
+                store_root.display()
+            );
+            continue;
+        }
+
+        for entry in walkdir::WalkDir::new(&store_root)
+            .follow_links(false)
+            .sort_by_file_name()
+        {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let path = entry.path().strip_prefix(config.releases_directory())?;
+            if !released_files.contains(path) {
+                problems.push(vec![
+                    store_name.clone(),
+                    entry
+                        .path()
+                        .strip_prefix(&store_root)?
+                        .display()
+                        .to_string(),
+                    String::new(),
+                    String::new(),
+                    String::from("File is not released in the database"),
+                ]);
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        writeln!(
+            std::io::stderr(),
+            "Verified {} releases, no problems found",
+            releases.len()
+        )?;
+        return Ok(());
+    }
+
+    let problem_count = problems.len();
+    let header = crate::commands::util::mk_header(
+        ["Store", "Path", "Package", "Version", "Problem"].to_vec(),
+    );
+    crate::commands::util::display_data(header, problems, csv)?;
+    Err(anyhow!(
+        "Found {} problems in the release stores",
+        problem_count
+    ))
+}
+
+fn handlebars_for_release_path(template: &str) -> Result<Handlebars<'_>> {
+    let mut hb = Handlebars::new();
+    hb.register_escape_fn(handlebars::no_escape);
+    hb.set_strict_mode(true);
+    hb.register_template_string("release_path", template)
+        .context("Parsing release_path_template")?;
+    Ok(hb)
+}
+
+/// Render the path of a released artifact inside the release store
+fn render_release_path(
+    hb: &Handlebars,
+    art: &dbmodels::Artifact,
+    name: &str,
+    version: &str,
+    image: &str,
+    arch: Option<&str>,
+    date: &NaiveDateTime,
+) -> Result<String> {
+    let art_path = art.path_buf();
+    let filename = art_path
+        .file_name()
+        .and_then(|f| f.to_str())
+        .ok_or_else(|| anyhow!("Artifact path has no file name: {}", art.path))?;
+    let date = date.format("%Y-%m-%d").to_string();
+
+    let mut data = BTreeMap::new();
+    data.insert("name", name);
+    data.insert("version", version);
+    data.insert("image", image);
+    data.insert("arch", arch.unwrap_or_default());
+    data.insert("date", date.as_str());
+    data.insert("path", art.path.as_str());
+    data.insert("filename", filename);
+
+    let release_path = hb
+        .render("release_path", &data)
+        .with_context(|| anyhow!("Rendering release path for {}", art.path))?;
+
+    // The rendered path must stay inside the release store
+    let is_inside_store = !release_path.is_empty()
+        && Path::new(&release_path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if !is_inside_store {
+        return Err(anyhow!(
+            "Release path for {} is not a relative path inside the release store: '{}'",
+            art.path,
+            release_path
+        ));
+    }
+
+    Ok(release_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artifact(path: &str) -> dbmodels::Artifact {
+        dbmodels::Artifact {
+            id: 1,
+            path: String::from(path),
+            job_id: 1,
+        }
+    }
+
+    fn date() -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2021, 3, 4)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_default_template_keeps_artifact_path() {
+        let hb = handlebars_for_release_path("{{#if arch}}{{arch}}/{{/if}}{{path}}").unwrap();
+        let art = artifact("foo-1.0.tar.gz");
+        let path = render_release_path(&hb, &art, "foo", "1.0", "debian:bullseye", None, &date());
+        assert_eq!(path.unwrap(), "foo-1.0.tar.gz");
+
+        let path = render_release_path(&hb, &art, "foo", "1.0", "img", Some("arm64"), &date());
+        assert_eq!(path.unwrap(), "arm64/foo-1.0.tar.gz");
+    }
+
+    #[test]
+    fn test_template_variables() {
+        let hb = handlebars_for_release_path("{{date}}/{{name}}-{{version}}-x86_64.pkg").unwrap();
+        let art = artifact("foo-1.0.tar.gz");
+        let path = render_release_path(&hb, &art, "foo", "1.0", "debian:bullseye", None, &date());
+        assert_eq!(path.unwrap(), "2021-03-04/foo-1.0-x86_64.pkg");
+
+        let hb = handlebars_for_release_path("{{arch}}/{{filename}}").unwrap();
+        let path = render_release_path(&hb, &art, "foo", "1.0", "img", Some("arm64"), &date());
+        assert_eq!(path.unwrap(), "arm64/foo-1.0.tar.gz");
+    }
+
+    #[test]
+    fn test_template_must_not_leave_release_store() {
+        let art = artifact("foo-1.0.tar.gz");
+        for template in ["/{{filename}}", "../{{filename}}", ""] {
+            let hb = handlebars_for_release_path(template).unwrap();
+            let path =
+                render_release_path(&hb, &art, "foo", "1.0", "debian:bullseye", None, &date());
+            assert!(path.is_err(), "Template should be rejected: {template}");
+        }
+    }
+}
@@ -9,6 +9,7 @@
 //
 
 use std::concat;
+use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -23,6 +24,7 @@ use tokio_stream::StreamExt;
 use tracing::{info, trace, warn};
 
 use crate::config::*;
+use crate::package::HashCheck;
 use crate::package::PackageName;
 use crate::package::PackageVersionConstraint;
 use crate::repository::Repository;
@@ -114,15 +116,43 @@ impl ProgressWrapper {
     }
 }
 
-async fn perform_download(
+/// Get the additional HTTP headers for downloading the `source`
+///
+/// The headers of the source override the headers with the same name from the configuration. The
+/// values are marked as sensitive and never included in errors, as they might contain secrets.
+fn request_headers(
+    config: &Configuration,
     source: &SourceEntry,
-    progress: Arc<Mutex<ProgressWrapper>>,
-    timeout: Option<u64>,
-) -> Result<()> {
-    trace!("Downloading: {:?}", source);
+) -> Result<reqwest::header::HeaderMap> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    for (name, value) in config
+        .source_download_headers()
+        .iter()
+        .chain(source.headers().iter())
+    {
+        let header_name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| anyhow!("Invalid HTTP header name: {}", name))?;
+        let mut header_value = reqwest::header::HeaderValue::from_str(value)
+            .map_err(|_| anyhow!("Invalid value for the HTTP header {}", name))?;
+        header_value.set_sensitive(true);
+        headers.insert(header_name, header_value);
+    }
+    Ok(headers)
+}
 
+/// Build the HTTP client for requests for the `source`
+fn http_client(
+    source: &SourceEntry,
+    timeout: Option<u64>,
+    config: &Configuration,
+) -> Result<reqwest::Client> {
+    let user_agent = source
+        .user_agent()
+        .or(config.source_download_user_agent().as_deref())
+        .unwrap_or(APP_USER_AGENT);
     let client_builder = reqwest::Client::builder()
-        .user_agent(APP_USER_AGENT)
+        .user_agent(user_agent)
+        .default_headers(request_headers(config, source)?)
         .redirect(reqwest::redirect::Policy::limited(10));
 
     let client_builder = if let Some(to) = timeout {
@@ -131,10 +161,24 @@ async fn perform_download(
         client_builder
     };
 
-    let client = client_builder
+    client_builder
         .build()
-        .context("Building HTTP client failed")?;
+        .context("Building HTTP client failed")
+}
+
+async fn perform_download(
+    source: &SourceEntry,
+    progress: Arc<Mutex<ProgressWrapper>>,
+    timeout: Option<u64>,
+    config: &Configuration,
+) -> Result<()> {
+    trace!("Downloading: {:?}", source);
+
+    if source.url().scheme() == "file" {
+        return copy_local_source(source, progress, *config.max_source_size()).await;
+    }
 
+    let client = http_client(source, timeout, config)?;
     let request = client
         .get(source.url().as_ref())
         .build()
@@ -154,6 +198,11 @@ async fn perform_download(
         .with_context(|| anyhow!("Downloading \"{}\" failed", &source.url()));
     }
 
+    let max_size = *config.max_source_size();
+    if let Some(content_length) = response.content_length() {
+        check_source_size(source, content_length, max_size)?;
+    }
+
     progress
         .lock()
         .await
@@ -190,8 +239,17 @@ async fn perform_download(
     let mut file = tokio::io::BufWriter::new(file);
 
     let mut stream = response.bytes_stream();
+    let mut downloaded_bytes: u64 = 0;
     while let Some(bytes) = stream.next().await {
         let bytes = bytes?;
+        downloaded_bytes += bytes.len() as u64;
+        // The server might not send a Content-Length (or a wrong one)
+        if let Err(e) = check_source_size(source, downloaded_bytes, max_size) {
+            drop(file);
+            source.remove_file().await?;
+            return Err(e);
+        }
+
         tokio::try_join!(file.write_all(bytes.as_ref()), async {
             progress.lock().await.add_bytes(bytes.len()).await;
             Ok(())
@@ -201,6 +259,313 @@ async fn perform_download(
     file.flush().await.map_err(Error::from).map(|_| ())
 }
 
+/// Fail if the `size` of the `source` exceeds the maximum source size (`max_source_size`)
+fn check_source_size(source: &SourceEntry, size: u64, max_size: Option<u64>) -> Result<()> {
+    match max_size {
+        Some(max_size) if size > max_size => Err(anyhow!(
+            "The source {} exceeds the maximum source size (max_source_size) of {}",
+            source.url(),
+            bytesize::ByteSize::b(max_size)
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// "Download" a source with a `file://` URL by copying the local file into the source cache
+///
+/// The copied file is verified like a downloaded file.
+async fn copy_local_source(
+    source: &SourceEntry,
+    progress: Arc<Mutex<ProgressWrapper>>,
+    max_size: Option<u64>,
+) -> Result<()> {
+    let path = source
+        .url()
+        .to_file_path()
+        .map_err(|_| anyhow!("Not a valid local file path: {}", source.url()))?;
+    let metadata = tokio::fs::metadata(&path)
+        .await
+        .with_context(|| anyhow!("The local source does not exist: {}", path.display()))?;
+    if !metadata.is_file() {
+        return Err(anyhow!(
+            "The local source is not a file: {}",
+            path.display()
+        ));
+    }
+    check_source_size(source, metadata.len(), max_size)?;
+
+    progress
+        .lock()
+        .await
+        .inc_download_bytes(metadata.len())
+        .await;
+
+    let mut reader = tokio::fs::File::open(&path)
+        .await
+        .with_context(|| anyhow!("Opening local source: {}", path.display()))?;
+    let file = source.create().await.with_context(|| {
+        anyhow!(
+            "Creating source file destination: {}",
+            source.path().display()
+        )
+    })?;
+    let mut file = tokio::io::BufWriter::new(file);
+    let bytes = tokio::io::copy(&mut reader, &mut file)
+        .await
+        .with_context(|| anyhow!("Copying local source: {}", path.display()))?;
+    progress.lock().await.add_bytes(bytes as usize).await;
+
+    file.flush().await.map_err(Error::from).map(|_| ())
+}
+
+/// Get the size of the `source` without downloading it
+///
+/// Returns `None` if the server does not report the size (with a HEAD request).
+async fn remote_size(
+    source: &SourceEntry,
+    timeout: Option<u64>,
+    config: &Configuration,
+) -> Result<Option<u64>> {
+    if source.url().scheme() == "file" {
+        let path = source
+            .url()
+            .to_file_path()
+            .map_err(|_| anyhow!("Not a valid local file path: {}", source.url()))?;
+        let metadata = tokio::fs::metadata(&path)
+            .await
+            .with_context(|| anyhow!("The local source does not exist: {}", path.display()))?;
+        return Ok(Some(metadata.len()));
+    }
+
+    let response = http_client(source, timeout, config)?
+        .head(source.url().as_ref())
+        .send()
+        .await
+        .with_context(|| anyhow!("Requesting the size of '{}'", source.url()))?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Received HTTP status code \"{}\" when requesting the size of '{}'",
+            response.status(),
+            source.url()
+        ));
+    }
+
+    // Not Response::content_length(), which is the length of the (empty) body of the response
+    Ok(response
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok()))
+}
+
+/// Implementation of "source download --check-only"
+///
+/// Prints the sources that would be downloaded and their sizes, without downloading anything.
+async fn check_only(
+    sources: Vec<&SourceEntry>,
+    force: bool,
+    timeout: Option<u64>,
+    config: &Configuration,
+) -> Result<()> {
+    let missing = sources
+        .into_iter()
+        .filter(|source| force || !source.path().exists())
+        .collect::<Vec<_>>();
+
+    let request_sema = Arc::new(tokio::sync::Semaphore::new(
+        NUMBER_OF_MAX_CONCURRENT_DOWNLOADS,
+    ));
+    let sizes = futures::future::join_all(missing.iter().map(|source| {
+        let request_sema = request_sema.clone();
+        async move {
+            if source.download_manually() {
+                return Ok(None);
+            }
+            let _permit = request_sema.acquire_owned().await?;
+            remote_size(source, timeout, config).await
+        }
+    }))
+    .await;
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    let mut total_size = 0;
+    let mut unknown_sizes = 0;
+    for (source, size) in missing.iter().zip(sizes) {
+        let size = match size {
+            Ok(Some(size)) => {
+                total_size += size;
+                bytesize::ByteSize::b(size).to_string()
+            }
+            Ok(None) if source.download_manually() => {
+                unknown_sizes += 1;
+                String::from("manual download")
+            }
+            Ok(None) => {
+                unknown_sizes += 1;
+                String::from("unknown size")
+            }
+            Err(e) => {
+                warn!("{:#}", e);
+                unknown_sizes += 1;
+                String::from("unknown size")
+            }
+        };
+        writeln!(
+            outlock,
+            "{} -> {} ({})",
+            source.url(),
+            source.path().display(),
+            size
+        )?;
+    }
+
+    if missing.is_empty() {
+        writeln!(outlock, "All sources are present")?;
+    } else {
+        writeln!(
+            outlock,
+            "{} sources would be downloaded, {} in total{}",
+            missing.len(),
+            bytesize::ByteSize::b(total_size),
+            if unknown_sizes > 0 {
+                format!(" (without {unknown_sizes} sources of unknown size)")
+            } else {
+                String::new()
+            }
+        )?;
+    }
+    Ok(())
+}
+
+/// Get the instructions for downloading a source that is marked for manual download
+pub(super) fn manual_download_instructions(source: &SourceEntry) -> String {
+    indoc::formatdoc!(
+        r#"
+            The source has to be downloaded manually:
+
+                URL:         {url}
+                Destination: {path}
+                Hash:        {hash_type} {hash_value}
+        "#,
+        url = source.url(),
+        path = source.path().display(),
+        hash_type = source.hash().hashtype(),
+        hash_value = source.hash().value(),
+    )
+}
+
+/// Handle a missing source that is marked for manual download
+///
+/// The instructions for the download are printed. If `interactive` is set, this waits until the
+/// user placed the file and verifies it afterwards, otherwise it fails.
+/// With `force`, an existing file is removed first (only if `interactive` is set, as it could not
+/// be replaced otherwise).
+async fn manual_download(source: &SourceEntry, interactive: bool, force: bool) -> Result<()> {
+    let out = std::io::stdout();
+    writeln!(out.lock(), "{}", manual_download_instructions(source))?;
+
+    if !interactive {
+        return Err(anyhow!(
+            "Cannot download source that is marked for manual download: {}",
+            source.path().display()
+        ));
+    }
+
+    if force && source.path().exists() {
+        source.remove_file().await?;
+    }
+
+    if let Some(dir) = source.path().parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .with_context(|| anyhow!("Creating directory {}", dir.display()))?;
+    }
+
+    loop {
+        let placed = dialoguer::Confirm::new()
+            .with_prompt("Continue after the file was placed at the destination?")
+            .default(true)
+            .interact()?;
+
+        if !placed {
+            return Err(anyhow!(
+                "Manual download aborted: {}",
+                source.path().display()
+            ));
+        }
+
+        if !source.path().exists() {
+            writeln!(
+                out.lock(),
+                "File does not exist: {}",
+                source.path().display()
+            )?;
+            continue;
+        }
+
+        match source.verify_hash().await.and_then(HashCheck::into_result) {
+            Ok(()) => {
+                info!(
+                    "Manually downloaded source verified: {}",
+                    source.path().display()
+                );
+                return Ok(());
+            }
+            Err(e) => writeln!(
+                out.lock(),
+                "Hash verification failed for {}: {:#}",
+                source.path().display(),
+                e
+            )?,
+        }
+    }
+}
+
+/// Download the sources again, existing files are removed first
+///
+/// Returns the result of the download for each source, in the order of `sources`.
+pub(super) async fn redownload(
+    sources: &[SourceEntry],
+    timeout: Option<u64>,
+    progressbars: &ProgressBars,
+    config: &Configuration,
+) -> Result<Vec<Result<()>>> {
+    let progressbar = Arc::new(Mutex::new(ProgressWrapper::new(progressbars.bar()?)));
+
+    let download_sema = Arc::new(tokio::sync::Semaphore::new(
+        NUMBER_OF_MAX_CONCURRENT_DOWNLOADS,
+    ));
+
+    let results = futures::future::join_all(sources.iter().map(|source| {
+        let download_sema = download_sema.clone();
+        let progressbar = progressbar.clone();
+        async move {
+            if source.path().exists() {
+                source.remove_file().await?;
+            }
+
+            progressbar.lock().await.inc_download_count().await;
+            {
+                let permit = download_sema.acquire_owned().await?;
+                perform_download(source, progressbar.clone(), timeout, config).await?;
+                drop(permit);
+            }
+            progressbar.lock().await.finish_one_download().await;
+            Ok(())
+        }
+    }))
+    .await;
+
+    if results.iter().any(Result::is_err) {
+        progressbar.lock().await.error().await;
+    } else {
+        progressbar.lock().await.success().await;
+    }
+
+    Ok(results)
+}
+
 // Implementation of the 'source download' subcommand
 pub async fn download(
     matches: &ArgMatches,
@@ -209,9 +574,10 @@ pub async fn download(
     progressbars: ProgressBars,
 ) -> Result<()> {
     let force = matches.get_flag("force");
+    let interactive = !matches.get_flag("noninteractive");
     let timeout = matches.get_one::<u64>("timeout").copied();
     let cache = PathBuf::from(config.source_cache_root());
-    let sc = SourceCache::new(cache);
+    let sc = SourceCache::new(cache, config.source_mirror_base().clone());
     let pname = matches
         .get_one::<String>("package_name")
         .map(|s| s.to_owned())
@@ -227,48 +593,70 @@ pub async fn download(
         .map(|s| crate::commands::util::mk_package_name_regex(s.as_ref()))
         .transpose()?;
 
+    let packages = repo
+        .search_packages(&pname, &pvers, &matching_regexp)?
+        .collect::<Vec<_>>();
+    let dags;
+    let packages = if matches.get_flag("recursive") {
+        dags = super::dependency_dags(matches, config, &repo, &packages)?;
+        super::dag_packages(&dags, matches)
+    } else {
+        packages
+    };
+
+    let (manual_sources, sources): (Vec<_>, Vec<_>) = packages
+        .into_iter()
+        .map(|p| sc.sources_for(p))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .partition(|source| source.download_manually() && (force || !source.path().exists()));
+
+    if matches.get_flag("check_only") {
+        let sources = manual_sources.iter().chain(sources.iter()).collect();
+        return check_only(sources, force, timeout, config).await;
+    }
+
+    // The sources that have to be downloaded manually are handled first (one after
+    // another), because the user might have to interact with us
+    for source in manual_sources.iter() {
+        manual_download(source, interactive, force)
+            .await
+            .context(anyhow!("Downloading source: {}", source.url()))?;
+    }
+
     let progressbar = Arc::new(Mutex::new(ProgressWrapper::new(progressbars.bar()?)));
 
     let download_sema = Arc::new(tokio::sync::Semaphore::new(
         NUMBER_OF_MAX_CONCURRENT_DOWNLOADS,
     ));
 
-    let r = repo
-        .search_packages(&pname, &pvers, &matching_regexp)?
-        .flat_map(|p| {
-            sc.sources_for(p).into_iter().map(|source| {
-                let download_sema = download_sema.clone();
-                let progressbar = progressbar.clone();
-                async move {
-                    let source_path_exists = source.path().exists();
-                    if !source_path_exists && source.download_manually() {
-                        return Err(anyhow!(
-                            "Cannot download source that is marked for manual download"
-                        ))
-                        .context(anyhow!("Creating source: {}", source.path().display()))
-                        .context(anyhow!("Downloading source: {}", source.url()));
+    let r = sources
+        .into_iter()
+        .map(|source| {
+            let download_sema = download_sema.clone();
+            let progressbar = progressbar.clone();
+            async move {
+                let source_path_exists = source.path().exists();
+                if source_path_exists && !force {
+                    Err(anyhow!("Source exists: {}", source.path().display()))
+                } else {
+                    if source_path_exists
+                    /* && force is implied by 'if' above*/
+                    {
+                        source.remove_file().await?;
                     }
 
-                    if source_path_exists && !force {
-                        Err(anyhow!("Source exists: {}", source.path().display()))
-                    } else {
-                        if source_path_exists
-                        /* && force is implied by 'if' above*/
-                        {
-                            source.remove_file().await?;
-                        }
-
-                        progressbar.lock().await.inc_download_count().await;
-                        {
-                            let permit = download_sema.acquire_owned().await?;
-                            perform_download(&source, progressbar.clone(), timeout).await?;
-                            drop(permit);
-                        }
-                        progressbar.lock().await.finish_one_download().await;
-                        Ok(())
+                    progressbar.lock().await.inc_download_count().await;
+                    {
+                        let permit = download_sema.acquire_owned().await?;
+                        perform_download(&source, progressbar.clone(), timeout, config).await?;
+                        drop(permit);
                     }
+                    progressbar.lock().await.finish_one_download().await;
+                    Ok(())
                 }
-            })
+            }
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
         .collect::<Vec<Result<()>>>()
@@ -11,6 +11,7 @@
 //! Implementation of the 'source' subcommand
 
 use std::io::Write;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
@@ -19,16 +20,21 @@ use anyhow::Error;
 use anyhow::Result;
 use clap::ArgMatches;
 use colored::Colorize;
+use itertools::Itertools;
 use tokio_stream::StreamExt;
 use tracing::{info, trace};
 
 use crate::config::*;
+use crate::package::condition::ConditionData;
+use crate::package::Dag;
+use crate::package::HashCheck;
 use crate::package::Package;
 use crate::package::PackageName;
 use crate::package::PackageVersionConstraint;
 use crate::repository::Repository;
 use crate::source::*;
 use crate::util::progress::ProgressBars;
+use crate::util::EnvironmentVariableName;
 
 mod download;
 
@@ -42,7 +48,7 @@ pub async fn source(
     match matches.subcommand() {
         Some(("verify", matches)) => verify(matches, config, repo, progressbars).await,
         Some(("list-missing", matches)) => list_missing(matches, config, repo).await,
-        Some(("url", matches)) => url(matches, repo).await,
+        Some(("url", matches)) => url(matches, config, repo).await,
         Some(("download", matches)) => {
             crate::commands::source::download::download(matches, config, repo, progressbars).await
         }
@@ -58,7 +64,10 @@ pub async fn verify(
     repo: Repository,
     progressbars: ProgressBars,
 ) -> Result<()> {
-    let sc = SourceCache::new(config.source_cache_root().clone());
+    let sc = SourceCache::new(
+        config.source_cache_root().clone(),
+        config.source_mirror_base().clone(),
+    );
     let pname = matches
         .get_one::<String>("package_name")
         .map(|s| s.to_owned())
@@ -74,53 +83,198 @@ pub async fn verify(
         .map(|s| crate::commands::util::mk_package_name_regex(s.as_ref()))
         .transpose()?;
 
-    let packages = repo.search_packages(&pname, &pvers, &matching_regexp)?;
+    let packages = repo
+        .search_packages(&pname, &pvers, &matching_regexp)?
+        .collect::<Vec<_>>();
+
+    let recursive = matches.get_flag("recursive");
+    let dags;
+    let packages = if recursive {
+        dags = dependency_dags(matches, config, &repo, &packages)?;
+        dag_packages(&dags, matches)
+    } else {
+        packages
+    };
+    let packages = packages.into_iter();
+
+    // The "redownload", "print_hash" and "parallel" arguments do not exist for "source download",
+    // which uses this function as well
+    let parallel = matches
+        .try_get_one::<NonZeroUsize>("parallel")
+        .ok()
+        .flatten()
+        .map(|n| n.get())
+        .unwrap_or_else(default_parallel_verifications);
+    if std::matches!(matches.try_get_one::<bool>("redownload"), Ok(Some(true))) {
+        verify_and_redownload(packages, &sc, &progressbars, parallel, config).await
+    } else {
+        let print_hash = std::matches!(matches.try_get_one::<bool>("print_hash"), Ok(Some(true)));
+        verify_impl(packages, &sc, &progressbars, print_hash, parallel).await
+    }
+}
 
-    verify_impl(packages, &sc, &progressbars).await
+/// Get the (unique) packages of the dependency `dags` for "--recursive"
+///
+/// With "--runtime-only" (only for "source download"), the build time dependencies (with their
+/// dependencies) are left out.
+fn dag_packages<'a>(dags: &'a [Dag], matches: &ArgMatches) -> Vec<&'a Package> {
+    let runtime_only = std::matches!(matches.try_get_one::<bool>("runtime_only"), Ok(Some(true)));
+    dags.iter()
+        .flat_map(|dag| {
+            if runtime_only {
+                dag.runtime_packages()
+            } else {
+                dag.all_packages()
+            }
+        })
+        .unique_by(|p| (p.name(), p.version()))
+        .collect()
 }
 
+/// Build the dependency DAGs of the `packages` for "source verify/download --recursive"
+fn dependency_dags(
+    matches: &ArgMatches,
+    config: &Configuration,
+    repo: &Repository,
+    packages: &[&Package],
+) -> Result<Vec<Dag>> {
+    let image_name = crate::commands::util::get_image_name(matches, config)?;
+    let additional_env = matches
+        .get_many::<String>("env")
+        .unwrap_or_default()
+        .map(AsRef::as_ref)
+        .map(crate::util::env::parse_to_env)
+        .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?;
+    let condition_data = ConditionData {
+        image_name: image_name.as_ref(),
+        env: &additional_env,
+    };
+
+    packages
+        .iter()
+        .map(|p| Dag::for_root_package((*p).clone(), repo, None, &condition_data))
+        .collect()
+}
+
+/// The number of sources that are verified concurrently if not specified otherwise
+pub(in crate::commands) fn default_parallel_verifications() -> usize {
+    std::thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+}
+
+/// Verify that the source exists and that its hash matches
+async fn verify_source(source: &SourceEntry) -> Result<()> {
+    check_source(source)
+        .await?
+        .into_result()
+        .with_context(|| anyhow!("Hash verification failed for: {}", source.path().display()))?;
+
+    trace!("Success verifying: {}", source.path().display());
+    Ok(())
+}
+
+/// Check that the source exists and compute its hash
+///
+/// A hash mismatch is not an error here, it is only reported by the returned [HashCheck].
+async fn check_source(source: &SourceEntry) -> Result<HashCheck> {
+    trace!("Verifying: {}", source.path().display());
+    if source.path().exists() {
+        trace!("Exists: {}", source.path().display());
+        source
+            .verify_hash()
+            .await
+            .with_context(|| anyhow!("Hash verification failed for: {}", source.path().display()))
+    } else {
+        trace!("Failed verifying: {}", source.path().display());
+        let err = anyhow!("Source missing: {}", source.path().display());
+        if source.download_manually() {
+            Err(err.context(download::manual_download_instructions(source)))
+        } else {
+            Err(err)
+        }
+    }
+}
+
+/// Verify the sources of the packages
+///
+/// At most `parallel` sources are verified concurrently. If `print_hash` is `true`, the expected
+/// and the actual hash of each source are printed.
 pub(in crate::commands) async fn verify_impl<'a, I>(
     packages: I,
     sc: &SourceCache,
     progressbars: &ProgressBars,
+    print_hash: bool,
+    parallel: usize,
 ) -> Result<()>
 where
     I: Iterator<Item = &'a Package> + 'a,
 {
     let sources = packages
-        .flat_map(|p| sc.sources_for(p).into_iter())
+        .map(|p| sc.sources_for(p))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
         .collect::<Vec<_>>();
 
     let bar = progressbars.bar()?;
     bar.set_message("Verifying sources");
     bar.set_length(sources.len() as u64);
 
-    let results = sources
-        .into_iter()
-        .map(|src| (bar.clone(), src))
-        .map(|(bar, source)| async move {
-            trace!("Verifying: {}", source.path().display());
-            if source.path().exists() {
-                trace!("Exists: {}", source.path().display());
-                source.verify_hash().await.with_context(|| {
-                    anyhow!("Hash verification failed for: {}", source.path().display())
-                })?;
-
-                trace!("Success verifying: {}", source.path().display());
+    let checks =
+        sources
+            .into_iter()
+            .map(|src| (bar.clone(), src))
+            .map(|(bar, source)| async move {
+                let res = check_source(&source).await;
                 bar.inc(1);
-                Ok(())
-            } else {
-                trace!("Failed verifying: {}", source.path().display());
-                bar.inc(1);
-                Err(anyhow!("Source missing: {}", source.path().display()))
-            }
-        })
-        .collect::<futures::stream::FuturesUnordered<_>>()
-        .collect::<Vec<Result<_>>>()
+                (source, res)
+            });
+    let checks = {
+        use futures::stream::StreamExt;
+        futures::stream::iter(checks).buffer_unordered(parallel)
+    };
+
+    let results = checks
+        .collect::<Vec<(SourceEntry, Result<HashCheck>)>>()
         .await;
 
     info!("Verification processes finished");
 
+    if print_hash {
+        let hdr = crate::commands::util::mk_header(["Source", "Expected", "Actual"].to_vec());
+        // Sources that are missing or cannot be hashed are reported as errors below
+        let data = results
+            .iter()
+            .filter_map(|(source, res)| res.as_ref().ok().map(|check| (source.path(), check)))
+            .sorted_by(|(path1, _), (path2, _)| path1.cmp(path2))
+            .map(|(path, check)| {
+                let actual = if check.matches() {
+                    check.actual().to_string().green()
+                } else {
+                    check.actual().to_string().red()
+                };
+                vec![
+                    path.display().to_string().normal(),
+                    check.expected().to_string().normal(),
+                    actual,
+                ]
+            })
+            .collect::<Vec<_>>();
+        crate::commands::util::display_data(hdr, data, false)?;
+    }
+
+    let results = results
+        .into_iter()
+        .map(|(source, res)| {
+            res.and_then(|check| {
+                check.into_result().with_context(|| {
+                    anyhow!("Hash verification failed for: {}", source.path().display())
+                })
+            })
+        })
+        .collect::<Vec<Result<()>>>();
+
     if results.iter().any(Result::is_err) {
         bar.finish_with_message("Source verification failed");
     } else {
@@ -149,13 +303,129 @@ where
     }
 }
 
+/// Verify the sources of the packages and download the sources that are missing or that fail the
+/// hash check again
+///
+/// Sources that have to be downloaded manually are not downloaded but reported.
+async fn verify_and_redownload<'a, I>(
+    packages: I,
+    sc: &SourceCache,
+    progressbars: &ProgressBars,
+    parallel: usize,
+    config: &Configuration,
+) -> Result<()>
+where
+    I: Iterator<Item = &'a Package> + 'a,
+{
+    let sources = packages
+        .map(|p| sc.sources_for(p))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    let bar = progressbars.bar()?;
+    bar.set_message("Verifying sources");
+    bar.set_length(sources.len() as u64);
+
+    let verifications = sources.into_iter().map(|source| {
+        let bar = bar.clone();
+        async move {
+            let res = verify_source(&source).await;
+            bar.inc(1);
+            (source, res)
+        }
+    });
+    let verifications = {
+        use futures::stream::StreamExt;
+        futures::stream::iter(verifications).buffer_unordered(parallel)
+    };
+
+    let failed_sources = verifications
+        .filter_map(|(source, res)| res.err().map(|e| (source, e)))
+        .collect::<Vec<_>>()
+        .await;
+    bar.finish_with_message(format!(
+        "Source verification finished, {} sources failed",
+        failed_sources.len()
+    ));
+
+    let number_of_failed = failed_sources.len();
+    let (manual_sources, sources): (Vec<_>, Vec<_>) = failed_sources
+        .into_iter()
+        .partition(|(source, _)| source.download_manually());
+
+    let mut errors = manual_sources
+        .into_iter()
+        .map(|(source, e)| {
+            e.context(anyhow!(
+                "Source has to be downloaded manually: {}",
+                source.path().display()
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    let sources = sources
+        .into_iter()
+        .map(|(source, e)| {
+            info!(
+                "Downloading source again: {} ({:#})",
+                source.path().display(),
+                e
+            );
+            source
+        })
+        .collect::<Vec<_>>();
+
+    let download_results = download::redownload(&sources, None, progressbars, config).await?;
+    for (source, download_result) in sources.iter().zip(download_results) {
+        let res = match download_result {
+            Ok(()) => verify_source(source).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = res {
+            errors.push(e.context(anyhow!(
+                "Downloading source again failed: {}",
+                source.path().display()
+            )));
+        }
+    }
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    for e in errors.iter() {
+        for cause in e.chain() {
+            writeln!(outlock, "Error: {}", cause.to_string().red())?;
+        }
+        writeln!(outlock)?;
+    }
+
+    writeln!(
+        outlock,
+        "Fixed {} of {} failed sources, {} still failing",
+        number_of_failed - errors.len(),
+        number_of_failed,
+        errors.len()
+    )?;
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("At least one source could not be fixed"))
+    }
+}
+
 pub async fn list_missing(_: &ArgMatches, config: &Configuration, repo: Repository) -> Result<()> {
-    let sc = SourceCache::new(config.source_cache_root().clone());
+    let sc = SourceCache::new(
+        config.source_cache_root().clone(),
+        config.source_mirror_base().clone(),
+    );
     let out = std::io::stdout();
     let mut outlock = out.lock();
 
     repo.packages().try_for_each(|p| {
-        for source in sc.sources_for(p) {
+        for source in sc.sources_for(p)? {
             if !source.path().exists() {
                 writeln!(
                     outlock,
@@ -171,7 +441,11 @@ pub async fn list_missing(_: &ArgMatches, config: &Configuration, repo: Reposito
     })
 }
 
-pub async fn url(matches: &ArgMatches, repo: Repository) -> Result<()> {
+pub async fn url(matches: &ArgMatches, config: &Configuration, repo: Repository) -> Result<()> {
+    let sc = SourceCache::new(
+        config.source_cache_root().clone(),
+        config.source_mirror_base().clone(),
+    );
     let out = std::io::stdout();
     let mut outlock = out.lock();
 
@@ -187,20 +461,24 @@ pub async fn url(matches: &ArgMatches, repo: Repository) -> Result<()> {
 
     repo.packages()
         .filter(|p| pname.as_ref().map(|n| p.name() == n).unwrap_or(true))
-        .filter(|p| {
+        .map(|p| {
             pvers
                 .as_ref()
                 .map(|v| v.matches(p.version()))
-                .unwrap_or(true)
+                .unwrap_or(Ok(true))
+                .map(|b| (b, p))
         })
+        .filter_ok(|(b, _)| *b)
+        .map_ok(|tpl| tpl.1)
         .try_for_each(|p| {
-            p.sources().iter().try_for_each(|(source_name, source)| {
+            let p = p?;
+            sc.sources_for(p)?.iter().try_for_each(|source| {
                 writeln!(
                     outlock,
                     "{} {} -> {} = {}",
                     p.name(),
                     p.version(),
-                    source_name,
+                    source.name(),
                     source.url()
                 )
                 .map_err(Error::from)
@@ -210,7 +488,7 @@ pub async fn url(matches: &ArgMatches, repo: Repository) -> Result<()> {
 
 async fn of(matches: &ArgMatches, config: &Configuration, repo: Repository) -> Result<()> {
     let cache = PathBuf::from(config.source_cache_root());
-    let sc = SourceCache::new(cache);
+    let sc = SourceCache::new(cache, config.source_mirror_base().clone());
     let pname = matches
         .get_one::<String>("package_name")
         .map(|s| s.to_owned())
@@ -223,22 +501,27 @@ async fn of(matches: &ArgMatches, config: &Configuration, repo: Repository) -> R
 
     repo.packages()
         .filter(|p| pname.as_ref().map(|n| p.name() == n).unwrap_or(true))
-        .filter(|p| {
+        .map(|p| {
             pvers
                 .as_ref()
                 .map(|v| v.matches(p.version()))
-                .unwrap_or(true)
+                .unwrap_or(Ok(true))
+                .map(|b| (b, p))
         })
+        .filter_ok(|(b, _)| *b)
+        .map_ok(|tpl| tpl.1)
         .map(|p| {
+            let p = p?;
             let pathes = sc
-                .sources_for(p)
+                .sources_for(p)?
                 .into_iter()
                 .map(|source| source.path())
                 .collect::<Vec<PathBuf>>();
 
-            (p, pathes)
+            Ok((p, pathes))
         })
-        .try_fold(std::io::stdout(), |mut out, (package, pathes)| {
+        .try_fold(std::io::stdout(), |mut out, r: Result<_>| {
+            let (package, pathes) = r?;
             writeln!(out, "{} {}", package.name(), package.version())?;
             for path in pathes {
                 writeln!(out, "\t{}", path.display())?;
@@ -0,0 +1,135 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'staging' subcommand
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use diesel::prelude::*;
+use tracing::{debug, info};
+
+use crate::config::Configuration;
+use crate::db::models as dbmodels;
+use crate::db::DbConnectionConfig;
+use crate::filestore::StagingDirectory;
+
+/// Implementation of the "staging" subcommand
+pub async fn staging(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    match matches.subcommand() {
+        Some(("gc", matches)) => gc(db_connection_config, config, matches).await,
+        Some((other, _matches)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("Missing subcommand")),
+    }
+}
+
+/// Implementation of the "staging gc" subcommand
+///
+/// Removes the staging directories that are older than the given date (or the configured
+/// retention) and that are not referenced by a submit with artifacts that were not released.
+async fn gc(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let csv = matches.get_flag("csv");
+    let older_than = match crate::commands::util::get_date_filter("older_than", matches)? {
+        Some(date) => date,
+        None => {
+            let retention = config.staging_retention().as_ref().ok_or_else(|| {
+                anyhow!("Neither --older-than was passed nor staging_retention is configured")
+            })?;
+            let retention = humantime::parse_duration(retention)
+                .map_err(anyhow::Error::from)
+                .and_then(|d| chrono::Duration::from_std(d).map_err(anyhow::Error::from))
+                .with_context(|| anyhow!("Invalid duration: staging_retention = {}", retention))?;
+            chrono::offset::Local::now()
+                .checked_sub_signed(retention)
+                .ok_or_else(|| anyhow!("Time calculation would overflow"))?
+        }
+    };
+    debug!("Collecting staging directories older than {}", older_than);
+
+    let mut conn = db_connection_config.establish_connection()?;
+    let mut unused = Vec::new();
+    for dir in StagingDirectory::list(config.staging_directory())? {
+        if dir.modified() >= older_than {
+            continue;
+        }
+
+        let submit = crate::schema::submits::table
+            .filter(crate::schema::submits::uuid.eq(dir.submit_uuid()))
+            .first::<dbmodels::Submit>(&mut conn)
+            .optional()?;
+
+        if let Some(submit) = submit {
+            let unreleased_artifacts = crate::schema::artifacts::table
+                .inner_join(crate::schema::jobs::table)
+                .left_outer_join(crate::schema::releases::table)
+                .filter(crate::schema::jobs::submit_id.eq(submit.id))
+                .filter(crate::schema::releases::id.is_null())
+                .count()
+                .get_result::<i64>(&mut conn)?;
+
+            if unreleased_artifacts > 0 {
+                debug!(
+                    "Keeping {}, the submit has {} unreleased artifacts",
+                    dir.path().display(),
+                    unreleased_artifacts
+                );
+                continue;
+            }
+        }
+
+        unused.push(dir);
+    }
+
+    if unused.is_empty() {
+        info!("No staging directories to remove");
+        return Ok(());
+    }
+
+    unused.sort_by_key(|dir| dir.modified());
+    let hdr = crate::commands::util::mk_header(["Path", "Submit", "Modified"].to_vec());
+    let data = unused
+        .iter()
+        .map(|dir| {
+            vec![
+                dir.path().display().to_string(),
+                dir.submit_uuid().to_string(),
+                dir.modified().to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data(hdr, data, csv)?;
+
+    if matches.get_flag("dry_run") {
+        return Ok(());
+    }
+
+    let prompt = format!("Really delete {} staging directories?", unused.len());
+    if !dialoguer::Confirm::new().with_prompt(prompt).interact()? {
+        return Ok(());
+    }
+
+    for dir in unused {
+        tokio::fs::remove_dir_all(dir.path())
+            .await
+            .with_context(|| anyhow!("Removing staging directory: {}", dir.path().display()))?;
+        info!("Removed {}", dir.path().display());
+    }
+
+    Ok(())
+}
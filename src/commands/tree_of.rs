@@ -10,9 +10,12 @@
 
 //! Implementation of the 'tree-of' subcommand
 
+use std::io::Write;
+
 use anyhow::Error;
 use anyhow::Result;
 use clap::ArgMatches;
+use itertools::Itertools;
 use petgraph::dot::Dot;
 use resiter::AndThen;
 
@@ -23,7 +26,6 @@ use crate::package::DependencyType;
 use crate::package::PackageName;
 use crate::package::PackageVersionConstraint;
 use crate::repository::Repository;
-use crate::util::docker::ImageNameLookup;
 use crate::util::EnvironmentVariableName;
 
 /// Implementation of the "tree_of" subcommand
@@ -38,11 +40,7 @@ pub async fn tree_of(matches: &ArgMatches, repo: Repository, config: &Configurat
         .map(PackageVersionConstraint::try_from)
         .transpose()?;
 
-    let image_name_lookup = ImageNameLookup::create(config.docker().images())?;
-    let image_name = matches
-        .get_one::<String>("image")
-        .map(|s| image_name_lookup.expand(s))
-        .transpose()?;
+    let image_name = crate::commands::util::get_image_name(matches, config)?;
 
     let additional_env = matches
         .get_many::<String>("env")
@@ -58,19 +56,34 @@ pub async fn tree_of(matches: &ArgMatches, repo: Repository, config: &Configurat
 
     let dot = matches.get_flag("dot");
 
+    let json = matches.get_flag("json");
+
     let serial_buildorder = matches.get_flag("serial-buildorder");
 
+    let max_depth = matches.get_one::<usize>("depth").copied();
+
+    let color =
+        !matches.get_flag("no_color") && colored::control::SHOULD_COLORIZE.should_colorize();
+
+    let mut json_trees = Vec::new();
     repo.packages()
         .filter(|p| pname.as_ref().map(|n| p.name() == n).unwrap_or(true))
-        .filter(|p| {
+        .map(|p| {
             pvers
                 .as_ref()
                 .map(|v| v.matches(p.version()))
-                .unwrap_or(true)
+                .unwrap_or(Ok(true))
+                .map(|b| (b, p))
         })
-        .map(|package| Dag::for_root_package(package.clone(), &repo, None, &condition_data))
+        .filter_ok(|(b, _)| *b)
+        .map_ok(|tpl| tpl.1)
+        .and_then_ok(|package| Dag::for_root_package(package.clone(), &repo, None, &condition_data))
         .and_then_ok(|dag| {
             if dot {
+                let dag = match max_depth {
+                    Some(max_depth) => dag.limit_depth(max_depth),
+                    None => dag,
+                };
                 let dot = Dot::with_attr_getters(
                     dag.dag(),
                     &[
@@ -101,13 +114,30 @@ pub async fn tree_of(matches: &ArgMatches, repo: Repository, config: &Configurat
                 }
                 println!();
 
+                Ok(())
+            } else if json {
+                for tree in dag.display(max_depth, false) {
+                    json_trees.push(serde_json::to_value(&tree)?);
+                }
                 Ok(())
             } else {
                 let stdout = std::io::stdout();
                 let mut outlock = stdout.lock();
 
-                ptree::write_tree(&dag.display(), &mut outlock).map_err(Error::from)
+                dag.display(max_depth, color)
+                    .iter()
+                    .try_for_each(|tree| ptree::write_tree(tree, &mut outlock))
+                    .map_err(Error::from)
             }
         })
-        .collect::<Result<()>>()
+        .collect::<Result<()>>()?;
+
+    if json {
+        writeln!(
+            std::io::stdout(),
+            "{}",
+            serde_json::to_string_pretty(&json_trees)?
+        )?;
+    }
+    Ok(())
 }
@@ -30,6 +30,8 @@ use crate::package::Package;
 use crate::package::PhaseName;
 use crate::package::ScriptBuilder;
 use crate::package::Shebang;
+use crate::util::docker::ImageName;
+use crate::util::docker::ImageNameLookup;
 
 /// Helper for getting a boolean value by name form the argument object
 pub fn getbool(m: &ArgMatches, name: &str, cmp: &str) -> bool {
@@ -161,6 +163,60 @@ pub fn mk_package_name_regex(regex: &str) -> Result<Regex> {
         .with_context(|| anyhow!("Failed to build regex from '{}'", regex))
 }
 
+/// Helper function to make a package name regex out of a glob pattern (e.g. "foo-*")
+///
+/// The pattern must match the whole package name. Supported are `*` (any characters), `?` (one
+/// character) and character classes (`[abc]`, `[a-z]`, `[!abc]`).
+pub fn mk_package_name_glob(glob: &str) -> Result<Regex> {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                let mut class = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') if !class.is_empty() && class != "!" => break,
+                        Some(c) => class.push(c),
+                        None => return Err(anyhow!("Unclosed character class in '{}'", glob)),
+                    }
+                }
+                let class = match class.strip_prefix('!') {
+                    Some(negated) => format!("^{negated}"),
+                    None => class,
+                };
+                regex.push('[');
+                regex.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+
+    mk_package_name_regex(&regex).with_context(|| anyhow!("Invalid glob pattern '{}'", glob))
+}
+
+/// Get the package name regexes that were passed with `--exclude`
+pub fn get_exclude_regexes(matches: &ArgMatches) -> Result<Vec<Regex>> {
+    matches
+        .get_many::<String>("exclude")
+        .into_iter()
+        .flatten()
+        .map(|s| mk_package_name_regex(s))
+        .collect()
+}
+
+/// Print the dependencies that were removed from a package because of an `--exclude`
+pub fn write_pruned<W: Write>(out: &mut W, pruned: &[String]) -> std::io::Result<()> {
+    if pruned.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "Pruned by --exclude: {}", pruned.join(", "))
+}
+
 /// Make a header column for the ascii_table crate
 pub fn mk_header(vec: Vec<&str>) -> Vec<ascii_table::Column> {
     vec.into_iter()
@@ -173,6 +229,68 @@ pub fn mk_header(vec: Vec<&str>) -> Vec<ascii_table::Column> {
         .collect()
 }
 
+/// The columns of a table, as selected with `--columns` (all columns by default)
+///
+/// A column is named by its header in lowercase, with '_' between the words (e.g. "package_version"
+/// for the "Package Version" column).
+pub struct Columns<'a> {
+    header: Vec<&'a str>,
+    selected: Vec<usize>,
+}
+
+impl<'a> Columns<'a> {
+    /// Select the columns of the table with the `header` that were passed with `--columns`
+    pub fn select(header: Vec<&'a str>, matches: &ArgMatches) -> Result<Self> {
+        let names = matches
+            .get_many::<String>("columns")
+            .map(|names| names.map(String::as_str).collect());
+        Self::new(header, names)
+    }
+
+    fn new(header: Vec<&'a str>, names: Option<Vec<&str>>) -> Result<Self> {
+        let selected = match names {
+            None => (0..header.len()).collect(),
+            Some(names) => names
+                .into_iter()
+                .map(|name| {
+                    header
+                        .iter()
+                        .position(|h| column_name(h) == name.to_lowercase())
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "Unknown column '{}', valid columns: {}",
+                                name,
+                                header.iter().map(|h| column_name(h)).join(", ")
+                            )
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?,
+        };
+        Ok(Columns { header, selected })
+    }
+
+    /// Display the selected columns of the `data` (see `display_data()`)
+    pub fn display<D: Display>(&self, data: Vec<Vec<D>>, csv: bool) -> Result<()> {
+        let header = mk_header(self.selected.iter().map(|i| self.header[*i]).collect());
+        display_data(header, self.select_data(data), csv)
+    }
+
+    fn select_data<D: Display>(&self, data: Vec<Vec<D>>) -> Vec<Vec<String>> {
+        data.into_iter()
+            .map(|row| self.selected.iter().map(|i| row[*i].to_string()).collect())
+            .collect()
+    }
+}
+
+/// Get the name of the column with the `header` for `--columns`
+fn column_name(header: &str) -> String {
+    header
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .join("_")
+}
+
 /// Display the passed data as nice ascii table,
 /// or, if stdout is a pipe, print it nicely parseable
 ///
@@ -226,6 +344,54 @@ pub fn display_data<D: Display>(
     }
 }
 
+/// Get the image that was passed with `--image` or, if none was passed, the configured default
+/// image (`docker.default_image`)
+pub fn get_image_name(matches: &ArgMatches, config: &Configuration) -> Result<Option<ImageName>> {
+    let image_name_lookup = ImageNameLookup::create(config.docker().images())?;
+    matches
+        .get_one::<String>("image")
+        .or(config.docker().default_image().as_ref())
+        .map(|s| image_name_lookup.expand(s))
+        .transpose()
+}
+
+/// Get the configurations of all configured endpoints for a build
+///
+/// Because the endpoints are always set up sequentially, the configurations are shuffled to have a
+/// bit more spread over the endpoints. Not a perfect solution, but a working one.
+pub fn get_endpoint_configurations(
+    config: &Configuration,
+    network_mode: Option<&String>,
+) -> Vec<crate::endpoint::EndpointConfiguration> {
+    let mut endpoint_configurations = config
+        .docker()
+        .endpoints()
+        .iter()
+        .map(|(ep_name, ep_cfg)| {
+            crate::endpoint::EndpointConfiguration::builder()
+                .endpoint_name(ep_name.clone())
+                .endpoint(ep_cfg.clone())
+                .required_images(
+                    config
+                        .docker()
+                        .images()
+                        .iter()
+                        .map(|img| img.name.clone())
+                        .collect::<Vec<_>>(),
+                )
+                .required_docker_versions(config.docker().docker_versions().clone())
+                .required_docker_api_versions(config.docker().docker_api_versions().clone())
+                .network_mode(network_mode.cloned())
+                .build()
+        })
+        .collect::<Vec<_>>();
+
+    use rand::seq::SliceRandom;
+    let mut rng = rand::thread_rng();
+    endpoint_configurations.shuffle(&mut rng);
+    endpoint_configurations
+}
+
 pub fn get_date_filter(
     name: &str,
     matches: &ArgMatches,
@@ -261,3 +427,54 @@ pub fn get_date_filter(
         })
         .transpose()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mk_package_name_glob() {
+        let matches = |glob: &str, name: &str| mk_package_name_glob(glob).unwrap().is_match(name);
+
+        assert!(matches("foo-*", "foo-bar"));
+        assert!(matches("foo-*", "foo-"));
+        assert!(!matches("foo-*", "libfoo-bar"));
+        assert!(!matches("foo", "foobar"));
+        assert!(matches("lib?", "libc"));
+        assert!(!matches("lib?", "lib"));
+        assert!(matches("py[23]-*", "py3-numpy"));
+        assert!(!matches("py[!23]-*", "py3-numpy"));
+        assert!(matches("c++", "c++"));
+        assert!(matches("a.b", "a.b"));
+        assert!(!matches("a.b", "axb"));
+        assert!(mk_package_name_glob("foo[").is_err());
+    }
+
+    #[test]
+    fn test_columns() {
+        let header = vec!["Job", "Ok?", "Package Version", "Peak Memory (bytes)"];
+        let data = vec![vec!["a", "yes", "1.0", "42"]];
+
+        let all = Columns::new(header.clone(), None).unwrap();
+        assert_eq!(
+            all.select_data(data.clone()),
+            vec![vec!["a", "yes", "1.0", "42"]]
+        );
+
+        let selected = Columns::new(
+            header.clone(),
+            Some(vec!["peak_memory_bytes", "Job", "ok", "job"]),
+        )
+        .unwrap();
+        assert_eq!(
+            selected.select_data(data),
+            vec![vec!["42", "a", "yes", "a"]]
+        );
+
+        let err = Columns::new(header, Some(vec!["version"])).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Unknown column 'version', valid columns: job, ok, package_version, peak_memory_bytes"
+        );
+    }
+}
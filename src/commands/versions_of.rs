@@ -13,13 +13,24 @@
 use anyhow::Error;
 use anyhow::Result;
 use clap::ArgMatches;
-use tracing::trace;
+use tracing::{debug, trace};
 
+use crate::config::Configuration;
+use crate::package::condition::ConditionData;
+use crate::package::Dag;
+use crate::package::Package;
 use crate::package::PackageName;
 use crate::repository::Repository;
+use crate::util::docker::ImageName;
+use crate::util::docker::ImageNameLookup;
+use crate::util::EnvironmentVariableName;
 
 /// Implementation of the "versions_of" subcommand
-pub async fn versions_of(matches: &ArgMatches, repo: Repository) -> Result<()> {
+pub async fn versions_of(
+    matches: &ArgMatches,
+    config: &Configuration,
+    repo: Repository,
+) -> Result<()> {
     use filters::filter::Filter;
     use std::io::Write;
 
@@ -34,11 +45,137 @@ pub async fn versions_of(matches: &ArgMatches, repo: Repository) -> Result<()> {
         crate::util::filters::build_package_filter_by_name(name)
     };
 
+    let image_name_lookup = ImageNameLookup::create(config.docker().images())?;
+    let image_name = matches
+        .get_one::<String>("image")
+        .map(|s| image_name_lookup.expand(s))
+        .transpose()?;
+
+    let additional_env = matches
+        .get_many::<String>("env")
+        .unwrap_or_default()
+        .map(AsRef::as_ref)
+        .map(crate::util::env::parse_to_env)
+        .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?;
+
     let mut stdout = std::io::stdout();
     repo.packages()
         .filter(|package| package_filter.filter(package))
+        .filter(|package| match image_name.as_ref() {
+            Some(image_name) => {
+                match check_buildable(package, &repo, image_name, &additional_env) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        debug!(
+                            "{} {} is not buildable on {}: {:#}",
+                            package.name(),
+                            package.version(),
+                            image_name,
+                            e
+                        );
+                        false
+                    }
+                }
+            }
+            None => true,
+        })
         .inspect(|pkg| trace!("Found package: {:?}", pkg))
         .map(|pkg| writeln!(stdout, "{}", pkg.version()).map_err(Error::from))
         .collect::<Result<Vec<_>>>()
         .map(|_| ())
 }
+
+/// Check whether the `package` can be built on the image `image_name` with the environment `env`
+///
+/// The dependencies that are required with this image and environment (considering their
+/// conditions) have to be available in the repository and every package in the dependency tree
+/// has to be allowed on the image.
+fn check_buildable(
+    package: &Package,
+    repo: &Repository,
+    image_name: &ImageName,
+    env: &[(EnvironmentVariableName, String)],
+) -> Result<()> {
+    let condition_data = ConditionData {
+        image_name: Some(image_name),
+        env,
+    };
+
+    Dag::for_root_package(package.clone(), repo, None, &condition_data)?
+        .all_packages()
+        .into_iter()
+        .try_for_each(|pkg| pkg.check_image(image_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::package::condition::Condition;
+    use crate::package::condition::OneOrMore;
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+    use crate::package::Dependencies;
+    use crate::package::Dependency;
+
+    // Package "a" exists in version 1 (without dependencies), version 2 that depends on the
+    // package "b" when built on "fooimage" and version 3 that depends on the package "c" when
+    // "ENABLE_C" is set. Neither "b" nor "c" exist, so these versions are not buildable then.
+    fn repo() -> Repository {
+        let mut btree = BTreeMap::new();
+
+        let a1 = package("a", "1", "https://rust-lang.org", "123");
+        btree.insert((pname("a"), pversion("1")), a1);
+
+        let mut a2 = package("a", "2", "https://rust-lang.org", "124");
+        let in_image = Some(OneOrMore::<String>::One(String::from("fooimage")));
+        a2.set_dependencies(Dependencies::with_runtime_dependency(
+            Dependency::new_conditional(String::from("b =1"), Condition::new(None, None, in_image)),
+        ));
+        btree.insert((pname("a"), pversion("2")), a2);
+
+        let mut a3 = package("a", "3", "https://rust-lang.org", "125");
+        let has_env = Some(OneOrMore::<EnvironmentVariableName>::One(
+            EnvironmentVariableName::from("ENABLE_C"),
+        ));
+        a3.set_dependencies(Dependencies::with_runtime_dependency(
+            Dependency::new_conditional(String::from("c =1"), Condition::new(has_env, None, None)),
+        ));
+        btree.insert((pname("a"), pversion("3")), a3);
+
+        Repository::from(btree)
+    }
+
+    fn buildable_versions(
+        repo: &Repository,
+        image: &str,
+        env: &[(EnvironmentVariableName, String)],
+    ) -> Vec<String> {
+        repo.find_by_name(&pname("a"))
+            .into_iter()
+            .filter(|p| check_buildable(p, repo, &ImageName::from(image), env).is_ok())
+            .map(|p| p.version().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_version_gated_behind_image_condition() {
+        let repo = repo();
+        assert_eq!(
+            buildable_versions(&repo, "barimage", &[]),
+            vec!["1", "2", "3"]
+        );
+        assert_eq!(buildable_versions(&repo, "fooimage", &[]), vec!["1", "3"]);
+    }
+
+    #[test]
+    fn test_version_gated_behind_env_condition() {
+        let repo = repo();
+        let env = [(EnvironmentVariableName::from("ENABLE_C"), String::from("1"))];
+        assert_eq!(buildable_versions(&repo, "barimage", &env), vec!["1", "2"]);
+        assert_eq!(buildable_versions(&repo, "fooimage", &env), vec!["1"]);
+    }
+}
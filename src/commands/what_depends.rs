@@ -59,6 +59,9 @@ pub async fn what_depends(
         )
     };
 
+    let excludes = crate::commands::util::get_exclude_regexes(matches)?;
+    let is_excluded = |name: &PackageName| excludes.iter().any(|re| re.is_match(name));
+
     let hb = crate::ui::handlebars_for_package_printing(config.package_print_format())?;
     let stdout = std::io::stdout();
     let mut outlock = stdout.lock();
@@ -76,28 +79,50 @@ pub async fn what_depends(
         print_denied_images: false,
         print_phases: false,
         print_script: false,
+        print_field_origins: false,
         script_line_numbers: false,
         script_highlighting: false,
     };
 
     let mut i = 0;
+    let mut excluded_packages = Vec::new();
     let iter = repo
         .packages()
         .map(|package| package_filter.filter(package).map(|b| (b, package)))
         .filter_ok(|(b, _)| *b)
         .map_ok(|tpl| tpl.1)
         .inspect(|pkg| trace!("Found package: {:?}", pkg))
+        .filter_ok(|p| {
+            let excluded = is_excluded(p.name());
+            if excluded {
+                excluded_packages.push(p.display_name_version());
+            }
+            !excluded
+        })
         .map_ok(|p| {
             // poor mans enumerate_ok()
             i += 1;
-            p.prepare_print(config, &flags, &hb, i)
+            let mut p = p.clone();
+            let pruned = p.remove_dependencies(is_excluded);
+            (p.prepare_print(config, &flags, &hb, i), pruned)
         });
 
     tokio_stream::iter(iter)
-        .map(|pp| pp.and_then(|p| p.into_displayable()))
-        .try_for_each(|p| {
-            let r = writeln!(&mut outlock, "{p}").map_err(anyhow::Error::from);
+        .map(|pp| pp.and_then(|(p, pruned)| Ok((p.into_displayable()?, pruned))))
+        .try_for_each(|(p, pruned)| {
+            let r = writeln!(&mut outlock, "{p}")
+                .and_then(|_| crate::commands::util::write_pruned(&mut outlock, &pruned))
+                .map_err(anyhow::Error::from);
             futures::future::ready(r)
         })
-        .await
+        .await?;
+
+    if !excluded_packages.is_empty() {
+        writeln!(
+            outlock,
+            "Excluded by --exclude: {}",
+            excluded_packages.join(", ")
+        )?;
+    }
+    Ok(())
 }
@@ -10,10 +10,13 @@
 
 use std::ops::Deref;
 
+use serde::Serialize;
+
 use crate::config::NotValidatedConfiguration;
 
 /// A valid configuration (validated via NotValidatedConfiguration::validate())
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
 pub struct Configuration {
     pub(in crate::config) inner: NotValidatedConfiguration,
 }
@@ -8,25 +8,37 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
-use getset::CopyGetters;
+use anyhow::anyhow;
+use anyhow::Result;
 use getset::Getters;
 use serde::Deserialize;
+use serde::Serialize;
 
+use crate::package::PackageFlag;
 use crate::util::EnvironmentVariableName;
 
 /// The configuration for the containers
-#[derive(Debug, CopyGetters, Getters, Deserialize)]
+#[derive(Debug, Getters, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ContainerConfig {
     /// Whether to check if environment variables are allowed (i.e., if their
     /// names are listed in `allowed_env`).
-    #[getset(get_copy = "pub")]
     check_env_names: bool,
 
     /// Allowed environment variables (names)
-    #[getset(get = "pub")]
     allowed_env: Vec<EnvironmentVariableName>,
 
+    /// Environment variables (names) that must never be passed to a container, independent of
+    /// `check_env_names`
+    #[serde(default)]
+    denied_env: Vec<EnvironmentVariableName>,
+
+    /// Package flags that weaken the isolation of the build containers but are allowed anyways
+    /// (see [crate::package::ContainerFlags])
+    #[serde(default)]
+    #[getset(get = "pub")]
+    allowed_privileged_flags: Vec<PackageFlag>,
+
     /// Pass the current Git author to the container
     /// This can be used for the "packager" name in a package, for example
     #[getset(get = "pub")]
@@ -36,3 +48,72 @@ pub struct ContainerConfig {
     #[getset(get = "pub")]
     git_commit_hash: Option<EnvironmentVariableName>,
 }
+
+impl ContainerConfig {
+    /// Check whether an environment variable with the name `name` may be passed to a container
+    ///
+    /// The name must not be listed in `denied_env` and, if `check_env_names` is enabled, it must
+    /// be listed in `allowed_env`.
+    pub fn check_env_name(&self, name: &EnvironmentVariableName) -> Result<()> {
+        if self.denied_env.contains(name) {
+            Err(anyhow!("Environment variable name denied: {}", name))
+        } else if self.check_env_names && !self.allowed_env.contains(name) {
+            Err(anyhow!("Environment variable name not allowed: {}", name))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container_config(check_env_names: bool) -> ContainerConfig {
+        ContainerConfig {
+            check_env_names,
+            allowed_env: vec![EnvironmentVariableName::from("FOO")],
+            denied_env: vec![
+                EnvironmentVariableName::from("PATH"),
+                EnvironmentVariableName::from("LD_PRELOAD"),
+            ],
+            allowed_privileged_flags: vec![],
+            git_author: None,
+            git_commit_hash: None,
+        }
+    }
+
+    #[test]
+    fn test_allowlisted_env_name_passes() {
+        let config = container_config(true);
+        assert!(config
+            .check_env_name(&EnvironmentVariableName::from("FOO"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_env_name_not_on_allowlist_is_rejected() {
+        let config = container_config(true);
+        let err = config
+            .check_env_name(&EnvironmentVariableName::from("BAR"))
+            .unwrap_err();
+        assert!(err.to_string().contains("BAR"));
+
+        let config = container_config(false);
+        assert!(config
+            .check_env_name(&EnvironmentVariableName::from("BAR"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_denied_env_name_is_rejected() {
+        // The denylist also applies if the allowlist is not checked
+        for check_env_names in [true, false] {
+            let config = container_config(check_env_names);
+            let err = config
+                .check_env_name(&EnvironmentVariableName::from("LD_PRELOAD"))
+                .unwrap_err();
+            assert!(err.to_string().contains("LD_PRELOAD"));
+        }
+    }
+}
@@ -12,13 +12,15 @@ use std::collections::HashMap;
 
 use getset::{CopyGetters, Getters};
 use serde::Deserialize;
+use serde::Serialize;
 
+use crate::config::util::default_endpoint_retry_attempts;
 use crate::config::Endpoint;
 use crate::config::EndpointName;
 use crate::util::docker::ContainerImage;
 
 /// Configuration of the Docker daemon interfacing functionality
-#[derive(Debug, Getters, CopyGetters, Deserialize)]
+#[derive(Debug, Getters, CopyGetters, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct DockerConfig {
     /// The required Docker version
@@ -48,7 +50,17 @@ pub struct DockerConfig {
     #[getset(get = "pub")]
     images: Vec<ContainerImage>,
 
+    /// The image that is used if no image is passed on the commandline (`--image`)
+    #[getset(get = "pub")]
+    default_image: Option<String>,
+
     /// A map of endpoints (name -> settings) that are used as container hosts to run builds on
     #[getset(get = "pub")]
     endpoints: HashMap<EndpointName, Endpoint>,
+
+    /// How often a job is retried (on another endpoint, if possible) if the endpoint fails before
+    /// the build script was started (e.g., because the endpoint is temporarily unreachable)
+    #[serde(default = "default_endpoint_retry_attempts")]
+    #[getset(get_copy = "pub")]
+    endpoint_retry_attempts: usize,
 }
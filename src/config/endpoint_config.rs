@@ -8,10 +8,17 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Result;
 use getset::{CopyGetters, Getters};
 use serde::Deserialize;
+use serde::Serialize;
+
+use crate::util::docker::ImageName;
 
-#[derive(Debug, Clone, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
 #[serde(transparent)]
 pub struct EndpointName(String);
 
@@ -40,7 +47,7 @@ impl EndpointName {
 }
 
 /// Configuration of a single endpoint
-#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+#[derive(Clone, Debug, Getters, CopyGetters, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Endpoint {
     /// The URI where the endpoint is reachable
@@ -62,13 +69,76 @@ pub struct Endpoint {
     #[getset(get = "pub")]
     network_mode: Option<String>,
 
+    /// The images that jobs on this endpoint may use, all images are allowed if not set
+    #[getset(get = "pub")]
+    allowed_images: Option<Vec<ImageName>>,
+
+    /// The images that jobs on this endpoint must not use
+    #[getset(get = "pub")]
+    denied_images: Option<Vec<ImageName>>,
+
     /// Timeout in seconds for connecting to this endpoint
     #[getset(get = "pub")]
     timeout: Option<u64>,
+
+    /// Path to the PEM encoded client certificate for TLS-secured endpoints
+    /// (requires `tls_key` and `tls_ca` to be set as well)
+    #[getset(get = "pub")]
+    tls_cert: Option<PathBuf>,
+
+    /// Path to the PEM encoded private key of the client certificate for TLS-secured endpoints
+    /// (requires `tls_cert` and `tls_ca` to be set as well)
+    #[getset(get = "pub")]
+    tls_key: Option<PathBuf>,
+
+    /// Path to the PEM encoded CA certificate that is used to verify TLS-secured endpoints
+    /// (requires `tls_cert` and `tls_key` to be set as well)
+    #[getset(get = "pub")]
+    tls_ca: Option<PathBuf>,
+}
+
+/// The TLS client configuration of an endpoint
+#[derive(Debug, Getters)]
+pub struct EndpointTlsConfig<'a> {
+    #[getset(get = "pub")]
+    cert: &'a PathBuf,
+
+    #[getset(get = "pub")]
+    key: &'a PathBuf,
+
+    #[getset(get = "pub")]
+    ca: &'a PathBuf,
+}
+
+impl Endpoint {
+    /// Get the TLS client configuration of the endpoint (if any)
+    ///
+    /// Fails if only some of the TLS settings (`tls_cert`, `tls_key`, and `tls_ca`) are set.
+    pub fn tls_config(&self) -> Result<Option<EndpointTlsConfig<'_>>> {
+        match (
+            self.tls_cert.as_ref(),
+            self.tls_key.as_ref(),
+            self.tls_ca.as_ref(),
+        ) {
+            (None, None, None) => Ok(None),
+            (Some(cert), Some(key), Some(ca)) => Ok(Some(EndpointTlsConfig { cert, key, ca })),
+            (cert, key, ca) => {
+                let missing = [("tls_cert", cert), ("tls_key", key), ("tls_ca", ca)]
+                    .into_iter()
+                    .filter(|(_, path)| path.is_none())
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>();
+                Err(anyhow!(
+                    "Incomplete TLS configuration, the following settings are missing: {}",
+                    missing.join(", ")
+                ))
+            }
+        }
+    }
 }
 
 /// The type of an endpoint
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub enum EndpointType {
     #[serde(rename = "socket")]
     Socket,
@@ -35,4 +35,7 @@ pub use endpoint_config::*;
 mod not_validated;
 pub use not_validated::*;
 
+mod release_signing_config;
+pub use release_signing_config::*;
+
 mod util;
@@ -12,14 +12,28 @@ use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use getset::Getters;
+use itertools::Itertools;
 use serde::Deserialize;
+use serde::Serialize;
+use serde::Serializer;
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
 use std::path::PathBuf;
+use tracing::warn;
 
 use crate::config::util::*;
 use crate::config::Configuration;
 use crate::config::ContainerConfig;
 use crate::config::DockerConfig;
+use crate::config::EndpointType;
+use crate::config::ReleaseSigningConfig;
+use crate::package::HttpHeaders;
+use crate::package::PackageName;
 use crate::package::PhaseName;
+use crate::package::Shebang;
+use crate::util::docker::ImageName;
+use crate::util::docker::ImageNameLookup;
 
 // The configuration version must be increased each time breaking configuration changes are made
 // (that require users to update their configurations) and the required changes must be documented
@@ -27,7 +41,10 @@ use crate::package::PhaseName;
 const CONFIGURATION_VERSION: u16 = 1;
 
 /// The configuration that is loaded from the filesystem
-#[derive(Debug, Getters, Deserialize)]
+///
+/// Secrets (the database password and the values of the HTTP headers) are masked when the
+/// configuration is serialized.
+#[derive(Debug, Getters, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct NotValidatedConfiguration {
     /// Compatibility setting to check if the butido configuration from the user is compatible with
@@ -64,6 +81,17 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     build_error_lines: usize,
 
+    /// The maximum number of log lines of a job, unlimited if not set
+    #[serde(default)]
+    #[getset(get = "pub")]
+    max_log_lines: Option<usize>,
+
+    /// Whether the time at which a log line was received is stored with the line in the
+    /// database (required for "db log-of --timestamps", not readable by older butido versions)
+    #[serde(default)]
+    #[getset(get = "pub")]
+    record_log_times: bool,
+
     /// The theme used to highlight scripts when printing them to the CLI
     #[getset(get = "pub")]
     script_highlight_theme: Option<String>,
@@ -72,6 +100,12 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     script_linter: Option<PathBuf>,
 
+    /// The executable that is run for each artifact of a job before it is accepted, the job fails
+    /// if it exits non-zero
+    #[serde(default)]
+    #[getset(get = "pub")]
+    artifact_verifier: Option<PathBuf>,
+
     /// The shebang that is added at the very beginning of the package scripts
     #[serde(default = "default_script_shebang")]
     #[getset(get = "pub")]
@@ -86,6 +120,29 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     release_stores: Vec<String>,
 
+    /// The path of released files inside the release store
+    ///
+    /// This is handlebars syntax
+    #[serde(default = "default_release_path_template")]
+    #[getset(get = "pub")]
+    release_path_template: String,
+
+    /// Whether a released file is hardlinked to a file with identical content in the same release
+    /// store (if there is one) instead of being copied
+    #[serde(default)]
+    #[getset(get = "pub")]
+    dedup_releases: bool,
+
+    /// The configuration for signing released files, releases are not signed if this is not set
+    #[serde(default)]
+    #[getset(get = "pub")]
+    release_signing: Option<ReleaseSigningConfig>,
+
+    /// The file where the index of the artifacts is stored, no index is used if this is not set
+    #[serde(default)]
+    #[getset(get = "pub")]
+    artifact_index: Option<PathBuf>,
+
     /// The directory where intermediate ("staging") artifacts are stored.
     /// This is used as a root directory, a UUID-named directory will be added below this, using
     /// the UUID of the submit
@@ -93,11 +150,37 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     staging_directory: PathBuf,
 
+    /// How long staging directories are kept before "staging gc" removes them (if they are not
+    /// needed anymore), e.g. "30days"
+    #[serde(default)]
+    #[getset(get = "pub")]
+    staging_retention: Option<String>,
+
     /// Where the sources are cached
     #[serde(rename = "source_cache")]
     #[getset(get = "pub")]
     source_cache_root: PathBuf,
 
+    /// The User-Agent for downloading sources (can be overridden per source)
+    #[serde(default)]
+    #[getset(get = "pub")]
+    source_download_user_agent: Option<String>,
+
+    /// The maximum size of a source in bytes, larger downloads are aborted, unlimited if not set
+    #[serde(default)]
+    #[getset(get = "pub")]
+    max_source_size: Option<u64>,
+
+    /// Additional HTTP headers for downloading sources (can be overridden per source)
+    #[serde(default, serialize_with = "serialize_masked_headers")]
+    #[getset(get = "pub")]
+    source_download_headers: HttpHeaders,
+
+    /// The value for `${mirror_base}` in the URLs of sources
+    #[serde(default)]
+    #[getset(get = "pub")]
+    source_mirror_base: Option<String>,
+
     /// The hostname/FQDN/IP used to connect to the database
     #[getset(get = "pub")]
     database_host: String,
@@ -111,6 +194,7 @@ pub struct NotValidatedConfiguration {
     database_user: String,
 
     /// The password used to connect to the database
+    #[serde(serialize_with = "serialize_masked")]
     #[getset(get = "pub")]
     database_password: String,
 
@@ -123,6 +207,21 @@ pub struct NotValidatedConfiguration {
     #[serde(default = "default_database_connection_timeout")]
     database_connection_timeout: u16,
 
+    /// The maximum number of connections in the database connection pool
+    #[getset(get = "pub")]
+    #[serde(default = "default_database_pool_max_size")]
+    database_pool_max_size: u32,
+
+    /// The minimum number of idle connections that the database connection pool maintains
+    #[getset(get = "pub")]
+    #[serde(default = "default_database_pool_min_idle")]
+    database_pool_min_idle: u32,
+
+    /// How long to wait for a free connection from the database connection pool (in seconds)
+    #[getset(get = "pub")]
+    #[serde(default = "default_database_pool_timeout")]
+    database_pool_timeout: u16,
+
     /// The default limit for database queries (when listing tables with the `db` subcommand;
     /// 0=unlimited (not recommended as it might result in OOM kills))
     #[serde(default = "default_database_query_limit")]
@@ -140,6 +239,40 @@ pub struct NotValidatedConfiguration {
     /// The names of the phases which should be compiled into the packaging script
     #[getset(get = "pub")]
     available_phases: Vec<PhaseName>,
+
+    /// The packages that are used to satisfy dependencies on virtual packages that are provided
+    /// by multiple packages (virtual package name -> package name)
+    #[serde(default)]
+    #[getset(get = "pub")]
+    virtual_package_providers: HashMap<PackageName, PackageName>,
+
+    /// The configuration profiles (`profiles.<NAME>`), the selected profile is already applied
+    /// when the configuration is loaded (see `load_profile()`)
+    #[serde(default, rename = "profiles", skip_serializing)]
+    _profiles: Option<serde::de::IgnoredAny>,
+
+    /// The selected configuration profile (`BUTIDO_PROFILE`), see `_profiles`
+    #[serde(default, rename = "profile", skip_serializing)]
+    _profile: Option<serde::de::IgnoredAny>,
+
+    /// The strict mode (`BUTIDO_STRICT`), this is not a setting but picked up from the
+    /// environment
+    #[serde(default, rename = "strict", skip_serializing)]
+    _strict: Option<serde::de::IgnoredAny>,
+}
+
+/// The replacement for secrets when the configuration is serialized
+const MASKED_SECRET: &str = "********";
+
+fn serialize_masked<S: Serializer>(_: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(MASKED_SECRET)
+}
+
+fn serialize_masked_headers<S: Serializer>(
+    headers: &HttpHeaders,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(headers.iter().map(|(name, _)| (name, MASKED_SECRET)))
 }
 
 fn load_changelog() -> Result<std::collections::HashMap<String, String>> {
@@ -149,6 +282,63 @@ fn load_changelog() -> Result<std::collections::HashMap<String, String>> {
     toml::from_str(changelog_toml).context("Butido bug: Couldn't parse the embedded CHANGELOG.toml")
 }
 
+/// Load the settings of the configuration profile `profile` (`profiles.<NAME>`)
+///
+/// The returned configuration only contains the settings of the profile and is meant to be added
+/// as a source on top of the configuration files, so that the settings of the profile override
+/// the settings from the files (tables are merged, all other values are replaced).
+pub fn load_profile(config: &config::Config, profile: &str) -> Result<config::Config> {
+    let mut profiles = match config.get_table("profiles") {
+        Ok(profiles) => profiles,
+        Err(config::ConfigError::NotFound(_)) => config::Map::new(),
+        Err(e) => return Err(e).context("Failed to load the configuration profiles"),
+    };
+
+    let settings = profiles.remove(profile).ok_or_else(|| {
+        let available = profiles.keys().sorted().join(", ");
+        if available.is_empty() {
+            anyhow!("Unknown profile '{}', no profiles are configured", profile)
+        } else {
+            anyhow!(
+                "Unknown profile '{}', available profiles: {}",
+                profile,
+                available
+            )
+        }
+    })?;
+    let settings = settings
+        .into_table()
+        .with_context(|| anyhow!("The profile '{}' must be a table", profile))?;
+
+    let mut overrides = Vec::new();
+    flatten_table(None, settings, &mut overrides);
+    overrides
+        .into_iter()
+        .try_fold(config::Config::builder(), |builder, (key, value)| {
+            builder.set_override(key, value)
+        })
+        .and_then(|builder| builder.build())
+        .with_context(|| anyhow!("Failed to load the profile '{}'", profile))
+}
+
+/// Collect the non-table values of `table` with their full (dotted) keys
+fn flatten_table(
+    prefix: Option<&str>,
+    table: config::Map<String, config::Value>,
+    values: &mut Vec<(String, config::Value)>,
+) {
+    for (key, value) in table {
+        let key = match prefix {
+            Some(prefix) => format!("{prefix}.{key}"),
+            None => key,
+        };
+        match value.clone().into_table() {
+            Ok(table) => flatten_table(Some(&key), table, values),
+            Err(_) => values.push((key, value)),
+        }
+    }
+}
+
 // Helper function to check if the configuration should be compatible before loading (type checking) it:
 pub fn check_compatibility(config: &config::Config) -> Result<()> {
     // We don't use config.get_int() as it is petty lax and, e.g., converts `true` to `1`:
@@ -207,25 +397,79 @@ pub fn check_compatibility(config: &config::Config) -> Result<()> {
 }
 
 impl NotValidatedConfiguration {
+    /// Get the shebang for the package scripts that are run in the `image`
+    ///
+    /// This is the shebang that is configured for the image, if there is one, and the global
+    /// shebang otherwise.
+    pub fn shebang_for_image(&self, image: &ImageName) -> &String {
+        self.docker
+            .images()
+            .iter()
+            .find(|i| i.name == *image)
+            .and_then(|i| i.shebang.as_ref())
+            .unwrap_or(&self.shebang)
+    }
+
+    /// Get the shell the containers of the `image` are started with and that runs the scripts
+    ///
+    /// This is the shell that is configured for the image, if there is one, and `/bin/bash`
+    /// otherwise.
+    pub fn shell_for_image(&self, image: &ImageName) -> &str {
+        self.docker
+            .images()
+            .iter()
+            .find(|i| i.name == *image)
+            .and_then(|i| i.shell.as_deref())
+            .unwrap_or(crate::consts::DEFAULT_CONTAINER_SHELL)
+    }
+
     /// Validate the NotValidatedConfiguration object and make it into a Configuration object, if
     /// validation succeeds
     ///
     /// This function does sanity-checking on the configuration values.
     /// It fails with the appropriate error message if a setting is bogus.
-    pub fn validate(self) -> Result<Configuration> {
-        self.validate_config(false)
+    /// Validate the configuration
+    ///
+    /// Relative paths in the configuration (`script_linter`, `artifact_verifier`) are resolved
+    /// relative to the repository at `repo_path`.
+    /// Settings that are suspicious but usable result in warnings, which are errors if `strict`
+    /// is set.
+    pub fn validate(self, repo_path: &Path, strict: bool) -> Result<Configuration> {
+        let (config, warnings) = self.validate_config(repo_path, false)?;
+        if strict && !warnings.is_empty() {
+            return Err(anyhow!("{}", warnings.join("\n")))
+                .context("The configuration has warnings, which are errors in strict mode");
+        }
+        for warning in warnings {
+            warn!("{}", warning);
+        }
+        Ok(config)
     }
-    fn validate_config(self, skip_filesystem_checks: bool) -> Result<Configuration> {
+
+    /// Validate the configuration and return it, together with the warnings
+    fn validate_config(
+        self,
+        repo_path: &Path,
+        skip_filesystem_checks: bool,
+    ) -> Result<(Configuration, Vec<String>)> {
+        let mut warnings = Vec::new();
+
         // A trivial helper to check if a directory is missing:
         let check_directory_exists = |path: &PathBuf, config_key_name: &str| -> Result<()> {
             if skip_filesystem_checks || path.is_dir() {
                 Ok(())
-            } else {
+            } else if path.exists() {
                 Err(anyhow!(
                     "Not a directory: {} = {}",
                     config_key_name,
                     path.display()
                 ))
+            } else {
+                Err(anyhow!(
+                    "The directory does not exist, it has to be created first: {} = {}",
+                    config_key_name,
+                    path.display()
+                ))
             }
         };
 
@@ -242,12 +486,153 @@ impl NotValidatedConfiguration {
         check_directory_exists(&self.staging_directory, "staging")?;
         check_directory_exists(&self.source_cache_root, "source_cache")?;
 
+        // The artifact index is created when it is used, but it must not be a directory:
+        if let Some(artifact_index) = self.artifact_index.as_ref() {
+            if !skip_filesystem_checks && artifact_index.is_dir() {
+                return Err(anyhow!(
+                    "Not a file: artifact_index = {}",
+                    artifact_index.display()
+                ));
+            }
+        }
+
+        // The directories of the release stores are created when they are used, so only warn
+        // about missing ones (e.g. in case of a typo in the name):
+        if !skip_filesystem_checks {
+            for release_store in self.release_stores.iter() {
+                let path = self.releases_directory.join(release_store);
+                if !path.exists() {
+                    warnings.push(format!(
+                        "The directory of the release store {} does not exist yet, it will be created when it is used: {}",
+                        release_store,
+                        path.display()
+                    ));
+                } else if !path.is_dir() {
+                    return Err(anyhow!(
+                        "Not a directory: The release store {} in releases_root = {}",
+                        release_store,
+                        path.display()
+                    ));
+                }
+            }
+        }
+
+        // Error if the linter cannot be found (a relative path is relative to the repository),
+        // otherwise every command that lints scripts would fail:
+        if let Some(linter) = self.script_linter.as_ref() {
+            let path = repo_path.join(linter);
+            if !skip_filesystem_checks {
+                if !path.is_file() {
+                    return Err(anyhow!(
+                        "Cannot find the linter: script_linter = {} (resolved to {})",
+                        linter.display(),
+                        path.display()
+                    ));
+                }
+                if path.metadata()?.permissions().mode() & 0o111 == 0 {
+                    warnings.push(format!(
+                        "The linter is not executable: script_linter = {}",
+                        path.display()
+                    ));
+                }
+            }
+        }
+
+        // Error if the artifact verifier cannot be found, otherwise every job would fail:
+        if let Some(verifier) = self.artifact_verifier.as_ref() {
+            let path = repo_path.join(verifier);
+            if !skip_filesystem_checks && !path.is_file() {
+                return Err(anyhow!(
+                    "Cannot find the artifact verifier: artifact_verifier = {} (resolved to {})",
+                    verifier.display(),
+                    path.display()
+                ));
+            }
+        }
+
+        if let Some(retention) = self.staging_retention.as_ref() {
+            humantime::parse_duration(retention)
+                .with_context(|| anyhow!("Invalid duration: staging_retention = {}", retention))?;
+        }
+
+        // Error if the TLS configuration of an endpoint is incomplete, used with a socket
+        // endpoint, or references missing files:
+        for (endpoint_name, endpoint) in self.docker.endpoints().iter() {
+            let tls_config = endpoint
+                .tls_config()
+                .with_context(|| anyhow!("Invalid configuration for endpoint {}", endpoint_name))?;
+
+            if let Some(tls_config) = tls_config {
+                if *endpoint.endpoint_type() != EndpointType::Http {
+                    return Err(anyhow!(
+                        "TLS settings are only supported for \"http\" endpoints: {}",
+                        endpoint_name
+                    ));
+                }
+
+                for (path, setting) in [
+                    (tls_config.cert(), "tls_cert"),
+                    (tls_config.key(), "tls_key"),
+                    (tls_config.ca(), "tls_ca"),
+                ] {
+                    if !skip_filesystem_checks && !path.is_file() {
+                        return Err(anyhow!(
+                            "Not a file: docker.endpoints.{}.{} = {}",
+                            endpoint_name,
+                            setting,
+                            path.display()
+                        ));
+                    }
+                }
+            }
+        }
+
         if self.release_stores.is_empty() {
             return Err(anyhow!(
                 "You need at least one release store in 'release_stores'"
             ));
         }
 
+        if let Some(release_signing) = self.release_signing.as_ref() {
+            release_signing.validate()?;
+        }
+
+        // Error if the release path template cannot be parsed
+        handlebars::Template::compile(&self.release_path_template).with_context(|| {
+            anyhow!(
+                "Invalid release_path_template: {}",
+                self.release_path_template
+            )
+        })?;
+
+        // Error if a configured shebang is malformed (it would only fail inside the container):
+        Shebang::from(self.shebang.clone())
+            .validate()
+            .context("Invalid configuration: shebang")?;
+        for image in self.docker.images().iter() {
+            if let Some(shebang) = image.shebang.as_ref() {
+                Shebang::from(shebang.clone())
+                    .validate()
+                    .with_context(|| anyhow!("Invalid shebang for the image {}", image.name))?;
+            }
+            if let Some(shell) = image.shell.as_ref() {
+                if !shell.starts_with('/') {
+                    return Err(anyhow!(
+                        "Invalid shell for the image {}: {} is not an absolute path",
+                        image.name,
+                        shell
+                    ));
+                }
+            }
+        }
+
+        // Error if the default image is not one of the configured images
+        if let Some(default_image) = self.docker.default_image().as_ref() {
+            ImageNameLookup::create(self.docker.images())?
+                .expand(default_image)
+                .context("Invalid configuration: docker.default_image")?;
+        }
+
         // Error if there are no phases configured
         if self.available_phases.is_empty() {
             return Err(anyhow!("No phases configured"));
@@ -255,25 +640,17 @@ impl NotValidatedConfiguration {
 
         // Error if script highlighting theme is not valid
         if let Some(configured_theme) = self.script_highlight_theme.as_ref() {
-            let allowed_theme_present = [
-                // from syntect
-                "base16-ocean.dark",
-                "base16-eighties.dark",
-                "base16-mocha.dark",
-                "base16-ocean.light",
-                "InspiredGitHub",
-                "Solarized (dark)",
-                "Solarized (light)",
-            ]
-            .iter()
-            .any(|allowed_theme| configured_theme == *allowed_theme);
-
-            if !allowed_theme_present {
-                return Err(anyhow!("Theme not known: {}", configured_theme));
+            let themes = crate::package::highlight_themes();
+            if !themes.contains(configured_theme) {
+                return Err(anyhow!(
+                    "Theme not known: script_highlight_theme = {}, the available themes are: {}",
+                    configured_theme,
+                    themes.join(", ")
+                ));
             }
         }
 
-        Ok(Configuration { inner: self })
+        Ok((Configuration { inner: self }, warnings))
     }
 }
 
@@ -281,8 +658,13 @@ impl NotValidatedConfiguration {
 mod tests {
     use super::check_compatibility;
     use super::load_changelog;
+    use super::load_profile;
     use super::NotValidatedConfiguration;
     use super::CONFIGURATION_VERSION;
+    use super::MASKED_SECRET;
+
+    use std::path::Path;
+    use std::path::PathBuf;
 
     use anyhow::Result;
 
@@ -305,7 +687,7 @@ mod tests {
         assert!(check_compatibility(&config).is_ok());
         let config = config.try_deserialize::<NotValidatedConfiguration>();
         assert!(config.is_ok(), "Config loading failed: {config:?}");
-        let config = config.unwrap().validate_config(true);
+        let config = config.unwrap().validate_config(Path::new("."), true);
         assert!(config.is_ok(), "Config validation failed: {config:?}");
 
         Ok(())
@@ -318,6 +700,175 @@ mod tests {
         Ok(())
     }
 
+    /// Create a new temporary directory with the subdirectories `dirs`
+    fn tmp_dir_with(dirs: &[&str]) -> Result<PathBuf> {
+        let tmp = std::env::temp_dir().join(format!("butido-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp)?;
+        for dir in dirs {
+            std::fs::create_dir_all(tmp.join(dir))?;
+        }
+        Ok(tmp)
+    }
+
+    /// Load the example configuration with all directories in `tmp`
+    fn example_configuration_in(tmp: &Path) -> Result<NotValidatedConfiguration> {
+        let mut config = config::Config::builder()
+            .add_source(config::File::with_name("config.toml"))
+            .build()?
+            .try_deserialize::<NotValidatedConfiguration>()?;
+        config.log_dir = tmp.join("logs");
+        config.releases_directory = tmp.join("releases");
+        config.staging_directory = tmp.join("staging");
+        config.source_cache_root = tmp.join("sources");
+        Ok(config)
+    }
+
+    #[test]
+    fn test_missing_source_cache_root() -> Result<()> {
+        let tmp = tmp_dir_with(&["logs", "releases", "staging"])?;
+        let err = example_configuration_in(&tmp)?
+            .validate(&tmp, false)
+            .unwrap_err();
+        std::fs::remove_dir_all(&tmp)?;
+
+        let err = err.to_string();
+        assert!(err.contains("The directory does not exist"), "{err}");
+        assert!(
+            err.contains(&format!("source_cache = {}", tmp.join("sources").display())),
+            "{err}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_cache_root_is_a_file() -> Result<()> {
+        let tmp = tmp_dir_with(&["logs", "releases", "staging"])?;
+        std::fs::write(tmp.join("sources"), "")?;
+        let err = example_configuration_in(&tmp)?
+            .validate(&tmp, false)
+            .unwrap_err();
+        std::fs::remove_dir_all(&tmp)?;
+
+        assert!(
+            err.to_string().contains("Not a directory: source_cache"),
+            "{err}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_script_linter() -> Result<()> {
+        let tmp = tmp_dir_with(&["logs", "releases", "staging", "sources"])?;
+
+        let mut config = example_configuration_in(&tmp)?;
+        config.script_linter = Some(PathBuf::from("scripts/lint"));
+        let err = config.validate(&tmp, false).unwrap_err();
+
+        let mut config = example_configuration_in(&tmp)?;
+        config.script_linter = None;
+        let ok = config.validate(&tmp, false);
+        std::fs::remove_dir_all(&tmp)?;
+
+        assert!(
+            err.to_string()
+                .contains("Cannot find the linter: script_linter = scripts/lint"),
+            "{err}"
+        );
+        assert!(ok.is_ok(), "{ok:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_artifact_verifier() -> Result<()> {
+        let tmp = tmp_dir_with(&["logs", "releases", "staging", "sources"])?;
+
+        let mut config = example_configuration_in(&tmp)?;
+        config.artifact_verifier = Some(PathBuf::from("scripts/verify"));
+        let err = config.validate(&tmp, false).unwrap_err();
+        std::fs::remove_dir_all(&tmp)?;
+
+        assert!(
+            err.to_string()
+                .contains("Cannot find the artifact verifier: artifact_verifier = scripts/verify"),
+            "{err}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_mode() -> Result<()> {
+        // The directories of the release stores are missing, which is only a warning:
+        let tmp = tmp_dir_with(&["logs", "releases", "staging", "sources"])?;
+        let ok = example_configuration_in(&tmp)?.validate(&tmp, false);
+        let err = example_configuration_in(&tmp)?.validate(&tmp, true);
+        std::fs::remove_dir_all(&tmp)?;
+
+        assert!(ok.is_ok(), "{ok:?}");
+        let err = format!("{:#}", err.unwrap_err());
+        assert!(err.contains("strict mode"), "{err}");
+        assert!(err.contains("The directory of the release store"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialize_masks_secrets() -> Result<()> {
+        let config = config::Config::builder()
+            .add_source(config::File::with_name("config.toml"))
+            .set_override("source_download_headers.Authorization", "Bearer secret")?
+            .build()?
+            .try_deserialize::<NotValidatedConfiguration>()?;
+
+        let serialized = toml::to_string(&config)?;
+        assert!(!serialized.contains("Bearer secret"), "{serialized}");
+
+        // The serialized configuration is a valid configuration (with the secrets masked):
+        let deserialized = toml::from_str::<NotValidatedConfiguration>(&serialized)?;
+        assert_eq!(deserialized.database_password, MASKED_SECRET);
+        let header_values = deserialized
+            .source_download_headers
+            .iter()
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(header_values, vec![MASKED_SECRET]);
+        assert_eq!(deserialized.database_host, config.database_host);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_profile() -> Result<()> {
+        let toml = indoc::indoc!(
+            r#"
+            releases_root = "/releases"
+            [database]
+            host = "localhost"
+            port = 5432
+            [profiles.prod]
+            releases_root = "/prod/releases"
+            [profiles.prod.database]
+            host = "db.example.com"
+            "#
+        );
+        let files = config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()?;
+
+        let profile = load_profile(&files, "prod")?;
+        let config = config::Config::builder()
+            .add_source(files.clone())
+            .add_source(profile)
+            .build()?;
+        assert_eq!(config.get_string("releases_root")?, "/prod/releases");
+        assert_eq!(config.get_string("database.host")?, "db.example.com");
+        assert_eq!(config.get_int("database.port")?, 5432);
+
+        let err = load_profile(&files, "dev").unwrap_err();
+        assert!(
+            err.to_string().contains("available profiles: prod"),
+            "{err}"
+        );
+        Ok(())
+    }
+
     #[test]
     // A test to ensure the example repo config file is up-to-date and valid
     fn test_loading_example_repo_configuration_file() -> Result<()> {
@@ -0,0 +1,68 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Result;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The placeholder for the path of the released file in the signing commands
+pub const SIGNING_ARTIFACT_PLACEHOLDER: &str = "{{artifact}}";
+
+/// The placeholder for the path of the signature in the signing commands
+pub const SIGNING_SIGNATURE_PLACEHOLDER: &str = "{{signature}}";
+
+fn default_signature_extension() -> String {
+    String::from("sig")
+}
+
+/// The configuration for signing released artifacts
+///
+/// The commands are given as program and arguments, the placeholders
+/// [SIGNING_ARTIFACT_PLACEHOLDER] and [SIGNING_SIGNATURE_PLACEHOLDER] in the arguments are
+/// replaced with the paths of the released file and of its detached signature.
+#[derive(Debug, Getters, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReleaseSigningConfig {
+    /// The command that creates the detached signature of a released file
+    #[getset(get = "pub")]
+    sign_command: Vec<String>,
+
+    /// The command that verifies the detached signature of a released file (used by
+    /// "release verify --signatures")
+    #[serde(default)]
+    #[getset(get = "pub")]
+    verify_command: Option<Vec<String>>,
+
+    /// The extension of the signatures, which are stored next to the released files
+    /// ("<FILE>.<EXTENSION>")
+    #[serde(default = "default_signature_extension")]
+    #[getset(get = "pub")]
+    signature_extension: String,
+}
+
+impl ReleaseSigningConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.sign_command.is_empty() {
+            return Err(anyhow!("release_signing.sign_command must not be empty"));
+        }
+        if self.verify_command.as_ref().is_some_and(Vec::is_empty) {
+            return Err(anyhow!("release_signing.verify_command must not be empty"));
+        }
+        if self.signature_extension.is_empty() || self.signature_extension.contains('/') {
+            return Err(anyhow!(
+                "Invalid release_signing.signature_extension: '{}'",
+                self.signature_extension
+            ));
+        }
+        Ok(())
+    }
+}
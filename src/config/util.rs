@@ -19,6 +19,14 @@ pub fn default_progress_format() -> String {
     String::from("{elapsed_precise} {percent:>3}% {bar:5.cyan/blue} | {msg}")
 }
 
+/// The default path of released files, which is the path of the artifact in the staging store
+///
+/// If the package was built for a specific architecture, the path is prefixed with it, so that the
+/// artifacts of different architectures do not overwrite each other.
+pub fn default_release_path_template() -> String {
+    String::from("{{#if arch}}{{arch}}/{{/if}}{{path}}")
+}
+
 /// The default format that is used to print one package
 pub fn default_package_print_format() -> String {
     String::from(indoc::indoc!(
@@ -91,6 +99,13 @@ pub fn default_package_print_format() -> String {
             {{/each}}
             {{/if~}}
 
+            {{#if print_field_origins}}
+            Defined in:
+            {{#each field_origins}}
+                {{@key}}: {{this}}
+            {{/each}}
+            {{/if~}}
+
             {{~#if print_script}}
             {{script}}
             {{/if~}}
@@ -114,11 +129,32 @@ pub fn default_build_error_lines() -> usize {
     10
 }
 
+/// The default value for the number of times a job is retried if the endpoint fails before the
+/// build script was started
+pub fn default_endpoint_retry_attempts() -> usize {
+    0
+}
+
 /// The default value for the database connection timeout (in seconds)
 pub fn default_database_connection_timeout() -> u16 {
     30
 }
 
+/// The default value for the maximum number of connections in the database connection pool
+pub fn default_database_pool_max_size() -> u32 {
+    10
+}
+
+/// The default value for the minimum number of idle connections in the database connection pool
+pub fn default_database_pool_min_idle() -> u32 {
+    1
+}
+
+/// The default value for the database connection pool timeout (in seconds)
+pub fn default_database_pool_timeout() -> u16 {
+    30
+}
+
 /// The default value for the number of results/rows that should be returned for DB queries that
 /// list things (LIMIT)
 pub fn default_database_query_limit() -> usize {
@@ -21,3 +21,16 @@ pub const PATCH_DIR_PATH: &str = "/patches";
 
 /// The path where the script that is executed inside the container is copied to.
 pub const SCRIPT_PATH: &str = "/script";
+
+/// The shell that the containers are started with and that runs the script, if no shell is
+/// configured for the image
+pub const DEFAULT_CONTAINER_SHELL: &str = "/bin/bash";
+
+pub const SECRETS_DIR_PATH: &str = "/run/secrets";
+
+/// The labels of the containers butido creates, to find the containers of a submit (or job) on
+/// the endpoints
+pub const CONTAINER_LABEL_SUBMIT: &str = "butido.submit";
+pub const CONTAINER_LABEL_JOB: &str = "butido.job";
+pub const CONTAINER_LABEL_PACKAGE: &str = "butido.package";
+pub const CONTAINER_LABEL_VERSION: &str = "butido.version";
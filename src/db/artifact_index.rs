@@ -0,0 +1,463 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! An on-disk index of the artifacts in the database
+//!
+//! Finding the artifacts for a package with [FindArtifacts](crate::db::FindArtifacts) requires
+//! joining several tables and loading the environment and release of each candidate separately,
+//! which is slow for large databases. The index contains everything that is needed for finding
+//! artifacts, by package name and version, so that a lookup is a map access.
+//!
+//! The index only contains data from the database. Whether an artifact is actually in a store is
+//! still checked with the loaded stores on each lookup, so artifacts that were removed from the
+//! stores are never returned.
+//!
+//! The index records the IDs and the number of the artifacts and releases it contains. If new
+//! artifacts or releases were added to the database, only these are added to the index. If some
+//! were removed (e.g. with "release rm"), the index is rebuilt completely.
+//!
+//! The index file is replaced atomically, so concurrent butido processes never read a partially
+//! written index. If two processes update the index at the same time, both write a complete index
+//! and the last one wins.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::ExpressionMethods;
+use diesel::JoinOnDsl;
+use diesel::PgConnection;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::{debug, trace, warn};
+
+use crate::db::models as dbmodels;
+use crate::schema;
+
+/// The version of the format of the index file, an index with another version is rebuilt
+const INDEX_FORMAT_VERSION: u32 = 2;
+
+/// The state of the database that an index was built for
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+struct IndexFingerprint {
+    max_artifact_id: Option<i32>,
+    artifact_count: i64,
+    max_release_id: Option<i32>,
+    release_count: i64,
+}
+
+impl IndexFingerprint {
+    fn load(conn: &mut PgConnection) -> Result<Self> {
+        Ok(IndexFingerprint {
+            max_artifact_id: schema::artifacts::table
+                .select(diesel::dsl::max(schema::artifacts::id))
+                .first(conn)?,
+            artifact_count: schema::artifacts::table.count().get_result(conn)?,
+            max_release_id: schema::releases::table
+                .select(diesel::dsl::max(schema::releases::id))
+                .first(conn)?,
+            release_count: schema::releases::table.count().get_result(conn)?,
+        })
+    }
+}
+
+/// The release of an indexed artifact
+#[derive(Clone, Debug, Serialize, Deserialize, Getters)]
+pub struct IndexedRelease {
+    /// The path of the released file inside the release store
+    #[getset(get = "pub")]
+    path: String,
+
+    #[getset(get = "pub")]
+    date: NaiveDateTime,
+}
+
+/// An artifact in the index
+#[derive(Clone, Debug, Serialize, Deserialize, Getters)]
+pub struct IndexEntry {
+    artifact_id: i32,
+
+    #[getset(get = "pub")]
+    artifact_path: String,
+
+    /// The name of the image that was requested for the submit that built the artifact
+    #[getset(get = "pub")]
+    image: String,
+
+    /// The architecture the artifact was built for, if any
+    #[getset(get = "pub")]
+    arch: Option<String>,
+
+    /// The hash of the environment of the job, see [env_hash]
+    #[getset(get = "pub")]
+    env_hash: String,
+
+    /// The hash of the script of the job, see [script_hash]
+    #[getset(get = "pub")]
+    script_hash: String,
+
+    /// The latest release of the artifact, if it was released
+    #[getset(get = "pub")]
+    release: Option<IndexedRelease>,
+}
+
+/// The index of the artifacts in the database, see the module documentation
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ArtifactIndex {
+    format_version: u32,
+    fingerprint: IndexFingerprint,
+
+    /// The entries by package name and package version
+    entries: BTreeMap<String, BTreeMap<String, Vec<IndexEntry>>>,
+}
+
+impl ArtifactIndex {
+    /// Load the index from `path`, update it and write it back if it changed
+    ///
+    /// If the file does not exist or cannot be used, the index is built from scratch.
+    pub fn load_updated(path: &Path, conn: &mut PgConnection) -> Result<Self> {
+        let mut index = match Self::load(path) {
+            Ok(Some(index)) => index,
+            Ok(None) => {
+                debug!("No artifact index at {}, building it", path.display());
+                Self::default()
+            }
+            Err(e) => {
+                warn!(
+                    "Cannot use the artifact index at {}, rebuilding it: {:#}",
+                    path.display(),
+                    e
+                );
+                Self::default()
+            }
+        };
+
+        if index.update(conn)? {
+            index.save(path)?;
+        }
+        Ok(index)
+    }
+
+    /// Build the index from scratch and write it to `path`
+    pub fn rebuild(path: &Path, conn: &mut PgConnection) -> Result<Self> {
+        let mut index = Self::default();
+        index.update(conn)?;
+        index.save(path)?;
+        Ok(index)
+    }
+
+    /// Get the indexed artifacts of the package with `name` and `version`
+    pub fn find(&self, name: &str, version: &str) -> &[IndexEntry] {
+        self.entries
+            .get(name)
+            .and_then(|versions| versions.get(version))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Get the number of artifacts in the index
+    pub fn artifact_count(&self) -> usize {
+        self.entries
+            .values()
+            .flat_map(BTreeMap::values)
+            .map(Vec::len)
+            .sum()
+    }
+
+    fn load(path: &Path) -> Result<Option<Self>> {
+        let content = match std::fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| anyhow!("Reading {}", path.display()));
+            }
+        };
+
+        let index = serde_json::from_slice::<Self>(&content)
+            .with_context(|| anyhow!("Parsing {}", path.display()))?;
+        if index.format_version != INDEX_FORMAT_VERSION {
+            return Err(anyhow!(
+                "Unsupported format version {} (expected {})",
+                index.format_version,
+                INDEX_FORMAT_VERSION
+            ));
+        }
+        Ok(Some(index))
+    }
+
+    /// Write the index to `path`
+    ///
+    /// The index is written to a temporary file next to `path` first, which is then renamed, so
+    /// that readers either see the old or the new index.
+    fn save(&self, path: &Path) -> Result<()> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Not a file path: {}", path.display()))?
+            .to_string_lossy();
+        let tmp_path = path.with_file_name(format!(".{}.{}", file_name, uuid::Uuid::new_v4()));
+
+        let content = serde_json::to_vec(self)?;
+        std::fs::write(&tmp_path, content)
+            .with_context(|| anyhow!("Writing {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path)
+            .inspect_err(|_| {
+                let _ = std::fs::remove_file(&tmp_path);
+            })
+            .with_context(|| anyhow!("Renaming {} to {}", tmp_path.display(), path.display()))?;
+        debug!(
+            "Wrote artifact index with {} artifacts",
+            self.artifact_count()
+        );
+        Ok(())
+    }
+
+    /// Update the index to the current state of the database
+    ///
+    /// Returns whether the index changed.
+    fn update(&mut self, conn: &mut PgConnection) -> Result<bool> {
+        conn.build_transaction()
+            .read_only()
+            .repeatable_read()
+            .run::<_, Error, _>(|conn| {
+                let fingerprint = IndexFingerprint::load(conn)?;
+                if self.format_version == INDEX_FORMAT_VERSION && self.fingerprint == fingerprint {
+                    trace!("Artifact index is up to date");
+                    return Ok(false);
+                }
+
+                if self.format_version != INDEX_FORMAT_VERSION
+                    || !self.add_new(conn, &fingerprint)?
+                {
+                    debug!("Artifacts or releases were removed, rebuilding the artifact index");
+                    *self = Self {
+                        format_version: INDEX_FORMAT_VERSION,
+                        ..Self::default()
+                    };
+                    self.add_new(conn, &fingerprint)?;
+                }
+                self.fingerprint = fingerprint;
+                Ok(true)
+            })
+    }
+
+    /// Add the artifacts and releases that were added to the database since the index was
+    /// updated the last time
+    ///
+    /// Returns false if the index cannot be updated incrementally because artifacts or releases
+    /// were removed from the database.
+    fn add_new(&mut self, conn: &mut PgConnection, fingerprint: &IndexFingerprint) -> Result<bool> {
+        let after_artifact_id = self.fingerprint.max_artifact_id.unwrap_or(0);
+        let after_release_id = self.fingerprint.max_release_id.unwrap_or(0);
+
+        let mut job_envs = HashMap::<i32, Vec<(String, String)>>::new();
+        schema::job_envs::table
+            .inner_join(schema::envvars::table)
+            .inner_join(schema::jobs::table.inner_join(schema::artifacts::table))
+            .filter(schema::artifacts::id.gt(after_artifact_id))
+            .select((
+                schema::job_envs::job_id,
+                schema::envvars::name,
+                schema::envvars::value,
+            ))
+            .load::<(i32, String, String)>(conn)?
+            .into_iter()
+            .for_each(|(job_id, name, value)| {
+                job_envs.entry(job_id).or_default().push((name, value));
+            });
+
+        let artifacts = schema::packages::table
+            .inner_join(schema::jobs::table.inner_join(schema::submits::table))
+            .inner_join(schema::artifacts::table.on(schema::jobs::id.eq(schema::artifacts::job_id)))
+            .inner_join(
+                schema::images::table
+                    .on(schema::submits::requested_image_id.eq(schema::images::id)),
+            )
+            .filter(schema::artifacts::id.gt(after_artifact_id))
+            .select((
+                schema::artifacts::id,
+                schema::artifacts::path,
+                schema::jobs::id,
+                schema::packages::name,
+                schema::packages::version,
+                schema::images::name,
+                schema::jobs::arch,
+                schema::jobs::script_text,
+            ))
+            .load::<(
+                i32,
+                String,
+                i32,
+                String,
+                String,
+                String,
+                Option<String>,
+                String,
+            )>(conn)?;
+
+        let new_artifact_count = artifacts.len() as i64;
+        for (artifact_id, artifact_path, job_id, name, version, image, arch, script_text) in
+            artifacts
+        {
+            let env = job_envs.get(&job_id).map(Vec::as_slice).unwrap_or_default();
+            let entry = IndexEntry {
+                artifact_id,
+                artifact_path,
+                image,
+                arch,
+                env_hash: env_hash(env.iter().map(|(k, v)| (k.as_str(), v.as_str()))),
+                script_hash: script_hash(&script_text),
+                release: None,
+            };
+            self.entries
+                .entry(name)
+                .or_default()
+                .entry(version)
+                .or_default()
+                .push(entry);
+        }
+
+        let releases = schema::releases::table
+            .inner_join(schema::artifacts::table)
+            .filter(schema::releases::id.gt(after_release_id))
+            .order_by(schema::releases::id.asc())
+            .select((
+                schema::releases::all_columns,
+                schema::artifacts::all_columns,
+            ))
+            .load::<(dbmodels::Release, dbmodels::Artifact)>(conn)?;
+
+        let new_release_count = releases.len() as i64;
+        let mut entries_by_artifact_id = self
+            .entries
+            .values_mut()
+            .flat_map(BTreeMap::values_mut)
+            .flatten()
+            .map(|entry| (entry.artifact_id, entry))
+            .collect::<HashMap<_, _>>();
+        for (release, artifact) in releases {
+            if let Some(entry) = entries_by_artifact_id.get_mut(&artifact.id) {
+                entry.release = Some(IndexedRelease {
+                    path: release.released_path(&artifact).to_string(),
+                    date: release.release_date,
+                });
+            }
+        }
+
+        // If nothing was removed, the index contains all artifacts and releases now:
+        let complete = self.fingerprint.artifact_count + new_artifact_count
+            == fingerprint.artifact_count
+            && self.fingerprint.release_count + new_release_count == fingerprint.release_count;
+        Ok(complete)
+    }
+}
+
+/// Hash an environment for the index
+///
+/// The order and duplicates of the variables do not matter, i.e., the hashes of two environments
+/// are equal if they contain the same variables.
+pub fn env_hash<'a>(env: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    use sha2::Digest;
+
+    let mut m = sha2::Sha256::new();
+    for (key, value) in env.into_iter().collect::<BTreeSet<_>>() {
+        m.update(key.as_bytes());
+        m.update([0]);
+        m.update(value.as_bytes());
+        m.update([0]);
+    }
+    format!("{:x}", m.finalize())
+}
+
+/// Hash a script for the index
+pub fn script_hash(script: &str) -> String {
+    use sha2::Digest;
+
+    format!("{:x}", sha2::Sha256::digest(script.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(artifact_id: i32, image: &str) -> IndexEntry {
+        IndexEntry {
+            artifact_id,
+            artifact_path: format!("a-{artifact_id}.tar.gz"),
+            image: image.to_string(),
+            arch: None,
+            env_hash: env_hash([("FOO", "1")]),
+            script_hash: script_hash("#!/bin/bash"),
+            release: None,
+        }
+    }
+
+    #[test]
+    fn test_env_hash_ignores_order_and_duplicates() {
+        let h = env_hash([("A", "1"), ("B", "2")]);
+        assert_eq!(h, env_hash([("B", "2"), ("A", "1")]));
+        assert_eq!(h, env_hash([("A", "1"), ("B", "2"), ("A", "1")]));
+        assert_ne!(h, env_hash([("A", "1"), ("B", "3")]));
+        assert_ne!(h, env_hash([("A", "1")]));
+        assert_ne!(env_hash([("AB", "")]), env_hash([("A", "B")]));
+    }
+
+    #[test]
+    fn test_find() {
+        let mut index = ArtifactIndex::default();
+        index
+            .entries
+            .entry("a".to_string())
+            .or_default()
+            .insert("1".to_string(), vec![entry(1, "img1"), entry(2, "img2")]);
+
+        assert_eq!(index.find("a", "1").len(), 2);
+        assert!(index.find("a", "2").is_empty());
+        assert!(index.find("b", "1").is_empty());
+        assert_eq!(index.artifact_count(), 2);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("butido-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("artifact-index.json");
+
+        assert!(ArtifactIndex::load(&path).unwrap().is_none());
+
+        let mut index = ArtifactIndex {
+            format_version: INDEX_FORMAT_VERSION,
+            ..ArtifactIndex::default()
+        };
+        index
+            .entries
+            .entry("a".to_string())
+            .or_default()
+            .insert("1".to_string(), vec![entry(1, "img1")]);
+        index.save(&path).unwrap();
+
+        let loaded = ArtifactIndex::load(&path).unwrap().unwrap();
+        assert_eq!(loaded.find("a", "1")[0].artifact_path(), "a-1.tar.gz");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1); // no temporary files left
+
+        std::fs::write(&path, "{}").unwrap();
+        assert!(ArtifactIndex::load(&path).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -8,14 +8,17 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use anyhow::anyhow;
 use anyhow::Error;
 use anyhow::Result;
 use clap::ArgMatches;
 use diesel::prelude::*;
+use diesel::r2d2::event::TimeoutEvent;
 use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::HandleEvent;
 use diesel::r2d2::Pool;
 use getset::Getters;
-use tracing::debug;
+use tracing::{debug, error};
 
 use crate::config::Configuration;
 
@@ -38,6 +41,15 @@ pub struct DbConnectionConfig<'a> {
 
     #[getset(get = "pub")]
     database_connection_timeout: u16,
+
+    #[getset(get = "pub")]
+    database_pool_max_size: u32,
+
+    #[getset(get = "pub")]
+    database_pool_min_idle: u32,
+
+    #[getset(get = "pub")]
+    database_pool_timeout: u16,
 }
 
 impl std::fmt::Debug for DbConnectionConfig<'_> {
@@ -77,6 +89,18 @@ impl<'a> DbConnectionConfig<'a> {
                 *cli.get_one::<u16>("database_connection_timeout")
                     .unwrap_or_else(|| config.database_connection_timeout())
             },
+            database_pool_max_size: {
+                *cli.get_one::<u32>("database_pool_max_size")
+                    .unwrap_or_else(|| config.database_pool_max_size())
+            },
+            database_pool_min_idle: {
+                *cli.get_one::<u32>("database_pool_min_idle")
+                    .unwrap_or_else(|| config.database_pool_min_idle())
+            },
+            database_pool_timeout: {
+                *cli.get_one::<u16>("database_pool_timeout")
+                    .unwrap_or_else(|| config.database_pool_timeout())
+            },
         })
     }
 
@@ -102,10 +126,52 @@ impl<'a> DbConnectionConfig<'a> {
             "Trying to create a connection pool for database: {:?}",
             self
         );
+        // The pool builder panics on invalid values:
+        if self.database_pool_max_size == 0 {
+            return Err(anyhow!("The database pool size must be at least 1"));
+        }
+        if self.database_pool_min_idle > self.database_pool_max_size {
+            return Err(anyhow!(
+                "The minimum number of idle database connections ({}) must not exceed the pool size ({})",
+                self.database_pool_min_idle,
+                self.database_pool_max_size
+            ));
+        }
+        if self.database_pool_timeout == 0 {
+            return Err(anyhow!(
+                "The database pool timeout must be at least 1 second"
+            ));
+        }
+
+        let max_size = self.database_pool_max_size;
+        let min_idle = self.database_pool_min_idle;
+        let timeout = std::time::Duration::from_secs(self.database_pool_timeout.into());
         let manager = ConnectionManager::<PgConnection>::new(self.get_database_uri());
         Pool::builder()
-            .min_idle(Some(1))
+            .max_size(max_size)
+            .min_idle(Some(min_idle))
+            .connection_timeout(timeout)
+            .event_handler(Box::new(PoolEventHandler { max_size }))
             .build(manager)
             .map_err(Error::from)
     }
 }
+
+/// Reports when no connection could be taken from the pool in time
+///
+/// Otherwise the only hint is the (generic) error from the pool, which doesn't tell that the pool
+/// was exhausted.
+#[derive(Debug)]
+struct PoolEventHandler {
+    max_size: u32,
+}
+
+impl HandleEvent for PoolEventHandler {
+    fn handle_timeout(&self, event: TimeoutEvent) {
+        error!(
+            "Timed out after {:?} while waiting for a database connection, all {} connections of the pool are in use (see the database_pool_max_size setting)",
+            event.timeout(),
+            self.max_size
+        );
+    }
+}
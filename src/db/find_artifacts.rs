@@ -28,6 +28,7 @@ use tracing::{debug, trace};
 
 use crate::config::Configuration;
 use crate::db::models as dbmodels;
+use crate::db::ArtifactIndex;
 use crate::filestore::path::ArtifactPath;
 use crate::filestore::path::FullArtifactPath;
 use crate::filestore::ReleaseStore;
@@ -72,18 +73,41 @@ pub struct FindArtifacts<'a> {
     /// Filter for these environment variables
     env_filter: &'a [(EnvironmentVariableName, String)],
 
+    /// Whether to ignore the environment of the jobs
+    ///
+    /// If set, artifacts are returned even if they were built with a different environment than
+    /// the package environment and the `env_filter`
+    #[builder(default)]
+    ignore_env: bool,
+
     /// Filter for image name
     #[builder(default)]
     image_name: Option<&'a ImageName>,
 
+    /// The architecture the artifacts must have been built for
+    ///
+    /// Artifacts are only returned if they were built for the same architecture, i.e., if this is
+    /// `None` only artifacts that were built without an architecture are returned.
+    #[builder(default)]
+    arch: Option<&'a str>,
+
     /// Search for this package
     package: &'a Package,
+
+    /// The artifact index to search in instead of querying the database, if any
+    #[builder(default)]
+    index: Option<&'a ArtifactIndex>,
 }
 
 impl<'a> FindArtifacts<'a> {
     /// Run the FindArtifact as configured
     pub fn run(self) -> Result<Vec<(FullArtifactPath<'a>, Option<NaiveDateTime>)>> {
-        let shebang = Shebang::from(self.config.shebang().clone());
+        let shebang = Shebang::from(
+            self.image_name
+                .map(|image| self.config.shebang_for_image(image))
+                .unwrap_or_else(|| self.config.shebang())
+                .clone(),
+        );
         let script = if self.script_filter {
             let script = ScriptBuilder::new(&shebang).build(
                 self.package,
@@ -95,6 +119,131 @@ impl<'a> FindArtifacts<'a> {
             None
         };
 
+        let candidates = match self.index {
+            Some(index) => self.candidates_from_index(index, script.as_ref().map(AsRef::as_ref)),
+            None => self.candidates_from_database(script.as_ref().map(AsRef::as_ref))?,
+        };
+
+        candidates
+            .into_iter()
+            .map(|(artifact_path, release_path, ndt)| -> Result<_> {
+                let artpath = ArtifactPath::new(PathBuf::from(artifact_path))?;
+                let release_path = ArtifactPath::new(PathBuf::from(release_path))?;
+                Ok((artpath, release_path, ndt))
+            })
+            .and_then_ok(|(artpath, release_path, ndt)| {
+                if let Some(staging) = self.staging_store.as_ref() {
+                    trace!(
+                        "Searching in staging: {:?} for {:?}",
+                        staging.root_path(),
+                        artpath
+                    );
+                    if let Some(art) = staging.get(&artpath) {
+                        trace!("Found in staging: {:?}", art);
+                        return staging.root_path().join(art).map(|p| p.map(|p| (p, ndt)));
+                    }
+                }
+
+                // If we cannot find the artifact in the release store either, we return None.
+                // This is the case if there indeed was a release, but it was removed from the
+                // filesystem.
+                for release_store in self.release_stores {
+                    if let Some(art) = release_store.get(&release_path) {
+                        trace!("Found in release: {:?}", art);
+                        return release_store
+                            .root_path()
+                            .join(art)
+                            .map(|p| p.map(|p| (p, ndt)));
+                    }
+                }
+
+                trace!(
+                    "Found no release for artifact {:?} in any release store",
+                    artpath.display()
+                );
+                Ok(None)
+            })
+            .filter_map_ok(|opt| opt)
+            .collect::<Result<Vec<(FullArtifactPath<'a>, Option<NaiveDateTime>)>>>()
+    }
+
+    /// Find the artifacts in the `index`
+    ///
+    /// Returns the paths of the artifacts, the paths of their released files (or the paths of
+    /// the artifacts if they were not released) and the release dates.
+    fn candidates_from_index(
+        &self,
+        index: &ArtifactIndex,
+        script_text: Option<&str>,
+    ) -> Vec<(String, String, Option<NaiveDateTime>)> {
+        let script_hash = script_text.map(crate::db::artifact_index::script_hash);
+
+        // The environment of the job has to contain exactly the variables of the package
+        // environment and the `env_filter`, see environments_equal()
+        let env_hash = crate::db::artifact_index::env_hash(
+            self.package
+                .environment()
+                .iter()
+                .flat_map(|env| env.iter())
+                .chain(self.env_filter.iter().map(|(k, v)| (k, v)))
+                .map(|(k, v)| (k.as_ref(), v.as_str())),
+        );
+
+        let is_allowed_image = |image: &str| {
+            let allowed = self
+                .package
+                .allowed_images()
+                .as_ref()
+                .map(|imgs| imgs.iter().any(|i| i.as_ref() == image))
+                .unwrap_or(true);
+            let denied = self
+                .package
+                .denied_images()
+                .as_ref()
+                .map(|imgs| imgs.iter().any(|i| i.as_ref() == image))
+                .unwrap_or(false);
+            let requested = self.image_name.map(|i| i.as_ref() == image).unwrap_or(true);
+            allowed && !denied && requested
+        };
+
+        index
+            .find(
+                self.package.name().as_ref(),
+                self.package.version().as_ref(),
+            )
+            .iter()
+            .filter(|entry| is_allowed_image(entry.image()))
+            .filter(|entry| entry.arch().as_deref() == self.arch)
+            .filter(|entry| {
+                script_hash
+                    .as_ref()
+                    .map(|h| entry.script_hash() == h)
+                    .unwrap_or(true)
+            })
+            .filter(|entry| self.ignore_env || *entry.env_hash() == env_hash)
+            .inspect(|entry| trace!("Found in artifact index: {:?}", entry))
+            .map(|entry| match entry.release() {
+                Some(release) => (
+                    entry.artifact_path().clone(),
+                    release.path().clone(),
+                    Some(*release.date()),
+                ),
+                None => (
+                    entry.artifact_path().clone(),
+                    entry.artifact_path().clone(),
+                    None,
+                ),
+            })
+            .collect()
+    }
+
+    /// Find the artifacts in the database
+    ///
+    /// Returns the same as [FindArtifacts::candidates_from_index].
+    fn candidates_from_database(
+        &self,
+        script_text: Option<&str>,
+    ) -> Result<Vec<(String, String, Option<NaiveDateTime>)>> {
         let package_environment = self.package.environment();
         let mut query = schema::packages::table
             .filter({
@@ -138,14 +287,19 @@ impl<'a> FindArtifacts<'a> {
             query = query.filter(schema::images::name.ne_all(imgs));
         }
 
-        if let Some(script_text) = script.as_ref() {
-            query = query.filter(schema::jobs::script_text.eq(script_text.as_ref()));
+        if let Some(script_text) = script_text {
+            query = query.filter(schema::jobs::script_text.eq(script_text));
         }
 
         if let Some(image_name) = self.image_name.as_ref() {
             query = query.filter(schema::images::name.eq(image_name.as_ref()));
         }
 
+        query = match self.arch {
+            Some(arch) => query.filter(schema::jobs::arch.eq(arch)),
+            None => query.filter(schema::jobs::arch.is_null()),
+        };
+
         trace!("Query = {}", diesel::debug_query(&query));
 
         query
@@ -173,6 +327,10 @@ impl<'a> FindArtifacts<'a> {
                 // This is a Iterator::filter() but because our condition here might fail, we
                 // map() and do the actual filtering later.
 
+                if self.ignore_env {
+                    return Ok((tpl.0, true));
+                }
+
                 let job = tpl.1;
                 let job_env: Vec<(String, String)> = job
                     .env(&mut self.database_pool.get().unwrap())?
@@ -193,46 +351,14 @@ impl<'a> FindArtifacts<'a> {
             })
             .and_then_ok(|(art, _)| {
                 if let Some(release) = art.get_release(&mut self.database_pool.get().unwrap())? {
-                    Ok((art, Some(release.release_date)))
+                    let release_path = release.released_path(&art).to_string();
+                    Ok((art.path, release_path, Some(release.release_date)))
                 } else {
-                    Ok((art, None))
+                    let release_path = art.path.clone();
+                    Ok((art.path, release_path, None))
                 }
             })
-            .and_then_ok(|(p, ndt)| ArtifactPath::new(PathBuf::from(p.path)).map(|a| (a, ndt)))
-            .and_then_ok(|(artpath, ndt)| {
-                if let Some(staging) = self.staging_store.as_ref() {
-                    trace!(
-                        "Searching in staging: {:?} for {:?}",
-                        staging.root_path(),
-                        artpath
-                    );
-                    if let Some(art) = staging.get(&artpath) {
-                        trace!("Found in staging: {:?}", art);
-                        return staging.root_path().join(art).map(|p| p.map(|p| (p, ndt)));
-                    }
-                }
-
-                // If we cannot find the artifact in the release store either, we return None.
-                // This is the case if there indeed was a release, but it was removed from the
-                // filesystem.
-                for release_store in self.release_stores {
-                    if let Some(art) = release_store.get(&artpath) {
-                        trace!("Found in release: {:?}", art);
-                        return release_store
-                            .root_path()
-                            .join(art)
-                            .map(|p| p.map(|p| (p, ndt)));
-                    }
-                }
-
-                trace!(
-                    "Found no release for artifact {:?} in any release store",
-                    artpath.display()
-                );
-                Ok(None)
-            })
-            .filter_map_ok(|opt| opt)
-            .collect::<Result<Vec<(FullArtifactPath<'a>, Option<NaiveDateTime>)>>>()
+            .collect::<Result<Vec<_>>>()
     }
 }
 
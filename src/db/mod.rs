@@ -11,6 +11,9 @@
 mod connection;
 pub use connection::*;
 
+mod artifact_index;
+pub use artifact_index::ArtifactIndex;
+
 mod find_artifacts;
 pub use find_artifacts::FindArtifacts;
 
@@ -8,6 +8,8 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::str::FromStr;
+
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
@@ -36,6 +38,19 @@ pub struct Job {
     pub script_text: String,
     pub log_text: String,
     pub uuid: ::uuid::Uuid,
+    pub peak_memory_bytes: Option<i64>,
+    pub cpu_time_ns: Option<i64>,
+
+    /// Whether the script of the job exited successfully, `None` if this is unknown (or for jobs
+    /// that were recorded before this was stored, see [Job::backfill_success])
+    pub success: Option<bool>,
+
+    /// The architecture the job was built for (`build --arch`), if any
+    pub arch: Option<String>,
+
+    /// Whether `peak_memory_bytes` is the peak memory usage, otherwise it is the memory usage when
+    /// the script finished (because the peak was not available), NULL for older jobs (peak)
+    pub memory_is_peak: Option<bool>,
 }
 
 #[derive(Debug, Insertable)]
@@ -49,6 +64,8 @@ struct NewJob<'a> {
     pub script_text: String,
     pub log_text: String,
     pub uuid: &'a ::uuid::Uuid,
+    pub success: Option<bool>,
+    pub arch: Option<&'a str>,
 }
 
 impl Job {
@@ -63,6 +80,8 @@ impl Job {
         container: &ContainerHash,
         script: &Script,
         log: &str,
+        job_success: Option<bool>,
+        job_arch: Option<&str>,
     ) -> Result<Job> {
         let new_job = NewJob {
             uuid: job_uuid,
@@ -73,6 +92,8 @@ impl Job {
             container_hash: container.as_ref(),
             script_text: script.as_ref().replace('\0', ""),
             log_text: log.replace('\0', ""),
+            success: job_success,
+            arch: job_arch,
         };
 
         trace!("Creating Job in database: {:?}", new_job);
@@ -95,6 +116,101 @@ impl Job {
         })
     }
 
+    /// Record the resource usage of the container the job ran in
+    ///
+    /// Values that are `None` (because they could not be gathered) are stored as NULL.
+    pub fn update_resource_usage(
+        &self,
+        database_connection: &mut PgConnection,
+        job_peak_memory_bytes: Option<i64>,
+        job_memory_is_peak: Option<bool>,
+        job_cpu_time_ns: Option<i64>,
+    ) -> Result<()> {
+        diesel::update(self)
+            .set((
+                peak_memory_bytes.eq(job_peak_memory_bytes),
+                memory_is_peak.eq(job_memory_is_peak),
+                cpu_time_ns.eq(job_cpu_time_ns),
+            ))
+            .execute(database_connection)
+            .with_context(|| format!("Updating resource usage of job {}", self.uuid))
+            .map(|_| ())
+    }
+
+    /// Append the `output` of the artifact verifier to the log of the job and mark the job as
+    /// failed if the verifier did not accept the artifacts
+    pub fn record_artifact_verification(
+        &self,
+        database_connection: &mut PgConnection,
+        output: &str,
+        verified: bool,
+    ) -> Result<()> {
+        let mut log = self.log_text.clone();
+        if !log.is_empty() && !log.ends_with('\n') {
+            log.push('\n');
+        }
+        log.push_str(&output.replace('\0', ""));
+        let job_success = if verified { self.success } else { Some(false) };
+
+        diesel::update(self)
+            .set((log_text.eq(log), success.eq(job_success)))
+            .execute(database_connection)
+            .with_context(|| format!("Recording artifact verification of job {}", self.uuid))
+            .map(|_| ())
+    }
+
+    /// Set the `success` column of the jobs for which it is not set yet, by parsing their logs
+    ///
+    /// Jobs whose result cannot be decided from the log are left as they are. Returns the number
+    /// of updated jobs.
+    pub fn backfill_success(database_connection: &mut PgConnection) -> Result<usize> {
+        let undecided = dsl::jobs
+            .filter(success.is_null())
+            .select((id, log_text))
+            .load::<(i32, String)>(database_connection)
+            .context("Loading jobs without success state")?;
+
+        let (succeeded, failed): (Vec<_>, Vec<_>) = undecided
+            .into_iter()
+            .filter_map(|(job_id, log)| {
+                crate::log::ParsedLog::from_str(&log)
+                    .ok()
+                    .and_then(|parsed_log| parsed_log.is_successfull().to_bool())
+                    .map(|job_success| (job_id, job_success))
+            })
+            .partition(|(_, job_success)| *job_success);
+
+        database_connection.transaction::<_, Error, _>(|conn| {
+            let mut updated = 0;
+            for (ids, job_success) in [(succeeded, true), (failed, false)] {
+                let ids = ids
+                    .into_iter()
+                    .map(|(job_id, _)| job_id)
+                    .collect::<Vec<_>>();
+                updated += diesel::update(dsl::jobs.filter(id.eq_any(ids)))
+                    .set(success.eq(job_success))
+                    .execute(conn)?;
+            }
+            Ok(updated)
+        })
+    }
+
+    /// Get the jobs of the `submit` that finished successfully, i.e., that produced artifacts
+    pub fn successful_for_submit(
+        database_connection: &mut PgConnection,
+        submit: &Submit,
+    ) -> Result<Vec<Job>> {
+        use crate::schema;
+
+        dsl::jobs
+            .filter(submit_id.eq(submit.id))
+            .filter(diesel::dsl::exists(
+                schema::artifacts::table.filter(schema::artifacts::job_id.eq(jobs::id)),
+            ))
+            .load::<Job>(database_connection)
+            .with_context(|| format!("Loading successful jobs of submit {}", submit.uuid))
+    }
+
     pub fn env(
         &self,
         database_connection: &mut PgConnection,
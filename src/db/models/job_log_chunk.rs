@@ -0,0 +1,72 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::Result;
+use diesel::prelude::*;
+
+use crate::schema::job_log_chunks;
+use crate::schema::job_log_chunks::dsl;
+
+/// A part of the log of a job that is still running
+///
+/// The chunks are only used to follow the log of a running job. The complete log is written to
+/// the job itself once the job finished, and the chunks are removed afterwards.
+#[derive(Debug, Identifiable, Queryable)]
+#[diesel(table_name = job_log_chunks)]
+pub struct JobLogChunk {
+    pub id: i32,
+    pub job_uuid: uuid::Uuid,
+    pub log_text: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = job_log_chunks)]
+struct NewJobLogChunk<'a> {
+    pub job_uuid: &'a uuid::Uuid,
+    pub log_text: &'a str,
+}
+
+impl JobLogChunk {
+    pub fn create(
+        database_connection: &mut PgConnection,
+        job: &uuid::Uuid,
+        text: &str,
+    ) -> Result<()> {
+        let new_chunk = NewJobLogChunk {
+            job_uuid: job,
+            log_text: text,
+        };
+
+        diesel::insert_into(job_log_chunks::table)
+            .values(&new_chunk)
+            .execute(database_connection)?;
+        Ok(())
+    }
+
+    /// Load the chunks of a job that were written after the chunk with the ID `after_id`
+    pub fn load_after(
+        database_connection: &mut PgConnection,
+        job: &uuid::Uuid,
+        after_id: i32,
+    ) -> Result<Vec<JobLogChunk>> {
+        dsl::job_log_chunks
+            .filter(dsl::job_uuid.eq(job))
+            .filter(dsl::id.gt(after_id))
+            .order(dsl::id.asc())
+            .load::<JobLogChunk>(database_connection)
+            .map_err(anyhow::Error::from)
+    }
+
+    pub fn delete_for_job(database_connection: &mut PgConnection, job: &uuid::Uuid) -> Result<()> {
+        diesel::delete(dsl::job_log_chunks.filter(dsl::job_uuid.eq(job)))
+            .execute(database_connection)?;
+        Ok(())
+    }
+}
@@ -26,6 +26,9 @@ pub use job::*;
 mod job_env;
 pub use job_env::*;
 
+mod job_log_chunk;
+pub use job_log_chunk::*;
+
 mod githash;
 pub use githash::*;
 
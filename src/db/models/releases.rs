@@ -10,6 +10,8 @@
 
 use anyhow::Error;
 use anyhow::Result;
+use chrono::DateTime;
+use chrono::Local;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 
@@ -26,6 +28,14 @@ pub struct Release {
     pub artifact_id: i32,
     pub release_date: NaiveDateTime,
     pub release_store_id: i32,
+
+    /// The path of the released file inside the release store, if it differs from the path of
+    /// the artifact
+    pub path: Option<String>,
+
+    /// The path of the detached signature of the released file inside the release store, if the
+    /// release was signed
+    pub signature_path: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -34,6 +44,8 @@ struct NewRelease<'a> {
     pub artifact_id: i32,
     pub release_date: &'a NaiveDateTime,
     pub release_store_id: i32,
+    pub path: Option<&'a str>,
+    pub signature_path: Option<&'a str>,
 }
 
 impl Release {
@@ -42,11 +54,15 @@ impl Release {
         art: &Artifact,
         date: &'a NaiveDateTime,
         store: &'a ReleaseStore,
+        release_path: Option<&'a str>,
+        sig_path: Option<&'a str>,
     ) -> Result<Release> {
         let new_rel = NewRelease {
             artifact_id: art.id,
             release_date: date,
             release_store_id: store.id,
+            path: release_path,
+            signature_path: sig_path,
         };
 
         database_connection.transaction::<_, Error, _>(|conn| {
@@ -55,9 +71,75 @@ impl Release {
                 .execute(conn)?;
 
             dsl::releases
-                .filter(artifact_id.eq(art.id).and(release_date.eq(date)))
+                .filter(artifact_id.eq(art.id))
+                .filter(release_date.eq(date))
+                .filter(release_store_id.eq(store.id))
                 .first::<Release>(conn)
                 .map_err(Error::from)
         })
     }
+
+    /// Get the releases in the release store `store_name` that are older than `date`, the oldest
+    /// release first
+    ///
+    /// Returns the releases with their artifacts and the names and versions of the packages.
+    pub fn older_than(
+        database_connection: &mut PgConnection,
+        store_name: &str,
+        date: &DateTime<Local>,
+    ) -> Result<Vec<(Release, Artifact, String, String)>> {
+        use crate::schema;
+
+        schema::releases::table
+            .inner_join(
+                schema::artifacts::table
+                    .inner_join(schema::jobs::table.inner_join(schema::packages::table)),
+            )
+            .inner_join(schema::release_stores::table)
+            .filter(schema::release_stores::store_name.eq(store_name))
+            .filter(schema::releases::release_date.lt(date))
+            .order_by(schema::releases::release_date.asc())
+            .select((
+                schema::releases::all_columns,
+                schema::artifacts::all_columns,
+                schema::packages::name,
+                schema::packages::version,
+            ))
+            .load::<(Release, Artifact, String, String)>(database_connection)
+            .map_err(Error::from)
+    }
+
+    /// Whether a release in the release store `store_name` references the file at `path` (relative
+    /// to the release store)
+    pub fn is_released(
+        database_connection: &mut PgConnection,
+        store_name: &str,
+        released_path: &str,
+    ) -> Result<bool> {
+        use crate::schema;
+
+        let releases = schema::releases::table
+            .inner_join(schema::artifacts::table)
+            .inner_join(schema::release_stores::table)
+            .filter(schema::release_stores::store_name.eq(store_name))
+            .filter(
+                schema::releases::path
+                    .eq(released_path)
+                    .or(schema::artifacts::path.eq(released_path)),
+            )
+            .select((
+                schema::releases::all_columns,
+                schema::artifacts::all_columns,
+            ))
+            .load::<(Release, Artifact)>(database_connection)?;
+
+        Ok(releases
+            .iter()
+            .any(|(release, art)| release.released_path(art) == released_path))
+    }
+
+    /// The path of the released file inside the release store
+    pub fn released_path<'a>(&'a self, art: &'a Artifact) -> &'a str {
+        self.path.as_deref().unwrap_or(&art.path)
+    }
 }
@@ -31,6 +31,13 @@ pub struct Submit {
     pub requested_image_id: i32,
     pub requested_package_id: i32,
     pub repo_hash_id: i32,
+
+    /// The version of butido that created the submit (`git describe`), `None` for submits that
+    /// were created before the version was recorded
+    pub butido_version: Option<String>,
+
+    /// Whether the build of the submit was interrupted by a signal (and can be resumed)
+    pub interrupted: bool,
 }
 
 #[derive(Insertable)]
@@ -41,6 +48,7 @@ struct NewSubmit<'a> {
     pub requested_image_id: i32,
     pub requested_package_id: i32,
     pub repo_hash_id: i32,
+    pub butido_version: &'a str,
 }
 
 impl Submit {
@@ -51,6 +59,7 @@ impl Submit {
         requested_image: &Image,
         requested_package: &Package,
         repo_hash: &GitHash,
+        version: &str,
     ) -> Result<Submit> {
         let new_submit = NewSubmit {
             uuid: submit_id,
@@ -58,6 +67,7 @@ impl Submit {
             requested_image_id: requested_image.id,
             requested_package_id: requested_package.id,
             repo_hash_id: repo_hash.id,
+            butido_version: version,
         };
 
         database_connection.transaction::<_, Error, _>(|conn| {
@@ -81,4 +91,17 @@ impl Submit {
             .first::<Submit>(database_connection)
             .context("Loading submit")
     }
+
+    /// Record whether the build of the submit `submit_id` was interrupted
+    pub fn set_interrupted(
+        database_connection: &mut PgConnection,
+        submit_id: &::uuid::Uuid,
+        is_interrupted: bool,
+    ) -> Result<()> {
+        diesel::update(dsl::submits.filter(submits::uuid.eq(submit_id)))
+            .set(interrupted.eq(is_interrupted))
+            .execute(database_connection)
+            .context("Updating the interrupted state of the submit")?;
+        Ok(())
+    }
 }
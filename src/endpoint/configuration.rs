@@ -32,4 +32,9 @@ pub struct EndpointConfiguration {
     #[getset(get = "pub")]
     #[builder(default)]
     required_docker_api_versions: Option<Vec<String>>,
+
+    /// Overrides the network mode that is configured for the endpoint
+    #[getset(get = "pub")]
+    #[builder(default)]
+    network_mode: Option<String>,
 }
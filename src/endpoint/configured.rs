@@ -8,10 +8,13 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
+use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::OnceLock;
 
 use anyhow::anyhow;
 use anyhow::Context;
@@ -25,10 +28,13 @@ use shiplift::ExecContainerOptions;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::RwLock;
 use tokio_stream::StreamExt;
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 use typed_builder::TypedBuilder;
+use uuid::Uuid;
 
+use crate::config::Configuration;
 use crate::config::EndpointName;
+use crate::config::EndpointTlsConfig;
 use crate::endpoint::EndpointConfiguration;
 use crate::filestore::path::ArtifactPath;
 use crate::filestore::ReleaseStore;
@@ -41,6 +47,61 @@ use crate::package::Script;
 use crate::util::docker::ContainerHash;
 use crate::util::docker::ImageName;
 
+/// The Docker clients of the endpoints with TLS settings, see [create_tls_clients]
+static TLS_CLIENTS: OnceLock<HashMap<EndpointName, Result<Docker>>> = OnceLock::new();
+
+/// Create the Docker clients of all configured endpoints with TLS settings
+///
+/// shiplift only reads the TLS client certificates from the environment (see
+/// `Endpoint::setup_tls_docker()`) and modifying the environment is only sound while no other
+/// threads are running. Therefore, this has to be called before the async runtime is started.
+/// Errors are reported when an endpoint is set up, so that a broken endpoint doesn't affect
+/// commands that don't use it.
+pub fn create_tls_clients(config: &Configuration) {
+    let clients = config
+        .docker()
+        .endpoints()
+        .iter()
+        .filter_map(|(ep_name, ep)| {
+            // Invalid TLS settings are reported by `Endpoint::setup_endpoint()`
+            let tls_config = ep.tls_config().ok().flatten()?;
+            let docker = shiplift::Uri::from_str(ep.uri())
+                .map_err(Error::from)
+                .and_then(|uri| Endpoint::setup_tls_docker(uri, &tls_config));
+            Some((ep_name.clone(), docker))
+        })
+        .collect();
+
+    // There is only one configuration, so only the first call has to have an effect
+    let _ = TLS_CLIENTS.set(clients);
+}
+
+/// Check that the TLS files can be loaded like shiplift loads them
+///
+/// shiplift panics if it cannot load the files or if the key doesn't match the certificate.
+fn validate_tls_files(cert: &Path, key: &Path, ca: &Path) -> Result<()> {
+    use openssl::ssl::{SslConnector, SslFiletype, SslMethod};
+
+    let mut connector = SslConnector::builder(SslMethod::tls())?;
+    connector
+        .set_certificate_file(cert, SslFiletype::PEM)
+        .with_context(|| anyhow!("Loading TLS certificate {}", cert.display()))?;
+    connector
+        .set_private_key_file(key, SslFiletype::PEM)
+        .with_context(|| anyhow!("Loading TLS key {}", key.display()))?;
+    connector.check_private_key().with_context(|| {
+        anyhow!(
+            "The TLS key {} does not match the certificate {}",
+            key.display(),
+            cert.display()
+        )
+    })?;
+    connector
+        .set_ca_file(ca)
+        .with_context(|| anyhow!("Loading TLS CA certificate {}", ca.display()))?;
+    Ok(())
+}
+
 #[derive(Getters, CopyGetters, TypedBuilder)]
 pub struct Endpoint {
     #[getset(get = "pub")]
@@ -58,8 +119,23 @@ pub struct Endpoint {
     #[getset(get = "pub")]
     uri: String,
 
+    #[getset(get_copy = "pub")]
+    timeout: std::time::Duration,
+
+    /// The images that jobs on this endpoint may use, all images are allowed if `None`
+    #[builder(default)]
+    allowed_images: Option<Vec<ImageName>>,
+
+    /// The images that jobs on this endpoint must not use
+    #[builder(default)]
+    denied_images: Option<Vec<ImageName>>,
+
     #[builder(default)]
     running_jobs: std::sync::atomic::AtomicUsize,
+
+    /// Notified when a job on this endpoint finished (i.e., an [EndpointHandle] was dropped)
+    #[builder(default)]
+    job_finished: tokio::sync::Notify,
 }
 
 impl Debug for Endpoint {
@@ -70,7 +146,7 @@ impl Debug for Endpoint {
 
 impl Endpoint {
     pub(super) async fn setup(epc: EndpointConfiguration) -> Result<Self> {
-        let ep =
+        let mut ep =
             Endpoint::setup_endpoint(epc.endpoint_name(), epc.endpoint()).with_context(|| {
                 anyhow!(
                     "Setting up endpoint: {} -> {}",
@@ -79,11 +155,27 @@ impl Endpoint {
                 )
             })?;
 
+        if let Some(network_mode) = epc.network_mode() {
+            trace!(
+                "Overriding network mode of endpoint {} with {}",
+                epc.endpoint_name(),
+                network_mode
+            );
+            ep.network_mode = Some(network_mode.clone());
+        }
+
         let versions_compat =
             Endpoint::check_version_compat(epc.required_docker_versions().as_ref(), &ep);
         let api_versions_compat =
             Endpoint::check_api_version_compat(epc.required_docker_api_versions().as_ref(), &ep);
-        let imgs_avail = Endpoint::check_images_available(epc.required_images().as_ref(), &ep);
+        // Only the images that jobs on this endpoint may use have to be available
+        let required_images = epc
+            .required_images()
+            .iter()
+            .filter(|img| ep.can_run_image(img))
+            .cloned()
+            .collect::<Vec<_>>();
+        let imgs_avail = Endpoint::check_images_available(&required_images, &ep);
 
         let (versions_compat, api_versions_compat, imgs_avail) = {
             let timeout = std::time::Duration::from_secs(epc.endpoint().timeout().unwrap_or(10));
@@ -118,10 +210,36 @@ impl Endpoint {
         Ok(ep)
     }
 
+    /// Whether jobs that use the image `image_name` may run on this endpoint
+    pub fn can_run_image(&self, image_name: &ImageName) -> bool {
+        let allowed = self
+            .allowed_images
+            .as_ref()
+            .map(|allowed| allowed.contains(image_name))
+            .unwrap_or(true);
+        let denied = self
+            .denied_images
+            .as_ref()
+            .map(|denied| denied.contains(image_name))
+            .unwrap_or(false);
+        allowed && !denied
+    }
+
     fn setup_endpoint(ep_name: &EndpointName, ep: &crate::config::Endpoint) -> Result<Endpoint> {
         match ep.endpoint_type() {
             crate::config::EndpointType::Http => shiplift::Uri::from_str(ep.uri())
-                .map(shiplift::Docker::host)
+                .map_err(Error::from)
+                .and_then(|uri| match ep.tls_config()? {
+                    // The client was created before the runtime was started:
+                    Some(_) => TLS_CLIENTS
+                        .get()
+                        .and_then(|clients| clients.get(ep_name))
+                        .ok_or_else(|| anyhow!("No TLS client was created for the endpoint"))?
+                        .as_ref()
+                        .cloned()
+                        .map_err(|e| anyhow!("{:#}", e)),
+                    None => Ok(shiplift::Docker::host(uri)),
+                })
                 .with_context(|| anyhow!("Connecting to {}", ep.uri()))
                 .map(|docker| {
                     Endpoint::builder()
@@ -130,6 +248,9 @@ impl Endpoint {
                         .docker(docker)
                         .num_max_jobs(ep.maxjobs())
                         .network_mode(ep.network_mode().clone())
+                        .timeout(std::time::Duration::from_secs(ep.timeout().unwrap_or(10)))
+                        .allowed_images(ep.allowed_images().clone())
+                        .denied_images(ep.denied_images().clone())
                         .build()
                 }),
 
@@ -139,12 +260,81 @@ impl Endpoint {
                     .uri(ep.uri().clone())
                     .num_max_jobs(ep.maxjobs())
                     .network_mode(ep.network_mode().clone())
+                    .timeout(std::time::Duration::from_secs(ep.timeout().unwrap_or(10)))
                     .docker(shiplift::Docker::unix(ep.uri()))
+                    .allowed_images(ep.allowed_images().clone())
+                    .denied_images(ep.denied_images().clone())
                     .build()
             }),
         }
     }
 
+    /// Create a Docker client for a TLS-secured endpoint
+    ///
+    /// shiplift doesn't offer an API to pass the TLS client certificates directly. It only reads
+    /// them from the directory in `DOCKER_CERT_PATH` (as "cert.pem", "key.pem", and "ca.pem") when
+    /// the client is created. Therefore, the configured files are linked into a temporary
+    /// directory with the expected layout and the environment is modified while the client is
+    /// created. The files are loaded during the client creation so the directory is removed
+    /// afterwards.
+    ///
+    /// Because of the modification of the environment, this must only be called while no other
+    /// threads are running (see [create_tls_clients]).
+    fn setup_tls_docker(uri: shiplift::Uri, tls_config: &EndpointTlsConfig<'_>) -> Result<Docker> {
+        match uri.scheme_str() {
+            Some("https") | Some("tcp") => {}
+            other => {
+                return Err(anyhow!(
+                    "TLS requires an \"https\" or \"tcp\" URI, got scheme: {}",
+                    other.unwrap_or("none")
+                ))
+            }
+        }
+
+        validate_tls_files(tls_config.cert(), tls_config.key(), tls_config.ca())?;
+
+        let cert_dir = std::env::temp_dir().join(format!("butido-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&cert_dir)
+            .with_context(|| anyhow!("Creating directory {}", cert_dir.display()))?;
+
+        let docker = [
+            (tls_config.cert(), "cert.pem"),
+            (tls_config.key(), "key.pem"),
+            (tls_config.ca(), "ca.pem"),
+        ]
+        .into_iter()
+        .try_for_each(|(path, name)| {
+            let path = path
+                .canonicalize()
+                .with_context(|| anyhow!("Resolving TLS file {}", path.display()))?;
+            std::os::unix::fs::symlink(&path, cert_dir.join(name))
+                .with_context(|| anyhow!("Linking TLS file {}", path.display()))
+        })
+        .map(|_| {
+            let old_cert_path = std::env::var_os("DOCKER_CERT_PATH");
+            let old_tls_verify = std::env::var_os("DOCKER_TLS_VERIFY");
+
+            std::env::set_var("DOCKER_CERT_PATH", &cert_dir);
+            std::env::set_var("DOCKER_TLS_VERIFY", "1");
+            let docker = shiplift::Docker::host(uri);
+
+            match old_cert_path {
+                Some(val) => std::env::set_var("DOCKER_CERT_PATH", val),
+                None => std::env::remove_var("DOCKER_CERT_PATH"),
+            }
+            match old_tls_verify {
+                Some(val) => std::env::set_var("DOCKER_TLS_VERIFY", val),
+                None => std::env::remove_var("DOCKER_TLS_VERIFY"),
+            }
+
+            docker
+        });
+
+        std::fs::remove_dir_all(&cert_dir)
+            .with_context(|| anyhow!("Removing directory {}", cert_dir.display()))?;
+        docker
+    }
+
     async fn check_version_compat(req: Option<&Vec<String>>, ep: &Endpoint) -> Result<()> {
         match req {
             None => Ok(()),
@@ -230,10 +420,22 @@ impl Endpoint {
     pub async fn prepare_container(
         &self,
         job: &RunnableJob,
+        submit_uuid: &Uuid,
         staging_store: Arc<RwLock<StagingStore>>,
         release_stores: Vec<Arc<ReleaseStore>>,
     ) -> Result<PreparedContainer<'_>> {
-        PreparedContainer::new(self, job, staging_store, release_stores).await
+        PreparedContainer::new(self, job, submit_uuid, staging_store, release_stores).await
+    }
+
+    /// Remove a container, even if it is still running
+    async fn remove_container(&self, container_id: &str) -> Result<()> {
+        self.docker
+            .containers()
+            .get(container_id)
+            .remove(shiplift::RmContainerOptions::builder().force(true).build())
+            .await
+            .inspect(|_| trace!("Removed container {} on '{}'", container_id, self.name))
+            .with_context(|| anyhow!("Removing container {} on '{}'", container_id, self.name))
     }
 
     pub fn running_jobs(&self) -> usize {
@@ -242,8 +444,24 @@ impl Endpoint {
 
     /// Super non-scientific utilization calculation for the endpoint
     pub fn utilization(&self) -> f64 {
+        self.utilization_with(self.running_jobs())
+    }
+
+    /// Wait until a job on this endpoint finished
+    ///
+    /// Notifications are received as soon as the returned future was created (even before it is
+    /// polled), so it should be created before checking whether the endpoint is free.
+    pub fn job_finished(&self) -> tokio::sync::futures::Notified<'_> {
+        self.job_finished.notified()
+    }
+
+    /// The utilization of the endpoint if `running_jobs` jobs were running on it
+    ///
+    /// This is useful if the jobs were not started by this process, e.g., to calculate the
+    /// utilization from the running containers.
+    pub fn utilization_with(&self, running_jobs: usize) -> f64 {
         let max_jobs = self.num_max_jobs() as f64;
-        let run_jobs = self.running_jobs() as f64;
+        let run_jobs = running_jobs as f64;
         trace!(
             "utilization of {}: 100.0 / {} * {}",
             self.name(),
@@ -258,6 +476,17 @@ impl Endpoint {
         self.docker.ping().await.map_err(Error::from)
     }
 
+    /// Check whether the endpoint is reachable by pinging it
+    ///
+    /// Fails if the ping fails or does not succeed within the configured timeout of the endpoint.
+    pub async fn health_check(&self) -> Result<()> {
+        tokio::time::timeout(self.timeout, self.ping())
+            .await
+            .map_err(|_| anyhow!("Timeout while pinging endpoint {}", self.name))?
+            .map(|_| ())
+            .with_context(|| anyhow!("Pinging endpoint {}", self.name))
+    }
+
     pub async fn stats(&self) -> Result<EndpointStats> {
         self.docker
             .info()
@@ -267,13 +496,41 @@ impl Endpoint {
     }
 
     pub async fn container_stats(&self) -> Result<Vec<ContainerStat>> {
+        self.list_container_stats(
+            &shiplift::builder::ContainerListOptions::builder()
+                .all()
+                .build(),
+        )
+        .await
+    }
+
+    /// Get the stats of the containers of the submit `submit_uuid`
+    ///
+    /// The containers are found by their label, so containers that were created by older versions
+    /// of butido (without labels) are not found.
+    pub async fn container_stats_of_submit(
+        &self,
+        submit_uuid: &Uuid,
+    ) -> Result<Vec<ContainerStat>> {
+        self.list_container_stats(
+            &shiplift::builder::ContainerListOptions::builder()
+                .all()
+                .filter(vec![shiplift::builder::ContainerFilter::Label(
+                    crate::consts::CONTAINER_LABEL_SUBMIT.to_string(),
+                    submit_uuid.to_string(),
+                )])
+                .build(),
+        )
+        .await
+    }
+
+    async fn list_container_stats(
+        &self,
+        opts: &shiplift::builder::ContainerListOptions,
+    ) -> Result<Vec<ContainerStat>> {
         self.docker
             .containers()
-            .list({
-                &shiplift::builder::ContainerListOptions::builder()
-                    .all()
-                    .build()
-            })
+            .list(opts)
             .await
             .map_err(Error::from)
             .map(|containers| containers.into_iter().map(ContainerStat::from).collect())
@@ -313,6 +570,33 @@ impl Endpoint {
             .map_err(Error::from)
             .map(|v| v.into_iter().map(Image::from))
     }
+
+    /// Pull the image `image` from its registry, to update the image on the endpoint
+    ///
+    /// Docker checks the registry for newer layers when pulling, so this replaces a stale image
+    /// (only the layers that changed are downloaded).
+    pub async fn pull_image(&self, image: &ImageName) -> Result<()> {
+        let opts = shiplift::PullOptions::builder()
+            .image(image.as_ref())
+            .build();
+        let images = self.docker.images();
+        let mut progress = images.pull(&opts);
+        while let Some(item) = progress.next().await {
+            let item = item.with_context(|| anyhow!("Pulling image {} on {}", image, self.name))?;
+
+            // Errors (e.g., a missing image) are reported in the stream, not as HTTP errors
+            if let Some(error) = item.get("error").and_then(|e| e.as_str()) {
+                return Err(anyhow!(
+                    "Pulling image {} on {} failed: {}",
+                    image,
+                    self.name,
+                    error
+                ));
+            }
+            trace!("Pulling image {} on {}: {}", image, self.name, item);
+        }
+        Ok(())
+    }
 }
 
 /// Helper type to store endpoint statistics
@@ -321,6 +605,7 @@ impl Endpoint {
 /// values the shiplift::rep::Info type holds, because some of these are not relevant for us.
 ///
 /// Later, this might hold endpoint stats from other endpoint implementations as well
+#[derive(serde::Serialize)]
 pub struct EndpointStats {
     pub name: String,
     pub containers: u64,
@@ -361,6 +646,20 @@ pub struct ContainerStat {
     pub status: String,
 }
 
+impl ContainerStat {
+    /// Check whether the container was created in the time window from `since` until `until`
+    ///
+    /// Both bounds are inclusive, a missing bound does not restrict the window.
+    pub fn created_between(
+        &self,
+        since: Option<&chrono::DateTime<chrono::Local>>,
+        until: Option<&chrono::DateTime<chrono::Local>>,
+    ) -> bool {
+        since.map(|since| self.created >= *since).unwrap_or(true)
+            && until.map(|until| self.created <= *until).unwrap_or(true)
+    }
+}
+
 impl From<shiplift::rep::Container> for ContainerStat {
     fn from(cont: shiplift::rep::Container) -> Self {
         ContainerStat {
@@ -415,6 +714,7 @@ impl Drop for EndpointHandle {
             .running_jobs
             .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
         trace!("Endpoint {} has one job less: {}", self.0.name(), res - 1);
+        self.0.job_finished.notify_waiters();
     }
 }
 
@@ -430,6 +730,8 @@ impl std::ops::Deref for EndpointHandle {
 pub struct PreparedContainer<'a> {
     endpoint: &'a Endpoint,
     script: Script,
+    shell: String,
+    secret_paths: Vec<String>,
 
     #[getset(get = "pub")]
     create_info: shiplift::rep::ContainerCreateInfo,
@@ -439,71 +741,132 @@ impl<'a> PreparedContainer<'a> {
     async fn new(
         endpoint: &'a Endpoint,
         job: &RunnableJob,
+        submit_uuid: &Uuid,
         staging_store: Arc<RwLock<StagingStore>>,
         release_stores: Vec<Arc<ReleaseStore>>,
     ) -> Result<PreparedContainer<'a>> {
         let script = job.script().clone();
-        let create_info = Self::build_container(endpoint, job).await?;
+        let create_info = Self::build_container(endpoint, job, submit_uuid).await?;
         let container = endpoint.docker.containers().get(&create_info.id);
+        let secret_paths = job
+            .secrets()
+            .iter()
+            .map(|secret| secret.container_path().display().to_string())
+            .collect::<Vec<_>>();
 
-        let (cpysrc, cpypch, cpyart, cpyscr) = tokio::join!(
+        let (cpysrc, cpypch, cpyart, cpyscr, cpysec) = tokio::join!(
             Self::copy_source_to_container(&container, job),
             Self::copy_patches_to_container(&container, job),
             Self::copy_artifacts_to_container(&container, job, staging_store, &release_stores),
-            Self::copy_script_to_container(&container, &script)
+            Self::copy_script_to_container(&container, &script),
+            Self::copy_secrets_to_container(&container, job)
         );
 
-        cpysrc.with_context(|| {
-            anyhow!(
-                "Copying the sources to container {} on '{}'",
-                create_info.id,
-                endpoint.name
-            )
-        })?;
+        let copied = (|| -> Result<()> {
+            cpysrc.with_context(|| {
+                anyhow!(
+                    "Copying the sources to container {} on '{}'",
+                    create_info.id,
+                    endpoint.name
+                )
+            })?;
 
-        cpypch.with_context(|| {
-            anyhow!(
-                "Copying the patches to container {} on '{}'",
-                create_info.id,
-                endpoint.name
-            )
-        })?;
+            cpypch.with_context(|| {
+                anyhow!(
+                    "Copying the patches to container {} on '{}'",
+                    create_info.id,
+                    endpoint.name
+                )
+            })?;
 
-        cpyart.with_context(|| {
-            anyhow!(
-                "Copying the artifacts to container {} on '{}'",
-                create_info.id,
-                endpoint.name
-            )
-        })?;
+            cpyart.with_context(|| {
+                anyhow!(
+                    "Copying the artifacts to container {} on '{}'",
+                    create_info.id,
+                    endpoint.name
+                )
+            })?;
 
-        cpyscr.with_context(|| {
-            anyhow!(
-                "Copying the script to container {} on '{}'",
-                create_info.id,
-                endpoint.name
-            )
-        })?;
+            cpyscr.with_context(|| {
+                anyhow!(
+                    "Copying the script to container {} on '{}'",
+                    create_info.id,
+                    endpoint.name
+                )
+            })?;
 
-        Ok({
-            PreparedContainer {
-                endpoint,
-                script,
-                create_info,
-            }
-        })
+            cpysec.with_context(|| {
+                anyhow!(
+                    "Copying the secrets to container {} on '{}'",
+                    create_info.id,
+                    endpoint.name
+                )
+            })
+        })();
+
+        let prepared_container = PreparedContainer {
+            endpoint,
+            script,
+            shell: job.shell().clone(),
+            secret_paths,
+            create_info,
+        };
+
+        if let Err(e) = copied {
+            prepared_container.remove().await;
+            return Err(e);
+        }
+        Ok(prepared_container)
+    }
+
+    /// Remove the container because it could not be prepared or started
+    ///
+    /// The container would be left on the endpoint otherwise (with the secrets, if any, that were
+    /// copied into it already), e.g. when the job is retried on another endpoint.
+    async fn remove(&self) {
+        if let Err(e) = self.endpoint.remove_container(&self.create_info.id).await {
+            warn!(
+                "Removing container {} that could not be started failed: {:#}",
+                self.create_info.id, e
+            );
+        }
     }
 
     async fn build_container(
         endpoint: &Endpoint,
         job: &RunnableJob,
+        submit_uuid: &Uuid,
     ) -> Result<shiplift::rep::ContainerCreateInfo> {
+        // The architecture is not part of the job environment (which is recorded in the database
+        // and compared when searching for artifacts), it is matched separately
         let envs = job
             .environment()
             .map(|(k, v)| format!("{}={}", k.as_ref(), v))
+            .chain(
+                job.arch()
+                    .as_ref()
+                    .map(|arch| format!("BUTIDO_ARCH={arch}")),
+            )
             .collect::<Vec<_>>();
         trace!("Job resources: Environment variables = {:?}", envs);
 
+        // The labels identify the containers of a submit, e.g. for "endpoint containers list"
+        let submit_uuid = submit_uuid.to_string();
+        let job_uuid = job.uuid().to_string();
+        let labels = HashMap::from([
+            (crate::consts::CONTAINER_LABEL_SUBMIT, submit_uuid.as_str()),
+            (crate::consts::CONTAINER_LABEL_JOB, job_uuid.as_str()),
+            (
+                crate::consts::CONTAINER_LABEL_PACKAGE,
+                job.package().name().as_ref(),
+            ),
+            (
+                crate::consts::CONTAINER_LABEL_VERSION,
+                job.package().version().as_ref(),
+            ),
+        ]);
+        trace!("Container labels = {:?}", labels);
+
         let builder_opts = {
             let mut builder_opts = shiplift::ContainerOptions::builder(job.image().as_ref());
             let container_name = format!(
@@ -515,13 +878,31 @@ impl<'a> PreparedContainer<'a> {
             trace!("container name = {}", container_name);
             builder_opts.name(&container_name);
             builder_opts.env(envs.iter().map(AsRef::as_ref).collect::<Vec<&str>>());
-            builder_opts.cmd(vec!["/bin/bash"]); // we start the container with /bin/bash, but exec() the script in it later
-            builder_opts.attach_stdin(true); // we have to attach, otherwise bash exits
+            builder_opts.labels(&labels);
+            builder_opts.cmd(vec![job.shell().as_str()]); // we start the container with the shell, but exec() the script in it later
+            builder_opts.attach_stdin(true); // we have to attach, otherwise the shell exits
 
             if let Some(network_mode) = endpoint.network_mode().as_ref() {
                 builder_opts.network_mode(network_mode);
             }
 
+            // Requested by the package flags, see crate::package::flags for the security implications
+            let container_flags = job.container_flags();
+            if !container_flags.capabilities().is_empty() {
+                trace!("Adding capabilities: {:?}", container_flags.capabilities());
+                builder_opts.capabilities(
+                    container_flags
+                        .capabilities()
+                        .iter()
+                        .map(AsRef::as_ref)
+                        .collect(),
+                );
+            }
+            if container_flags.privileged() {
+                trace!("Running container in privileged mode");
+                builder_opts.privileged(true);
+            }
+
             builder_opts.build()
         };
         trace!("Builder options = {:?}", builder_opts);
@@ -545,7 +926,7 @@ impl<'a> PreparedContainer<'a> {
     async fn copy_source_to_container(container: &Container<'_>, job: &RunnableJob) -> Result<()> {
         use tokio::io::AsyncReadExt;
 
-        job.package_sources()
+        job.package_sources()?
             .into_iter()
             .map(|entry| async {
                 let source_path = entry.path();
@@ -772,8 +1153,35 @@ impl<'a> PreparedContainer<'a> {
             .with_context(|| anyhow!("Copying the script into container {}", container.id()))
     }
 
+    /// Copy the secrets into the container
+    ///
+    /// The content of the secrets must not end up in the log, so it is never traced.
+    async fn copy_secrets_to_container(container: &Container<'_>, job: &RunnableJob) -> Result<()> {
+        for secret in job.secrets() {
+            container
+                .copy_file_into(secret.container_path(), secret.content())
+                .await
+                .inspect(|_| {
+                    trace!(
+                        "Successfully copied secret {} to container {}",
+                        secret.name(),
+                        container.id()
+                    )
+                })
+                .with_context(|| {
+                    anyhow!(
+                        "Copying secret {} to container {}",
+                        secret.name(),
+                        container.id()
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
     pub async fn start(self) -> Result<StartedContainer<'a>> {
-        self.endpoint
+        let started = self
+            .endpoint
             .docker
             .containers()
             .get(&self.create_info.id)
@@ -788,21 +1196,33 @@ impl<'a> PreparedContainer<'a> {
                     )
                 })
             })
-            .await?;
+            .await;
+
+        if let Err(e) = started {
+            self.remove().await;
+            return Err(e);
+        }
 
         Ok({
             StartedContainer {
                 endpoint: self.endpoint,
                 script: self.script,
+                shell: self.shell,
+                secret_paths: self.secret_paths,
                 create_info: self.create_info,
             }
         })
     }
 }
 
+#[derive(Getters)]
 pub struct StartedContainer<'a> {
     endpoint: &'a Endpoint,
     script: Script,
+    shell: String,
+    secret_paths: Vec<String>,
+
+    #[getset(get = "pub")]
     create_info: shiplift::rep::ContainerCreateInfo,
 }
 
@@ -812,7 +1232,7 @@ impl<'a> StartedContainer<'a> {
         logsink: UnboundedSender<LogItem>,
     ) -> Result<ExecutedContainer<'a>> {
         let exec_opts = ExecContainerOptions::builder()
-            .cmd(vec!["/bin/bash", "/script"])
+            .cmd(vec![self.shell.as_str(), crate::consts::SCRIPT_PATH])
             .attach_stderr(true)
             .attach_stdout(true)
             .build();
@@ -829,63 +1249,81 @@ impl<'a> StartedContainer<'a> {
             .get(&self.create_info.id)
             .exec(&exec_opts);
 
-        let exited_successfully: Option<(bool, Option<String>)> =
-            buffer_stream_to_line_stream(stream)
-                .map(|line| {
-                    trace!(
-                        "['{}':{}] Found log line: {:?}",
+        let log_result = buffer_stream_to_line_stream(stream)
+            .map(|line| {
+                trace!(
+                    "['{}':{}] Found log line: {:?}",
+                    self.endpoint.name,
+                    self.create_info.id,
+                    line
+                );
+                line.with_context(|| {
+                    anyhow!(
+                        "Getting log from {}:{}",
                         self.endpoint.name,
-                        self.create_info.id,
-                        line
-                    );
-                    line.with_context(|| {
-                        anyhow!(
-                            "Getting log from {}:{}",
-                            self.endpoint.name,
-                            self.create_info.id
-                        )
-                    })
-                    .and_then(|l| {
-                        crate::log::parser().parse(l.as_bytes()).with_context(|| {
-                            anyhow!(
-                                "Parsing log from {}:{}: {:?}",
-                                self.endpoint.name,
-                                self.create_info.id,
-                                l
-                            )
-                        })
-                    })
-                    .and_then(|item| {
-                        let exited_successfully = match item {
-                            LogItem::State(Ok(_)) => Some((true, None)),
-                            LogItem::State(Err(ref msg)) => Some((false, Some(msg.clone()))),
-                            _ => None, // Nothing
-                        };
-
-                        trace!("Log item: {}", item.display()?);
-                        logsink
-                            .send(item)
-                            .with_context(|| anyhow!("Sending log to log sink"))
-                            .map(|_| exited_successfully)
-                    })
+                        self.create_info.id
+                    )
                 })
-                .collect::<Result<Vec<_>>>()
-                .map(|r| {
-                    r.with_context(|| {
+                .and_then(|l| {
+                    crate::log::parser().parse(l.as_bytes()).with_context(|| {
                         anyhow!(
-                            "Fetching log from container {} on {}",
+                            "Parsing log from {}:{}: {:?}",
+                            self.endpoint.name,
                             self.create_info.id,
-                            self.endpoint.name
+                            l
                         )
                     })
                 })
-                .await
-                .with_context(|| {
+                .and_then(|item| {
+                    let exited_successfully = match item {
+                        LogItem::State(Ok(_)) => Some((true, None)),
+                        LogItem::State(Err(ref msg)) => Some((false, Some(msg.clone()))),
+                        _ => None, // Nothing
+                    };
+
+                    trace!("Log item: {}", item.display()?);
+                    logsink
+                        .send(item)
+                        .with_context(|| anyhow!("Sending log to log sink"))
+                        .map(|_| exited_successfully)
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|r| {
+                r.with_context(|| {
                     anyhow!(
-                        "Copying script to container, running container and getting logs: {}",
-                        self.create_info.id
+                        "Fetching log from container {} on {}",
+                        self.create_info.id,
+                        self.endpoint.name
                     )
-                })?
+                })
+            })
+            .await
+            .with_context(|| {
+                anyhow!(
+                    "Copying script to container, running container and getting logs: {}",
+                    self.create_info.id
+                )
+            });
+
+        // Remove the secrets before anything else happens with the container (even if getting
+        // the logs failed), e.g., before a failed container is kept for debugging.
+        // If that is not possible (e.g., because the container was killed), the whole container
+        // is removed instead:
+        if !self.secret_paths.is_empty() {
+            if let Err(e) = self.remove_secrets().await {
+                if let Err(rm_err) = self.endpoint.remove_container(&self.create_info.id).await {
+                    return Err(e.context(rm_err));
+                }
+                return Err(e.context(anyhow!(
+                    "Removed container {} because it still contained the secrets",
+                    self.create_info.id
+                )));
+            }
+        }
+
+        let exited_successfully: Option<(bool, Option<String>)> =
+            log_result?
                 .into_iter()
                 .fold(None, |accu, elem| match (accu, elem) {
                     (None, b) => b,
@@ -900,16 +1338,40 @@ impl<'a> StartedContainer<'a> {
                 endpoint: self.endpoint,
                 create_info: self.create_info,
                 script: self.script,
+                shell: self.shell,
                 exit_info: exited_successfully,
             }
         })
     }
+
+    /// Remove the secrets from the container
+    ///
+    /// Only the files of the secrets are removed, other files in the secrets directory (e.g., from
+    /// the image) are left untouched.
+    async fn remove_secrets(&self) -> Result<()> {
+        let cmd = ["rm", "-f", "--"]
+            .into_iter()
+            .chain(self.secret_paths.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+
+        exec_in_container(&self.endpoint.docker, &self.create_info.id, cmd)
+            .await
+            .inspect(|_| trace!("Removed the secrets from container {}", self.create_info.id))
+            .with_context(|| {
+                anyhow!(
+                    "Removing the secrets from container {}",
+                    self.create_info.id
+                )
+            })
+            .map(|_| ())
+    }
 }
 
 pub struct ExecutedContainer<'a> {
     endpoint: &'a Endpoint,
     create_info: shiplift::rep::ContainerCreateInfo,
     script: Script,
+    shell: String,
     exit_info: Option<(bool, Option<String>)>,
 }
 
@@ -922,10 +1384,101 @@ impl ExecutedContainer<'_> {
         &self.script
     }
 
+    /// Whether the script exited successfully, `None` if the exit state is unknown
+    pub fn success(&self) -> Option<bool> {
+        self.exit_info.as_ref().map(|(success, _)| *success)
+    }
+
+    /// Get the resource usage of the container from the endpoint
+    ///
+    /// If the endpoint uses cgroup v2, the statistics of the endpoint lack the peak memory usage
+    /// (and can't be parsed), so the usage is read from the cgroup of the container instead.
+    /// Returns `None` if the usage could not be gathered (e.g., because the container exited
+    /// already).
+    async fn resource_usage(&self) -> Option<ContainerResourceUsage> {
+        let container = self.endpoint.docker.containers().get(&self.create_info.id);
+        let mut stats = container.stats();
+
+        // The stats endpoint streams the statistics, we only need the first element
+        match tokio::time::timeout(self.endpoint.timeout, stats.next()).await {
+            Ok(Some(Ok(stats))) if stats.memory_stats.max_usage > 0 => {
+                return Some(ContainerResourceUsage {
+                    memory_bytes: stats.memory_stats.max_usage,
+                    memory_is_peak: true,
+                    cpu_time_ns: stats.cpu_stats.cpu_usage.total_usage,
+                });
+            }
+            Ok(Some(Ok(_))) => debug!(
+                "No peak memory usage in the statistics of container {}",
+                self.create_info.id
+            ),
+            Ok(Some(Err(e))) => debug!(
+                "Failed to get statistics of container {}: {}",
+                self.create_info.id, e
+            ),
+            Ok(None) | Err(_) => debug!("No statistics for container {}", self.create_info.id),
+        }
+
+        match self.cgroup_v2_resource_usage().await {
+            Ok(usage) => Some(usage),
+            Err(e) => {
+                debug!(
+                    "Failed to get the resource usage of container {} from its cgroup: {:#}",
+                    self.create_info.id, e
+                );
+                None
+            }
+        }
+    }
+
+    /// Get the resource usage of the container from its cgroup (v2)
+    ///
+    /// The peak memory usage (`memory.peak`) is only available since Linux 5.19, with older
+    /// kernels the current memory usage (`memory.current`) is used instead (and marked as such).
+    async fn cgroup_v2_resource_usage(&self) -> Result<ContainerResourceUsage> {
+        let cpu_stat = self.read_file("/sys/fs/cgroup/cpu.stat").await?;
+        let (memory, memory_is_peak) = match self.read_file("/sys/fs/cgroup/memory.peak").await {
+            Ok(memory) => (memory, true),
+            Err(e) => {
+                debug!(
+                    "No peak memory usage for container {}, using the current usage: {:#}",
+                    self.create_info.id, e
+                );
+                (
+                    self.read_file("/sys/fs/cgroup/memory.current").await?,
+                    false,
+                )
+            }
+        };
+        parse_cgroup_v2_usage(&cpu_stat, &memory, memory_is_peak)
+    }
+
+    /// Read a (text) file in the container
+    ///
+    /// The file is read with the shell of the job, as the image may not contain other tools
+    /// (like `cat`).
+    async fn read_file(&self, path: &str) -> Result<String> {
+        const READ_FILE: &str = r#"while IFS= read -r line; do printf '%s\n' "$line"; done < "$1""#;
+        let cmd = vec![self.shell.as_str(), "-c", READ_FILE, "butido", path];
+        exec_in_container(&self.endpoint.docker, &self.create_info.id, cmd).await
+    }
+
+    /// Collect the artifacts and stop the container
+    ///
+    /// If the script failed, the container is only stopped if `keep_on_failure` is `false`.
     pub async fn finalize(
         self,
         staging_store: Arc<RwLock<StagingStore>>,
+        keep_on_failure: bool,
     ) -> Result<FinalizedContainer> {
+        // Has to happen before the container is stopped
+        let resource_usage = self.resource_usage().await;
+        trace!(
+            "Resource usage of container {}: {:?}",
+            self.create_info.id,
+            resource_usage
+        );
+
         let (exit_info, artifacts) = match self.exit_info {
             Some((false, msg)) => {
                 let err = anyhow!(
@@ -933,6 +1486,14 @@ impl ExecutedContainer<'_> {
                     msg = msg.as_deref().unwrap_or("")
                 );
 
+                if !keep_on_failure {
+                    let container = self.endpoint.docker.containers().get(&self.create_info.id);
+                    if let Err(e) = container.stop(Some(std::time::Duration::new(1, 0))).await {
+                        // Not fatal, the job failed anyways
+                        debug!("Stopping container {} failed: {}", self.create_info.id, e);
+                    }
+                }
+
                 // error because the container errored
                 (Err(err), vec![])
             }
@@ -973,15 +1534,36 @@ impl ExecutedContainer<'_> {
             FinalizedContainer {
                 artifacts,
                 exit_info,
+                resource_usage,
             }
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, CopyGetters)]
 pub struct FinalizedContainer {
     artifacts: Vec<ArtifactPath>,
     exit_info: Result<()>,
+
+    #[getset(get_copy = "pub")]
+    resource_usage: Option<ContainerResourceUsage>,
+}
+
+/// The resource usage of a container, as reported by the endpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CopyGetters)]
+pub struct ContainerResourceUsage {
+    /// The memory usage of the container in bytes
+    #[getset(get_copy = "pub")]
+    memory_bytes: u64,
+
+    /// Whether `memory_bytes` is the maximum memory usage, otherwise it is the usage when the
+    /// script finished (if the peak is not available)
+    #[getset(get_copy = "pub")]
+    memory_is_peak: bool,
+
+    /// The CPU time used by the container in nanoseconds
+    #[getset(get_copy = "pub")]
+    cpu_time_ns: u64,
 }
 
 impl FinalizedContainer {
@@ -989,3 +1571,145 @@ impl FinalizedContainer {
         (self.artifacts, self.exit_info)
     }
 }
+
+/// Run `cmd` in the container and return what it printed to stdout
+///
+/// Fails if the command exits with a non-zero exit code.
+async fn exec_in_container(docker: &Docker, container_id: &str, cmd: Vec<&str>) -> Result<String> {
+    let exec_opts = ExecContainerOptions::builder()
+        .cmd(cmd.clone())
+        .attach_stdout(true)
+        .attach_stderr(true)
+        .build();
+
+    let exec = shiplift::Exec::create(docker, container_id, &exec_opts).await?;
+    let (stdout, stderr) = exec
+        .start()
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<std::result::Result<Vec<_>, _>>()?
+        .into_iter()
+        .fold((vec![], vec![]), |(mut stdout, mut stderr), chunk| {
+            match chunk {
+                shiplift::tty::TtyChunk::StdOut(bytes) => stdout.extend(bytes),
+                shiplift::tty::TtyChunk::StdErr(bytes) => stderr.extend(bytes),
+                shiplift::tty::TtyChunk::StdIn(_) => {}
+            }
+            (stdout, stderr)
+        });
+
+    match exec.inspect().await?.exit_code {
+        Some(0) => String::from_utf8(stdout).map_err(Error::from),
+        exit_code => Err(anyhow!(
+            "'{}' failed in container {} with exit code {}: {}",
+            cmd.join(" "),
+            container_id,
+            exit_code
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+            String::from_utf8_lossy(&stderr).trim()
+        )),
+    }
+}
+
+/// Parse the resource usage of a container from the contents of the cgroup (v2) files `cpu.stat`
+/// and `memory.peak` (or `memory.current`, then `memory_is_peak` is `false`)
+fn parse_cgroup_v2_usage(
+    cpu_stat: &str,
+    memory: &str,
+    memory_is_peak: bool,
+) -> Result<ContainerResourceUsage> {
+    let cpu_time_us = cpu_stat
+        .lines()
+        .find_map(|line| line.strip_prefix("usage_usec "))
+        .ok_or_else(|| anyhow!("No usage_usec in cpu.stat"))?
+        .trim()
+        .parse::<u64>()
+        .context("Parsing usage_usec of cpu.stat")?;
+    let memory_bytes = memory
+        .trim()
+        .parse::<u64>()
+        .with_context(|| anyhow!("Parsing the memory usage: {:?}", memory.trim()))?;
+
+    Ok(ContainerResourceUsage {
+        memory_bytes,
+        memory_is_peak,
+        cpu_time_ns: cpu_time_us * 1000,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cgroup_v2_usage() {
+        let cpu_stat = "usage_usec 1500\nuser_usec 1000\nsystem_usec 500\nnr_periods 0\n";
+        assert_eq!(
+            parse_cgroup_v2_usage(cpu_stat, "4096\n", true).unwrap(),
+            ContainerResourceUsage {
+                memory_bytes: 4096,
+                memory_is_peak: true,
+                cpu_time_ns: 1_500_000,
+            }
+        );
+        assert!(!parse_cgroup_v2_usage(cpu_stat, "4096\n", false)
+            .unwrap()
+            .memory_is_peak());
+
+        assert!(parse_cgroup_v2_usage("user_usec 1000\n", "4096\n", true).is_err());
+        assert!(parse_cgroup_v2_usage(cpu_stat, "", true).is_err());
+    }
+
+    #[test]
+    fn test_validate_tls_files() {
+        use openssl::asn1::Asn1Time;
+        use openssl::hash::MessageDigest;
+        use openssl::pkey::PKey;
+        use openssl::rsa::Rsa;
+        use openssl::x509::{X509NameBuilder, X509};
+
+        let dir = std::env::temp_dir().join(format!("butido-tls-test-{}", Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "butido").unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        let cert = cert.build();
+        let other_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+        let write = |name: &str, content: &[u8]| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+        let cert_path = write("cert.pem", &cert.to_pem().unwrap());
+        let key_path = write("key.pem", &key.private_key_to_pem_pkcs8().unwrap());
+        let other_key_path = write(
+            "other-key.pem",
+            &other_key.private_key_to_pem_pkcs8().unwrap(),
+        );
+        let invalid_path = write("invalid.pem", b"-----BEGIN CERTIFICATE-----\nMIIB\n");
+
+        assert!(validate_tls_files(&cert_path, &key_path, &cert_path).is_ok());
+        assert!(validate_tls_files(&cert_path, &other_key_path, &cert_path).is_err());
+        assert!(validate_tls_files(&invalid_path, &key_path, &cert_path).is_err());
+        assert!(validate_tls_files(&cert_path, &invalid_path, &cert_path).is_err());
+        assert!(validate_tls_files(&cert_path, &key_path, &invalid_path).is_err());
+        assert!(validate_tls_files(&cert_path, &dir.join("missing.pem"), &cert_path).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
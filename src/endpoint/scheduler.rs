@@ -8,8 +8,12 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::anyhow;
 use anyhow::Context;
@@ -25,19 +29,73 @@ use itertools::Itertools;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::RwLock;
-use tracing::trace;
+use tracing::{trace, warn};
 use uuid::Uuid;
 
+use crate::config::EndpointName;
 use crate::db::models as dbmodels;
 use crate::endpoint::Endpoint;
 use crate::endpoint::EndpointConfiguration;
 use crate::endpoint::EndpointHandle;
+use crate::endpoint::StartedContainer;
 use crate::filestore::ArtifactPath;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
 use crate::job::JobResource;
 use crate::job::RunnableJob;
 use crate::log::LogItem;
+use crate::log::TimedLogItem;
+use crate::orchestrator::Event;
+use crate::orchestrator::EventSink;
+use crate::orchestrator::Shutdown;
+use crate::util::docker::ImageName;
+
+/// A decision of the scheduler: The endpoint a job was scheduled on and how long the job had to
+/// wait for a free endpoint
+#[derive(Clone, Debug, Getters, CopyGetters)]
+pub struct SchedulingDecision {
+    #[getset(get = "pub")]
+    endpoint_name: EndpointName,
+
+    #[getset(get_copy = "pub")]
+    wait_time: Duration,
+}
+
+impl SchedulingDecision {
+    pub fn new(endpoint_name: EndpointName, wait_time: Duration) -> Self {
+        SchedulingDecision {
+            endpoint_name,
+            wait_time,
+        }
+    }
+}
+
+/// The scheduling decisions that were made during a build, in the order they were made
+///
+/// A job has multiple decisions if it was moved to another endpoint because starting it failed.
+/// Recording the decisions does not influence the scheduling.
+#[derive(Clone, Debug, Default)]
+pub struct SchedulingLog(Arc<Mutex<Vec<SchedulingDecision>>>);
+
+impl SchedulingLog {
+    fn record(&self, job_uuid: Uuid, endpoint_name: EndpointName, wait_time: Duration) {
+        trace!(
+            "Job {} scheduled on endpoint {} after waiting {:?}",
+            job_uuid,
+            endpoint_name,
+            wait_time
+        );
+        self.0
+            .lock()
+            .unwrap() // only poisoned if a thread panicked while pushing
+            .push(SchedulingDecision::new(endpoint_name, wait_time));
+    }
+
+    /// Get a copy of the decisions that were recorded so far
+    pub fn decisions(&self) -> Vec<SchedulingDecision> {
+        self.0.lock().unwrap().clone()
+    }
+}
 
 #[derive(Getters, CopyGetters)]
 pub struct EndpointScheduler {
@@ -49,10 +107,26 @@ pub struct EndpointScheduler {
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     db: Pool<ConnectionManager<PgConnection>>,
+    #[getset(get = "pub")]
     submit: crate::db::models::Submit,
+    retry_attempts: usize,
+    keep_failed_containers: bool,
+    artifact_verifier: Option<PathBuf>,
+    max_log_lines: Option<usize>,
+    record_log_times: bool,
+
+    #[getset(get = "pub")]
+    events: EventSink,
+
+    #[getset(get = "pub")]
+    shutdown: Shutdown,
+
+    #[getset(get = "pub")]
+    scheduling_log: SchedulingLog,
 }
 
 impl EndpointScheduler {
+    #[allow(clippy::too_many_arguments)]
     pub async fn setup(
         endpoints: Vec<EndpointConfiguration>,
         staging_store: Arc<RwLock<StagingStore>>,
@@ -60,6 +134,13 @@ impl EndpointScheduler {
         db: Pool<ConnectionManager<PgConnection>>,
         submit: crate::db::models::Submit,
         log_dir: Option<PathBuf>,
+        retry_attempts: usize,
+        keep_failed_containers: bool,
+        artifact_verifier: Option<PathBuf>,
+        max_log_lines: Option<usize>,
+        record_log_times: bool,
+        events: EventSink,
+        shutdown: Shutdown,
     ) -> Result<Self> {
         let endpoints = crate::endpoint::util::setup_endpoints(endpoints).await?;
         let max_endpoint_name_length = endpoints
@@ -76,6 +157,14 @@ impl EndpointScheduler {
             release_stores,
             db,
             submit,
+            retry_attempts,
+            keep_failed_containers,
+            artifact_verifier,
+            max_log_lines,
+            record_log_times,
+            events,
+            shutdown,
+            scheduling_log: SchedulingLog::default(),
         })
     }
 
@@ -89,49 +178,173 @@ impl EndpointScheduler {
         job: RunnableJob,
         bar: indicatif::ProgressBar,
     ) -> Result<JobHandle> {
-        let endpoint = self.select_free_endpoint().await?;
+        let start = Instant::now();
+        let endpoint = select_free_endpoint(&self.endpoints, &[], job.image()).await?;
+        self.scheduling_log
+            .record(*job.uuid(), endpoint.name().clone(), start.elapsed());
 
         Ok(JobHandle {
             log_dir: self.log_dir.clone(),
             bar,
             endpoint,
+            endpoints: self.endpoints.clone(),
+            retry_attempts: self.retry_attempts,
+            keep_failed_containers: self.keep_failed_containers,
+            artifact_verifier: self.artifact_verifier.clone(),
+            max_log_lines: self.max_log_lines,
+            record_log_times: self.record_log_times,
             max_endpoint_name_length: self.max_endpoint_name_length,
             job,
             staging_store: self.staging_store.clone(),
             release_stores: self.release_stores.clone(),
             db: self.db.clone(),
             submit: self.submit.clone(),
+            events: self.events.clone(),
+            shutdown: self.shutdown.clone(),
+            scheduling_log: self.scheduling_log.clone(),
         })
     }
+}
 
-    async fn select_free_endpoint(&self) -> Result<EndpointHandle> {
-        loop {
-            let ep = self
-                .endpoints
-                .iter()
-                .filter(|ep| {
-                    // filter out all running containers where the number of max jobs is reached
-                    let r = ep.running_jobs() < ep.num_max_jobs();
-                    trace!(
-                        "Endpoint {} considered for scheduling job: {}",
-                        ep.name(),
-                        r
-                    );
+/// Run the `verifier` for each of the `artifacts` of a job
+///
+/// The path of the artifact is passed as argument, the package and the job via environment
+/// variables. Returns whether all artifacts were accepted, together with the output of the
+/// verifier (for the log of the job).
+async fn verify_artifacts(
+    verifier: &Path,
+    artifacts: &[PathBuf],
+    job_uuid: &Uuid,
+    package: &dbmodels::Package,
+    image: &dbmodels::Image,
+) -> Result<(bool, String)> {
+    let mut verified = true;
+    let mut output = String::new();
+    for artifact in artifacts {
+        trace!(
+            "Verifying artifact {} with {}",
+            artifact.display(),
+            verifier.display()
+        );
+        let verification = tokio::process::Command::new(verifier)
+            .arg(artifact)
+            .env("BUTIDO_PACKAGE_NAME", &package.name)
+            .env("BUTIDO_PACKAGE_VERSION", &package.version)
+            .env("BUTIDO_IMAGE", &image.name)
+            .env("BUTIDO_JOB_UUID", job_uuid.to_string())
+            .stdin(std::process::Stdio::null())
+            .output()
+            .await
+            .with_context(|| anyhow!("Running the artifact verifier {}", verifier.display()))?;
+
+        output.push_str(&format!(
+            "Verifying {} ({}):\n",
+            artifact.display(),
+            verification.status
+        ));
+        output.push_str(&String::from_utf8_lossy(&verification.stdout));
+        output.push_str(&String::from_utf8_lossy(&verification.stderr));
+        verified &= verification.status.success();
+    }
+    Ok((verified, output))
+}
+
+/// The range of the interval (in milliseconds) in which [select_free_endpoint] checks for a free
+/// endpoint if it wasn't notified about a finished job
+const ENDPOINT_POLL_INTERVAL_MS: std::ops::RangeInclusive<u64> = 50..=250;
+
+/// Whether starting a job can be retried (on another endpoint) after it failed with `error`
+///
+/// Only errors talking to the endpoint and errors of the endpoint itself (e.g. when it could not
+/// start the container) are retried. Other errors (e.g. an unknown image or an artifact that
+/// cannot be read) would occur again on any other endpoint.
+fn is_retryable(error: &Error) -> bool {
+    error
+        .chain()
+        .any(|cause| match cause.downcast_ref::<shiplift::Error>() {
+            Some(shiplift::Error::Hyper(_))
+            | Some(shiplift::Error::IO(_))
+            | Some(shiplift::Error::ConnectionNotUpgraded) => true,
+            Some(shiplift::Error::Fault { code, .. }) => code.is_server_error(),
+            _ => false,
+        })
+}
+
+/// Wait for a free endpoint that can run jobs on the image `image` and return a handle for it
+///
+/// Endpoints that are listed in `excluded` or that are not allowed to run the image (see the
+/// `allowed_images` and `denied_images` settings of the endpoints) are not considered. If no
+/// endpoint is left after excluding them, an error is returned.
+async fn select_free_endpoint(
+    endpoints: &[Arc<Endpoint>],
+    excluded: &[EndpointName],
+    image: &ImageName,
+) -> Result<EndpointHandle> {
+    let eligible = endpoints
+        .iter()
+        .filter(|ep| ep.can_run_image(image))
+        .collect::<Vec<_>>();
+    if eligible.is_empty() {
+        return Err(anyhow!(
+            "No endpoint can run jobs on image {}, it is not allowed on any of the endpoints: {}",
+            image,
+            endpoints.iter().map(|ep| ep.name()).join(", ")
+        ));
+    }
+
+    if eligible.iter().all(|ep| excluded.contains(ep.name())) {
+        return Err(anyhow!(
+            "No usable endpoint left for scheduling jobs on image {}, the eligible endpoints are: {}",
+            image,
+            eligible.iter().map(|ep| ep.name()).join(", ")
+        ));
+    }
+
+    loop {
+        // Register for the notifications before checking the endpoints, so that a job that
+        // finishes in between is not missed
+        let job_finished = eligible
+            .iter()
+            .filter(|ep| !excluded.contains(ep.name()))
+            .map(|ep| Box::pin(ep.job_finished()))
+            .collect::<Vec<_>>();
+
+        let ep = eligible
+            .iter()
+            .copied()
+            .filter(|ep| !excluded.contains(ep.name()))
+            .filter(|ep| {
+                // filter out all running containers where the number of max jobs is reached
+                let r = ep.running_jobs() < ep.num_max_jobs();
+                trace!(
+                    "Endpoint {} considered for scheduling job: {}",
+                    ep.name(),
                     r
-                })
-                .sorted_by(|ep1, ep2| {
-                    ep1.utilization()
-                        .partial_cmp(&ep2.utilization())
-                        .unwrap_or(std::cmp::Ordering::Equal)
-                })
-                .next();
-
-            if let Some(endpoint) = ep {
-                return Ok(EndpointHandle::new(endpoint.clone()));
-            } else {
-                trace!("No free endpoint found, retry...");
-                tokio::task::yield_now().await
-            }
+                );
+                r
+            })
+            .sorted_by(|ep1, ep2| {
+                ep1.utilization()
+                    .partial_cmp(&ep2.utilization())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .next();
+
+        if let Some(endpoint) = ep {
+            return Ok(EndpointHandle::new(endpoint.clone()));
+        } else {
+            trace!("No free endpoint found, waiting for a job to finish...");
+            // Polling again after a short time is only a fallback, the jitter spreads the jobs
+            // that are waiting for an endpoint
+            let fallback_wait = {
+                use rand::Rng;
+                rand::thread_rng().gen_range(ENDPOINT_POLL_INTERVAL_MS)
+            };
+            let _ = tokio::time::timeout(
+                std::time::Duration::from_millis(fallback_wait),
+                futures::future::select_all(job_finished),
+            )
+            .await;
         }
     }
 }
@@ -139,6 +352,12 @@ impl EndpointScheduler {
 pub struct JobHandle {
     log_dir: Option<PathBuf>,
     endpoint: EndpointHandle,
+    endpoints: Vec<Arc<Endpoint>>,
+    retry_attempts: usize,
+    keep_failed_containers: bool,
+    artifact_verifier: Option<PathBuf>,
+    max_log_lines: Option<usize>,
+    record_log_times: bool,
     max_endpoint_name_length: usize,
     job: RunnableJob,
     bar: ProgressBar,
@@ -146,6 +365,9 @@ pub struct JobHandle {
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     submit: crate::db::models::Submit,
+    events: EventSink,
+    shutdown: Shutdown,
+    scheduling_log: SchedulingLog,
 }
 
 impl std::fmt::Debug for JobHandle {
@@ -155,47 +377,106 @@ impl std::fmt::Debug for JobHandle {
 }
 
 impl JobHandle {
-    pub async fn run(self) -> Result<Result<Vec<ArtifactPath>>> {
+    /// Run the job
+    ///
+    /// `is_cancelled` is polled while the job is running. Once it returns `true`, the job is not
+    /// started anymore or its container is killed.
+    pub async fn run(
+        self,
+        is_cancelled: &(dyn Fn() -> bool + Sync),
+    ) -> Result<Result<Vec<ArtifactPath>>> {
         let (log_sender, log_receiver) = tokio::sync::mpsc::unbounded_channel::<LogItem>();
-        let endpoint_uri = self.endpoint.uri().clone();
-        let endpoint_name = self.endpoint.name().clone();
-        let endpoint =
-            dbmodels::Endpoint::create_or_fetch(&mut self.db.get().unwrap(), self.endpoint.name())?;
         let package =
             dbmodels::Package::create_or_fetch(&mut self.db.get().unwrap(), self.job.package())?;
         let image =
             dbmodels::Image::create_or_fetch(&mut self.db.get().unwrap(), self.job.image())?;
         let envs = self.create_env_in_db()?;
         let job_id = *self.job.uuid();
-        trace!(
-            "Running on Job {} on Endpoint {}",
-            job_id,
-            self.endpoint.name()
-        );
-        let prepared_container = self
-            .endpoint
-            .prepare_container(
+        let job_arch = self.job.arch().clone();
+        let shell = self.job.shell().clone();
+
+        // Failures before the script is executed (e.g., because the endpoint is temporarily
+        // unreachable, see `is_retryable()`) are retried on a (possibly) different endpoint, the
+        // container of the failed attempt is removed already.
+        // Once the script was started, the job is never retried.
+        let mut endpoint = self.endpoint;
+        let mut unreachable_endpoints: Vec<EndpointName> = vec![];
+        let mut retries_left = self.retry_attempts;
+        let started_container = loop {
+            if is_cancelled() {
+                return Err(anyhow!(
+                    "Job {} was cancelled before it was started",
+                    job_id
+                ));
+            }
+
+            trace!("Running on Job {} on Endpoint {}", job_id, endpoint.name());
+            let res = Self::start_container(
+                &endpoint,
                 &self.job,
+                &self.submit.uuid,
                 self.staging_store.clone(),
                 self.release_stores.clone(),
+                &package,
             )
-            .await?;
-        let container_id = prepared_container.create_info().id.clone();
-        let running_container = prepared_container
-            .start()
-            .await
-            .with_context(|| {
-                Self::create_job_run_error(
-                    &job_id,
-                    &package.name,
-                    &package.version,
-                    &endpoint_uri,
-                    &container_id,
-                )
-            })?
-            .execute_script(log_sender);
+            .await;
+
+            match res {
+                Ok(started_container) => break started_container,
+                Err(e) if retries_left > 0 && is_retryable(&e) => {
+                    retries_left -= 1;
+                    let failed_endpoint_name = endpoint.name().clone();
+                    warn!(
+                        "Starting job {} on endpoint {} failed, retrying ({} attempts left): {:#}",
+                        job_id, failed_endpoint_name, retries_left, e
+                    );
+
+                    if let Err(health_err) = endpoint.health_check().await {
+                        warn!(
+                            "Endpoint {} is not used for job {} anymore: {:#}",
+                            failed_endpoint_name, job_id, health_err
+                        );
+                        unreachable_endpoints.push(failed_endpoint_name);
+                    }
+
+                    // Release the slot on the failed endpoint before waiting for a free one
+                    drop(endpoint);
+                    let start = Instant::now();
+                    endpoint = match select_free_endpoint(
+                        &self.endpoints,
+                        &unreachable_endpoints,
+                        self.job.image(),
+                    )
+                    .await
+                    {
+                        Ok(endpoint) => endpoint,
+                        Err(select_err) => return Err(e.context(select_err)),
+                    };
+                    self.scheduling_log
+                        .record(job_id, endpoint.name().clone(), start.elapsed());
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let endpoint_uri = endpoint.uri().clone();
+        let endpoint_name = endpoint.name().clone();
+        let db_endpoint =
+            dbmodels::Endpoint::create_or_fetch(&mut self.db.get().unwrap(), &endpoint_name)?;
+        let container_id = started_container.create_info().id.clone();
+        self.events.emit(Event::JobStarted {
+            job_uuid: &job_id,
+            package_name: self.job.package().name(),
+            package_version: self.job.package().version(),
+            endpoint: endpoint_name.as_ref(),
+        });
+        let running_container = started_container.execute_script(log_sender);
 
         let logres = LogReceiver {
+            endpoint: &endpoint,
+            container_id: &container_id,
+            max_log_lines: self.max_log_lines,
+            record_log_times: self.record_log_times,
             endpoint_name: endpoint_name.as_ref(),
             max_endpoint_name_length: &self.max_endpoint_name_length,
             container_id_chrs: container_id.chars().take(7).collect(),
@@ -205,12 +486,24 @@ impl JobHandle {
             job: self.job,
             log_receiver,
             bar: self.bar.clone(),
+            db: self.db.clone(),
+            events: self.events.clone(),
+            shutdown: self.shutdown.clone(),
+            is_cancelled,
         }
         .join();
         drop(self.bar);
 
         let (run_container, logres) = tokio::join!(running_container, logres);
-        let log =
+
+        // The partial log is not needed anymore once the job is finished: Either the complete log
+        // is stored with the job below, or the job failed before it could be recorded.
+        if let Err(e) = dbmodels::JobLogChunk::delete_for_job(&mut self.db.get().unwrap(), &job_id)
+        {
+            warn!("Removing partial log of job {} failed: {:#}", job_id, e);
+        }
+
+        let (log, log_limit_exceeded) =
             logres.with_context(|| anyhow!("Collecting logs for job on '{}'", endpoint_name))?;
         let run_container = run_container
             .with_context(|| anyhow!("Running container {} failed", container_id))
@@ -221,6 +514,7 @@ impl JobHandle {
                     &package.version,
                     &endpoint_uri,
                     &container_id,
+                    &shell,
                 )
             })?;
 
@@ -228,16 +522,19 @@ impl JobHandle {
             &mut self.db.get().unwrap(),
             &job_id,
             &self.submit,
-            &endpoint,
+            &db_endpoint,
             &package,
             &image,
             &run_container.container_hash(),
             run_container.script(),
             &log,
+            run_container.success(),
+            job_arch.as_deref(),
         )
         .context("Recording job that is ready in database")?;
 
         trace!("DB: Job entry for job {} created: {}", job.uuid, job.id);
+
         for env in envs {
             dbmodels::JobEnv::create(&mut self.db.get().unwrap(), &job, &env).with_context(
                 || {
@@ -250,7 +547,7 @@ impl JobHandle {
         }
 
         let res: crate::endpoint::FinalizedContainer = run_container
-            .finalize(self.staging_store.clone())
+            .finalize(self.staging_store.clone(), self.keep_failed_containers)
             .await
             .context("Finalizing container")
             .with_context(|| {
@@ -260,11 +557,34 @@ impl JobHandle {
                     &package.version,
                     &endpoint_uri,
                     &container_id,
+                    &shell,
                 )
             })?;
 
+        let resource_usage = res.resource_usage();
+        job.update_resource_usage(
+            &mut self.db.get().unwrap(),
+            resource_usage
+                .map(|usage| usage.memory_bytes())
+                .map(i64::try_from)
+                .transpose()?,
+            resource_usage.map(|usage| usage.memory_is_peak()),
+            resource_usage
+                .map(|usage| usage.cpu_time_ns())
+                .map(i64::try_from)
+                .transpose()?,
+        )?;
+
         trace!("Found result for job {}: {:?}", job_id, res);
         let (paths, res) = res.unpack();
+        let res = match self.max_log_lines {
+            // The script failed anyways because the container was killed:
+            Some(max_log_lines) if log_limit_exceeded => Err(anyhow!(
+                "Log limit exceeded: The job printed more than {} log lines and was killed",
+                max_log_lines
+            )),
+            _ => res,
+        };
         let res = res
             .with_context(|| anyhow!("Error during running job on '{}'", endpoint_name))
             .with_context(|| {
@@ -274,16 +594,73 @@ impl JobHandle {
                     &package.version,
                     &endpoint_uri,
                     &container_id,
+                    &shell,
                 )
             });
 
         if res.is_err() {
             trace!("Error was returned from script");
+            if self.keep_failed_containers {
+                warn!(
+                    "Keeping container {} of failed job {} for inspection, connect with: {}",
+                    container_id.yellow().bold(),
+                    job.uuid,
+                    format!("docker --host {endpoint_uri} exec -it {container_id} {shell}")
+                        .yellow()
+                        .bold(),
+                );
+            }
             return Ok({
                 res.map(|_| vec![]) // to have the proper type, will never be executed
             });
         }
 
+        if let Some(verifier) = self.artifact_verifier.as_ref() {
+            let artifacts = {
+                let staging_read = self.staging_store.read().await;
+                paths
+                    .iter()
+                    .map(|p| {
+                        staging_read
+                            .root_path()
+                            .join(p)?
+                            .map(|p| p.joined())
+                            .ok_or_else(|| anyhow!("Artifact not in store: {:?}", p))
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            };
+            let (verified, output) =
+                verify_artifacts(verifier, &artifacts, &job_id, &package, &image).await?;
+            job.record_artifact_verification(&mut self.db.get().unwrap(), &output, verified)?;
+
+            if !verified {
+                // The rejected artifacts must not be used by the jobs that depend on this one
+                let mut staging_write = self.staging_store.write().await;
+                for p in paths.iter() {
+                    trace!(
+                        "Removing rejected artifact from staging store: {}",
+                        p.display()
+                    );
+                    staging_write.remove(p).await?;
+                }
+
+                return Ok(Err(anyhow!(
+                    "The artifact verifier {} rejected the artifacts",
+                    verifier.display()
+                ))
+                .with_context(|| {
+                    Self::create_job_run_error(
+                        &job.uuid,
+                        &package.name,
+                        &package.version,
+                        &endpoint_uri,
+                        &container_id,
+                        &shell,
+                    )
+                }));
+            }
+        }
+
         // Have to do it the ugly way here because of borrowing semantics
         let mut r = vec![];
         let staging_read = self.staging_store.read().await;
@@ -300,13 +677,41 @@ impl JobHandle {
         Ok(Ok(r))
     }
 
+    /// Prepare and start the container for the job on the endpoint
+    ///
+    /// Errors returned from here happen before the script is executed, so the job can be retried.
+    async fn start_container<'e>(
+        endpoint: &'e Endpoint,
+        job: &RunnableJob,
+        submit_uuid: &Uuid,
+        staging_store: Arc<RwLock<StagingStore>>,
+        release_stores: Vec<Arc<ReleaseStore>>,
+        package: &dbmodels::Package,
+    ) -> Result<StartedContainer<'e>> {
+        let prepared_container = endpoint
+            .prepare_container(job, submit_uuid, staging_store, release_stores)
+            .await?;
+        let container_id = prepared_container.create_info().id.clone();
+        prepared_container.start().await.with_context(|| {
+            Self::create_job_run_error(
+                job.uuid(),
+                &package.name,
+                &package.version,
+                endpoint.uri(),
+                &container_id,
+                job.shell(),
+            )
+        })
+    }
+
     /// Helper to create an error object with a nice message.
-    fn create_job_run_error(
+    pub(crate) fn create_job_run_error(
         job_id: &Uuid,
         package_name: &str,
         package_version: &str,
         endpoint_uri: &str,
         container_id: &str,
+        shell: &str,
     ) -> Error {
         anyhow!(indoc::formatdoc!(
             r#"Error while running job for {package_name} {package_version} with id:
@@ -325,7 +730,7 @@ impl JobHandle {
             package_name = package_name.to_string().red(),
             package_version = package_version.to_string().red(),
             docker_connect_string =
-                format!("docker --host {endpoint_uri} exec -it {container_id} /bin/bash")
+                format!("docker --host {endpoint_uri} exec -it {container_id} {shell}")
                     .yellow()
                     .bold(),
         ))
@@ -370,6 +775,10 @@ impl JobHandle {
 }
 
 struct LogReceiver<'a> {
+    endpoint: &'a Endpoint,
+    container_id: &'a str,
+    max_log_lines: Option<usize>,
+    record_log_times: bool,
     endpoint_name: &'a str,
     max_endpoint_name_length: &'a usize,
     container_id_chrs: String,
@@ -379,11 +788,23 @@ struct LogReceiver<'a> {
     job: RunnableJob,
     log_receiver: UnboundedReceiver<LogItem>,
     bar: ProgressBar,
+    db: Pool<ConnectionManager<PgConnection>>,
+    events: EventSink,
+    shutdown: Shutdown,
+    is_cancelled: &'a (dyn Fn() -> bool + Sync),
 }
 
 impl LogReceiver<'_> {
-    async fn join(mut self) -> Result<String> {
+    /// Receive the log of the job until the container closes the log stream
+    ///
+    /// Returns the log and whether the log limit (`max_log_lines`) was exceeded, in which case
+    /// the container was killed and the rest of the log was dropped.
+    /// The container is killed as well if the shutdown of butido is forced or if the job is
+    /// cancelled.
+    async fn join(mut self) -> Result<(String, bool)> {
         let mut success = None;
+        let mut log_limit_exceeded = false;
+        let mut killed = false;
         // Reserve a reasonable amount of elements.
         let mut accu = Vec::with_capacity(4096);
 
@@ -403,7 +824,39 @@ impl LogReceiver<'_> {
         let timeout_duration = std::time::Duration::from_millis(250);
         let max_endpoint_name_length = self.max_endpoint_name_length;
 
+        // The log lines are written to the database in chunks while the job is running, so that
+        // the log can be followed with "db log-of --follow".
+        // The complete log is still collected in `accu` and stored when the job is finished.
+        let flush_interval = std::time::Duration::from_secs(2);
+        let mut last_flush = std::time::Instant::now();
+        let mut unflushed_lines = Vec::new();
+
         loop {
+            if last_flush.elapsed() >= flush_interval {
+                self.flush_log_chunk(&mut unflushed_lines);
+                last_flush = std::time::Instant::now();
+            }
+
+            let kill_reason = if self.shutdown.is_forced() {
+                Some("butido is shutting down")
+            } else if (self.is_cancelled)() {
+                Some("the build was cancelled")
+            } else {
+                None
+            };
+            if let Some(kill_reason) = kill_reason.filter(|_| !killed && !log_limit_exceeded) {
+                killed = true;
+                warn!(
+                    "Killing container {} of job {}, {}",
+                    self.container_id,
+                    self.job.uuid(),
+                    kill_reason
+                );
+                if let Err(e) = self.kill_container().await {
+                    warn!("{:#}", e);
+                }
+            }
+
             // Timeout for receiving from the log receiver channel
             // This way we can update (`tick()`) the progress bar and show the user that things are
             // happening, even if there was no log output for several seconds.
@@ -417,6 +870,31 @@ impl LogReceiver<'_> {
                     Ok(None) => break, // if the log is empty, we're done
                     Ok(Some(logitem)) => logitem,
                 };
+            let received_at = chrono::Utc::now();
+
+            if log_limit_exceeded {
+                continue; // Drop the rest of the log, until the container is gone
+            }
+            let logitem = match self.max_log_lines {
+                Some(max_log_lines) if accu.len() >= max_log_lines => {
+                    log_limit_exceeded = true;
+                    warn!(
+                        "Job {} exceeded the log limit of {} lines, killing container {}",
+                        self.job.uuid(),
+                        max_log_lines,
+                        self.container_id
+                    );
+                    if let Err(e) = self.kill_container().await {
+                        warn!("{:#}", e);
+                    }
+
+                    // The marker marks the log as failed as well
+                    LogItem::State(Err(format!(
+                        "Log limit exceeded, the log was truncated after {max_log_lines} lines"
+                    )))
+                }
+                _ => logitem,
+            };
 
             if let Some(lf) = logfile.as_mut() {
                 lf.write_all(logitem.display()?.to_string().as_bytes())
@@ -434,6 +912,10 @@ impl LogReceiver<'_> {
                 }
                 LogItem::CurrentPhase(ref phasename) => {
                     trace!("Setting bar phase to {}", phasename);
+                    self.events.emit(Event::PhaseChanged {
+                        job_uuid: self.job.uuid(),
+                        phase: phasename,
+                    });
                     self.bar.set_message(format!(
                         "{:<max_endpoint_name_length$} {} {} {} {} {} {}",
                         self.endpoint_name,
@@ -473,6 +955,9 @@ impl LogReceiver<'_> {
                     success = Some(false);
                 }
             }
+            // The time is only stored if enabled, as it changes the format of the stored log
+            let logitem = TimedLogItem::new(self.record_log_times.then_some(received_at), logitem);
+            unflushed_lines.push(logitem.raw()?);
             accu.push(logitem);
         }
 
@@ -498,12 +983,46 @@ impl LogReceiver<'_> {
             lf.flush().await?;
         }
 
-        Ok({
-            accu.iter()
-                .map(crate::log::LogItem::raw)
-                .collect::<Result<Vec<String>>>()?
-                .join("\n")
-        })
+        let log = accu
+            .iter()
+            .map(TimedLogItem::raw)
+            .collect::<Result<Vec<String>>>()?
+            .join("\n");
+        Ok((log, log_limit_exceeded))
+    }
+
+    /// Kill the container of the job (after it exceeded the log limit)
+    async fn kill_container(&self) -> Result<()> {
+        self.endpoint
+            .get_container_by_id(self.container_id)
+            .await?
+            .ok_or_else(|| anyhow!("Container {} not found", self.container_id))?
+            .kill(None)
+            .await
+            .with_context(|| anyhow!("Killing container {}", self.container_id))
+    }
+
+    /// Write the passed log lines to the database, if there are any
+    ///
+    /// The lines are kept if writing them fails, so that they can be written with the next chunk
+    /// and no lines are missing in the partial log.
+    fn flush_log_chunk(&self, lines: &mut Vec<String>) {
+        if lines.is_empty() {
+            return;
+        }
+
+        let res = self.db.get().map_err(Error::from).and_then(|mut conn| {
+            dbmodels::JobLogChunk::create(&mut conn, self.job.uuid(), &lines.join("\n"))
+        });
+
+        match res {
+            Ok(()) => lines.clear(),
+            Err(e) => warn!(
+                "Writing partial log of job {} to database failed: {:#}",
+                self.job.uuid(),
+                e
+            ),
+        }
     }
 
     async fn get_logfile(&self) -> Option<Result<tokio::io::BufWriter<tokio::fs::File>>> {
@@ -529,3 +1048,23 @@ impl LogReceiver<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_retryable() {
+        let connection_error = Error::from(shiplift::Error::IO(std::io::Error::other(
+            "connection refused",
+        )))
+        .context("Starting the container");
+        assert!(is_retryable(&connection_error));
+
+        let invalid_response = Error::from(shiplift::Error::InvalidResponse(String::from("?")))
+            .context("Starting the container");
+        assert!(!is_retryable(&invalid_response));
+
+        assert!(!is_retryable(&anyhow!("Artifact not found")));
+    }
+}
@@ -14,6 +14,7 @@ use anyhow::Result;
 use futures::FutureExt;
 use tokio_stream::StreamExt;
 
+use crate::config::EndpointName;
 use crate::endpoint::Endpoint;
 use crate::endpoint::EndpointConfiguration;
 
@@ -26,3 +27,19 @@ pub async fn setup_endpoints(endpoints: Vec<EndpointConfiguration>) -> Result<Ve
 
     unordered.collect().await
 }
+
+/// Set up the endpoints, but do not fail if some of them cannot be set up
+///
+/// Returns the result of the setup of each endpoint together with the name of the endpoint.
+pub async fn try_setup_endpoints(
+    endpoints: Vec<EndpointConfiguration>,
+) -> Vec<(EndpointName, Result<Arc<Endpoint>>)> {
+    let unordered = futures::stream::FuturesUnordered::new();
+
+    for cfg in endpoints.into_iter() {
+        let name = cfg.endpoint_name().clone();
+        unordered.push(Endpoint::setup(cfg).map(|r_ep| (name, r_ep.map(Arc::new))));
+    }
+
+    unordered.collect().await
+}
@@ -9,13 +9,21 @@
 //
 
 use std::fmt::Debug;
+use std::path::Path;
+use std::path::PathBuf;
 
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
 use anyhow::Result;
 use indicatif::ProgressBar;
+use tracing::trace;
 
 use crate::filestore::path::ArtifactPath;
 use crate::filestore::path::StoreRoot;
 use crate::filestore::util::FileStoreImpl;
+use crate::package::HashType;
+use crate::package::HashValue;
 
 // The implementation of this type must be available in the merged filestore.
 pub struct ReleaseStore(pub(in crate::filestore) FileStoreImpl);
@@ -38,4 +46,65 @@ impl ReleaseStore {
     pub fn get(&self, p: &ArtifactPath) -> Option<&ArtifactPath> {
         self.0.get(p)
     }
+
+    /// Find an artifact in this store that has the same content as the file at `path`
+    ///
+    /// Only artifacts with the same size as the file are candidates, their content is compared
+    /// by the SHA256 hashes (the same size alone does not mean that the content is identical).
+    /// The file at `exclude` (the file that is going to be replaced) and artifacts that were removed
+    /// since the store was loaded are not candidates.
+    pub async fn find_identical(&self, path: &Path, exclude: &Path) -> Result<Option<PathBuf>> {
+        let size = tokio::fs::metadata(path)
+            .await
+            .with_context(|| anyhow!("Getting the metadata of {}", path.display()))?
+            .len();
+
+        let mut hash = None;
+        for artifact in self.0.iter() {
+            let Some(candidate) = self.0.root_path().join(artifact)? else {
+                continue;
+            };
+            let candidate = candidate.joined();
+            if candidate == exclude {
+                continue;
+            }
+            let candidate_size = match tokio::fs::metadata(&candidate).await {
+                Ok(metadata) => metadata.len(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(Error::from(e)).with_context(|| {
+                        anyhow!("Getting the metadata of {}", candidate.display())
+                    })
+                }
+            };
+            if candidate_size != size {
+                continue;
+            }
+
+            if hash.is_none() {
+                hash = Some(sha256(path).await?);
+            }
+            if hash == Some(sha256(&candidate).await?) {
+                trace!(
+                    "Found artifact with identical content for {}: {}",
+                    path.display(),
+                    candidate.display()
+                );
+                return Ok(Some(candidate));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Compute the SHA256 hash of a file
+pub(crate) async fn sha256(path: &Path) -> Result<HashValue> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| anyhow!("Opening {}", path.display()))?;
+    HashType::Sha256
+        .hash_from_reader(file)
+        .await
+        .with_context(|| anyhow!("Hashing {}", path.display()))
 }
@@ -9,13 +9,19 @@
 //
 
 use std::fmt::Debug;
+use std::path::Path;
+use std::path::PathBuf;
 
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
+use chrono::DateTime;
+use chrono::Local;
 use futures::stream::Stream;
+use getset::{CopyGetters, Getters};
 use indicatif::ProgressBar;
 use tracing::trace;
+use uuid::Uuid;
 
 use crate::filestore::path::ArtifactPath;
 use crate::filestore::path::StoreRoot;
@@ -79,4 +85,65 @@ impl StagingStore {
     pub fn get(&self, p: &ArtifactPath) -> Option<&ArtifactPath> {
         self.0.get(p)
     }
+
+    /// Remove the artifact `p` from the store and delete its file
+    pub async fn remove(&mut self, p: &ArtifactPath) -> Result<()> {
+        if let Some(full_path) = self.0.root_path().join(p)? {
+            tokio::fs::remove_file(full_path.joined())
+                .await
+                .with_context(|| anyhow!("Removing artifact {}", full_path.display()))?;
+        }
+        self.0.remove(p);
+        Ok(())
+    }
+}
+
+/// The staging directory of a submit, inside the staging root
+#[derive(Debug, Getters, CopyGetters)]
+pub struct StagingDirectory {
+    #[getset(get = "pub")]
+    path: PathBuf,
+
+    #[getset(get_copy = "pub")]
+    submit_uuid: Uuid,
+
+    /// The time the directory was last modified (i.e. an artifact was added)
+    #[getset(get_copy = "pub")]
+    modified: DateTime<Local>,
+}
+
+impl StagingDirectory {
+    /// List the staging directories in the staging root
+    ///
+    /// Entries that are not directories or that are not named after a submit UUID were not created
+    /// by butido and are skipped.
+    pub fn list(staging_root: &Path) -> Result<Vec<StagingDirectory>> {
+        std::fs::read_dir(staging_root)
+            .with_context(|| anyhow!("Reading staging root: {}", staging_root.display()))?
+            .map(|entry| -> Result<Option<StagingDirectory>> {
+                let entry = entry?;
+                let path = entry.path();
+                let submit_uuid = entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| Uuid::parse_str(name).ok());
+
+                match submit_uuid {
+                    Some(submit_uuid) if entry.file_type()?.is_dir() => {
+                        let modified = entry.metadata()?.modified()?.into();
+                        Ok(Some(StagingDirectory {
+                            path,
+                            submit_uuid,
+                            modified,
+                        }))
+                    }
+                    _ => {
+                        trace!("Not a staging directory, skipping: {}", path.display());
+                        Ok(None)
+                    }
+                }
+            })
+            .filter_map(Result::transpose)
+            .collect()
+    }
 }
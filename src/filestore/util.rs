@@ -51,6 +51,10 @@ impl FileStoreImpl {
         self.store.get(artifact_path)
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &ArtifactPath> {
+        self.store.iter()
+    }
+
     pub(in crate::filestore) fn load_from_path<'a>(
         &mut self,
         artifact_path: &'a ArtifactPath,
@@ -60,4 +64,8 @@ impl FileStoreImpl {
         }
         artifact_path
     }
+
+    pub(in crate::filestore) fn remove(&mut self, artifact_path: &ArtifactPath) -> bool {
+        self.store.remove(artifact_path)
+    }
 }
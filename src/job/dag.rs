@@ -32,6 +32,7 @@ impl Dag {
         dag: crate::package::Dag,
         script_shebang: Shebang,
         image: ImageName,
+        arch: Option<String>,
         phases: Vec<PhaseName>,
         resources: Vec<JobResource>,
     ) -> Self {
@@ -40,6 +41,7 @@ impl Dag {
                 p.clone(),
                 script_shebang.clone(),
                 image.clone(),
+                arch.clone(),
                 phases.clone(),
                 resources.clone(),
             )
@@ -30,6 +30,10 @@ pub struct Job {
     #[getset(get = "pub")]
     image: ImageName,
 
+    /// The architecture the job is built for, if any (see `build --arch`)
+    #[getset(get = "pub")]
+    arch: Option<String>,
+
     #[getset(get = "pub")]
     script_shebang: Shebang,
 
@@ -45,6 +49,7 @@ impl Job {
         pkg: Package,
         script_shebang: Shebang,
         image: ImageName,
+        arch: Option<String>,
         phases: Vec<PhaseName>,
         resources: Vec<JobResource>,
     ) -> Self {
@@ -54,6 +59,7 @@ impl Job {
             uuid,
             package: pkg,
             image,
+            arch,
             script_shebang,
             script_phases: phases,
             resources,
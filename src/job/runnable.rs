@@ -12,13 +12,15 @@ use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use getset::Getters;
-use tracing::{debug, trace};
+use tracing::debug;
 use uuid::Uuid;
 
 use crate::config::Configuration;
 use crate::filestore::ArtifactPath;
+use crate::job::BuildSecret;
 use crate::job::Job;
 use crate::job::JobResource;
+use crate::package::ContainerFlags;
 use crate::package::Package;
 use crate::package::Script;
 use crate::package::ScriptBuilder;
@@ -39,6 +41,13 @@ pub struct RunnableJob {
     #[getset(get = "pub")]
     image: ImageName,
 
+    #[getset(get = "pub")]
+    arch: Option<String>,
+
+    /// The shell the container is started with and that runs the script
+    #[getset(get = "pub")]
+    shell: String,
+
     #[getset(get = "pub")]
     source_cache: SourceCache,
 
@@ -47,6 +56,13 @@ pub struct RunnableJob {
 
     #[getset(get = "pub")]
     resources: Vec<JobResource>,
+
+    #[getset(get = "pub")]
+    container_flags: ContainerFlags,
+
+    /// The secrets that are copied into the container, see [BuildSecret]
+    #[getset(get = "pub")]
+    secrets: Vec<BuildSecret>,
 }
 
 impl RunnableJob {
@@ -57,46 +73,44 @@ impl RunnableJob {
         git_author_env: Option<&(EnvironmentVariableName, String)>,
         git_commit_env: Option<&(EnvironmentVariableName, String)>,
         dependencies: Vec<ArtifactPath>,
+        secrets: &[BuildSecret],
     ) -> Result<Self> {
-        if config.containers().check_env_names() {
-            debug!("Checking environment if all variables are allowed!");
-            job.resources()
-                .iter()
-                .filter_map(|r| r.env())
-                .chain({
-                    job.package()
-                        .environment()
-                        .as_ref()
-                        .map(|hm| hm.iter())
-                        .into_iter()
-                        .flatten()
-                })
-                .chain(git_author_env.as_ref().into_iter().map(|(k, v)| (k, v)))
-                .chain(git_commit_env.as_ref().into_iter().map(|(k, v)| (k, v)))
-                .inspect(|(name, _)| debug!("Checking: {}", name))
-                .try_for_each(|(name, _)| {
-                    trace!(
-                        "{:?} contains? {:?}",
-                        config.containers().allowed_env(),
-                        name
-                    );
-                    if !config.containers().allowed_env().contains(name) {
-                        Err(anyhow!("Environment variable name not allowed: {}", name))
-                    } else {
-                        Ok(())
-                    }
-                })
-                .with_context(|| {
-                    anyhow!(
-                        "Checking allowed variables for package {} {}",
-                        job.package().name(),
-                        job.package().version()
-                    )
-                })
-                .context("Checking allowed variable names")?;
-        } else {
-            debug!("Environment checking disabled");
-        }
+        debug!("Checking environment if all variables are allowed!");
+        job.resources()
+            .iter()
+            .filter_map(|r| r.env())
+            .chain({
+                job.package()
+                    .environment()
+                    .as_ref()
+                    .map(|hm| hm.iter())
+                    .into_iter()
+                    .flatten()
+            })
+            .chain(git_author_env.as_ref().into_iter().map(|(k, v)| (k, v)))
+            .chain(git_commit_env.as_ref().into_iter().map(|(k, v)| (k, v)))
+            .inspect(|(name, _)| debug!("Checking: {}", name))
+            .try_for_each(|(name, _)| config.containers().check_env_name(name))
+            .with_context(|| {
+                anyhow!(
+                    "Checking allowed variables for package {} {}",
+                    job.package().name(),
+                    job.package().version()
+                )
+            })
+            .context("Checking allowed variable names")?;
+
+        let container_flags = ContainerFlags::from_package_flags(
+            job.package().flags().as_deref().unwrap_or_default(),
+            config.containers().allowed_privileged_flags(),
+        )
+        .with_context(|| {
+            anyhow!(
+                "Checking the flags of package {} {}",
+                job.package().name(),
+                job.package().version()
+            )
+        })?;
 
         let resources = dependencies
             .into_iter()
@@ -122,14 +136,83 @@ impl RunnableJob {
             uuid: *job.uuid(),
             package: job.package().clone(),
             image: job.image().clone(),
+            arch: job.arch().clone(),
+            shell: config.shell_for_image(job.image()).to_string(),
+            resources,
+            container_flags,
+            secrets: secrets.to_vec(),
+            source_cache: source_cache.clone(),
+
+            script,
+        })
+    }
+
+    /// Reconstruct a job from its record in the database, to run it again (see "db requeue")
+    ///
+    /// The recorded `script` and `environment` are used as they are instead of building them from
+    /// the `package`. The recorded environment already contains the environment of the package
+    /// (and the git environment), so the environment of the `package` is not used.
+    /// The job gets a new UUID.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_recorded_job(
+        mut package: Package,
+        image: ImageName,
+        arch: Option<String>,
+        script: Script,
+        environment: Vec<(EnvironmentVariableName, String)>,
+        source_cache: &SourceCache,
+        config: &Configuration,
+        dependencies: Vec<ArtifactPath>,
+    ) -> Result<Self> {
+        debug!("Checking environment if all variables are allowed!");
+        environment
+            .iter()
+            .inspect(|(name, _)| debug!("Checking: {}", name))
+            .try_for_each(|(name, _)| config.containers().check_env_name(name))
+            .with_context(|| {
+                anyhow!(
+                    "Checking allowed variables for package {} {}",
+                    package.name(),
+                    package.version()
+                )
+            })
+            .context("Checking allowed variable names")?;
+
+        let container_flags = ContainerFlags::from_package_flags(
+            package.flags().as_deref().unwrap_or_default(),
+            config.containers().allowed_privileged_flags(),
+        )
+        .with_context(|| {
+            anyhow!(
+                "Checking the flags of package {} {}",
+                package.name(),
+                package.version()
+            )
+        })?;
+
+        let resources = dependencies
+            .into_iter()
+            .map(JobResource::from)
+            .chain(environment.into_iter().map(JobResource::from))
+            .collect();
+
+        package.clear_environment();
+        Ok(RunnableJob {
+            uuid: Uuid::new_v4(),
+            package,
+            shell: config.shell_for_image(&image).to_string(),
+            image,
+            arch,
             resources,
+            container_flags,
+            secrets: vec![],
             source_cache: source_cache.clone(),
 
             script,
         })
     }
 
-    pub fn package_sources(&self) -> Vec<SourceEntry> {
+    pub fn package_sources(&self) -> Result<Vec<SourceEntry>> {
         self.source_cache.sources_for(self.package())
     }
 
@@ -0,0 +1,121 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use getset::Getters;
+
+/// A secret that is made available to the build script (e.g. a token for fetching a private
+/// dependency)
+///
+/// The secret is copied into the container at `/run/secrets/<NAME>` before the script runs and
+/// removed again after the script finished (before the artifacts are collected and before a
+/// failed container is kept). Unlike environment variables, secrets are neither stored in the
+/// database nor logged.
+#[derive(Clone, Getters)]
+pub struct BuildSecret {
+    #[getset(get = "pub")]
+    name: String,
+    content: Vec<u8>,
+}
+
+impl BuildSecret {
+    /// Parse a "NAME=PATH" argument and read the secret from PATH
+    pub fn load(s: &str) -> Result<Self> {
+        let (name, path) = parse_secret_arg(s)?;
+        let content = std::fs::read(path)
+            .with_context(|| anyhow!("Reading secret {} from {}", name, path.display()))?;
+        Ok(BuildSecret {
+            name: name.to_string(),
+            content,
+        })
+    }
+
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// The path of the secret inside the container
+    pub fn container_path(&self) -> PathBuf {
+        PathBuf::from(crate::consts::SECRETS_DIR_PATH).join(&self.name)
+    }
+}
+
+// Never print the content of a secret
+impl std::fmt::Debug for BuildSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BuildSecret")
+            .field("name", &self.name)
+            .field("content", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Split a "NAME=PATH" argument
+///
+/// NAME is used as file name in the container, so it may only contain alphanumeric characters,
+/// '_', '-' and '.' (but must not start with a '.').
+fn parse_secret_arg(s: &str) -> Result<(&str, &Path)> {
+    let (name, path) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected NAME=PATH for the secret, got: {}", s))?;
+
+    let valid_name = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if !valid_name {
+        return Err(anyhow!("Invalid name for a secret: '{}'", name));
+    }
+    if path.is_empty() {
+        return Err(anyhow!("Missing path for the secret {}", name));
+    }
+    Ok((name, Path::new(path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_secret_arg() {
+        let (name, path) = parse_secret_arg("token=/home/user/.token").unwrap();
+        assert_eq!(name, "token");
+        assert_eq!(path, Path::new("/home/user/.token"));
+
+        let (name, path) = parse_secret_arg("npm.rc=a=b").unwrap();
+        assert_eq!(name, "npm.rc");
+        assert_eq!(path, Path::new("a=b"));
+
+        assert!(parse_secret_arg("token").is_err());
+        assert!(parse_secret_arg("=/path").is_err());
+        assert!(parse_secret_arg("token=").is_err());
+        assert!(parse_secret_arg("../token=/path").is_err());
+        assert!(parse_secret_arg("a/b=/path").is_err());
+        assert!(parse_secret_arg(".hidden=/path").is_err());
+    }
+
+    #[test]
+    fn test_debug_does_not_show_content() {
+        let secret = BuildSecret {
+            name: String::from("token"),
+            content: b"very-secret".to_vec(),
+        };
+        let debug = format!("{secret:?}");
+        assert!(debug.contains("token"));
+        assert!(!debug.contains("very-secret"));
+        assert_eq!(secret.container_path(), Path::new("/run/secrets/token"));
+    }
+}
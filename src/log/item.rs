@@ -10,6 +10,8 @@
 
 use anyhow::Error;
 use anyhow::Result;
+use chrono::DateTime;
+use chrono::Utc;
 use colored::Colorize;
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -53,3 +55,54 @@ impl LogItem {
 #[derive(parse_display::Display)]
 #[display("{0}")]
 pub struct Display(colored::ColoredString);
+
+/// A [LogItem] with the time it was received
+///
+/// Logs of jobs that were run before the time was recorded do not contain the time.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TimedLogItem {
+    time: Option<DateTime<Utc>>,
+    item: LogItem,
+}
+
+impl TimedLogItem {
+    pub fn new(time: Option<DateTime<Utc>>, item: LogItem) -> Self {
+        TimedLogItem { time, item }
+    }
+
+    pub fn time(&self) -> Option<&DateTime<Utc>> {
+        self.time.as_ref()
+    }
+
+    pub fn item(&self) -> &LogItem {
+        &self.item
+    }
+
+    pub fn into_item(self) -> LogItem {
+        self.item
+    }
+
+    /// Display the item, prefixed with the time it was received (in local time)
+    pub fn display_with_time(&self) -> Result<String> {
+        let time = self
+            .time()
+            .map(|t| {
+                t.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S%.3f")
+                    .to_string()
+            })
+            .unwrap_or_else(|| format!("{:^23}", "no timestamp"));
+
+        self.item
+            .display()
+            .map(|d| format!("[{}] {}", time.dimmed(), d))
+    }
+
+    pub fn raw(&self) -> Result<String> {
+        let raw = self.item.raw()?;
+        match self.time {
+            Some(time) => Ok(format!("#BUTIDO:TIME:{}:{raw}", time.timestamp_millis())),
+            None => Ok(raw),
+        }
+    }
+}
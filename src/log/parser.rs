@@ -22,6 +22,7 @@ use shiplift::tty::TtyChunk;
 
 use crate::log::util::*;
 use crate::log::LogItem;
+use crate::log::TimedLogItem;
 
 type IoResult<T> = RResult<T, futures::io::Error>;
 
@@ -36,13 +37,13 @@ where
         .lines()
 }
 
-pub struct ParsedLog(Vec<LogItem>);
+pub struct ParsedLog(Vec<TimedLogItem>);
 
 impl std::fmt::Debug for ParsedLog {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "ParsedLog [")?;
         for (i, line) in self.0.iter().enumerate() {
-            match line {
+            match line.item() {
                 LogItem::Line(l) => {
                     let s = std::str::from_utf8(l).unwrap_or("ERROR UTF8 ENCODING");
                     writeln!(f, "[{i}] Line('{s}')")?
@@ -62,7 +63,7 @@ impl FromStr for ParsedLog {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self> {
-        let p = parser();
+        let p = timed_parser();
         s.lines()
             .map(|line| p.parse(line.as_bytes()).map_err(Error::from))
             .collect::<Result<Vec<_>>>()
@@ -92,7 +93,7 @@ impl ParsedLog {
         self.0
             .iter()
             .rev()
-            .filter_map(|line| match line {
+            .filter_map(|line| match line.item() {
                 LogItem::State(Ok(_)) => Some(JobResult::Success),
                 LogItem::State(Err(_)) => Some(JobResult::Errored),
                 _ => None,
@@ -102,10 +103,31 @@ impl ParsedLog {
     }
 
     pub fn into_iter(self) -> impl Iterator<Item = LogItem> {
+        self.0.into_iter().map(TimedLogItem::into_item)
+    }
+
+    pub fn into_timed_iter(self) -> impl Iterator<Item = TimedLogItem> {
         self.0.into_iter()
     }
 }
 
+/// Parser for a log line that is (optionally) prefixed with the time it was received
+pub fn timed_parser<'a>() -> PomParser<'a, u8, TimedLogItem> {
+    use pom::parser::*;
+
+    let millis = one_of(b"0123456789")
+        .repeat(1..)
+        .collect()
+        .convert(|b| String::from_utf8(b.to_vec()))
+        .convert(|s| i64::from_str(&s));
+    let time = seq(b"#BUTIDO:TIME:") * millis - sym(b':');
+
+    (time.opt() + parser()).map(|(millis, item)| {
+        let time = millis.and_then(chrono::DateTime::from_timestamp_millis);
+        TimedLogItem::new(time, item)
+    })
+}
+
 pub fn parser<'a>() -> PomParser<'a, u8, LogItem> {
     use pom::parser::*;
 
@@ -462,4 +484,52 @@ mod tests {
         let log = ParsedLog::from_str(buffer).unwrap();
         assert_eq!(log.is_successfull(), JobResult::Errored);
     }
+
+    #[test]
+    fn test_timed_line() {
+        let s = "#BUTIDO:TIME:947638923004:foo bar";
+        let p = timed_parser();
+        let r = p.parse(s.as_bytes());
+
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        let r = r.unwrap();
+        let time = chrono::DateTime::from_timestamp_millis(947638923004).unwrap();
+        assert_eq!(r.time(), Some(&time));
+        assert_eq!(*r.item(), LogItem::Line("foo bar".bytes().collect()));
+    }
+
+    #[test]
+    fn test_timed_state() {
+        let s = "#BUTIDO:TIME:947638923004:#BUTIDO:STATE:OK";
+        let p = timed_parser();
+        let r = p.parse(s.as_bytes());
+
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        let r = r.unwrap();
+        assert!(r.time().is_some());
+        assert_eq!(*r.item(), LogItem::State(Ok(())));
+    }
+
+    #[test]
+    fn test_untimed_line() {
+        let s = "#BUTIDO:PHASE:a";
+        let p = timed_parser();
+        let r = p.parse(s.as_bytes());
+
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        let r = r.unwrap();
+        assert!(r.time().is_none());
+        assert_eq!(*r.item(), LogItem::CurrentPhase(String::from("a")));
+    }
+
+    #[test]
+    fn test_timed_raw_roundtrip() {
+        let time = chrono::DateTime::from_timestamp_millis(947638923004).unwrap();
+        let item = TimedLogItem::new(Some(time), LogItem::Progress(42));
+        let raw = item.raw().unwrap();
+
+        let r = timed_parser().parse(raw.as_bytes());
+        assert!(r.is_ok(), "Not ok: {r:?}");
+        assert_eq!(r.unwrap(), item);
+    }
 }
@@ -46,6 +46,7 @@
 #[macro_use]
 extern crate diesel;
 
+use std::io::Write;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
@@ -88,8 +89,7 @@ pub const VERSION_LONG: &str = concatdoc! {"
     Debug Build:          ", env!("VERGEN_CARGO_DEBUG")
 };
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     human_panic::setup_panic!(human_panic::Metadata::new(
         env!("CARGO_PKG_NAME"),
         env!("CARGO_PKG_VERSION")
@@ -101,6 +101,16 @@ async fn main() -> Result<()> {
     let app = cli::cli();
     let cli = app.get_matches();
 
+    // With "auto", `colored` decides whether to use colors (if stdout is a terminal):
+    let color_override = match cli.get_one::<String>("color").map(String::as_str) {
+        Some("always") => Some(true),
+        Some("never") => Some(false),
+        _ => None,
+    };
+    if let Some(color) = color_override {
+        colored::control::set_override(color);
+    }
+
     let (chrome_layer, _guard) = match cli
         .get_flag("tracing-chrome")
         .then(|| tracing_chrome::ChromeLayerBuilder::new().build())
@@ -109,14 +119,31 @@ async fn main() -> Result<()> {
         _ => (None, None),
     };
 
-    let subscriber = tracing_subscriber::fmt::fmt()
-        .with_env_filter(
-            tracing_subscriber::filter::EnvFilter::builder()
-                .with_default_directive(tracing_subscriber::filter::LevelFilter::WARN.into())
-                .from_env_lossy(),
-        )
-        .finish()
-        .with(chrome_layer);
+    // The default log level can be adjusted via CLI flags but `RUST_LOG` always takes precedence
+    // (the default directive is only used if `RUST_LOG` doesn't contain any valid directives):
+    let default_log_level = {
+        use tracing_subscriber::filter::LevelFilter;
+        if cli.get_flag("quiet") {
+            LevelFilter::ERROR
+        } else {
+            match cli.get_count("verbose") {
+                0 => LevelFilter::WARN,
+                1 => LevelFilter::INFO,
+                2 => LevelFilter::DEBUG,
+                _ => LevelFilter::TRACE,
+            }
+        }
+    };
+
+    let mut subscriber_builder = tracing_subscriber::fmt::fmt().with_env_filter(
+        tracing_subscriber::filter::EnvFilter::builder()
+            .with_default_directive(default_log_level.into())
+            .from_env_lossy(),
+    );
+    if let Some(color) = color_override {
+        subscriber_builder = subscriber_builder.with_ansi(color);
+    }
+    let subscriber = subscriber_builder.finish().with(chrome_layer);
 
     tracing::subscriber::set_global_default(subscriber)?;
     debug!("Debugging enabled");
@@ -127,6 +154,11 @@ async fn main() -> Result<()> {
         std::process::exit(0);
     }
 
+    // Listing the highlight themes needs neither a repository nor a configuration
+    if let Some(("list-highlight-themes", _)) = cli.subcommand() {
+        return list_highlight_themes();
+    }
+
     let repo = git2::Repository::open(PathBuf::from(".")).map_err(|e| match e.code() {
         git2::ErrorCode::NotFound => {
             eprintln!("Butido must be executed in the top-level of the Git repository");
@@ -159,6 +191,32 @@ async fn main() -> Result<()> {
         }
     }
 
+    if let Some(config_file) = cli.get_one::<PathBuf>("config") {
+        if !config_file.is_file() {
+            return Err(anyhow!(
+                "The configuration file does not exist: {}",
+                config_file.display()
+            ));
+        }
+        debug!("Configuration file from the CLI: {}", config_file.display());
+        config_builder = config_builder.add_source(::config::File::from(config_file.clone()));
+    }
+
+    let profile = cli
+        .get_one::<String>("profile")
+        .cloned()
+        .or_else(|| std::env::var("BUTIDO_PROFILE").ok());
+    if let Some(profile) = profile {
+        let files_config = config_builder
+            .build()
+            .context("Failed to load and build the butido configuration")?;
+        debug!("Using the configuration profile: {}", profile);
+        let profile_config = load_profile(&files_config, &profile)?;
+        config_builder = ::config::Config::builder()
+            .add_source(files_config)
+            .add_source(profile_config);
+    }
+
     config_builder = config_builder.add_source(::config::Environment::with_prefix("BUTIDO"));
 
     let config = config_builder
@@ -170,137 +228,195 @@ async fn main() -> Result<()> {
     check_compatibility(&config)
         .context("The butido configuration failed the compatibility check")?;
 
+    let strict = cli.get_flag("strict")
+        || std::env::var("BUTIDO_STRICT")
+            .map(|v| v != "0" && v != "false")
+            .unwrap_or(false);
     let config = config
         .try_deserialize::<NotValidatedConfiguration>()
         .context("Failed to load (type check) the butido configuration")?
-        .validate()
+        .validate(repo_path, strict)
         .context("Failed to validate the butido configuration")?;
 
     let hide_bars = cli.get_flag("hide_bars") || crate::util::stdout_is_pipe();
     let progressbars = ProgressBars::setup(config.progress_format().clone(), hide_bars);
 
-    let load_repo = || -> Result<Repository> {
-        let bar = progressbars.bar()?;
-        bar.set_message("Loading repository...");
-        let repo = Repository::load(repo_path, &bar).context("Loading the repository")?;
-        bar.finish_with_message("Repository loading finished");
-        Ok(repo)
-    };
-
     let db_connection_config = crate::db::DbConnectionConfig::parse(&config, &cli)?;
-    match cli.subcommand() {
-        Some(("generate-completions", matches)) => generate_completions(matches),
-        Some(("db", matches)) => crate::commands::db(db_connection_config, &config, matches)?,
-        Some(("build", matches)) => {
-            let pool = db_connection_config.establish_pool()?;
-
-            let repo = load_repo()?;
-
-            crate::commands::build(
-                repo_path,
-                matches,
-                progressbars,
-                pool,
-                &config,
-                repo,
-                repo_path,
-            )
-            .await
-            .context("build command failed")?
-        }
-        Some(("what-depends", matches)) => {
-            let repo = load_repo()?;
-            crate::commands::what_depends(matches, &config, repo)
-                .await
-                .context("what-depends command failed")?
-        }
-
-        Some(("dependencies-of", matches)) => {
-            let repo = load_repo()?;
-            crate::commands::dependencies_of(matches, &config, repo)
-                .await
-                .context("dependencies-of command failed")?
-        }
-
-        Some(("versions-of", matches)) => {
-            let repo = load_repo()?;
-            crate::commands::versions_of(matches, repo)
-                .await
-                .context("versions-of command failed")?
-        }
-
-        Some(("env-of", matches)) => {
-            let repo = load_repo()?;
-            crate::commands::env_of(matches, repo)
-                .await
-                .context("env-of command failed")?
-        }
-
-        Some(("find-artifact", matches)) => {
-            let repo = load_repo()?;
-            let pool = db_connection_config.establish_pool()?;
-            crate::commands::find_artifact(matches, &config, progressbars, repo, pool)
-                .await
-                .context("find-artifact command failed")?
-        }
-
-        Some(("find-pkg", matches)) => {
-            let repo = load_repo()?;
-            crate::commands::find_pkg(matches, &config, repo)
-                .await
-                .context("find-pkg command failed")?
-        }
-
-        Some(("source", matches)) => {
-            let repo = load_repo()?;
-            crate::commands::source(matches, &config, repo, progressbars)
-                .await
-                .context("source command failed")?
-        }
-
-        Some(("release", matches)) => {
-            crate::commands::release(db_connection_config, &config, matches)
-                .await
-                .context("release command failed")?
-        }
-
-        Some(("lint", matches)) => {
-            let repo = load_repo()?;
-            crate::commands::lint(repo_path, matches, progressbars, &config, repo)
-                .await
-                .context("lint command failed")?
-        }
 
-        Some(("tree-of", matches)) => {
-            let repo = load_repo()?;
-            crate::commands::tree_of(matches, repo, &config)
-                .await
-                .context("tree-of command failed")?
+    // This modifies the environment, so it has to be done before the runtime starts its threads
+    crate::endpoint::create_tls_clients(&config);
+    let runtime = tokio::runtime::Runtime::new().context("Starting the async runtime")?;
+
+    runtime.block_on(async {
+        let load_repo = || -> Result<Repository> {
+            let bar = progressbars.bar()?;
+            bar.set_message("Loading repository...");
+            let repo = Repository::load(repo_path, &bar)
+                .context("Loading the repository")?
+                .with_provider_defaults(config.virtual_package_providers().clone());
+            bar.finish_with_message("Repository loading finished");
+            Ok(repo)
+        };
+
+        match cli.subcommand() {
+            Some(("generate-completions", matches)) => generate_completions(matches),
+            Some(("config", matches)) => {
+                crate::commands::config(matches, &config, db_connection_config)?
+            }
+            Some(("db", matches)) => {
+                crate::commands::db(
+                    db_connection_config,
+                    &config,
+                    matches,
+                    progressbars.clone(),
+                    load_repo,
+                    repo_path,
+                )
+                .await?
+            }
+            Some(("build", matches)) => {
+                let pool = db_connection_config.establish_pool()?;
+
+                if matches.get_flag("watch") {
+                    crate::commands::build_watch(
+                        repo_path,
+                        matches,
+                        progressbars.clone(),
+                        pool,
+                        &config,
+                        load_repo,
+                        repo_path,
+                    )
+                    .await
+                    .context("build command failed")?
+                } else {
+                    let repo = load_repo()?;
+
+                    crate::commands::build(
+                        repo_path,
+                        matches,
+                        progressbars,
+                        pool,
+                        &config,
+                        repo,
+                        repo_path,
+                        None,
+                    )
+                    .await
+                    .context("build command failed")?
+                }
+            }
+            Some(("what-depends", matches)) => {
+                let repo = load_repo()?;
+                crate::commands::what_depends(matches, &config, repo)
+                    .await
+                    .context("what-depends command failed")?
+            }
+
+            Some(("dependencies-of", matches)) => {
+                let repo = load_repo()?;
+                crate::commands::dependencies_of(matches, &config, repo)
+                    .await
+                    .context("dependencies-of command failed")?
+            }
+
+            Some(("versions-of", matches)) => {
+                let repo = load_repo()?;
+                crate::commands::versions_of(matches, &config, repo)
+                    .await
+                    .context("versions-of command failed")?
+            }
+
+            Some(("env-of", matches)) => {
+                let repo = load_repo()?;
+                crate::commands::env_of(matches, &config, repo_path, repo)
+                    .await
+                    .context("env-of command failed")?
+            }
+
+            Some(("find-artifact", matches)) => {
+                let repo = load_repo()?;
+                let pool = db_connection_config.establish_pool()?;
+                crate::commands::find_artifact(matches, &config, progressbars, repo, pool)
+                    .await
+                    .context("find-artifact command failed")?
+            }
+
+            Some(("find-pkg", matches)) => {
+                let repo = load_repo()?;
+                crate::commands::find_pkg(repo_path, matches, &config, repo)
+                    .await
+                    .context("find-pkg command failed")?
+            }
+
+            Some(("source", matches)) => {
+                let repo = load_repo()?;
+                crate::commands::source(matches, &config, repo, progressbars)
+                    .await
+                    .context("source command failed")?
+            }
+
+            Some(("release", matches)) => {
+                crate::commands::release(db_connection_config, &config, matches)
+                    .await
+                    .context("release command failed")?
+            }
+
+            Some(("staging", matches)) => {
+                crate::commands::staging(db_connection_config, &config, matches)
+                    .await
+                    .context("staging command failed")?
+            }
+
+            Some(("lint", matches)) => {
+                let repo = load_repo()?;
+                crate::commands::lint(repo_path, matches, progressbars, &config, repo)
+                    .await
+                    .context("lint command failed")?
+            }
+
+            Some(("tree-of", matches)) => {
+                let repo = load_repo()?;
+                crate::commands::tree_of(matches, repo, &config)
+                    .await
+                    .context("tree-of command failed")?
+            }
+
+            Some(("metrics", matches)) => {
+                let repo = load_repo()?;
+                let pool = db_connection_config.establish_pool()?;
+                crate::commands::metrics(repo_path, matches, &config, repo, pool)
+                    .await
+                    .context("metrics command failed")?
+            }
+
+            Some(("endpoint", matches)) => {
+                crate::commands::endpoint(matches, &config, progressbars)
+                    .await
+                    .context("endpoint command failed")?
+            }
+            Some((other, _)) => {
+                error!("Unknown subcommand: {}", other);
+                error!("Use --help to find available subcommands");
+                return Err(anyhow!("Unknown subcommand: {}", other));
+            }
+            None => {
+                error!("No subcommand.");
+                error!("Use --help to find available subcommands");
+                return Err(anyhow!("No subcommand"));
+            }
         }
 
-        Some(("metrics", _)) => {
-            let repo = load_repo()?;
-            let pool = db_connection_config.establish_pool()?;
-            crate::commands::metrics(repo_path, &config, repo, pool)
-                .await
-                .context("metrics command failed")?
-        }
+        Ok(())
+    })
+}
 
-        Some(("endpoint", matches)) => crate::commands::endpoint(matches, &config, progressbars)
-            .await
-            .context("endpoint command failed")?,
-        Some((other, _)) => {
-            error!("Unknown subcommand: {}", other);
-            error!("Use --help to find available subcommands");
-            return Err(anyhow!("Unknown subcommand: {}", other));
-        }
-        None => {
-            error!("No subcommand.");
-            error!("Use --help to find available subcommands");
-            return Err(anyhow!("No subcommand"));
-        }
+fn list_highlight_themes() -> Result<()> {
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    for theme in crate::package::highlight_themes() {
+        writeln!(outlock, "{theme}")?;
     }
-
     Ok(())
 }
 
@@ -0,0 +1,194 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The machine-readable event stream of a build ("build --events-file/--events-fd")
+//!
+//! Each event is written as one JSON object per line (JSON Lines), with the name of the event in
+//! the "event" field and the time of the event in the "time" field.
+//! The stream is independent of the progress bars, so it is written with `--hide-bars` as well.
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Serialize;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::orchestrator::JobOutcome;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+
+/// An event of a build
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The container of a job was started on an endpoint
+    JobStarted {
+        job_uuid: &'a Uuid,
+        package_name: &'a PackageName,
+        package_version: &'a PackageVersion,
+        endpoint: &'a str,
+    },
+
+    /// The script of a job entered a phase
+    PhaseChanged { job_uuid: &'a Uuid, phase: &'a str },
+
+    /// The artifacts of an earlier build were reused, the job was not run
+    JobReused {
+        job_uuid: &'a Uuid,
+        package_name: &'a PackageName,
+        package_version: &'a PackageVersion,
+    },
+
+    /// A job was run (or skipped)
+    JobFinished {
+        job_uuid: &'a Uuid,
+        package_name: &'a PackageName,
+        package_version: &'a PackageVersion,
+        outcome: JobOutcome,
+        duration_secs: Option<f64>,
+        error: Option<String>,
+    },
+
+    /// All jobs of the submit are done
+    SubmitFinished {
+        submit_uuid: &'a Uuid,
+        success: bool,
+        built: usize,
+        reused: usize,
+        failed: usize,
+        skipped: usize,
+        wall_time_secs: f64,
+    },
+}
+
+/// An event together with the time it was emitted
+#[derive(Serialize)]
+struct TimedEvent<'a> {
+    time: String,
+
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+/// The sink the events of a build are written to
+///
+/// The default sink drops all events. Clones write to the same file.
+#[derive(Clone, Default)]
+pub struct EventSink(Option<Arc<Mutex<Box<dyn Write + Send>>>>);
+
+impl EventSink {
+    /// Write the events to the file at `path`, which is created (or truncated)
+    pub fn to_file(path: &Path) -> Result<Self> {
+        let file = std::fs::File::create(path)
+            .with_context(|| anyhow!("Creating event file {}", path.display()))?;
+        Ok(Self::new(file))
+    }
+
+    /// Write the events to the file descriptor `fd`, which was opened by the caller of butido
+    pub fn to_fd(fd: u32) -> Result<Self> {
+        let path = format!("/dev/fd/{fd}");
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .with_context(|| anyhow!("Opening file descriptor {} for the events", fd))?;
+        Ok(Self::new(file))
+    }
+
+    fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        EventSink(Some(Arc::new(Mutex::new(Box::new(writer)))))
+    }
+
+    /// Write the `event`
+    ///
+    /// A failure to write the event is not an error of the build, it is only logged.
+    pub fn emit(&self, event: Event<'_>) {
+        let Some(writer) = self.0.as_ref() else {
+            return;
+        };
+
+        let event = TimedEvent {
+            time: chrono::Local::now().to_rfc3339(),
+            event,
+        };
+        let res = serde_json::to_string(&event)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| {
+                let mut writer = writer
+                    .lock()
+                    .map_err(|_| anyhow!("Lock on the event sink is poisoned"))?;
+                writeln!(writer, "{line}")?;
+                writer.flush().map_err(anyhow::Error::from)
+            });
+        if let Err(e) = res {
+            warn!("Writing event failed: {:#}", e);
+        }
+    }
+}
+
+impl std::fmt::Debug for EventSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("EventSink").field(&self.0.is_some()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+
+    #[test]
+    fn test_events_are_written_as_json_lines() {
+        let path = std::env::temp_dir().join(format!("butido-events-{}.jsonl", Uuid::new_v4()));
+        let sink = EventSink::to_file(&path).unwrap();
+        let job_uuid = Uuid::new_v4();
+
+        sink.emit(Event::PhaseChanged {
+            job_uuid: &job_uuid,
+            phase: "build",
+        });
+        sink.clone().emit(Event::JobFinished {
+            job_uuid: &job_uuid,
+            package_name: &pname("a"),
+            package_version: &pversion("1"),
+            outcome: JobOutcome::Built,
+            duration_secs: Some(1.5),
+            error: None,
+        });
+        EventSink::default().emit(Event::PhaseChanged {
+            job_uuid: &job_uuid,
+            phase: "dropped",
+        });
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let events = content
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "phase_changed");
+        assert_eq!(events[0]["job_uuid"], job_uuid.to_string());
+        assert_eq!(events[0]["phase"], "build");
+        assert!(events[0]["time"].is_string());
+        assert_eq!(events[1]["event"], "job_finished");
+        assert_eq!(events[1]["package_name"], "a");
+        assert_eq!(events[1]["outcome"], "built");
+        assert_eq!(events[1]["duration_secs"], 1.5);
+        assert!(events[1]["error"].is_null());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -9,7 +9,19 @@
 //
 
 #![allow(clippy::module_inception)]
+mod events;
+pub use events::*;
+
 mod orchestrator;
 pub use orchestrator::*;
 
+mod plan;
+pub use plan::*;
+
+mod shutdown;
+pub use shutdown::*;
+
+mod summary;
+pub use summary::*;
+
 mod util;
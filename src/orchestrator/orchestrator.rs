@@ -13,8 +13,12 @@
 use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::anyhow;
 use anyhow::Context;
@@ -28,26 +32,37 @@ use git2::Repository;
 use indicatif::ProgressBar;
 use itertools::Itertools;
 use resiter::FilterMap;
+use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::Notify;
 use tokio::sync::RwLock;
 use tokio_stream::StreamExt;
 use tracing::Instrument;
-use tracing::{debug, error, trace};
+use tracing::{debug, error, trace, warn};
 use typed_builder::TypedBuilder;
 use uuid::Uuid;
 
 use crate::config::Configuration;
 use crate::db::models as dbmodels;
+use crate::db::ArtifactIndex;
 use crate::endpoint::EndpointConfiguration;
 use crate::endpoint::EndpointScheduler;
 use crate::filestore::ArtifactPath;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
+use crate::job::BuildSecret;
 use crate::job::Dag;
+use crate::job::Job;
 use crate::job::JobDefinition;
 use crate::job::RunnableJob;
 use crate::orchestrator::util::*;
+use crate::orchestrator::BuildSummary;
+use crate::orchestrator::Event;
+use crate::orchestrator::EventSink;
+use crate::orchestrator::JobOutcome;
+use crate::orchestrator::JobSummary;
+use crate::orchestrator::Shutdown;
 use crate::source::SourceCache;
 use crate::util::progress::ProgressBars;
 use crate::util::EnvironmentVariableName;
@@ -171,6 +186,11 @@ pub struct Orchestrator<'a> {
     config: &'a Configuration,
     repository: Repository,
     database: Pool<ConnectionManager<PgConnection>>,
+    artifact_index: Option<ArtifactIndex>,
+    secrets: Vec<BuildSecret>,
+    reuse_artifacts: bool,
+    fail_fast: bool,
+    cancellation: CancellationToken,
 }
 
 #[derive(TypedBuilder)]
@@ -184,6 +204,20 @@ pub struct OrchestratorSetup<'a> {
     database: Pool<ConnectionManager<PgConnection>>,
     submit: dbmodels::Submit,
     log_dir: Option<PathBuf>,
+    keep_failed_containers: bool,
+    #[builder(default)]
+    artifact_verifier: Option<PathBuf>,
+    #[builder(default)]
+    max_log_lines: Option<usize>,
+    #[builder(default = true)]
+    reuse_artifacts: bool,
+    fail_fast: bool,
+    #[builder(default)]
+    secrets: Vec<BuildSecret>,
+    #[builder(default)]
+    events: EventSink,
+    #[builder(default)]
+    shutdown: Shutdown,
     config: &'a Configuration,
     repository: Repository,
 }
@@ -197,9 +231,22 @@ impl<'a> OrchestratorSetup<'a> {
             self.database.clone(),
             self.submit.clone(),
             self.log_dir,
+            self.config.docker().endpoint_retry_attempts(),
+            self.keep_failed_containers,
+            self.artifact_verifier,
+            self.max_log_lines,
+            *self.config.record_log_times(),
+            self.events,
+            self.shutdown,
         )
         .await?;
 
+        let artifact_index = if self.reuse_artifacts {
+            load_artifact_index(self.config, &self.database)
+        } else {
+            None
+        };
+
         Ok(Orchestrator {
             scheduler,
             staging_store: self.staging_store.clone(),
@@ -209,7 +256,12 @@ impl<'a> OrchestratorSetup<'a> {
             jobdag: self.jobdag,
             config: self.config,
             database: self.database,
+            artifact_index,
+            secrets: self.secrets,
             repository: self.repository,
+            reuse_artifacts: self.reuse_artifacts,
+            fail_fast: self.fail_fast,
+            cancellation: CancellationToken::default(),
         })
     }
 }
@@ -264,13 +316,31 @@ impl Borrow<ArtifactPath> for ProducedArtifact {
 }
 
 impl Orchestrator<'_> {
-    pub async fn run(self, output: &mut Vec<ArtifactPath>) -> Result<HashMap<Uuid, Error>> {
-        let (results, errors) = self.run_tree().await?;
+    pub async fn run(
+        self,
+        output: &mut Vec<ArtifactPath>,
+    ) -> Result<(HashMap<Uuid, Error>, BuildSummary)> {
+        let start = Instant::now();
+        let scheduling_log = self.scheduler.scheduling_log().clone();
+        let events = self.scheduler.events().clone();
+        let submit_uuid = self.scheduler.submit().uuid;
+        let (results, errors, jobs) = self.run_tree().await?;
         output.extend(results);
-        Ok(errors)
+
+        let summary = BuildSummary::new(jobs, start.elapsed(), scheduling_log.decisions());
+        events.emit(Event::SubmitFinished {
+            submit_uuid: &submit_uuid,
+            success: errors.is_empty(),
+            built: summary.count(JobOutcome::Built),
+            reused: summary.count(JobOutcome::Reused),
+            failed: summary.count(JobOutcome::Failed),
+            skipped: summary.count(JobOutcome::Skipped),
+            wall_time_secs: summary.wall_time().as_secs_f64(),
+        });
+        Ok((errors, summary))
     }
 
-    async fn run_tree(self) -> Result<(Vec<ArtifactPath>, HashMap<Uuid, Error>)> {
+    async fn run_tree(self) -> Result<(Vec<ArtifactPath>, HashMap<Uuid, Error>, Vec<JobSummary>)> {
         let prepare_span = tracing::debug_span!("run tree preparation");
 
         // There is no async code until we drop this guard, so this is fine
@@ -287,30 +357,7 @@ impl Orchestrator<'_> {
             mp
         });
 
-        let git_author_env = {
-            self.config
-                .containers()
-                .git_author()
-                .as_ref()
-                .map(|varname| -> Result<_> {
-                    let username = self.repository.config()?.get_string("user.name")?;
-
-                    Ok((varname.clone(), username))
-                })
-                .transpose()?
-        };
-
-        let git_commit_env = {
-            self.config
-                .containers()
-                .git_commit_hash()
-                .as_ref()
-                .map(|varname| -> Result<_> {
-                    let hash = crate::util::git::get_repo_head_commit_hash(&self.repository)?;
-                    Ok((varname.clone(), hash))
-                })
-                .transpose()?
-        };
+        let (git_author_env, git_commit_env) = git_env(self.config, &self.repository)?;
 
         // For each job in the jobdag, built a tuple with
         //
@@ -349,6 +396,11 @@ impl Orchestrator<'_> {
                     staging_store: self.staging_store.clone(),
                     release_stores: self.release_stores.clone(),
                     database: self.database.clone(),
+                    artifact_index: self.artifact_index.as_ref(),
+                    secrets: &self.secrets,
+                    reuse_artifacts: self.reuse_artifacts,
+                    fail_fast: self.fail_fast,
+                    cancellation: &self.cancellation,
                 };
 
                 Ok((
@@ -414,28 +466,32 @@ impl Orchestrator<'_> {
             };
         }
 
-        // Find the id of the root task
+        // Find the root tasks
         //
         // By now, all tasks should be associated with their respective sender.
-        // Only one has None sender: The task that is the "root" of the tree.
-        // By that property, we can find the root task.
+        // Only the tasks that no other task depends on have None sender: The "roots" of the tree.
+        // There is one root task for each package that was requested (normally just one).
         // (Note: It should normally be the first jobs item as we start building the DAG from the
         // root task (-> first node in the DAG) but we don't explicitly guarantee this.)
-        //
-        // Here, we copy its uuid, because we need it later.
-        let root_job = jobs
+        let root_jobs = jobs
             .iter()
-            .find(|j| j.3.borrow().is_none())
-            .ok_or_else(|| anyhow!("Failed to find root task"))?;
-        let root_job_id = root_job.1.jobdef.job.uuid();
-        trace!(%root_job_id, "Root job id found");
-        // Move the progress bar for the root task to the bottom to ensure that it will be visible
-        // without having to scroll up (the MultiProgress implementation doesn't let us modify the
-        // order so we have to remove and re-add it - it works despite the clone because
-        // ProgressBar is an Arc around its internal state and is documented that way):
-        let root_job_bar = &root_job.1.bar;
-        multibar.remove(root_job_bar);
-        multibar.add(root_job_bar.clone());
+            .filter(|j| j.3.borrow().is_none())
+            .collect::<Vec<_>>();
+        if root_jobs.is_empty() {
+            return Err(anyhow!("Failed to find root task"));
+        }
+        let root_job_count = root_jobs.len();
+        for root_job in root_jobs {
+            let root_job_id = root_job.1.jobdef.job.uuid();
+            trace!(%root_job_id, "Root job id found");
+            // Move the progress bar for the root task to the bottom to ensure that it will be
+            // visible without having to scroll up (the MultiProgress implementation doesn't let us
+            // modify the order so we have to remove and re-add it - it works despite the clone
+            // because ProgressBar is an Arc around its internal state and is documented that way):
+            let root_job_bar = &root_job.1.bar;
+            multibar.remove(root_job_bar);
+            multibar.add(root_job_bar.clone());
+        }
 
         // Create a sender and a receiver for the root of the tree
         let (root_sender, mut root_receiver) = tokio::sync::mpsc::channel(100);
@@ -472,29 +528,254 @@ impl Orchestrator<'_> {
             })
             .collect::<futures::stream::FuturesUnordered<_>>();
         debug!("Built {} jobs", running_jobs.len());
+        // Only the root tasks hold a sender now, so that receiving fails instead of blocking if a
+        // root task does not send a result
+        drop(root_sender);
 
-        running_jobs
-            .collect::<Result<()>>()
+        let job_summaries = running_jobs
+            .collect::<Result<Vec<JobSummary>>>()
             .instrument(run_span.clone())
             .await?;
         trace!(parent: &run_span, "All jobs finished");
         drop(run_span);
 
-        match root_receiver.recv().await {
-            None => Err(anyhow!("No result received...")),
-            Some(Ok(results)) => {
-                let results = results
-                    .into_iter()
-                    .flat_map(|tpl| tpl.1.into_iter())
-                    .map(ProducedArtifact::unpack)
-                    .collect();
-                Ok((results, HashMap::with_capacity(0)))
+        if self.cancellation.is_cancelled() || self.scheduler.shutdown().is_requested() {
+            // The failed jobs did not send their errors to their parents, which were cancelled (or
+            // stopped because butido is shutting down)
+            return Ok((vec![], self.cancellation.take_errors(), job_summaries));
+        }
+
+        // Each root task sends its result (which includes the results of its dependencies), the
+        // results are merged by job, as the root tasks may share dependencies
+        let mut results = HashMap::new();
+        let mut errors = HashMap::new();
+        for _ in 0..root_job_count {
+            match root_receiver.recv().await {
+                None => return Err(anyhow!("No result received...")),
+                Some(Ok(r)) => results.extend(r),
+                Some(Err(e)) => errors.extend(e),
             }
-            Some(Err(errors)) => Ok((vec![], errors)),
+        }
+
+        if errors.is_empty() {
+            let results = results
+                .into_iter()
+                .flat_map(|tpl| tpl.1.into_iter())
+                .map(ProducedArtifact::unpack)
+                .collect();
+            Ok((results, errors, job_summaries))
+        } else {
+            Ok((vec![], errors, job_summaries))
         }
     }
 }
 
+/// An environment variable that is only set if it is configured
+type OptionalEnv = Option<(EnvironmentVariableName, String)>;
+
+/// Get the environment variables for the git author and the git commit hash, if configured
+pub fn git_env(
+    config: &Configuration,
+    repository: &Repository,
+) -> Result<(OptionalEnv, OptionalEnv)> {
+    let git_author_env = config
+        .containers()
+        .git_author()
+        .as_ref()
+        .map(|varname| -> Result<_> {
+            let username = repository.config()?.get_string("user.name")?;
+
+            Ok((varname.clone(), username))
+        })
+        .transpose()?;
+
+    let git_commit_env = config
+        .containers()
+        .git_commit_hash()
+        .as_ref()
+        .map(|varname| -> Result<_> {
+            let hash = crate::util::git::get_repo_head_commit_hash(repository)?;
+            Ok((varname.clone(), hash))
+        })
+        .transpose()?;
+
+    Ok((git_author_env, git_commit_env))
+}
+
+/// Load the artifact index, if one is configured
+///
+/// The artifact index is only needed for finding artifacts to reuse. If it cannot be loaded, the
+/// database is searched instead.
+pub(super) fn load_artifact_index(
+    config: &Configuration,
+    database: &Pool<ConnectionManager<PgConnection>>,
+) -> Option<ArtifactIndex> {
+    let path = config.artifact_index().as_ref()?;
+    let index = database
+        .get()
+        .map_err(anyhow::Error::from)
+        .and_then(|mut conn| ArtifactIndex::load_updated(path, &mut conn));
+
+    match index {
+        Ok(index) => Some(index),
+        Err(e) => {
+            warn!(
+                "Cannot use the artifact index, searching the database for artifacts to reuse: {:#}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Find artifacts in the stores that can be used instead of building the job
+///
+/// Artifacts from the staging store are preferred over artifacts from the release stores.
+#[allow(clippy::too_many_arguments)]
+pub fn find_replacement_artifacts(
+    job: &Job,
+    config: &Configuration,
+    database: Pool<ConnectionManager<PgConnection>>,
+    staging_store: &StagingStore,
+    release_stores: &[Arc<ReleaseStore>],
+    artifact_index: Option<&ArtifactIndex>,
+    git_author_env: Option<&(EnvironmentVariableName, String)>,
+    git_commit_env: Option<&(EnvironmentVariableName, String)>,
+) -> Result<Vec<ArtifactPath>> {
+    // Use the environment of the job definition, as it appears in the job DAG.
+    //
+    // This is because we do not have access to the commandline-passed (additional)
+    // environment variables at this point. But using the JobResource::env() variables
+    // works as well.
+    let additional_env = job
+        .resources()
+        .iter()
+        .filter_map(crate::job::JobResource::env)
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .chain(git_author_env.cloned())
+        .chain(git_commit_env.cloned())
+        .collect::<Vec<_>>();
+
+    let replacement_artifacts = crate::db::FindArtifacts::builder()
+        .database_pool(database)
+        .config(config)
+        .package(job.package())
+        .release_stores(release_stores)
+        .image_name(Some(job.image()))
+        .arch(job.arch().as_deref())
+        // We can simply pass the staging store here, because it doesn't hurt. There are
+        // two scenarios:
+        //
+        // 1. We are in a fresh build for a package. In this case, the artifacts for this
+        //    very build are not in there yet, and there won't be any artifacts from the
+        //    staging store (possibly from the release store, which would be fine).
+        // 2. We are in a re-build, where the user passed the staging store to the build
+        //    subcommand (or resumes a submit with `--resume`). In this case, there might be an
+        //    artifact for this job in the staging store. In this case, we want to use it as a
+        //    replacement, of course.
+        //
+        // The fact that released artifacts are returned preferably from this function
+        // call does not change anything, because if there is an artifact that's a released
+        // one that matches this job, we should use it anyways.
+        .staging_store(Some(staging_store))
+        .env_filter(&additional_env)
+        .script_filter(true)
+        .index(artifact_index)
+        .build()
+        .run()?;
+
+    debug!(
+        job_uuid = %job.uuid(),
+        replacement_artifacts_count = replacement_artifacts.len(),
+        "Found replacement artifacts",
+    );
+    trace!(
+        job_uuid = %job.uuid(),
+        ?replacement_artifacts,
+        "Found replacement artifacts",
+    );
+    let artifacts = replacement_artifacts
+        .into_iter()
+        // First of all, we sort by whether the artifact path is in the staging store,
+        // because we prefer staging store artifacts at this point.
+        .sorted_by(|(p1, _), (p2, _)| {
+            let r1 = p1.is_in_staging_store(staging_store);
+            let r2 = p2.is_in_staging_store(staging_store);
+            r1.cmp(&r2)
+        })
+        // We don't need duplicates here, so remove them by making the iterator unique
+        // If we have two artifacts that are the same, the one in the staging store will be
+        // preferred in the next step
+        .unique_by(|tpl| tpl.0.artifact_path().clone())
+        // Fetch the artifact from the staging store, if there is one.
+        // If there is none, try the release store.
+        // If there is none, there won't be a replacement artifact
+        .filter_map(|(full_artifact_path, _)| {
+            trace!("Searching for {:?} in stores", full_artifact_path.display());
+            if let Some(ap) = staging_store.get(full_artifact_path.artifact_path()) {
+                Some(ap.clone())
+            } else {
+                release_stores
+                    .iter()
+                    .find_map(|rs| rs.get(full_artifact_path.artifact_path()))
+                    .cloned()
+            }
+        })
+        .collect::<Vec<ArtifactPath>>();
+
+    Ok(artifacts)
+}
+
+/// Helper type: A token to cancel all jobs of a build as soon as one job failed (`--fail-fast`)
+///
+/// The token is checked by each `JobTask` while it waits for its dependencies or a free endpoint
+/// and while its container runs.
+/// The errors of the failed jobs are collected in the token instead of being sent to the parent
+/// tasks, as these are cancelled as well.
+#[derive(Default)]
+struct CancellationToken {
+    cancelled: AtomicBool,
+    notify: Notify,
+    errors: Mutex<HashMap<Uuid, Error>>,
+}
+
+impl CancellationToken {
+    /// Cancel all jobs because the job `job_uuid` failed with `error`
+    fn cancel(&self, job_uuid: Uuid, error: Error) {
+        self.errors.lock().unwrap().insert(job_uuid, error);
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Collect the `errors` of jobs that could not be sent to the parent tasks, without cancelling
+    /// the other jobs
+    fn add_errors(&self, errors: HashMap<Uuid, Error>) {
+        self.errors.lock().unwrap().extend(errors);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the jobs are cancelled
+    async fn cancelled(&self) {
+        loop {
+            // The future has to be created before checking the flag, otherwise a notification
+            // between the check and the creation of the future would be missed
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Take the errors of the jobs that caused the cancellation
+    fn take_errors(&self) -> HashMap<Uuid, Error> {
+        std::mem::take(&mut *self.errors.lock().unwrap())
+    }
+}
+
 /// Helper type: A task with all things attached, but not sender and receivers
 ///
 /// This is the preparation of the JobTask, but without the associated sender and receiver, because
@@ -514,6 +795,11 @@ struct TaskPreparation<'a> {
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     database: Pool<ConnectionManager<PgConnection>>,
+    artifact_index: Option<&'a ArtifactIndex>,
+    secrets: &'a [BuildSecret],
+    reuse_artifacts: bool,
+    fail_fast: bool,
+    cancellation: &'a CancellationToken,
 }
 
 /// Helper type for executing one job task
@@ -532,6 +818,15 @@ struct JobTask<'a> {
     staging_store: Arc<RwLock<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     database: Pool<ConnectionManager<PgConnection>>,
+    artifact_index: Option<&'a ArtifactIndex>,
+    secrets: &'a [BuildSecret],
+
+    /// Whether the artifacts of an earlier build may be reused instead of running the job
+    reuse_artifacts: bool,
+
+    /// Whether all jobs are cancelled if this job fails
+    fail_fast: bool,
+    cancellation: &'a CancellationToken,
 
     /// Channel where the dependencies arrive
     receiver: Receiver<JobResult>,
@@ -613,17 +908,65 @@ impl<'a> JobTask<'a> {
             staging_store: prep.staging_store,
             release_stores: prep.release_stores,
             database: prep.database.clone(),
+            artifact_index: prep.artifact_index,
+            secrets: prep.secrets,
+            reuse_artifacts: prep.reuse_artifacts,
+            fail_fast: prep.fail_fast,
+            cancellation: prep.cancellation,
 
             receiver,
             sender,
         }
     }
 
+    /// Get the summary of this job with the `outcome` and the `duration` of the job run
+    ///
+    /// The outcome is emitted as event as well.
+    fn summary(&self, outcome: JobOutcome, duration: Option<Duration>) -> JobSummary {
+        self.summary_with_error(outcome, duration, None)
+    }
+
+    /// Like `summary()`, with the `error` the job failed with
+    fn summary_with_error(
+        &self,
+        outcome: JobOutcome,
+        duration: Option<Duration>,
+        error: Option<&Error>,
+    ) -> JobSummary {
+        let job_uuid = self.jobdef.job.uuid();
+        let package_name = self.jobdef.job.package().name();
+        let package_version = self.jobdef.job.package().version();
+        let event = match outcome {
+            JobOutcome::Reused => Event::JobReused {
+                job_uuid,
+                package_name,
+                package_version,
+            },
+            _ => Event::JobFinished {
+                job_uuid,
+                package_name,
+                package_version,
+                outcome,
+                duration_secs: duration.map(|d| d.as_secs_f64()),
+                error: error.map(|e| format!("{e:#}")),
+            },
+        };
+        self.scheduler.events().emit(event);
+
+        JobSummary::new(
+            *job_uuid,
+            package_name.clone(),
+            package_version.clone(),
+            outcome,
+            duration,
+        )
+    }
+
     /// Run the job
     ///
     /// This function runs the job from this object on the scheduler as soon as all dependent jobs
     /// returned successfully.
-    async fn run(mut self) -> Result<()> {
+    async fn run(mut self) -> Result<JobSummary> {
         debug!(job_uuid = %self.jobdef.job.uuid(), "Running");
         debug!(
             job_uuid = %self.jobdef.job.uuid(),
@@ -676,11 +1019,18 @@ impl<'a> JobTask<'a> {
 
             let continue_receiving = {
                 let recv_span = tracing::trace_span!(parent: &dependency_receiving_span, "receiving", job_uuid = %self.jobdef.job.uuid(), errors = tracing::field::Empty);
+                let cancellation = self.cancellation;
+                let scheduler = self.scheduler;
                 // receive from the receiver
-                let continue_receiving = self
-                    .perform_receive(&mut received_dependencies, &mut received_errors)
-                    .instrument(recv_span.clone())
-                    .await?;
+                //
+                // The cancellation is checked first: The tasks of the dependencies stop as well,
+                // which closes the channel and must not be reported as missing dependencies
+                let continue_receiving = tokio::select! {
+                    biased;
+                    _ = cancellation.cancelled() => None,
+                    _ = scheduler.shutdown().requested() => None,
+                    r = self.perform_receive(&mut received_dependencies, &mut received_errors).instrument(recv_span.clone()) => Some(r?),
+                };
                 recv_span.record(
                     "errors",
                     tracing::field::display(&received_errors.display_error_map()),
@@ -688,37 +1038,50 @@ impl<'a> JobTask<'a> {
                 continue_receiving
             };
 
-            // if there are any errors from child tasks
-            if !received_errors.is_empty() {
-                // send them to the parent,...
-                //
-                // We only send to one parent, because it doesn't matter
-                // And we know that we have at least one sender
-                error!(parent: &dependency_receiving_span,
-                       job_uuid = %self.jobdef.job.uuid(),
-                       errors = tracing::field::display(&received_errors.display_error_map()),
-                       "Received errors");
-                self.sender[0].send(Err(received_errors)).await;
-
-                // ... and stop operation, because the whole tree will fail anyways.
-                self.bar.finish_with_message(format!(
-                    "{:-<max_endpoint_name_length$} {:-<CONTAINER_ID_LENGTH$} {} {} {} {} Stopping, errors from child received",
-                    "",
-                    "",
-                    self.jobdef.job.uuid(),
-                    "\u{2588}\u{2588}".yellow(),
-                    self.jobdef.job.package().name(),
-                    self.jobdef.job.package().version()
-                ));
-                return Ok(());
+            match continue_receiving {
+                None => {
+                    // The errors of the dependencies are reported by the orchestrator
+                    self.cancellation
+                        .add_errors(std::mem::take(&mut received_errors));
+                    return Ok(self.cancel());
+                }
+                Some(false) => break,
+                // Errors from child tasks are only forwarded once all children finished, so that
+                // independent branches of the tree can finish as well
+                Some(true) => {}
             }
+        }
 
-            if !continue_receiving {
-                break;
-            }
+        // if there are any errors from child tasks
+        if !received_errors.is_empty() {
+            // send them to the parent,...
+            //
+            // We only send to one parent, because it doesn't matter
+            // And we know that we have at least one sender
+            error!(parent: &dependency_receiving_span,
+                   job_uuid = %self.jobdef.job.uuid(),
+                   errors = tracing::field::display(&received_errors.display_error_map()),
+                   "Received errors");
+            self.sender[0].send(Err(received_errors)).await;
+
+            // ... and stop operation, because the whole tree will fail anyways.
+            self.bar.finish_with_message(format!(
+                "{:-<max_endpoint_name_length$} {:-<CONTAINER_ID_LENGTH$} {} {} {} {} Stopping, errors from child received",
+                "",
+                "",
+                self.jobdef.job.uuid(),
+                "\u{2588}\u{2588}".yellow(),
+                self.jobdef.job.package().name(),
+                self.jobdef.job.package().version()
+            ));
+            return Ok(self.summary(JobOutcome::Skipped, None));
         }
         drop(dependency_receiving_span);
 
+        if self.cancellation.is_cancelled() || self.scheduler.shutdown().is_requested() {
+            return Ok(self.cancel());
+        }
+
         // Check if any of the received dependencies was built (and not reused).
         // If any dependency was built, we need to build as well.
         let any_dependency_was_built = received_dependencies
@@ -729,106 +1092,28 @@ impl<'a> JobTask<'a> {
         // If no dependency was built, we can check for replacements for this job as well, so
         // check if a job that looks very similar to this job has already produced artifacts.
         // If it has, simply return those (plus the received ones)
-        if !any_dependency_was_built {
+        // Nothing is reused if a full rebuild was requested (`--no-reuse`).
+        if self.reuse_artifacts && !any_dependency_was_built {
             let staging_store = self.staging_store.read().await;
 
-            // Use the environment of the job definition, as it appears in the job DAG.
-            //
-            // This is because we do not have access to the commandline-passed (additional)
-            // environment variables at this point. But using the JobResource::env() variables
-            // works as well.
-            let additional_env = self
-                .jobdef
-                .job
-                .resources()
-                .iter()
-                .filter_map(crate::job::JobResource::env)
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .chain(self.git_author_env.cloned())
-                .chain(self.git_commit_env.cloned())
-                .collect::<Vec<_>>();
-
-            let replacement_artifacts = crate::db::FindArtifacts::builder()
-                .database_pool(self.database.clone())
-                .config(self.config)
-                .package(self.jobdef.job.package())
-                .release_stores(&self.release_stores)
-                .image_name(Some(self.jobdef.job.image()))
-                // We can simply pass the staging store here, because it doesn't hurt. There are
-                // two scenarios:
-                //
-                // 1. We are in a fresh build for a package. In this case, the artifacts for this
-                //    very build are not in there yet, and there won't be any artifacts from the
-                //    staging store (possibly from the release store, which would be fine).
-                // 2. We are in a re-build, where the user passed the staging store to the build
-                //    subcommand. In this case, there might be an artifact for this job in the
-                //    staging store. In this case, we want to use it as a replacement, of course.
-                //
-                // The fact that released artifacts are returned preferably from this function
-                // call does not change anything, because if there is an artifact that's a released
-                // one that matches this job, we should use it anyways.
-                .staging_store(Some(&staging_store))
-                .env_filter(&additional_env)
-                .script_filter(true)
-                .build()
-                .run()?;
-
-            debug!(
-                job_uuid = %self.jobdef.job.uuid(),
-                replacement_artifacts_count = replacement_artifacts.len(),
-                "Found replacement artifacts",
-            );
-            trace!(
-                job_uuid = %self.jobdef.job.uuid(),
-                ?replacement_artifacts,
-                "Found replacement artifacts",
-            );
-            let mut artifacts = replacement_artifacts
-                .into_iter()
-                // First of all, we sort by whether the artifact path is in the staging store,
-                // because we prefer staging store artifacts at this point.
-                .sorted_by(|(p1, _), (p2, _)| {
-                    let r1 = p1.is_in_staging_store(&staging_store);
-                    let r2 = p2.is_in_staging_store(&staging_store);
-                    r1.cmp(&r2)
-                })
-                // We don't need duplicates here, so remove them by making the iterator unique
-                // If we have two artifacts that are the same, the one in the staging store will be
-                // preferred in the next step
-                .unique_by(|tpl| tpl.0.artifact_path().clone())
-                // Fetch the artifact from the staging store, if there is one.
-                // If there is none, try the release store.
-                // If there is none, there won't be a replacement artifact
-                .filter_map(|(full_artifact_path, _)| {
-                    trace!("Searching for {:?} in stores", full_artifact_path.display());
-                    if let Some(ap) = staging_store.get(full_artifact_path.artifact_path()) {
-                        Some(ap.clone())
-                    } else {
-                        self.release_stores
-                            .iter()
-                            .find_map(|rs| rs.get(full_artifact_path.artifact_path()))
-                            .cloned()
-                    }
-                })
-                .map(ProducedArtifact::Reused)
-                .collect::<Vec<ProducedArtifact>>();
+            let mut artifacts = find_replacement_artifacts(
+                self.jobdef.job,
+                self.config,
+                self.database.clone(),
+                &staging_store,
+                &self.release_stores,
+                self.artifact_index,
+                self.git_author_env,
+                self.git_commit_env,
+            )?
+            .into_iter()
+            .map(ProducedArtifact::Reused)
+            .collect::<Vec<ProducedArtifact>>();
 
             if !artifacts.is_empty() {
                 received_dependencies.insert(*self.jobdef.job.uuid(), artifacts);
                 trace!(job_uuid = %self.jobdef.job.uuid(), "Sending to parent: {:?}", received_dependencies);
-                for s in self.sender.iter() {
-                    s.send(Ok(received_dependencies.clone()))
-                        .await
-                        .context("Cannot send received dependencies to parent")
-                        .with_context(|| {
-                            format!(
-                                "Sending-Channel is closed in Task for {}: {} {}",
-                                self.jobdef.job.uuid(),
-                                self.jobdef.job.package().name(),
-                                self.jobdef.job.package().version()
-                            )
-                        })?;
-                }
+                self.send_to_parents(received_dependencies).await?;
                 self.bar.finish_with_message(format!(
                     "{:-<max_endpoint_name_length$} {:-<CONTAINER_ID_LENGTH$} {} {} {} {} Reusing artifact",
                     "",
@@ -838,7 +1123,7 @@ impl<'a> JobTask<'a> {
                     self.jobdef.job.package().name(),
                     self.jobdef.job.package().version()
                 ));
-                return Ok(());
+                return Ok(self.summary(JobOutcome::Reused, None));
             }
         }
 
@@ -875,6 +1160,7 @@ impl<'a> JobTask<'a> {
             self.git_author_env,
             self.git_commit_env,
             dependency_artifacts,
+            self.secrets,
         )?;
 
         self.bar.set_message(format!(
@@ -889,15 +1175,43 @@ impl<'a> JobTask<'a> {
         let job_uuid = *self.jobdef.job.uuid();
 
         // Schedule the job on the scheduler
-        match self
-            .scheduler
-            .schedule_job(runnable, self.bar.clone())
-            .await?
-            .run()
-            .await?
-        {
+        let job_handle = tokio::select! {
+            job_handle = self.scheduler.schedule_job(runnable, self.bar.clone()) => job_handle?,
+            _ = self.cancellation.cancelled() => return Ok(self.cancel()),
+            _ = self.scheduler.shutdown().requested() => return Ok(self.cancel()),
+        };
+
+        // The time the job waits for a free endpoint is not part of its duration
+        let start = Instant::now();
+        // Errors while running the container (not only errors of the script) are errors of this
+        // job as well, so they are reported with the job UUID instead of aborting the whole build
+        // (which would lose the association with the job)
+        //
+        // If the build is cancelled, the container of the job is killed (the job isn't simply
+        // dropped, so that it is recorded and its container is removed)
+        let cancellation = self.cancellation;
+        let result = job_handle
+            .run(&|| cancellation.is_cancelled())
+            .await
+            .and_then(|result| result);
+        let duration = start.elapsed();
+        if result.is_err() && self.cancellation.is_cancelled() {
+            return Ok(self.cancel());
+        }
+
+        match result {
+            Err(e) if self.fail_fast => {
+                trace!(job_uuid = %self.jobdef.job.uuid(), "Scheduler returned error = {:?}", e);
+                let summary = self.summary_with_error(JobOutcome::Failed, Some(duration), Some(&e));
+                // The error is collected by the cancellation token, all other jobs are cancelled
+                self.cancellation.cancel(job_uuid, e);
+                return Ok(summary);
+            }
+
             Err(e) => {
                 trace!(job_uuid = %self.jobdef.job.uuid(), "Scheduler returned error = {:?}", e);
+                let summary = self.summary_with_error(JobOutcome::Failed, Some(duration), Some(&e));
+
                 // ... and we send that to our parent
                 //
                 // We only send to one parent, because it doesn't matter anymore
@@ -906,14 +1220,19 @@ impl<'a> JobTask<'a> {
                 errormap.insert(job_uuid, e);
 
                 // Every JobTask has at least one sender, so we can [] here.
-                self.sender[0]
-                    .send(Err(errormap))
-                    .await
-                    .context("Failed sending scheduler errors to parent")
-                    .with_context(|| {
-                        format!("Failed sending error from job {}", self.jobdef.job.uuid())
-                    })?;
-                return Ok(());
+                match self.sender[0].send(Err(errormap)).await {
+                    // The parent task is not running anymore if butido is shutting down, the
+                    // error is reported by the orchestrator then
+                    Err(SendError(Err(errormap))) if self.scheduler.shutdown().is_requested() => {
+                        self.cancellation.add_errors(errormap);
+                    }
+                    res => res
+                        .context("Failed sending scheduler errors to parent")
+                        .with_context(|| {
+                            format!("Failed sending error from job {}", self.jobdef.job.uuid())
+                        })?,
+                }
+                return Ok(summary);
             }
 
             // if the scheduler run reports success,
@@ -929,13 +1248,57 @@ impl<'a> JobTask<'a> {
                 let artifacts = artifacts.into_iter().map(ProducedArtifact::Built).collect();
 
                 received_dependencies.insert(*self.jobdef.job.uuid(), artifacts);
-                for s in self.sender.iter() {
-                    s.send(Ok(received_dependencies.clone())).await?;
-                }
+                self.send_to_parents(received_dependencies).await?;
             }
         }
 
         trace!("[{}]: Finished successfully", self.jobdef.job.uuid());
+        Ok(self.summary(JobOutcome::Built, Some(duration)))
+    }
+
+    /// Stop the job because another job failed and the build is cancelled (`--fail-fast`), or
+    /// because butido is shutting down
+    fn cancel(&self) -> JobSummary {
+        let max_endpoint_name_length = self.scheduler.max_endpoint_name_length();
+        self.bar.finish_with_message(format!(
+            "{:-<max_endpoint_name_length$} {:-<CONTAINER_ID_LENGTH$} {} {} {} {} Cancelled",
+            "",
+            "",
+            self.jobdef.job.uuid(),
+            "\u{2588}\u{2588}".yellow(),
+            self.jobdef.job.package().name(),
+            self.jobdef.job.package().version()
+        ));
+        self.summary(JobOutcome::Skipped, None)
+    }
+
+    /// Send the artifacts of this job (and its dependencies) to all parent tasks
+    ///
+    /// If the build was cancelled, the parent tasks might not be running anymore, which is not an
+    /// error then.
+    async fn send_to_parents(&self, artifacts: HashMap<Uuid, Vec<ProducedArtifact>>) -> Result<()> {
+        for s in self.sender.iter() {
+            let res = s
+                .send(Ok(artifacts.clone()))
+                .await
+                .context("Cannot send received dependencies to parent")
+                .with_context(|| {
+                    format!(
+                        "Sending-Channel is closed in Task for {}: {} {}",
+                        self.jobdef.job.uuid(),
+                        self.jobdef.job.package().name(),
+                        self.jobdef.job.package().version()
+                    )
+                });
+
+            if res.is_err()
+                && (self.cancellation.is_cancelled() || self.scheduler.shutdown().is_requested())
+            {
+                trace!(job_uuid = %self.jobdef.job.uuid(), "Parent task was cancelled");
+                continue;
+            }
+            res?;
+        }
         Ok(())
     }
 
@@ -0,0 +1,148 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::Pool;
+use diesel::PgConnection;
+use getset::{CopyGetters, Getters};
+use git2::Repository;
+use tokio::sync::RwLock;
+use tracing::{debug, trace};
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use crate::config::Configuration;
+use crate::config::EndpointName;
+use crate::endpoint::EndpointConfiguration;
+use crate::filestore::ReleaseStore;
+use crate::filestore::StagingStore;
+use crate::job::Dag;
+use crate::orchestrator::orchestrator::find_replacement_artifacts;
+use crate::orchestrator::orchestrator::git_env;
+use crate::orchestrator::orchestrator::load_artifact_index;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+
+/// Helper for planning a build without running it
+///
+/// This sets up the endpoints and searches for artifacts that can be reused, the same way the
+/// [Orchestrator](crate::orchestrator::Orchestrator) does it, but no containers are created and
+/// nothing is written to the database.
+#[derive(TypedBuilder)]
+pub struct BuildPlanner<'a> {
+    endpoint_config: Vec<EndpointConfiguration>,
+    staging_store: Arc<RwLock<StagingStore>>,
+    release_stores: Vec<Arc<ReleaseStore>>,
+    jobdag: Dag,
+    database: Pool<ConnectionManager<PgConnection>>,
+    #[builder(default = true)]
+    reuse_artifacts: bool,
+    config: &'a Configuration,
+    repository: Repository,
+}
+
+/// The plan for a build, as computed by the [BuildPlanner]
+#[derive(Debug, Getters)]
+pub struct BuildPlan {
+    /// The jobs of the build, dependencies are listed before the jobs that depend on them
+    #[getset(get = "pub")]
+    jobs: Vec<PlannedJob>,
+
+    /// The endpoints the jobs that have to be built can be scheduled on
+    #[getset(get = "pub")]
+    endpoints: Vec<EndpointName>,
+}
+
+/// A job of a [BuildPlan]
+#[derive(Debug, Getters, CopyGetters)]
+pub struct PlannedJob {
+    #[getset(get = "pub")]
+    uuid: Uuid,
+
+    #[getset(get = "pub")]
+    package_name: PackageName,
+
+    #[getset(get = "pub")]
+    package_version: PackageVersion,
+
+    /// Whether the artifacts of an earlier build would be reused instead of building the job
+    #[getset(get_copy = "pub")]
+    reuse: bool,
+}
+
+impl BuildPlanner<'_> {
+    pub async fn plan(self) -> Result<BuildPlan> {
+        let endpoints = crate::endpoint::util::setup_endpoints(self.endpoint_config)
+            .await?
+            .iter()
+            .map(|ep| ep.name().clone())
+            .collect::<Vec<_>>();
+        debug!("Endpoints available for the build: {:?}", endpoints);
+
+        let (git_author_env, git_commit_env) = git_env(self.config, &self.repository)?;
+        let staging_store = self.staging_store.read().await;
+        let jobdefs = self.jobdag.iter().collect::<Vec<_>>();
+        let artifact_index = if self.reuse_artifacts {
+            load_artifact_index(self.config, &self.database)
+        } else {
+            None
+        };
+
+        // Whether a job would reuse artifacts, by job UUID
+        let mut reuse: HashMap<Uuid, bool> = HashMap::with_capacity(jobdefs.len());
+        let mut jobs = Vec::with_capacity(jobdefs.len());
+
+        // A job can only be planned once all its dependencies are planned, because (like in the
+        // Orchestrator) a job has to be built if any of its dependencies has to be built
+        while reuse.len() < jobdefs.len() {
+            let plannable = jobdefs
+                .iter()
+                .filter(|jobdef| !reuse.contains_key(jobdef.job.uuid()))
+                .filter(|jobdef| jobdef.dependencies.iter().all(|d| reuse.contains_key(d)))
+                .collect::<Vec<_>>();
+
+            if plannable.is_empty() {
+                return Err(anyhow!("Cannot plan the build, dependencies are missing"));
+            }
+
+            for jobdef in plannable {
+                let job_reuse = self.reuse_artifacts
+                    && jobdef.dependencies.iter().all(|d| reuse[d])
+                    && !find_replacement_artifacts(
+                        jobdef.job,
+                        self.config,
+                        self.database.clone(),
+                        &staging_store,
+                        &self.release_stores,
+                        artifact_index.as_ref(),
+                        git_author_env.as_ref(),
+                        git_commit_env.as_ref(),
+                    )?
+                    .is_empty();
+
+                trace!(job_uuid = %jobdef.job.uuid(), reuse = job_reuse, "Planned job");
+                reuse.insert(*jobdef.job.uuid(), job_reuse);
+                jobs.push(PlannedJob {
+                    uuid: *jobdef.job.uuid(),
+                    package_name: jobdef.job.package().name().clone(),
+                    package_version: jobdef.job.package().version().clone(),
+                    reuse: job_reuse,
+                });
+            }
+        }
+
+        Ok(BuildPlan { jobs, endpoints })
+    }
+}
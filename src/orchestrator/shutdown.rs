@@ -0,0 +1,138 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::Context;
+use anyhow::Result;
+use tokio::signal::unix::signal;
+use tokio::signal::unix::SignalKind;
+use tokio::sync::Notify;
+use tracing::{error, warn};
+
+/// The shutdown of a build on SIGTERM or SIGINT
+///
+/// On the first signal, no new jobs are started anymore, but the running jobs are finished, so
+/// that their artifacts can be reused when the submit is resumed (`build --resume`).
+/// On the second signal, the containers of the running jobs are killed. On the third signal,
+/// butido exits immediately.
+///
+/// The default `Shutdown` is never requested.
+#[derive(Clone, Debug, Default)]
+pub struct Shutdown(Arc<ShutdownState>);
+
+#[derive(Debug, Default)]
+struct ShutdownState {
+    signals: AtomicUsize,
+    notify: Notify,
+
+    /// Whether the signal handler was dropped, i.e., there is nothing left to shut down gracefully
+    finished: AtomicBool,
+}
+
+impl Shutdown {
+    /// Request the shutdown on SIGTERM and SIGINT
+    ///
+    /// Once the returned handler is dropped, butido exits immediately on SIGTERM and SIGINT.
+    pub fn install(&self) -> Result<SignalHandler> {
+        let mut sigterm = signal(SignalKind::terminate()).context("Installing SIGTERM handler")?;
+        let mut sigint = signal(SignalKind::interrupt()).context("Installing SIGINT handler")?;
+
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = sigterm.recv() => {},
+                    _ = sigint.recv() => {},
+                }
+                shutdown.signal();
+            }
+        });
+        Ok(SignalHandler(self.clone()))
+    }
+
+    fn signal(&self) {
+        if self.0.finished.load(Ordering::SeqCst) {
+            error!("Exiting immediately");
+            std::process::exit(130)
+        }
+
+        match self.0.signals.fetch_add(1, Ordering::SeqCst) {
+            0 => warn!(
+                "Shutting down: No new jobs are started, waiting for the running jobs to finish (send the signal again to kill them)"
+            ),
+            1 => warn!("Shutting down: Killing the running jobs"),
+            _ => {
+                error!("Exiting immediately, the containers of the running jobs may be left behind");
+                std::process::exit(130)
+            }
+        }
+        self.0.notify.notify_waiters();
+    }
+
+    /// Whether no new jobs may be started anymore
+    pub fn is_requested(&self) -> bool {
+        self.0.signals.load(Ordering::SeqCst) >= 1
+    }
+
+    /// Whether the running jobs have to be killed
+    pub fn is_forced(&self) -> bool {
+        self.0.signals.load(Ordering::SeqCst) >= 2
+    }
+
+    /// Wait until the shutdown is requested
+    pub async fn requested(&self) {
+        loop {
+            // The future has to be created before checking the flag, otherwise a notification
+            // between the check and the creation of the future would be missed
+            let notified = self.0.notify.notified();
+            if self.is_requested() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// The handler of SIGTERM and SIGINT of a `Shutdown`, see `Shutdown::install()`
+///
+/// The signals are handled until butido exits, because tokio doesn't restore the default
+/// handlers. Once the handler is dropped, a signal exits butido immediately.
+#[derive(Debug)]
+pub struct SignalHandler(Shutdown);
+
+impl Drop for SignalHandler {
+    fn drop(&mut self) {
+        self.0 .0.finished.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signals() {
+        let shutdown = Shutdown::default();
+        assert!(!shutdown.is_requested());
+        assert!(!shutdown.is_forced());
+
+        shutdown.clone().signal();
+        assert!(shutdown.is_requested());
+        assert!(!shutdown.is_forced());
+
+        shutdown.signal();
+        assert!(shutdown.is_requested());
+        assert!(shutdown.is_forced());
+    }
+}
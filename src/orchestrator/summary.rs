@@ -0,0 +1,274 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use getset::{CopyGetters, Getters};
+use serde::ser::SerializeStruct;
+use serde::Serialize;
+use serde::Serializer;
+use uuid::Uuid;
+
+use crate::config::EndpointName;
+use crate::endpoint::SchedulingDecision;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+
+/// What happened to a job during a build
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobOutcome {
+    /// The job was run and succeeded
+    Built,
+
+    /// The artifacts of an earlier build were reused, the job was not run
+    Reused,
+
+    /// The job was run and failed
+    Failed,
+
+    /// The job was not run because a dependency failed (or it was cancelled because another job
+    /// failed, with `--fail-fast`)
+    Skipped,
+}
+
+/// The summary of a job of a build
+#[derive(Clone, Debug, Getters, CopyGetters, Serialize)]
+pub struct JobSummary {
+    #[getset(get = "pub")]
+    uuid: Uuid,
+
+    #[getset(get = "pub")]
+    package_name: PackageName,
+
+    #[getset(get = "pub")]
+    package_version: PackageVersion,
+
+    #[getset(get_copy = "pub")]
+    outcome: JobOutcome,
+
+    /// How long the job ran in the container, `None` if it was not run
+    #[getset(get_copy = "pub")]
+    #[serde(rename = "duration_secs", serialize_with = "serialize_duration_secs")]
+    duration: Option<Duration>,
+}
+
+impl JobSummary {
+    pub fn new(
+        uuid: Uuid,
+        package_name: PackageName,
+        package_version: PackageVersion,
+        outcome: JobOutcome,
+        duration: Option<Duration>,
+    ) -> Self {
+        JobSummary {
+            uuid,
+            package_name,
+            package_version,
+            outcome,
+            duration,
+        }
+    }
+}
+
+/// The summary of a build, as returned by the [Orchestrator](crate::orchestrator::Orchestrator)
+#[derive(Clone, Debug, Getters, CopyGetters)]
+pub struct BuildSummary {
+    #[getset(get = "pub")]
+    jobs: Vec<JobSummary>,
+
+    /// The time it took to run all jobs
+    #[getset(get_copy = "pub")]
+    wall_time: Duration,
+
+    /// The decisions of the scheduler, in the order they were made
+    #[getset(get = "pub")]
+    scheduling: Vec<SchedulingDecision>,
+}
+
+/// How many jobs were scheduled on an endpoint and how long they waited for it
+#[derive(Clone, Debug, Getters, CopyGetters)]
+pub struct EndpointSchedulingStats {
+    #[getset(get = "pub")]
+    endpoint_name: EndpointName,
+
+    #[getset(get_copy = "pub")]
+    jobs: usize,
+
+    #[getset(get_copy = "pub")]
+    avg_wait_time: Duration,
+
+    #[getset(get_copy = "pub")]
+    max_wait_time: Duration,
+}
+
+impl BuildSummary {
+    pub fn new(
+        jobs: Vec<JobSummary>,
+        wall_time: Duration,
+        scheduling: Vec<SchedulingDecision>,
+    ) -> Self {
+        BuildSummary {
+            jobs,
+            wall_time,
+            scheduling,
+        }
+    }
+
+    /// Get the number of jobs with the `outcome`
+    pub fn count(&self, outcome: JobOutcome) -> usize {
+        self.jobs.iter().filter(|j| j.outcome == outcome).count()
+    }
+
+    /// Get the `n` jobs that ran the longest, the slowest job first
+    pub fn slowest(&self, n: usize) -> Vec<&JobSummary> {
+        let mut jobs = self
+            .jobs
+            .iter()
+            .filter(|j| j.duration.is_some())
+            .collect::<Vec<_>>();
+        jobs.sort_by_key(|j| std::cmp::Reverse(j.duration));
+        jobs.truncate(n);
+        jobs
+    }
+
+    /// Get the scheduling statistics of each endpoint that jobs were scheduled on, sorted by the
+    /// name of the endpoint
+    pub fn scheduling_stats(&self) -> Vec<EndpointSchedulingStats> {
+        let mut wait_times = BTreeMap::<&EndpointName, Vec<Duration>>::new();
+        for decision in self.scheduling.iter() {
+            wait_times
+                .entry(decision.endpoint_name())
+                .or_default()
+                .push(decision.wait_time());
+        }
+
+        wait_times
+            .into_iter()
+            .map(|(endpoint_name, wait_times)| EndpointSchedulingStats {
+                endpoint_name: endpoint_name.clone(),
+                jobs: wait_times.len(),
+                avg_wait_time: wait_times.iter().sum::<Duration>() / wait_times.len() as u32,
+                max_wait_time: wait_times.iter().max().copied().unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+impl Serialize for BuildSummary {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("BuildSummary", 6)?;
+        s.serialize_field("built", &self.count(JobOutcome::Built))?;
+        s.serialize_field("reused", &self.count(JobOutcome::Reused))?;
+        s.serialize_field("failed", &self.count(JobOutcome::Failed))?;
+        s.serialize_field("skipped", &self.count(JobOutcome::Skipped))?;
+        s.serialize_field("wall_time_secs", &self.wall_time.as_secs_f64())?;
+        s.serialize_field("jobs", &self.jobs)?;
+        s.end()
+    }
+}
+
+fn serialize_duration_secs<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    duration.map(|d| d.as_secs_f64()).serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+
+    fn job(name: &str, outcome: JobOutcome, secs: Option<u64>) -> JobSummary {
+        JobSummary::new(
+            Uuid::new_v4(),
+            pname(name),
+            pversion("1"),
+            outcome,
+            secs.map(Duration::from_secs),
+        )
+    }
+
+    fn summary() -> BuildSummary {
+        BuildSummary::new(
+            vec![
+                job("a", JobOutcome::Built, Some(10)),
+                job("b", JobOutcome::Reused, None),
+                job("c", JobOutcome::Failed, Some(30)),
+                job("d", JobOutcome::Built, Some(20)),
+                job("e", JobOutcome::Skipped, None),
+            ],
+            Duration::from_secs(60),
+            vec![
+                decision("ep1", 100),
+                decision("ep2", 0),
+                decision("ep1", 300),
+                decision("ep1", 200),
+            ],
+        )
+    }
+
+    fn decision(endpoint_name: &str, wait_millis: u64) -> SchedulingDecision {
+        SchedulingDecision::new(
+            EndpointName::from(endpoint_name.to_string()),
+            Duration::from_millis(wait_millis),
+        )
+    }
+
+    #[test]
+    fn test_count() {
+        let summary = summary();
+        assert_eq!(summary.count(JobOutcome::Built), 2);
+        assert_eq!(summary.count(JobOutcome::Reused), 1);
+        assert_eq!(summary.count(JobOutcome::Failed), 1);
+        assert_eq!(summary.count(JobOutcome::Skipped), 1);
+    }
+
+    #[test]
+    fn test_slowest() {
+        let summary = summary();
+        let names = summary
+            .slowest(2)
+            .into_iter()
+            .map(|j| j.package_name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["c", "d"]);
+        assert_eq!(summary.slowest(10).len(), 3);
+    }
+
+    #[test]
+    fn test_scheduling_stats() {
+        let stats = summary().scheduling_stats();
+        assert_eq!(stats.len(), 2);
+
+        assert_eq!(stats[0].endpoint_name().as_ref(), "ep1");
+        assert_eq!(stats[0].jobs(), 3);
+        assert_eq!(stats[0].avg_wait_time(), Duration::from_millis(200));
+        assert_eq!(stats[0].max_wait_time(), Duration::from_millis(300));
+
+        assert_eq!(stats[1].endpoint_name().as_ref(), "ep2");
+        assert_eq!(stats[1].jobs(), 1);
+        assert_eq!(stats[1].avg_wait_time(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let json = serde_json::to_value(summary()).unwrap();
+        assert_eq!(json["built"], 2);
+        assert_eq!(json["skipped"], 1);
+        assert_eq!(json["wall_time_secs"], 60.0);
+        assert_eq!(json["jobs"][0]["outcome"], "built");
+        assert_eq!(json["jobs"][0]["duration_secs"], 10.0);
+        assert!(json["jobs"][1]["duration_secs"].is_null());
+    }
+}
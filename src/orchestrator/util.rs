@@ -33,6 +33,7 @@ impl std::fmt::Display for ReceivedErrorDisplay<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.0
             .iter()
-            .try_for_each(|(uuid, err)| writeln!(f, "{uuid}: {err}"))
+            .try_for_each(|(uuid, err)| writeln!(f, "{uuid}: {err:#}"))
     }
 }
+
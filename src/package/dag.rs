@@ -10,23 +10,31 @@
 
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::io::Result as IoResult;
 use std::io::Write;
 
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
+use colored::Colorize;
 use getset::Getters;
 use indicatif::ProgressBar;
 use itertools::Itertools;
 use petgraph::acyclic::Acyclic;
+use petgraph::acyclic::AcyclicEdgeError;
 use petgraph::data::Build;
 use petgraph::graph::DiGraph;
 use petgraph::graph::EdgeIndex;
 use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
 use ptree::Style;
 use ptree::TreeItem;
 use resiter::AndThen;
+use serde::ser::SerializeStruct;
+use serde::Serialize;
+use serde::Serializer;
 use tracing::trace;
 
 use crate::package::condition::ConditionCheckable;
@@ -42,8 +50,12 @@ pub struct Dag {
     #[getset(get = "pub")]
     dag: Acyclic<DiGraph<Package, DependencyType>>,
 
+    /// The packages that no other package in the DAG depends on
     #[getset(get = "pub")]
-    root_idx: NodeIndex,
+    root_idxs: Vec<NodeIndex>,
+
+    /// The packages the DAG was built for (including the ones that are not roots)
+    requested_idxs: Vec<NodeIndex>,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
@@ -59,6 +71,19 @@ impl Dag {
         repo: &Repository,
         progress: Option<&ProgressBar>,
         conditional_data: &ConditionData<'_>, // required for selecting packages with conditional dependencies
+    ) -> Result<Self> {
+        Self::for_root_packages(vec![p], repo, progress, conditional_data)
+    }
+
+    /// Builds one package/dependency DAG for all of the given packages
+    ///
+    /// Dependencies that are shared by the packages are only contained once. If a package is a
+    /// dependency of another one of the packages, it is not a root of the DAG.
+    pub fn for_root_packages(
+        packages: Vec<Package>,
+        repo: &Repository,
+        progress: Option<&ProgressBar>,
+        conditional_data: &ConditionData<'_>, // required for selecting packages with conditional dependencies
     ) -> Result<Self> {
         /// Helper fn to check the dependency condition of a dependency and parse the dependency
         /// into a tuple for further processing
@@ -133,7 +158,15 @@ impl Dag {
                         version,
                         kind
                     );
-                    let packs = repo.find_with_version(&name, &version);
+                    let packs = repo.find_dependency(&name, &version).with_context(|| {
+                        anyhow!(
+                            "Resolving the following dependency of {} {}: {} {}",
+                            p.name(),
+                            p.version(),
+                            name,
+                            version
+                        )
+                    })?;
                     trace!(
                         "Found the following matching packages in the repo: {:?}",
                         packs
@@ -184,6 +217,7 @@ impl Dag {
         // TODO: It seems easier and more efficient to do this in `add_sub_packages` as well (it
         // makes that function more complex but doing it separately is weird).
         fn add_edges(
+            repo: &Repository,
             mappings: &HashMap<&Package, NodeIndex>,
             dag: &mut Acyclic<DiGraph<&Package, DependencyType>>,
             conditional_data: &ConditionData<'_>,
@@ -191,30 +225,40 @@ impl Dag {
             for (package, idx) in mappings {
                 get_package_dependencies(package, conditional_data)
                     .and_then_ok(|(dep_name, dep_version, dep_kind)| {
+                        // The dependency might be satisfied by a package with a different name
+                        // (that provides it), so it has to be resolved the same way as in
+                        // `add_sub_packages()`:
+                        let packs = repo.find_dependency(&dep_name, &dep_version)?;
                         mappings
                             .iter()
                             .filter(|(pkg, _)| {
-                                *pkg.name() == dep_name && *pkg.version() == dep_version
+                                packs.iter().any(|pk| {
+                                    pk.name() == pkg.name() && pk.version() == pkg.version()
+                                })
                             })
                             .try_for_each(|(dep, dep_idx)| {
-                                dag.try_add_edge(*idx, *dep_idx, dep_kind.clone())
-                                    .map(|_| ())
-                                    // Only debug formatting is available for the error and for
-                                    // cycles it is quite useless (the context below is much more
-                                    // helpful than, e.g., "Cycle(Cycle(NodeIndex(0)))") but we'll
-                                    // include it for completeness and in case of the other errors
+                                match dag.try_add_edge(*idx, *dep_idx, dep_kind.clone()) {
+                                    Ok(_) => Ok(()),
+                                    Err(
+                                        AcyclicEdgeError::Cycle(_) | AcyclicEdgeError::SelfLoop,
+                                    ) => Err(anyhow!(
+                                        "Dependency cycle: {}",
+                                        format_cycle(dag, *idx, *dep_idx)
+                                    )),
+                                    // Only debug formatting is available for the other errors
                                     // that could theoretically occur:
-                                    .map_err(|e| anyhow!(format!("{e:?}")))
-                                    .with_context(|| {
-                                        anyhow!(
-                                            "Failed to add package dependency DAG edge \
+                                    Err(e) => Err(anyhow!(format!("{e:?}"))),
+                                }
+                                .with_context(|| {
+                                    anyhow!(
+                                        "Failed to add package dependency DAG edge \
                                             from package \"{}\" ({}) to dependency \"{}\" ({})",
-                                            package.name(),
-                                            package.version(),
-                                            dep.name(),
-                                            dep.version(),
-                                        )
-                                    })
+                                        package.name(),
+                                        package.version(),
+                                        dep.name(),
+                                        dep.version(),
+                                    )
+                                })
                             })
                     })
                     .collect::<Result<()>>()?
@@ -227,19 +271,35 @@ impl Dag {
         let mut dag = Acyclic::<DiGraph<&Package, DependencyType>>::new();
         let mut mappings = HashMap::new();
 
-        trace!("Building the package dependency DAG for package {:?}", p);
-        let root_idx = dag.add_node(&p);
-        mappings.insert(&p, root_idx);
-        add_sub_packages(
-            repo,
-            &mut mappings,
-            &mut dag,
-            &p,
-            progress,
-            conditional_data,
-        )?;
-        trace!("Adding the dependency edges to the DAG for package {:?}", p);
-        add_edges(&mappings, &mut dag, conditional_data)?;
+        // All root packages are added first, so that a root package that is a dependency of
+        // another root package is not added a second time by `add_sub_packages()`
+        let roots = packages
+            .iter()
+            .unique_by(|p| (p.name(), p.version()))
+            .collect::<Vec<_>>();
+        if roots.is_empty() {
+            return Err(anyhow!("No package to build the dependency DAG for"));
+        }
+        let mut root_idxs = Vec::with_capacity(roots.len());
+        for p in roots.iter() {
+            trace!("Building the package dependency DAG for package {:?}", p);
+            let idx = dag.add_node(*p);
+            mappings.insert(*p, idx);
+            root_idxs.push(idx);
+        }
+        for p in roots {
+            add_sub_packages(repo, &mut mappings, &mut dag, p, progress, conditional_data)?;
+        }
+        trace!("Adding the dependency edges to the DAG");
+        add_edges(repo, &mappings, &mut dag, conditional_data)?;
+
+        // Packages that are dependencies of other requested packages are not roots
+        let requested_idxs = root_idxs.clone();
+        root_idxs.retain(|idx| {
+            dag.neighbors_directed(*idx, petgraph::Incoming)
+                .next()
+                .is_none()
+        });
         trace!("Finished building the package DAG");
 
         Ok(Dag {
@@ -248,7 +308,8 @@ impl Dag {
                 |_, e| (*e).clone(),
             ))
             .unwrap(), // The dag is already acyclic so this cannot fail
-            root_idx,
+            root_idxs,
+            requested_idxs,
         })
     }
 
@@ -264,57 +325,267 @@ impl Dag {
             .collect()
     }
 
-    pub fn display(&self) -> DagDisplay {
-        DagDisplay(self, self.root_idx, None)
+    /// Get the requested packages and the packages they depend on at runtime (transitively)
+    ///
+    /// The build time dependencies are left out, with all their dependencies (unless a package is
+    /// a runtime dependency as well or was requested).
+    pub fn runtime_packages(&self) -> Vec<&Package> {
+        let mut runtime_idxs = HashSet::new();
+        let mut queue = self.requested_idxs.iter().copied().collect::<VecDeque<_>>();
+        while let Some(idx) = queue.pop_front() {
+            if !runtime_idxs.insert(idx) {
+                continue;
+            }
+            queue.extend(
+                self.dag
+                    .edges_directed(idx, petgraph::Outgoing)
+                    .filter(|edge| *edge.weight() == DependencyType::Runtime)
+                    .map(|edge| edge.target()),
+            );
+        }
+
+        self.dag
+            .node_indices()
+            .filter(|idx| runtime_idxs.contains(idx))
+            .filter_map(|idx| self.dag.node_weight(idx))
+            .collect()
+    }
+
+    /// Get a copy of the DAG that only contains the packages that are at most `max_depth` levels
+    /// below a root package
+    pub fn limit_depth(&self, max_depth: usize) -> Dag {
+        // The shortest distance of each package to a root package
+        let mut depths = HashMap::new();
+        let mut queue = self
+            .root_idxs
+            .iter()
+            .map(|idx| (*idx, 0))
+            .collect::<VecDeque<_>>();
+        while let Some((idx, depth)) = queue.pop_front() {
+            if depth > max_depth || depths.contains_key(&idx) {
+                continue;
+            }
+            depths.insert(idx, depth);
+            queue.extend(
+                self.dag
+                    .neighbors_directed(idx, petgraph::Outgoing)
+                    .map(|child| (child, depth + 1)),
+            );
+        }
+
+        // `filter_map()` keeps the order of the remaining nodes
+        let new_idx = |old_idx: &NodeIndex| {
+            NodeIndex::new(
+                self.dag
+                    .node_indices()
+                    .take_while(|idx| idx != old_idx)
+                    .filter(|idx| depths.contains_key(idx))
+                    .count(),
+            )
+        };
+        let root_idxs = self.root_idxs.iter().map(new_idx).collect();
+        let requested_idxs = self
+            .requested_idxs
+            .iter()
+            .filter(|idx| depths.contains_key(*idx))
+            .map(new_idx)
+            .collect();
+        let dag = self.dag.filter_map(
+            |idx, p| depths.contains_key(&idx).then(|| p.clone()),
+            |_, e| Some(e.clone()),
+        );
+
+        Dag {
+            dag: Acyclic::<_>::try_from_graph(dag).unwrap(), // A subgraph of a DAG is acyclic
+            root_idxs,
+            requested_idxs,
+        }
+    }
+
+    /// Display the DAG as trees (one for each root package), optionally only up to `max_depth`
+    /// levels below the root packages
+    ///
+    /// If `color` is `true`, build time dependencies are highlighted with a color.
+    pub fn display(&self, max_depth: Option<usize>, color: bool) -> Vec<DagDisplay<'_>> {
+        self.root_idxs
+            .iter()
+            .map(|root_idx| DagDisplay {
+                dag: self,
+                node: Some(*root_idx),
+                edge: None,
+                max_depth,
+                color,
+            })
+            .collect()
+    }
+}
+
+/// Format the dependency cycle that adding the edge from `from` to `to` to the `dag` would create
+/// (because there is a path from `to` to `from`), e.g., "a 1 -> b 2 -> a 1"
+fn format_cycle(
+    dag: &Acyclic<DiGraph<&Package, DependencyType>>,
+    from: NodeIndex,
+    to: NodeIndex,
+) -> String {
+    // Breadth-first search for the shortest path from `to` to `from`:
+    let mut predecessors = HashMap::new();
+    let mut queue = VecDeque::from([to]);
+    while let Some(node) = queue.pop_front() {
+        if node == from {
+            break;
+        }
+        for next in dag.neighbors_directed(node, petgraph::Outgoing) {
+            if next != to && !predecessors.contains_key(&next) {
+                predecessors.insert(next, node);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let mut path = vec![from];
+    while let Some(predecessor) = path.last().and_then(|node| predecessors.get(node)) {
+        path.push(*predecessor);
     }
+    path.push(from);
+    path.into_iter()
+        .rev()
+        .filter_map(|idx| dag.node_weight(idx))
+        .map(|p| format!("{} {}", p.name(), p.version()))
+        .join(" -> ")
 }
 
 #[derive(Clone)]
-pub struct DagDisplay<'a>(&'a Dag, NodeIndex, Option<EdgeIndex>);
+pub struct DagDisplay<'a> {
+    dag: &'a Dag,
 
-impl TreeItem for DagDisplay<'_> {
-    type Child = Self;
+    /// The package that is displayed, or `None` if this marks the dependencies that are not
+    /// displayed because of the depth limit
+    node: Option<NodeIndex>,
 
-    fn write_self<W: Write>(&self, f: &mut W, _: &Style) -> IoResult<()> {
+    /// The edge from the parent to this package (`None` for the root package)
+    edge: Option<EdgeIndex>,
+
+    /// How many levels of dependencies are displayed below this package (`None` for unlimited)
+    max_depth: Option<usize>,
+
+    /// Whether to highlight build time dependencies with a color
+    color: bool,
+}
+
+impl<'a> DagDisplay<'a> {
+    /// Get the package of `node` and the type of the dependency on it
+    fn package_and_dependency_type(
+        &self,
+        node: NodeIndex,
+    ) -> Result<(&'a Package, &'a DependencyType)> {
         let p = self
-            .0
             .dag
-            .node_weight(self.1)
-            .ok_or_else(|| anyhow!("Error finding node: {:?}", self.1))
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-        let dependency_type = match self.2 {
+            .dag
+            .node_weight(node)
+            .ok_or_else(|| anyhow!("Error finding node: {:?}", node))?;
+        let dependency_type = match self.edge {
             // Only the root package has no edge and we pretend it's a runtime dependency as we
             // only mark build time dependencies in the output:
             None => &DependencyType::Runtime,
             Some(edge_idx) => self
-                .0
+                .dag
                 .dag
                 .edge_weight(edge_idx)
-                .ok_or_else(|| anyhow!("Error finding edge: {:?}", self.2))
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?,
+                .ok_or_else(|| anyhow!("Error finding edge: {:?}", self.edge))?,
+        };
+        Ok((p, dependency_type))
+    }
+}
+
+impl TreeItem for DagDisplay<'_> {
+    type Child = Self;
+
+    fn write_self<W: Write>(&self, f: &mut W, _: &Style) -> IoResult<()> {
+        let Some(node) = self.node else {
+            return write!(f, "...");
         };
+
+        let (p, dependency_type) = self
+            .package_and_dependency_type(node)
+            .map_err(std::io::Error::other)?;
         let extra_info = match dependency_type {
             // We mark build time dependencies with a star:
             &DependencyType::Build => "*",
             _ => "",
         };
-        write!(f, "{}{} {}", extra_info, p.name(), p.version())
+        let text = format!("{}{} {}", extra_info, p.name(), p.version());
+        if self.color && *dependency_type == DependencyType::Build {
+            write!(f, "{}", text.yellow())
+        } else {
+            write!(f, "{text}")
+        }
     }
 
     fn children(&self) -> Cow<[Self::Child]> {
+        let Some(node) = self.node else {
+            return Cow::from(vec![]);
+        };
+
         let mut children_walker = self
-            .0
             .dag
-            .neighbors_directed(self.1, petgraph::Outgoing)
+            .dag
+            .neighbors_directed(node, petgraph::Outgoing)
             .detach();
         let mut children = Vec::<Self::Child>::new();
-        while let Some((edge_idx, node_idx)) = children_walker.next(&self.0.dag) {
-            children.push(DagDisplay(self.0, node_idx, Some(edge_idx)));
+        while let Some((edge_idx, node_idx)) = children_walker.next(&self.dag.dag) {
+            if self.max_depth == Some(0) {
+                // Only show that there are more dependencies
+                children.push(DagDisplay {
+                    dag: self.dag,
+                    node: None,
+                    edge: None,
+                    max_depth: None,
+                    color: self.color,
+                });
+                break;
+            }
+
+            children.push(DagDisplay {
+                dag: self.dag,
+                node: Some(node_idx),
+                edge: Some(edge_idx),
+                max_depth: self.max_depth.map(|d| d - 1),
+                color: self.color,
+            });
         }
         Cow::from(children)
     }
 }
 
+/// Serializes the tree as nested objects with the fields `name`, `version`, `build_dep` (whether
+/// the parent package depends on the package at build time), `children` and `truncated` (whether
+/// dependencies were left out because of the depth limit)
+impl Serialize for DagDisplay<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let node = self
+            .node
+            .ok_or_else(|| S::Error::custom("Cannot serialize the depth limit marker"))?;
+        let (p, dependency_type) = self
+            .package_and_dependency_type(node)
+            .map_err(S::Error::custom)?;
+        let (children, truncated): (Vec<_>, Vec<_>) = self
+            .children()
+            .into_owned()
+            .into_iter()
+            .partition(|child| child.node.is_some());
+
+        let mut s = serializer.serialize_struct("DagDisplay", 5)?;
+        s.serialize_field("name", p.name())?;
+        s.serialize_field("version", p.version())?;
+        s.serialize_field("build_dep", &(*dependency_type == DependencyType::Build))?;
+        s.serialize_field("children", &children)?;
+        s.serialize_field("truncated", &!truncated.is_empty())?;
+        s.end()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -326,8 +597,10 @@ mod tests {
     use crate::package::tests::package;
     use crate::package::tests::pname;
     use crate::package::tests::pversion;
+    use crate::package::BuildDependency;
     use crate::package::Dependencies;
     use crate::package::Dependency;
+    use crate::package::ProvidedPackage;
     use crate::util::docker::ImageName;
 
     #[test]
@@ -399,6 +672,131 @@ mod tests {
         assert!(ps.iter().any(|p| *p.version() == pversion("2")));
     }
 
+    #[test]
+    fn test_dependency_cycle() {
+        let mut btree = BTreeMap::new();
+        for (name, dependency) in [("a", "b =1"), ("b", "c =1"), ("c", "a =1")] {
+            let mut p = package(name, "1", "https://rust-lang.org", "123");
+            p.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(
+                String::from(dependency),
+            )));
+            btree.insert((pname(name), pversion("1")), p);
+        }
+        let a = btree.get(&(pname("a"), pversion("1"))).unwrap().clone();
+        let repo = Repository::from(btree);
+
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let err = Dag::for_root_package(a, &repo, None, &condition_data).unwrap_err();
+        let msg = format!("{err:#}");
+        assert!(
+            [
+                "a 1 -> b 1 -> c 1 -> a 1",
+                "b 1 -> c 1 -> a 1 -> b 1",
+                "c 1 -> a 1 -> b 1 -> c 1"
+            ]
+            .iter()
+            .any(|cycle| msg.contains(cycle)),
+            "Cycle not found in error: {msg}"
+        );
+    }
+
+    #[test]
+    fn test_dependency_on_itself() {
+        let mut a = package("a", "1", "https://rust-lang.org", "123");
+        a.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(
+            String::from("a =1"),
+        )));
+        let mut btree = BTreeMap::new();
+        btree.insert((pname("a"), pversion("1")), a.clone());
+        let repo = Repository::from(btree);
+
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let err = Dag::for_root_package(a, &repo, None, &condition_data).unwrap_err();
+        assert!(format!("{err:#}").contains("Dependency cycle: a 1 -> a 1"));
+    }
+
+    /// Get a repository with the packages "a", "b" and "c", where "a" and "b" both depend on "c"
+    fn repo_with_shared_dependency() -> (Package, Package, Repository) {
+        let mut btree = BTreeMap::new();
+
+        let mut a = package("a", "1", "https://rust-lang.org", "123");
+        a.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(
+            String::from("c =1"),
+        )));
+        let mut b = package("b", "1", "https://rust-lang.org", "124");
+        b.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(
+            String::from("c =1"),
+        )));
+        let c = package("c", "1", "https://rust-lang.org", "125");
+        for p in [&a, &b, &c] {
+            btree.insert((p.name().clone(), p.version().clone()), p.clone());
+        }
+
+        (a, b, Repository::from(btree))
+    }
+
+    fn root_names(dag: &Dag) -> Vec<String> {
+        dag.root_idxs()
+            .iter()
+            .map(|idx| dag.dag().node_weight(*idx).unwrap().name().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_multiple_root_packages_with_shared_dependency() {
+        let (a, b, repo) = repo_with_shared_dependency();
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_packages(vec![a, b], &repo, None, &condition_data).unwrap();
+
+        let mut names = dag
+            .all_packages()
+            .into_iter()
+            .map(|p| p.name().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["a", "b", "c"]);
+        assert_eq!(dag.dag().edge_count(), 2);
+        assert_eq!(root_names(&dag), vec!["a", "b"]);
+
+        let trees = display_to_string(&dag, None);
+        assert!(trees.contains("a 1"));
+        assert!(trees.contains("b 1"));
+        assert_eq!(trees.matches("c 1").count(), 2);
+
+        let limited = dag.limit_depth(0);
+        assert_eq!(limited.all_packages().len(), 2);
+        assert_eq!(root_names(&limited), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_root_package_that_is_a_dependency_of_another_one() {
+        let (a, _, repo) = repo_with_shared_dependency();
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+        let c = repo.find(&pname("c"), &pversion("1"))[0].clone();
+
+        let dag =
+            Dag::for_root_packages(vec![c.clone(), a, c], &repo, None, &condition_data).unwrap();
+
+        assert_eq!(dag.all_packages().len(), 2);
+        assert_eq!(dag.dag().edge_count(), 1);
+        assert_eq!(root_names(&dag), vec!["a"]);
+    }
+
     #[test]
     fn test_add_deep_package_tree() {
         let mut btree = BTreeMap::new();
@@ -885,4 +1283,274 @@ mod tests {
         assert!(ps.iter().any(|p| *p.name() == pname("b")));
         assert!(ps.iter().any(|p| *p.version() == pversion("2")));
     }
+
+    // Helper function to create a repository with package "a" that depends on the virtual package
+    // "smtp-server", which is provided by the packages with the passed names and versions
+    fn repo_with_smtp_server_providers(providers: &[(&str, &str)]) -> (Package, Repository) {
+        let mut btree = BTreeMap::new();
+
+        let mut p1 = {
+            let name = "a";
+            let vers = "1";
+            let pack = package(name, vers, "https://rust-lang.org", "123");
+            btree.insert((pname(name), pversion(vers)), pack.clone());
+            pack
+        };
+
+        for (name, vers) in providers {
+            let mut pack = package(name, vers, "https://rust-lang.org", "124");
+            pack.set_provides(vec![ProvidedPackage::from(String::from("smtp-server =1"))]);
+            btree.insert((pname(name), pversion(vers)), pack);
+        }
+
+        {
+            let d = Dependency::from(String::from("smtp-server =1"));
+            let ds = Dependencies::with_runtime_dependency(d);
+            p1.set_dependencies(ds);
+        }
+
+        (p1, Repository::from(btree))
+    }
+
+    #[test]
+    fn test_dependency_satisfied_by_provider() {
+        let (p1, repo) = repo_with_smtp_server_providers(&[("postfix", "2")]);
+        let progress = ProgressBar::hidden();
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        assert!(dag.is_ok(), "{dag:?}");
+        let dag = dag.unwrap();
+        let ps = dag.all_packages();
+
+        assert_eq!(ps.len(), 2);
+        assert!(ps.iter().any(|p| *p.name() == pname("a")));
+        assert!(ps.iter().any(|p| *p.name() == pname("postfix")));
+        assert_eq!(dag.dag().edge_count(), 1);
+    }
+
+    #[test]
+    fn test_dependency_with_multiple_providers_fails() {
+        let (p1, repo) = repo_with_smtp_server_providers(&[("postfix", "2"), ("exim", "2")]);
+        let progress = ProgressBar::hidden();
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        let err = format!("{:#}", dag.unwrap_err());
+        assert!(err.contains("exim 2"), "{err}");
+        assert!(err.contains("postfix 2"), "{err}");
+    }
+
+    #[test]
+    fn test_dependency_with_multiple_providers_and_default() {
+        let (p1, repo) = repo_with_smtp_server_providers(&[("postfix", "2"), ("exim", "2")]);
+        let repo =
+            repo.with_provider_defaults(HashMap::from([(pname("smtp-server"), pname("exim"))]));
+        let progress = ProgressBar::hidden();
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        assert!(dag.is_ok(), "{dag:?}");
+        let dag = dag.unwrap();
+        let ps = dag.all_packages();
+
+        assert_eq!(ps.len(), 2);
+        assert!(ps.iter().any(|p| *p.name() == pname("exim")));
+        assert!(!ps.iter().any(|p| *p.name() == pname("postfix")));
+    }
+
+    #[test]
+    fn test_dependency_with_multiple_versions_of_provider() {
+        let (p1, repo) = repo_with_smtp_server_providers(&[
+            ("postfix", "2"),
+            ("postfix", "10"),
+            ("postfix", "3"),
+        ]);
+        let progress = ProgressBar::hidden();
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        assert!(dag.is_ok(), "{dag:?}");
+        let dag = dag.unwrap();
+        let ps = dag.all_packages();
+
+        assert_eq!(ps.len(), 2);
+        assert!(ps
+            .iter()
+            .any(|p| *p.name() == pname("postfix") && *p.version() == pversion("10")));
+        assert_eq!(dag.dag().edge_count(), 1);
+    }
+
+    fn abc_chain_dag() -> Dag {
+        let mut btree = BTreeMap::new();
+
+        let mut a = package("a", "1", "https://rust-lang.org", "123");
+        a.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(
+            String::from("b =2"),
+        )));
+        let mut b = package("b", "2", "https://rust-lang.org", "124");
+        b.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(
+            String::from("c =3"),
+        )));
+        let c = package("c", "3", "https://rust-lang.org", "125");
+
+        btree.insert((pname("a"), pversion("1")), a.clone());
+        btree.insert((pname("b"), pversion("2")), b);
+        btree.insert((pname("c"), pversion("3")), c);
+        let repo = Repository::from(btree);
+
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+
+        Dag::for_root_package(a, &repo, None, &condition_data).unwrap()
+    }
+
+    fn display_to_string(dag: &Dag, max_depth: Option<usize>) -> String {
+        let mut out = Vec::new();
+        for tree in dag.display(max_depth, false) {
+            ptree::write_tree(&tree, &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_display_with_depth_limit() {
+        let dag = abc_chain_dag();
+
+        let full = display_to_string(&dag, None);
+        assert!(full.contains("c 3"));
+        assert!(!full.contains("..."));
+
+        let limited = display_to_string(&dag, Some(1));
+        assert!(limited.contains("a 1"));
+        assert!(limited.contains("b 2"));
+        assert!(!limited.contains("c 3"));
+        assert!(limited.contains("..."));
+
+        let root_only = display_to_string(&dag, Some(0));
+        assert!(root_only.contains("a 1"));
+        assert!(!root_only.contains("b 2"));
+        assert!(root_only.contains("..."));
+
+        // The leaf package is reached before the limit, so nothing is truncated
+        let exact = display_to_string(&dag, Some(2));
+        assert!(exact.contains("c 3"));
+        assert!(!exact.contains("..."));
+    }
+
+    #[test]
+    fn test_display_serialize() {
+        let dag = abc_chain_dag();
+
+        let json = serde_json::to_value(dag.display(Some(1), false)).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        let a = &json[0];
+        assert_eq!(a["name"], "a");
+        assert_eq!(a["version"], "1");
+        assert_eq!(a["build_dep"], false);
+        assert_eq!(a["truncated"], false);
+        let b = &a["children"][0];
+        assert_eq!(b["name"], "b");
+        assert_eq!(b["children"].as_array().unwrap().len(), 0);
+        assert_eq!(b["truncated"], true);
+    }
+
+    #[test]
+    fn test_runtime_packages() {
+        let mut btree = BTreeMap::new();
+
+        // a depends on b at build time and on c at runtime, b depends on d at runtime
+        let mut a = package("a", "1", "https://rust-lang.org", "123");
+        a.set_dependencies(Dependencies::with_build_and_runtime_dependencies(
+            vec![BuildDependency::Simple(String::from("b =2"))],
+            vec![Dependency::from(String::from("c =3"))],
+        ));
+        let mut b = package("b", "2", "https://rust-lang.org", "124");
+        b.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(
+            String::from("d =4"),
+        )));
+        let c = package("c", "3", "https://rust-lang.org", "125");
+        let d = package("d", "4", "https://rust-lang.org", "126");
+
+        btree.insert((pname("a"), pversion("1")), a.clone());
+        btree.insert((pname("b"), pversion("2")), b.clone());
+        btree.insert((pname("c"), pversion("3")), c);
+        btree.insert((pname("d"), pversion("4")), d);
+        let repo = Repository::from(btree);
+
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+        };
+        let dag = Dag::for_root_package(a.clone(), &repo, None, &condition_data).unwrap();
+        assert_eq!(dag.all_packages().len(), 4);
+
+        let mut names = dag
+            .runtime_packages()
+            .into_iter()
+            .map(|p| p.name().clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec![pname("a"), pname("c")]);
+
+        // b is requested as well, so it is not left out although a only needs it at build time
+        let dag = Dag::for_root_packages(vec![a, b], &repo, None, &condition_data).unwrap();
+        assert_eq!(dag.root_idxs().len(), 1);
+
+        let mut names = dag
+            .runtime_packages()
+            .into_iter()
+            .map(|p| p.name().clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec![pname("a"), pname("b"), pname("c"), pname("d")]);
+    }
+
+    #[test]
+    fn test_limit_depth() {
+        let dag = abc_chain_dag();
+
+        let limited = dag.limit_depth(1);
+        let names = limited
+            .all_packages()
+            .into_iter()
+            .map(|p| p.name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&pname("a")));
+        assert!(names.contains(&pname("b")));
+        assert_eq!(
+            *limited
+                .dag()
+                .node_weight(limited.root_idxs()[0])
+                .unwrap()
+                .name(),
+            pname("a")
+        );
+
+        let root_only = dag.limit_depth(0);
+        assert_eq!(root_only.all_packages().len(), 1);
+        assert_eq!(
+            *root_only
+                .dag()
+                .node_weight(root_only.root_idxs()[0])
+                .unwrap()
+                .name(),
+            pname("a")
+        );
+    }
 }
@@ -22,6 +22,9 @@ pub use build::*;
 mod runtime;
 pub use runtime::*;
 
+mod provides;
+pub use provides::*;
+
 pub mod condition;
 
 pub trait ParseDependency {
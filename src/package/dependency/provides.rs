@@ -0,0 +1,45 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::package::dependency::ParseDependency;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+
+/// A virtual package that is provided by a package
+///
+/// Dependencies on the virtual package can be satisfied by every package that provides it. The
+/// virtual package is specified like a dependency (e.g. `"smtp-server =1"`).
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(transparent)]
+pub struct ProvidedPackage(String);
+
+impl AsRef<str> for ProvidedPackage {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for ProvidedPackage {
+    fn from(s: String) -> ProvidedPackage {
+        ProvidedPackage(s)
+    }
+}
+
+impl ParseDependency for ProvidedPackage {
+    fn parse_as_name_and_version(&self) -> Result<(PackageName, PackageVersion)> {
+        crate::package::dependency::parse_package_dependency_string_into_name_and_version(
+            self.as_ref(),
+        )
+    }
+}
@@ -0,0 +1,177 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Flags of a package
+//!
+//! Flags are free-form markers that a packager can set for a package. Some of them are recognized
+//! by butido and change how the container for building the package is set up:
+//!
+//! * `cap:<CAPABILITY>` adds the Linux capability to the container, the name is given without the
+//!   `CAP_` prefix (e.g. `cap:SYS_PTRACE`)
+//! * `privileged` runs the container in privileged mode
+//!
+//! All other flags have no effect on the build.
+//!
+//! # Security
+//!
+//! The container is the only thing that isolates a build script from the endpoint host.
+//!
+//! A privileged container has access to all devices of the host and is neither restricted by the
+//! seccomp profile nor by AppArmor, so a build script can easily take over the host. Every
+//! capability lifts some restriction as well, e.g. `SYS_ADMIN` allows mounting filesystems, which
+//! is almost as bad as a privileged container.
+//!
+//! Therefore only the capabilities in [SAFE_CAPABILITIES] are granted without further ado. All
+//! other recognized flags are rejected, unless they are explicitly allowed in the configuration
+//! (`containers.allowed_privileged_flags`). Only allow them for endpoints that are not shared with
+//! anything that must not be compromised by a build.
+//!
+//! There is no flag for changing the seccomp profile, as it can't be set with the Docker API
+//! client that butido uses.
+
+use anyhow::anyhow;
+use anyhow::Result;
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The prefix of the flags that add a capability to the container
+const CAPABILITY_FLAG_PREFIX: &str = "cap:";
+
+/// The flag that runs the container in privileged mode
+const PRIVILEGED_FLAG: &str = "privileged";
+
+/// Capabilities that can be granted to a container without weakening its isolation from the host
+///
+/// These only affect processes inside the container: `SYS_PTRACE` is needed by debuggers and
+/// sanitizers (it only allows to bypass seccomp on kernels older than 4.8), `SYS_NICE` allows
+/// raising the priority of processes and `IPC_LOCK` allows locking memory (e.g. for tests of
+/// cryptographic libraries).
+pub const SAFE_CAPABILITIES: &[&str] = &["SYS_PTRACE", "SYS_NICE", "IPC_LOCK"];
+
+/// A flag of a package
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(transparent)]
+pub struct PackageFlag(String);
+
+impl AsRef<str> for PackageFlag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for PackageFlag {
+    fn from(s: String) -> PackageFlag {
+        PackageFlag(s)
+    }
+}
+
+impl std::fmt::Display for PackageFlag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The settings of the container for building a package, as requested by the flags of the package
+#[derive(Clone, Debug, Default, Getters, CopyGetters)]
+pub struct ContainerFlags {
+    /// The capabilities to add to the container
+    #[getset(get = "pub")]
+    capabilities: Vec<String>,
+
+    /// Whether to run the container in privileged mode
+    #[getset(get_copy = "pub")]
+    privileged: bool,
+}
+
+impl ContainerFlags {
+    /// Get the container settings for the `flags` of a package
+    ///
+    /// Fails if a flag would weaken the isolation of the container and is not listed in
+    /// `allowed_privileged_flags`.
+    pub fn from_package_flags(
+        flags: &[PackageFlag],
+        allowed_privileged_flags: &[PackageFlag],
+    ) -> Result<Self> {
+        let check_allowed = |flag: &PackageFlag| {
+            if allowed_privileged_flags.contains(flag) {
+                Ok(())
+            } else {
+                Err(anyhow!(
+                    "The package flag '{}' is not allowed, it has to be listed in containers.allowed_privileged_flags",
+                    flag
+                ))
+            }
+        };
+
+        let mut container_flags = ContainerFlags::default();
+        for flag in flags {
+            if let Some(capability) = flag.as_ref().strip_prefix(CAPABILITY_FLAG_PREFIX) {
+                if !SAFE_CAPABILITIES.contains(&capability) {
+                    check_allowed(flag)?;
+                }
+                if !container_flags.capabilities.iter().any(|c| c == capability) {
+                    container_flags.capabilities.push(capability.to_string());
+                }
+            } else if flag.as_ref() == PRIVILEGED_FLAG {
+                check_allowed(flag)?;
+                container_flags.privileged = true;
+            }
+        }
+        Ok(container_flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(flags: &[&str]) -> Vec<PackageFlag> {
+        flags
+            .iter()
+            .map(|f| PackageFlag::from(f.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_safe_capability_is_granted() {
+        let cf = ContainerFlags::from_package_flags(&flags(&["cap:SYS_PTRACE"]), &[]).unwrap();
+        assert_eq!(cf.capabilities(), &vec![String::from("SYS_PTRACE")]);
+        assert!(!cf.privileged());
+    }
+
+    #[test]
+    fn test_unsafe_capability_needs_to_be_allowed() {
+        let f = flags(&["cap:SYS_ADMIN"]);
+        let err = ContainerFlags::from_package_flags(&f, &[]).unwrap_err();
+        assert!(err.to_string().contains("cap:SYS_ADMIN"));
+
+        let cf = ContainerFlags::from_package_flags(&f, &f).unwrap();
+        assert_eq!(cf.capabilities(), &vec![String::from("SYS_ADMIN")]);
+    }
+
+    #[test]
+    fn test_privileged_needs_to_be_allowed() {
+        let f = flags(&["privileged"]);
+        assert!(ContainerFlags::from_package_flags(&f, &[]).is_err());
+
+        let cf = ContainerFlags::from_package_flags(&f, &f).unwrap();
+        assert!(cf.privileged());
+        assert!(cf.capabilities().is_empty());
+    }
+
+    #[test]
+    fn test_other_flags_are_ignored() {
+        let cf = ContainerFlags::from_package_flags(&flags(&["foo", "bar"]), &[]).unwrap();
+        assert!(cf.capabilities().is_empty());
+        assert!(!cf.privileged());
+    }
+}
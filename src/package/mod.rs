@@ -13,6 +13,9 @@
 mod dependency;
 pub use dependency::*;
 
+mod flags;
+pub use flags::*;
+
 mod name;
 pub use name::*;
 
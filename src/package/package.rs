@@ -8,6 +8,7 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::path::Path;
 use std::path::PathBuf;
@@ -16,10 +17,12 @@ use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use getset::Getters;
+use itertools::Itertools;
 use serde::Deserialize;
 use serde::Serialize;
 
 use crate::package::dependency::*;
+use crate::package::flags::*;
 use crate::package::name::*;
 use crate::package::source::*;
 use crate::package::version::*;
@@ -46,6 +49,11 @@ pub struct Package {
     #[getset(get = "pub")]
     dependencies: Dependencies,
 
+    /// The virtual packages this package provides
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provides: Option<Vec<ProvidedPackage>>,
+
     #[getset(get = "pub")]
     patches: Vec<PathBuf>,
 
@@ -61,6 +69,12 @@ pub struct Package {
     #[serde(skip_serializing_if = "Option::is_none")]
     denied_images: Option<Vec<ImageName>>,
 
+    /// Free-form flags, some of them change the setup of the build container (see
+    /// [ContainerFlags])
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<Vec<PackageFlag>>,
+
     #[getset(get = "pub")]
     phases: HashMap<PhaseName, Phase>,
 
@@ -72,6 +86,20 @@ pub struct Package {
     #[getset(get = "pub")]
     #[serde(skip_serializing_if = "Option::is_none")]
     meta: Option<HashMap<String, String>>,
+
+    /// The `pkg.toml` file (layer) each field was loaded from, by the dotted path of the field
+    /// (e.g. `sources.src.url`)
+    ///
+    /// Only the leaf values are listed, as tables are merged key by key.
+    #[getset(get = "pub")]
+    #[serde(skip)]
+    field_origins: BTreeMap<String, PathBuf>,
+
+    /// The `pkg.toml` files (layers) the package was merged from, from the root of the repository
+    /// to the directory of the package
+    #[getset(get = "pub")]
+    #[serde(skip)]
+    layers: Vec<PathBuf>,
 }
 
 impl std::hash::Hash for Package {
@@ -96,12 +124,16 @@ impl Package {
             version_is_semver,
             sources,
             dependencies,
+            provides: None,
             patches: vec![],
             environment: None,
             allowed_images: None,
             denied_images: None,
+            flags: None,
             phases: HashMap::new(),
             meta: None,
+            field_origins: BTreeMap::new(),
+            layers: vec![],
         }
     }
 
@@ -109,6 +141,14 @@ impl Package {
         format!("{} {}", self.name, self.version)
     }
 
+    pub fn set_field_origins(&mut self, field_origins: BTreeMap<String, PathBuf>) {
+        self.field_origins = field_origins;
+    }
+
+    pub fn set_layers(&mut self, layers: Vec<PathBuf>) {
+        self.layers = layers;
+    }
+
     // A function to prepend the path of the origin/base directory (where the `pkg.toml` file that
     // defined the "patches" resides in) to the relative paths of the patches (it usually only
     // makes sense to call this function once!):
@@ -146,11 +186,100 @@ impl Package {
         Ok(())
     }
 
+    /// Remove the dependencies on packages for which `is_excluded` returns true
+    ///
+    /// Dependencies that cannot be parsed are kept. Returns the removed dependencies.
+    pub fn remove_dependencies<F>(&mut self, is_excluded: F) -> Vec<String>
+    where
+        F: Fn(&PackageName) -> bool,
+    {
+        let excluded = |dep: &dyn ParseDependency| {
+            dep.parse_as_name_and_version()
+                .map(|(name, _)| is_excluded(&name))
+                .unwrap_or(false)
+        };
+
+        let mut removed = Vec::new();
+        self.dependencies.build.retain(|dep| {
+            let keep = !excluded(dep);
+            if !keep {
+                removed.push(dep.as_ref().to_string());
+            }
+            keep
+        });
+        self.dependencies.runtime.retain(|dep| {
+            let keep = !excluded(dep);
+            if !keep {
+                removed.push(dep.as_ref().to_string());
+            }
+            keep
+        });
+        removed
+    }
+
+    /// Remove the environment of the package
+    ///
+    /// Used if the environment is already part of the (recorded) environment of a job.
+    pub fn clear_environment(&mut self) {
+        self.environment = None;
+    }
+
     #[cfg(test)]
     pub fn set_dependencies(&mut self, dependencies: Dependencies) {
         self.dependencies = dependencies;
     }
 
+    #[cfg(test)]
+    pub fn set_phases(&mut self, phases: HashMap<PhaseName, Phase>) {
+        self.phases = phases;
+    }
+
+    #[cfg(test)]
+    pub fn set_provides(&mut self, provides: Vec<ProvidedPackage>) {
+        self.provides = Some(provides);
+    }
+
+    /// Check whether this package may be built on the image `image_name`
+    ///
+    /// Fails if the image is not in the allowed images or if it is in the denied images of the
+    /// package.
+    pub fn check_image(&self, image_name: &ImageName) -> Result<()> {
+        if let Some(allowlist) = self.allowed_images() {
+            if !allowlist.contains(image_name) {
+                return Err(anyhow!(
+                    "Package {} {} is only allowed on: {}",
+                    self.name(),
+                    self.version(),
+                    allowlist.iter().join(", ")
+                ));
+            }
+        }
+
+        if let Some(deniedlist) = self.denied_images() {
+            if deniedlist.iter().any(|denied| image_name == denied) {
+                return Err(anyhow!(
+                    "Package {} {} is not allowed to be built on {}",
+                    self.name(),
+                    self.version(),
+                    image_name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether this package provides the virtual package `name` in version `version`
+    pub fn does_provide(&self, name: &PackageName, version: &PackageVersion) -> Result<bool> {
+        for provided in self.provides.iter().flatten() {
+            let (provided_name, provided_version) = provided.parse_as_name_and_version()?;
+            if provided_name == *name && provided_version == *version {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     /// Get a wrapper object around self which implements a debug interface with all details about
     /// the Package object
     #[cfg(debug_assertions)]
@@ -314,14 +443,22 @@ impl Dependencies {
             runtime: runtime_dependencies,
         }
     }
+
+    pub fn with_build_and_runtime_dependencies(
+        build_dependencies: Vec<BuildDependency>,
+        runtime_dependencies: Vec<Dependency>,
+    ) -> Self {
+        Dependencies {
+            build: build_dependencies,
+            runtime: runtime_dependencies,
+        }
+    }
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
 
-    use url::Url;
-
     /// helper function for quick object construction
     pub fn pname(name: &str) -> PackageName {
         PackageName::from(String::from(name))
@@ -338,7 +475,7 @@ pub mod tests {
         let version = pversion(vers);
         let version_is_semver = false;
         let sources = {
-            let url = Url::parse(srcurl).unwrap();
+            let url = String::from(srcurl);
             let hashvalue = HashValue::from(String::from(hash));
             let mut hm = HashMap::new();
             hm.insert(
@@ -350,4 +487,24 @@ pub mod tests {
         let dependencies = Dependencies::empty();
         Package::new(name, version, version_is_semver, sources, dependencies)
     }
+
+    #[test]
+    fn test_remove_dependencies() {
+        let mut p = package("a", "1", "https://rust-lang.org", "123");
+        p.set_dependencies(Dependencies {
+            build: vec![BuildDependency::Simple(String::from("libc =1.0"))],
+            runtime: vec![
+                Dependency::from(String::from("b =2")),
+                Dependency::from(String::from("libm =1.0")),
+            ],
+        });
+
+        let removed = p.remove_dependencies(|name| name.as_ref().starts_with("lib"));
+        assert_eq!(removed, vec!["libc =1.0", "libm =1.0"]);
+        assert!(p.dependencies().build().is_empty());
+        assert_eq!(
+            p.dependencies().runtime(),
+            &vec![Dependency::from(String::from("b =2"))]
+        );
+    }
 }
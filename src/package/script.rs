@@ -98,6 +98,11 @@ impl Script {
     }
 }
 
+/// Get the names of the themes that are available for highlighting scripts
+pub fn highlight_themes() -> Vec<String> {
+    ThemeSet::load_defaults().themes.into_keys().collect()
+}
+
 #[derive(Debug)]
 pub struct HighlightedScript<'a> {
     script: &'a Script,
@@ -157,6 +162,32 @@ impl From<String> for Shebang {
     }
 }
 
+impl Shebang {
+    /// Check that the shebang is well-formed
+    ///
+    /// It has to start with `#!`, followed by the absolute path of the interpreter (optionally
+    /// with arguments) and must not span multiple lines.
+    pub fn validate(&self) -> Result<()> {
+        let interpreter = self
+            .0
+            .strip_prefix("#!")
+            .ok_or_else(|| anyhow!("Shebang does not start with '#!': {}", self.0))?;
+
+        if self.0.contains('\n') {
+            return Err(anyhow!("Shebang must be a single line: {:?}", self.0));
+        }
+
+        if !interpreter.trim_start().starts_with('/') {
+            return Err(anyhow!(
+                "Shebang does not point to the absolute path of an interpreter: {}",
+                self.0
+            ));
+        }
+
+        Ok(())
+    }
+}
+
 impl AsRef<str> for Script {
     fn as_ref(&self) -> &str {
         self.0.as_ref()
@@ -453,3 +484,30 @@ where
     out.write(&s)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shebang_validate() {
+        for valid in [
+            "#!/bin/bash",
+            "#!/bin/sh",
+            "#! /usr/bin/env bash",
+            "#!/bin/bash -e",
+        ] {
+            assert!(
+                Shebang::from(String::from(valid)).validate().is_ok(),
+                "{valid}"
+            );
+        }
+
+        for invalid in ["/bin/bash", "#bash", "#!bash", "#!", "#!/bin/bash\necho"] {
+            assert!(
+                Shebang::from(String::from(invalid)).validate().is_err(),
+                "{invalid}"
+            );
+        }
+    }
+}
@@ -8,6 +8,8 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
+
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
@@ -15,7 +17,6 @@ use getset::Getters;
 use serde::Deserialize;
 use serde::Serialize;
 use tracing::trace;
-use url::Url;
 
 fn default_download_manually() -> bool {
     false
@@ -23,8 +24,10 @@ fn default_download_manually() -> bool {
 
 #[derive(Clone, Debug, Serialize, Deserialize, Getters)]
 pub struct Source {
+    /// The URL of the source, it may contain the variables `${name}`, `${version}` and
+    /// `${mirror_base}` (see [SourceEntry](crate::source::SourceEntry))
     #[getset(get = "pub")]
-    url: Url,
+    url: String,
     #[getset(get = "pub")]
     hash: SourceHash,
 
@@ -33,19 +36,59 @@ pub struct Source {
     #[serde(default = "default_download_manually")]
     #[getset(get = "pub")]
     download_manually: bool,
+
+    /// The User-Agent for downloading this source (overrides `source_download_user_agent` from
+    /// the configuration)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[getset(get = "pub")]
+    user_agent: Option<String>,
+
+    /// Additional HTTP headers for downloading this source (they override the headers with the
+    /// same name from `source_download_headers` in the configuration)
+    #[serde(default, skip_serializing_if = "HttpHeaders::is_empty")]
+    #[getset(get = "pub")]
+    headers: HttpHeaders,
 }
 
 impl Source {
     #[cfg(test)]
-    pub fn new(url: Url, hash: SourceHash) -> Self {
+    pub fn new(url: String, hash: SourceHash) -> Self {
         Source {
             url,
             hash,
             download_manually: false,
+            user_agent: None,
+            headers: HttpHeaders::default(),
         }
     }
 }
 
+/// Additional HTTP headers that are sent when downloading sources (name -> value)
+///
+/// The values might contain secrets (e.g., an "Authorization" header), therefore only the names
+/// of the headers are included in the `Debug` output.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct HttpHeaders(BTreeMap<String, String>);
+
+impl HttpHeaders {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter()
+    }
+}
+
+impl std::fmt::Debug for HttpHeaders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.0.keys().map(|name| (name, "<redacted>")))
+            .finish()
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Getters)]
 pub struct SourceHash {
     #[serde(rename = "type")]
@@ -58,7 +101,14 @@ pub struct SourceHash {
 }
 
 impl SourceHash {
-    pub async fn matches_hash_of<R: tokio::io::AsyncRead + Unpin>(&self, reader: R) -> Result<()> {
+    /// Hash the data from `reader` and compare the result with this hash
+    ///
+    /// Only fails if the data cannot be hashed, a mismatch is reported by the returned
+    /// [HashCheck].
+    pub async fn check_hash_of<R: tokio::io::AsyncRead + Unpin>(
+        &self,
+        reader: R,
+    ) -> Result<HashCheck> {
         trace!("Hashing buffer with: {:?}", self.hashtype);
         let h = self
             .hashtype
@@ -67,17 +117,10 @@ impl SourceHash {
             .context("Hashing failed")?;
         trace!("Hashing buffer with: {} finished", self.hashtype);
 
-        if h == self.value {
-            trace!("Hash matches expected hash");
-            Ok(())
-        } else {
-            trace!("Hash mismatch expected hash");
-            Err(anyhow!(
-                "Hash mismatch, expected '{}', got '{}'",
-                self.value,
-                h
-            ))
-        }
+        Ok(HashCheck {
+            expected: self.value.clone(),
+            actual: h,
+        })
     }
 
     #[cfg(test)]
@@ -102,7 +145,7 @@ pub enum HashType {
 }
 
 impl HashType {
-    async fn hash_from_reader<R: tokio::io::AsyncRead + Unpin>(
+    pub(crate) async fn hash_from_reader<R: tokio::io::AsyncRead + Unpin>(
         &self,
         mut reader: R,
     ) -> Result<HashValue> {
@@ -171,7 +214,7 @@ impl HashType {
 
                     m.update(&buffer[..count]);
                 }
-                Ok(HashValue(String::from_utf8(m.finalize()[..].to_vec())?))
+                Ok(HashValue(format!("{:x}", m.finalize())))
             }
         }
     }
@@ -182,9 +225,91 @@ impl HashType {
 #[display("{0}")]
 pub struct HashValue(String);
 
+/// The result of comparing the hash of some data with the expected hash
+#[derive(Clone, Debug, Getters)]
+pub struct HashCheck {
+    #[getset(get = "pub")]
+    expected: HashValue,
+
+    /// The hash that was computed from the data
+    #[getset(get = "pub")]
+    actual: HashValue,
+}
+
+impl HashCheck {
+    pub fn matches(&self) -> bool {
+        self.expected == self.actual
+    }
+
+    /// Turn a hash mismatch into an error
+    pub fn into_result(self) -> Result<()> {
+        if self.matches() {
+            trace!("Hash matches expected hash");
+            Ok(())
+        } else {
+            trace!("Hash mismatch expected hash");
+            Err(anyhow!(
+                "Hash mismatch, expected '{}', got '{}'",
+                self.expected,
+                self.actual
+            ))
+        }
+    }
+}
+
 #[cfg(test)]
 impl From<String> for HashValue {
     fn from(s: String) -> Self {
         HashValue(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_check_matches() {
+        let check = HashCheck {
+            expected: HashValue::from(String::from("abc")),
+            actual: HashValue::from(String::from("abc")),
+        };
+        assert!(check.matches());
+        assert!(check.into_result().is_ok());
+    }
+
+    #[test]
+    fn test_hash_check_mismatch_reports_both_hashes() {
+        let check = HashCheck {
+            expected: HashValue::from(String::from("abc")),
+            actual: HashValue::from(String::from("def")),
+        };
+        assert!(!check.matches());
+        let err = check.into_result().unwrap_err().to_string();
+        assert!(err.contains("abc"));
+        assert!(err.contains("def"));
+    }
+
+    #[tokio::test]
+    async fn test_sha512_hash_is_hex_encoded() {
+        let hash = HashType::Sha512
+            .hash_from_reader(&b"butido"[..])
+            .await
+            .unwrap();
+        assert_eq!(
+            hash,
+            HashValue::from(String::from(
+                "8931738ca5b593af5d26b2b1e973faceb643d8110a96126d3fe626cbf73e9cd1\
+                 62f650530796c5561f19800b835571c4f3422015f7bf3e88ae6b0aa70be1eea6"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_http_headers_debug_is_redacted() {
+        let headers: HttpHeaders = toml::from_str(r#"Authorization = "Bearer secret""#).unwrap();
+        let debug = format!("{headers:?}");
+        assert!(debug.contains("Authorization"));
+        assert!(!debug.contains("secret"));
+    }
+}
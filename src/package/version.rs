@@ -65,34 +65,45 @@ impl PackageVersionConstraint {
             })
     }
 
-    pub fn matches(&self, v: &PackageVersion) -> bool {
-        use semver::{Version, VersionReq};
+    /// Check whether the version `v` matches this constraint
+    ///
+    /// Fails if the constraint is a SemVer constraint and `v` cannot be parsed as SemVer.
+    pub fn matches(&self, v: &PackageVersion) -> Result<bool> {
+        use semver::VersionReq;
         match self.constraint.as_str() {
             "" => {
                 let constraint =
                     VersionReq::parse(&(Self::get_default_constraint() + self.version.as_str()))
-                        .unwrap();
-                let version = Version::parse(v.as_str())
-                    .with_context(|| anyhow!("Failed to parse the package version as semver::Version"))
-                    .or_else(|eo| v.clone().try_into().map_err(|e: Error| e.context(eo)))
-                    .with_context(|| anyhow!("Also failed to parse the package version using our own SemVer converter"))
-                    .unwrap_or_else(|e| panic!(
-                        "Failed to parse the package version \"{}\" as SemVer to check if it matches \"{}\". Error: {:#}",
+                        .unwrap(); // safe because the parser already checked it
+                let version = v.to_semver().with_context(|| {
+                    anyhow!(
+                        "Failed to parse the package version \"{}\" as SemVer to check if it matches \"{}\"",
                         v,
-                        constraint,
-                        e
-                    ));
+                        constraint
+                    )
+                })?;
 
-                constraint.matches(&version)
+                Ok(constraint.matches(&version))
             }
-            "=" => self.version == *v,
-            _ => panic!(
+            "=" => Ok(self.version == *v),
+            _ => Err(anyhow!(
                 "Internal error: Unsupported version constraint: {} (version: {})",
-                self.constraint, self.version
-            ),
+                self.constraint,
+                self.version
+            )),
         }
     }
 
+    /// The version of the constraint (without the comparator)
+    pub fn version(&self) -> &PackageVersion {
+        &self.version
+    }
+
+    /// Get the version if this constraint only matches this exact version (`=VERSION`)
+    pub fn exact_version(&self) -> Option<&PackageVersion> {
+        (self.constraint == "=").then_some(&self.version)
+    }
+
     #[cfg(test)]
     #[allow(unused)]
     pub fn from_version(constraint: String, version: PackageVersion) -> Self {
@@ -226,6 +237,18 @@ impl TryInto<semver::Version> for PackageVersion {
 }
 
 impl PackageVersion {
+    /// Convert the version into a semver::Version
+    ///
+    /// If the version isn't valid SemVer, our own (more lenient) converter is used as a fallback.
+    pub fn to_semver(&self) -> Result<semver::Version> {
+        semver::Version::parse(self.as_str())
+            .with_context(|| anyhow!("Failed to parse the package version as semver::Version"))
+            .or_else(|eo| self.clone().try_into().map_err(|e: Error| e.context(eo)))
+            .with_context(|| {
+                anyhow!("Also failed to parse the package version using our own SemVer converter")
+            })
+    }
+
     fn parser<'a>() -> PomParser<'a, u8, Self> {
         (numbers() + ((dash() | under() | dot() | letters() | numbers()).repeat(0..)))
             .collect()
@@ -308,4 +331,18 @@ mod tests {
             PackageVersion::from(String::from("1-0B17-beta1247_commit_12653hasd"))
         );
     }
+
+    #[test]
+    fn test_constraint_matches() {
+        let version = |v: &str| PackageVersion::from(String::from(v));
+        let c = PackageVersionConstraint::try_from("1").unwrap();
+        assert!(c.matches(&version("1.2.3")).unwrap());
+        assert!(!c.matches(&version("2.0.0")).unwrap());
+
+        // A version that cannot be converted to SemVer is an error (and not a panic)
+        assert!(c.matches(&version("1.99999999999999999999")).is_err());
+
+        let c = PackageVersionConstraint::try_from("=1.99999999999999999999").unwrap();
+        assert!(c.matches(&version("1.99999999999999999999")).unwrap());
+    }
 }
@@ -14,11 +14,8 @@ use std::path::PathBuf;
 
 use anyhow::anyhow;
 use anyhow::Context;
-use anyhow::Error;
 use anyhow::Result;
-use resiter::AndThen;
 use resiter::Filter;
-use resiter::Map;
 use tracing::trace;
 use walkdir::DirEntry;
 use walkdir::WalkDir;
@@ -49,7 +46,10 @@ pub struct FileSystemRepresentation {
 
 impl FileSystemRepresentation {
     /// Load the FileSystemRepresentation object starting at `root`.
-    pub fn load(root: PathBuf) -> Result<Self> {
+    ///
+    /// The `pkg.toml` files are searched first, so that the `progress` bar can show how many of
+    /// them were already read.
+    pub fn load(root: PathBuf, progress: &indicatif::ProgressBar) -> Result<Self> {
         let mut fsr = FileSystemRepresentation {
             root: root.clone(),
             elements: HashMap::new(),
@@ -72,48 +72,49 @@ impl FileSystemRepresentation {
             root.display()
         );
         trace!("Loading with a maximum of {} files open", max_files_open);
-        WalkDir::new(root)
+        let pkgtoml_files = WalkDir::new(root)
             .follow_links(false)
             .max_open(max_files_open)
             .same_file_system(true)
             .into_iter()
             .filter_entry(|e| !is_hidden(e) && (is_pkgtoml(e) || is_dir(e)))
             .filter_ok(is_pkgtoml)
-            .inspect(|el| trace!("Loading: {:?}", el))
-            .map_err(Error::from)
-            .and_then_ok(|de| {
-                let mut curr_hm = &mut fsr.elements;
-                let de_path = de.path();
-                fsr.files.push(de_path.to_path_buf());
-
-                // Build/extend the HashMap tree by adding the current path (we strip the repo root
-                // prefix since we're only interested in the structure of the repo below its root):
-                let root_relative_path = de_path.strip_prefix(&fsr.root)?;
-                for cmp in root_relative_path.components() {
-                    match PathComponent::try_from(&cmp)? {
-                        PathComponent::PkgToml => {
-                            curr_hm
-                                .entry(PathComponent::PkgToml)
-                                .or_insert(Element::File(load_file(de_path)?));
-                        }
-                        dir @ PathComponent::DirName(_) => {
-                            curr_hm
-                                .entry(dir.clone())
-                                .or_insert_with(|| Element::Dir(HashMap::new()));
-
-                            // Step into the sub HashMap tree for the next iteration:
-                            curr_hm = curr_hm
-                                .get_mut(&dir)
-                                .unwrap() // safe, because we just inserted it
-                                .get_map_mut()
-                                .unwrap(); // safe, because we inserted Element::Dir
-                        }
+            .collect::<Result<Vec<_>, _>>()?;
+
+        progress.set_length(pkgtoml_files.len().try_into()?);
+        progress.set_position(0);
+        for de in pkgtoml_files {
+            trace!("Loading: {:?}", de);
+            progress.inc(1);
+            let mut curr_hm = &mut fsr.elements;
+            let de_path = de.path();
+            fsr.files.push(de_path.to_path_buf());
+
+            // Build/extend the HashMap tree by adding the current path (we strip the repo root
+            // prefix since we're only interested in the structure of the repo below its root):
+            let root_relative_path = de_path.strip_prefix(&fsr.root)?;
+            for cmp in root_relative_path.components() {
+                match PathComponent::try_from(&cmp)? {
+                    PathComponent::PkgToml => {
+                        curr_hm
+                            .entry(PathComponent::PkgToml)
+                            .or_insert(Element::File(load_file(de_path)?));
+                    }
+                    dir @ PathComponent::DirName(_) => {
+                        curr_hm
+                            .entry(dir.clone())
+                            .or_insert_with(|| Element::Dir(HashMap::new()));
+
+                        // Step into the sub HashMap tree for the next iteration:
+                        curr_hm = curr_hm
+                            .get_mut(&dir)
+                            .unwrap() // safe, because we just inserted it
+                            .get_map_mut()
+                            .unwrap(); // safe, because we inserted Element::Dir
                     }
                 }
-
-                Ok(())
-            })
-            .collect::<Result<Vec<_>>>()?;
+            }
+        }
 
         Ok(fsr)
     }
@@ -497,7 +498,7 @@ mod tests {
             String::from(pb(repo_relative_path).to_string_lossy())
         }
 
-        let fsr = FileSystemRepresentation::load(pb(""))?;
+        let fsr = FileSystemRepresentation::load(pb(""), &indicatif::ProgressBar::hidden())?;
 
         // Test the leaf file logic:
         assert!(!fsr.is_leaf_file(&pb("pkg.toml")).unwrap());
@@ -9,6 +9,7 @@
 //
 
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
@@ -17,6 +18,7 @@ use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
+use itertools::Itertools;
 use regex::Regex;
 use tracing::trace;
 
@@ -28,12 +30,15 @@ use crate::package::PackageVersionConstraint;
 /// A repository represents a collection of packages
 pub struct Repository {
     inner: BTreeMap<(PackageName, PackageVersion), Package>,
+
+    /// The default providers of virtual packages (virtual package name -> package name)
+    provider_defaults: HashMap<PackageName, PackageName>,
 }
 
 #[cfg(test)]
 impl From<BTreeMap<(PackageName, PackageVersion), Package>> for Repository {
     fn from(inner: BTreeMap<(PackageName, PackageVersion), Package>) -> Self {
-        Repository { inner }
+        Repository::new(inner)
     }
 }
 
@@ -93,19 +98,81 @@ pub fn normalize_relative_path(path: PathBuf) -> Result<PathBuf> {
     Ok(normalized_path)
 }
 
+/// Get the `pkg.toml` file each leaf value of the merged `table` was loaded from
+///
+/// Tables don't have a meaningful origin, as they are merged key by key, so they are only
+/// descended into.
+fn field_origins(table: &config::Map<String, config::Value>) -> BTreeMap<String, PathBuf> {
+    fn collect(
+        prefix: Option<&str>,
+        table: &config::Map<String, config::Value>,
+        origins: &mut BTreeMap<String, PathBuf>,
+    ) {
+        for (key, value) in table {
+            let field = match prefix {
+                Some(prefix) => format!("{prefix}.{key}"),
+                None => key.clone(),
+            };
+
+            if let config::ValueKind::Table(ref table) = value.kind {
+                collect(Some(&field), table, origins);
+            } else if let Some(origin) = value.origin() {
+                origins.insert(field, PathBuf::from(origin));
+            }
+        }
+    }
+
+    let mut origins = BTreeMap::new();
+    collect(None, table, &mut origins);
+    origins
+}
+
 impl Repository {
     fn new(inner: BTreeMap<(PackageName, PackageVersion), Package>) -> Self {
-        Repository { inner }
+        Repository {
+            inner,
+            provider_defaults: HashMap::new(),
+        }
+    }
+
+    /// Set the packages that are used if multiple packages provide the same virtual package
+    pub fn with_provider_defaults(
+        mut self,
+        provider_defaults: HashMap<PackageName, PackageName>,
+    ) -> Self {
+        self.provider_defaults = provider_defaults;
+        self
     }
 
+    /// Load the packages from the repository at `path`
+    ///
+    /// Each directory without subdirectories that contain `pkg.toml` files defines a package. The
+    /// `pkg.toml` files from the root of the repository down to that directory are the "layers"
+    /// of the package, a `pkg.toml` file in a parent directory provides the defaults for all
+    /// packages beneath it. The layers are merged as follows, from the root to the package:
+    ///
+    /// * Scalar values (e.g. `version` or `phases.build.script`) of a child override the values of
+    ///   its parents
+    /// * Tables (e.g. `sources`, `environment` or `phases`) are merged key by key, so a child
+    ///   can add keys to a table or override single keys
+    /// * Arrays (e.g. `patches`, `flags` or `dependencies.build`) of a child replace the arrays of
+    ///   its parents, they are not concatenated (a child has to repeat the entries of the parent
+    ///   if it wants to extend an array)
+    ///
+    /// A repository without nested `pkg.toml` files is simply a repository where each package has
+    /// a single layer. The file each value was loaded from is available via
+    /// [Package::field_origins].
     pub fn load(path: &Path, progress: &indicatif::ProgressBar) -> Result<Self> {
         use crate::repository::fs::FileSystemRepresentation;
         use config::Config;
+        use config::Source;
+        use rayon::iter::IntoParallelIterator;
         use rayon::iter::IntoParallelRefIterator;
         use rayon::iter::ParallelIterator;
 
         trace!("Loading files from filesystem");
-        let fsr = FileSystemRepresentation::load(path.to_path_buf())?;
+        progress.set_message("Loading repository: Reading package files...");
+        let fsr = FileSystemRepresentation::load(path.to_path_buf(), progress)?;
 
         let cwd = std::env::current_dir()?;
         let leaf_files = fsr
@@ -116,14 +183,20 @@ impl Repository {
                 Ok(true) => Some(Ok(path)),
                 Ok(false) => None,
                 Err(e) => Some(Err(e)),
-            });
-        progress.set_length(leaf_files.clone().count().try_into()?);
+            })
+            .collect::<Vec<_>>();
+
+        progress.set_message("Loading repository: Parsing packages...");
+        progress.set_length(leaf_files.len().try_into()?);
+        progress.set_position(0);
         leaf_files
+            .into_par_iter()
             .inspect(|r| trace!("Loading files for {:?}", r))
             .map(|path| {
                 progress.inc(1);
                 let path = path?;
-                let config = fsr.get_files_for(path)?
+                let layers = fsr.get_files_for(path)?;
+                let config = layers
                     .iter()
                     // Load all "layers":
                     .inspect(|(path, _)| trace!("Loading layer at {}", path.display()))
@@ -133,6 +206,7 @@ impl Repository {
                     })
                     .build()?;
 
+                let field_origins = field_origins(&config.collect()?);
                 let patches_value = config.get_array("patches");
                 let mut pkg = config
                     .try_deserialize::<Package>()
@@ -140,6 +214,8 @@ impl Repository {
                     .with_context(|| {
                         anyhow!("Could not load package configuration: {}", path.display())
                     })?;
+                pkg.set_field_origins(field_origins);
+                pkg.set_layers(layers.into_iter().map(|(path, _)| path).collect());
 
                 if !pkg.patches().is_empty() {
                     // We have to build the full relative paths to the patch files by
@@ -189,6 +265,45 @@ impl Repository {
             .map(Repository::new)
     }
 
+    /// Get the packages that are affected by changes of the `changed_files`
+    ///
+    /// A package is affected if one of its layers (including the `pkg.toml` files it shares with
+    /// other packages), one of its patches, or any other file in its directory changed.
+    /// Relative paths are relative to the current directory (like the paths of the layers and
+    /// patches, if the repository was loaded from a relative path).
+    pub fn packages_affected_by<'a>(
+        &'a self,
+        changed_files: &[PathBuf],
+    ) -> Result<Vec<&'a Package>> {
+        let cwd = std::env::current_dir()?;
+        let changed_files = changed_files
+            .iter()
+            .map(|path| cwd.join(path))
+            .collect::<Vec<_>>();
+
+        Ok(self
+            .inner
+            .values()
+            .filter(|package| {
+                let package_dir = package
+                    .layers()
+                    .last()
+                    .and_then(|layer| layer.parent())
+                    .map(|dir| cwd.join(dir));
+
+                package
+                    .layers()
+                    .iter()
+                    .chain(package.patches().iter())
+                    .map(|path| cwd.join(path))
+                    .any(|path| changed_files.contains(&path))
+                    || package_dir
+                        .is_some_and(|dir| changed_files.iter().any(|path| path.starts_with(&dir)))
+            })
+            .inspect(|package| trace!("Affected by the changes: {:?}", package))
+            .collect())
+    }
+
     pub fn find_by_name<'a>(&'a self, name: &PackageName) -> Vec<&'a Package> {
         trace!("Searching for '{}' in repository", name);
         self.inner
@@ -221,6 +336,141 @@ impl Repository {
             .collect()
     }
 
+    /// Find the package with the highest version that matches the version constraint
+    ///
+    /// A package with exactly the version of the constraint is always preferred, so that e.g. "1.2"
+    /// selects version 1.2 (if it exists) and not 1.2.5.
+    ///
+    /// Fails if multiple packages match and it cannot be decided which version is the highest,
+    /// either because a version cannot be converted to SemVer or because multiple versions are
+    /// equal as SemVer (e.g. "1.0" and "1.0.0").
+    pub fn find_best_match<'a>(
+        &'a self,
+        name: &PackageName,
+        constraint: &PackageVersionConstraint,
+    ) -> Result<Option<&'a Package>> {
+        if let Some(p) = self
+            .inner
+            .get(&(name.clone(), constraint.version().clone()))
+        {
+            trace!("Found {} {} with the exact version", name, p.version());
+            return Ok(Some(p));
+        }
+
+        let mut candidates = self
+            .find_by_name(name)
+            .into_iter()
+            .map(|p| constraint.matches(p.version()).map(|b| (b, p)))
+            .filter_ok(|(b, _)| *b)
+            .map_ok(|tpl| tpl.1)
+            .map(|p| {
+                let p = p?;
+                p.version().to_semver().map(|semver| (semver, p))
+            })
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| {
+                anyhow!(
+                    "Cannot decide which version of {} matching {} is the highest",
+                    name,
+                    constraint
+                )
+            })?;
+        candidates.sort_by(|(v1, _), (v2, _)| v2.cmp(v1));
+        trace!(
+            "Found {} versions of {} matching {}",
+            candidates.len(),
+            name,
+            constraint
+        );
+
+        match candidates.as_slice() {
+            [] => Ok(None),
+            [(_, p)] => Ok(Some(*p)),
+            [(v1, p1), (v2, p2), ..] if v1 == v2 => Err(anyhow!(
+                "Found multiple versions of {} matching {} that cannot be ordered: {}, {}",
+                name,
+                constraint,
+                p1.version(),
+                p2.version()
+            ))
+            .context("Please specify an exact version constraint (e.g. =VERSION)"),
+            [(_, p), ..] => Ok(Some(*p)),
+        }
+    }
+
+    /// Find the packages that satisfy a dependency
+    ///
+    /// If there is no package with the name of the dependency, the dependency is resolved
+    /// against the virtual packages the packages provide. If multiple packages (with different
+    /// names) provide the virtual package, the configured default provider is used, otherwise this
+    /// fails. If multiple versions of the provider provide it, the highest version is used.
+    pub fn find_dependency<'a>(
+        &'a self,
+        name: &PackageName,
+        version: &PackageVersion,
+    ) -> Result<Vec<&'a Package>> {
+        let packs = self.find_with_version(name, version);
+        if !packs.is_empty() {
+            return Ok(packs);
+        }
+
+        let mut providers = Vec::new();
+        for pack in self.inner.values() {
+            if pack.does_provide(name, version).with_context(|| {
+                anyhow!(
+                    "Parsing the provided packages of {} {}",
+                    pack.name(),
+                    pack.version()
+                )
+            })? {
+                providers.push(pack);
+            }
+        }
+        trace!(
+            "Found the following providers for {} {}: {:?}",
+            name,
+            version,
+            providers
+        );
+
+        if providers.iter().map(|p| p.name()).all_equal() {
+            return highest_version(providers).map(|p| p.into_iter().collect());
+        }
+
+        let candidates = || {
+            providers
+                .iter()
+                .map(|p| p.display_name_version())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let default = self.provider_defaults.get(name).ok_or_else(|| {
+            anyhow!(
+                "Multiple packages provide {} {} ({}), configure the default provider in 'virtual_package_providers'",
+                name,
+                version,
+                candidates()
+            )
+        })?;
+
+        let defaults = providers
+            .iter()
+            .filter(|p| p.name() == default)
+            .copied()
+            .collect::<Vec<_>>();
+        if defaults.is_empty() {
+            return Err(anyhow!(
+                "The default provider {} of {} {} does not provide it, candidates are: {}",
+                default,
+                name,
+                version,
+                candidates()
+            ));
+        }
+        highest_version(defaults).map(|p| p.into_iter().collect())
+    }
+
     pub fn packages(&self) -> impl Iterator<Item = &Package> {
         self.inner.values()
     }
@@ -231,22 +481,26 @@ impl Repository {
         pvers: &'a Option<PackageVersionConstraint>,
         matching_regexp: &'a Option<Regex>,
     ) -> Result<impl Iterator<Item = &'a Package> + 'a> {
-        let mut r = self.inner.values()
-        .filter(move |p| {
+        let r = self.inner.values()
+        .map(move |p| {
             match (pname, pvers, matching_regexp) {
-                (None, None, None)              => true,
-                (Some(pname), None, None)       => p.name() == pname,
-                (Some(pname), Some(vers), None) => p.name() == pname && vers.matches(p.version()),
-                (None, None, Some(regex))       => regex.is_match(p.name()),
+                (None, None, None)              => Ok(true),
+                (Some(pname), None, None)       => Ok(p.name() == pname),
+                (Some(pname), Some(vers), None) => Ok(p.name() == pname && vers.matches(p.version())?),
+                (None, None, Some(regex))       => Ok(regex.is_match(p.name())),
 
                 (_, _, _) => {
                     panic!("This should not be possible, either we select packages by name and (optionally) version, or by regex.")
                 },
             }
-        }).peekable();
+            .map(|b| (b, p))
+        })
+        .filter_ok(|(b, _)| *b)
+        .map_ok(|tpl| tpl.1)
+        .collect::<Result<Vec<_>>>()?;
 
         // check if the iterator is empty
-        if r.peek().is_none() {
+        if r.is_empty() {
             match (pname, pvers, matching_regexp) {
                 (Some(pname), None, None) => return Err(anyhow!("{} not found", pname)),
                 (Some(pname), Some(vers), None) => {
@@ -260,10 +514,24 @@ impl Repository {
             }
         }
 
-        Ok(r)
+        Ok(r.into_iter())
     }
 }
 
+/// Get the package with the highest version of the `packages` (which have the same name)
+fn highest_version(packages: Vec<&Package>) -> Result<Option<&Package>> {
+    packages
+        .into_iter()
+        .map(|p| p.version().to_semver().map(|semver| (semver, p)))
+        .collect::<Result<Vec<_>>>()
+        .map(|packages| {
+            packages
+                .into_iter()
+                .max_by(|(v1, _), (v2, _)| v1.cmp(v2))
+                .map(|(_, p)| p)
+        })
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -357,6 +625,69 @@ pub mod tests {
         assert!(!p.version_is_semver());
     }
 
+    #[test]
+    fn test_find_best_match() {
+        let mut btree = BTreeMap::new();
+
+        for (vers, hash) in [("1.2.0", "123"), ("1.10.1", "124"), ("2.0.0", "125")] {
+            let pack = package("a", vers, "https://rust-lang.org", hash);
+            btree.insert((pname("a"), pversion(vers)), pack);
+        }
+
+        let repo = Repository::from(btree);
+
+        let constraint = PackageVersionConstraint::try_from("1").unwrap();
+        let p = repo.find_best_match(&pname("a"), &constraint).unwrap();
+        assert_eq!(*p.unwrap().version(), pversion("1.10.1"));
+
+        let constraint = PackageVersionConstraint::try_from("=1.2.0").unwrap();
+        let p = repo.find_best_match(&pname("a"), &constraint).unwrap();
+        assert_eq!(*p.unwrap().version(), pversion("1.2.0"));
+
+        let constraint = PackageVersionConstraint::try_from("3").unwrap();
+        let p = repo.find_best_match(&pname("a"), &constraint).unwrap();
+        assert!(p.is_none());
+    }
+
+    #[test]
+    fn test_find_best_match_prefers_exact_version() {
+        let mut btree = BTreeMap::new();
+
+        for (vers, hash) in [("1.2", "123"), ("1.2.5", "124")] {
+            let pack = package("a", vers, "https://rust-lang.org", hash);
+            btree.insert((pname("a"), pversion(vers)), pack);
+        }
+
+        let repo = Repository::from(btree);
+
+        let constraint = PackageVersionConstraint::try_from("1.2").unwrap();
+        let p = repo.find_best_match(&pname("a"), &constraint).unwrap();
+        assert_eq!(*p.unwrap().version(), pversion("1.2"));
+
+        let constraint = PackageVersionConstraint::try_from("1").unwrap();
+        let p = repo.find_best_match(&pname("a"), &constraint).unwrap();
+        assert_eq!(*p.unwrap().version(), pversion("1.2.5"));
+    }
+
+    #[test]
+    fn test_find_best_match_ambiguous() {
+        let mut btree = BTreeMap::new();
+
+        for (vers, hash) in [("1.0", "123"), ("1.0.0", "124")] {
+            let pack = package("a", vers, "https://rust-lang.org", hash);
+            btree.insert((pname("a"), pversion(vers)), pack);
+        }
+
+        let repo = Repository::from(btree);
+
+        let constraint = PackageVersionConstraint::try_from("1").unwrap();
+        assert!(repo.find_best_match(&pname("a"), &constraint).is_err());
+
+        let constraint = PackageVersionConstraint::try_from("=1.0").unwrap();
+        let p = repo.find_best_match(&pname("a"), &constraint).unwrap();
+        assert_eq!(*p.unwrap().version(), pversion("1.0"));
+    }
+
     #[test]
     fn test_load_example_pkg_repo() -> Result<()> {
         use crate::package::Package;
@@ -418,6 +749,71 @@ pub mod tests {
             &vec![PathBuf::from("examples/packages/repo/s/19.3/s193.patch")]
         );
 
+        // Verify the origins of the (merged) fields:
+        let p = get_pkg(&repo, "s", "19.0");
+        let origin = |field: &str| p.field_origins().get(field).map(|p| p.as_path());
+        assert_eq!(
+            origin("version_is_semver"),
+            Some(Path::new("examples/packages/repo/pkg.toml"))
+        );
+        assert_eq!(
+            origin("name"),
+            Some(Path::new("examples/packages/repo/s/pkg.toml"))
+        );
+        assert_eq!(
+            origin("sources.src.hash.type"),
+            Some(Path::new("examples/packages/repo/pkg.toml"))
+        );
+        assert_eq!(
+            origin("sources.src.url"),
+            Some(Path::new("examples/packages/repo/s/pkg.toml"))
+        );
+        assert_eq!(
+            origin("patches"),
+            Some(Path::new("examples/packages/repo/s/19.0/pkg.toml"))
+        );
+        assert_eq!(origin("sources"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_packages_affected_by() -> Result<()> {
+        let repo = Repository::load(
+            &PathBuf::from("examples/packages/repo/"),
+            &indicatif::ProgressBar::hidden(),
+        )?;
+        let affected_by = |changed_files: &[&str]| -> Result<Vec<String>> {
+            let changed_files = changed_files.iter().map(PathBuf::from).collect::<Vec<_>>();
+            Ok(repo
+                .packages_affected_by(&changed_files)?
+                .into_iter()
+                .map(|p| format!("{} {}", p.name(), p.version()))
+                .collect())
+        };
+
+        // A layer that is shared by all versions of "s"
+        assert_eq!(
+            affected_by(&["examples/packages/repo/s/pkg.toml"])?,
+            vec!["s 19.0", "s 19.1", "s 19.2", "s 19.3"]
+        );
+        // A patch in a parent directory, that is only used by some of the versions
+        assert_eq!(
+            affected_by(&["examples/packages/repo/s/foo.patch"])?,
+            vec!["s 19.1", "s 19.2"]
+        );
+        // Any file in the directory of a package
+        assert_eq!(
+            affected_by(&["examples/packages/repo/s/19.3/README"])?,
+            vec!["s 19.3"]
+        );
+        // The layer at the root of the repository is shared by all packages
+        assert_eq!(
+            affected_by(&["examples/packages/repo/pkg.toml"])?.len(),
+            repo.packages().count()
+        );
+        assert!(affected_by(&["examples/packages/repo/config.toml", "README.md"])?.is_empty());
+
         Ok(())
     }
 
@@ -43,6 +43,14 @@ table! {
     }
 }
 
+table! {
+    job_log_chunks (id) {
+        id -> Int4,
+        job_uuid -> Uuid,
+        log_text -> Text,
+    }
+}
+
 table! {
     jobs (id) {
         id -> Int4,
@@ -54,6 +62,11 @@ table! {
         script_text -> Text,
         log_text -> Text,
         uuid -> Uuid,
+        peak_memory_bytes -> Nullable<Int8>,
+        cpu_time_ns -> Nullable<Int8>,
+        success -> Nullable<Bool>,
+        arch -> Nullable<Varchar>,
+        memory_is_peak -> Nullable<Bool>,
     }
 }
 
@@ -78,6 +91,8 @@ table! {
         artifact_id -> Int4,
         release_date -> Timestamptz,
         release_store_id -> Int4,
+        path -> Nullable<Varchar>,
+        signature_path -> Nullable<Varchar>,
     }
 }
 
@@ -97,6 +112,8 @@ table! {
         requested_image_id -> Int4,
         requested_package_id -> Int4,
         repo_hash_id -> Int4,
+        butido_version -> Nullable<Varchar>,
+        interrupted -> Bool,
     }
 }
 
@@ -122,6 +139,7 @@ allow_tables_to_appear_in_same_query!(
     githashes,
     images,
     job_envs,
+    job_log_chunks,
     jobs,
     packages,
     release_stores,
@@ -16,26 +16,45 @@ use anyhow::Result;
 use tracing::trace;
 use url::Url;
 
+use crate::package::HashCheck;
+use crate::package::HttpHeaders;
 use crate::package::Package;
 use crate::package::PackageName;
 use crate::package::PackageVersion;
 use crate::package::Source;
+use crate::package::SourceHash;
 
 #[derive(Clone, Debug)]
 pub struct SourceCache {
     root: PathBuf,
+
+    /// The value for `${mirror_base}` in source URLs (`source_mirror_base` from the configuration)
+    mirror_base: Option<String>,
 }
 
 impl SourceCache {
-    pub fn new(root: PathBuf) -> Self {
-        SourceCache { root }
+    pub fn new(root: PathBuf, mirror_base: Option<String>) -> Self {
+        SourceCache { root, mirror_base }
     }
 
-    pub fn sources_for(&self, p: &Package) -> Vec<SourceEntry> {
-        SourceEntry::for_package(self.root.clone(), p)
+    /// Get the sources of a package
+    ///
+    /// Fails if the URL of a source cannot be resolved (see [SourceEntry]).
+    pub fn sources_for(&self, p: &Package) -> Result<Vec<SourceEntry>> {
+        SourceEntry::for_package(self.root.clone(), self.mirror_base.as_deref(), p)
     }
 }
 
+/// A source of a package in the source cache
+///
+/// The URL of the source is resolved when the entry is constructed. The URL may contain the
+/// following variables:
+///
+/// * `${name}`: The name of the package
+/// * `${version}`: The version of the package
+/// * `${mirror_base}`: The `source_mirror_base` from the configuration
+///
+/// Using an unknown variable (or `${mirror_base}` without `source_mirror_base`) is an error.
 #[derive(Debug)]
 pub struct SourceEntry {
     cache_root: PathBuf,
@@ -43,6 +62,7 @@ pub struct SourceEntry {
     package_version: PackageVersion,
     package_source_name: String,
     package_source: Source,
+    url: Url,
 }
 
 impl SourceEntry {
@@ -51,21 +71,42 @@ impl SourceEntry {
             .join(format!("{}-{}", self.package_name, self.package_version))
     }
 
-    fn for_package(cache_root: PathBuf, package: &Package) -> Vec<Self> {
+    fn for_package(
+        cache_root: PathBuf,
+        mirror_base: Option<&str>,
+        package: &Package,
+    ) -> Result<Vec<Self>> {
         package
             .sources()
             .clone()
             .into_iter()
-            .map(|(source_name, source)| SourceEntry {
-                cache_root: cache_root.clone(),
-                package_name: package.name().clone(),
-                package_version: package.version().clone(),
-                package_source_name: source_name,
-                package_source: source,
+            .map(|(source_name, source)| {
+                let url =
+                    interpolate_url(source.url(), package, mirror_base).with_context(|| {
+                        anyhow!(
+                            "Resolving the URL of source {} of {} {}",
+                            source_name,
+                            package.name(),
+                            package.version()
+                        )
+                    })?;
+
+                Ok(SourceEntry {
+                    cache_root: cache_root.clone(),
+                    package_name: package.name().clone(),
+                    package_version: package.version().clone(),
+                    package_source_name: source_name,
+                    package_source: source,
+                    url,
+                })
             })
             .collect()
     }
 
+    pub fn name(&self) -> &str {
+        &self.package_source_name
+    }
+
     pub fn path(&self) -> PathBuf {
         self.source_file_directory().join({
             (self.package_source_name.as_ref() as &std::path::Path).with_extension("source")
@@ -73,20 +114,35 @@ impl SourceEntry {
     }
 
     pub fn url(&self) -> &Url {
-        self.package_source.url()
+        &self.url
+    }
+
+    pub fn hash(&self) -> &SourceHash {
+        self.package_source.hash()
     }
 
     pub fn download_manually(&self) -> bool {
         *self.package_source.download_manually()
     }
 
+    pub fn user_agent(&self) -> Option<&str> {
+        self.package_source.user_agent().as_deref()
+    }
+
+    pub fn headers(&self) -> &HttpHeaders {
+        self.package_source.headers()
+    }
+
     pub async fn remove_file(&self) -> Result<()> {
         let p = self.path();
         tokio::fs::remove_file(&p).await?;
         Ok(())
     }
 
-    pub async fn verify_hash(&self) -> Result<()> {
+    /// Hash the source file and compare the result with the hash from the package definition
+    ///
+    /// A hash mismatch is reported by the returned [HashCheck], not as an error.
+    pub async fn verify_hash(&self) -> Result<HashCheck> {
         let p = self.path();
         trace!("Verifying : {}", p.display());
 
@@ -100,7 +156,7 @@ impl SourceEntry {
             .context("Opening file failed")?;
 
         trace!("Reader constructed for path: {}", p.display());
-        self.package_source.hash().matches_hash_of(reader).await
+        self.package_source.hash().check_hash_of(reader).await
     }
 
     pub async fn create(&self) -> Result<tokio::fs::File> {
@@ -141,3 +197,86 @@ impl SourceEntry {
             .with_context(|| anyhow!("Creating file: {}", p.display()))
     }
 }
+
+/// Replace the variables in the URL of a source of `package` and parse the result
+fn interpolate_url(url: &str, package: &Package, mirror_base: Option<&str>) -> Result<Url> {
+    let mut resolved = String::with_capacity(url.len());
+    let mut rest = url;
+    while let Some(start) = rest.find("${") {
+        resolved.push_str(&rest[..start]);
+        let len = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unterminated variable in URL: {}", url))?;
+        let value = match &rest[start + 2..start + len] {
+            "name" => package.name().to_string(),
+            "version" => package.version().to_string(),
+            "mirror_base" => mirror_base.map(String::from).ok_or_else(|| {
+                anyhow!(
+                    "URL {} uses ${{mirror_base}}, but source_mirror_base is not configured",
+                    url
+                )
+            })?,
+            other => return Err(anyhow!("Unknown variable ${{{}}} in URL: {}", other, url)),
+        };
+        resolved.push_str(&value);
+        rest = &rest[start + len + 1..];
+    }
+    resolved.push_str(rest);
+
+    Url::parse(&resolved).with_context(|| anyhow!("Parsing URL: {}", resolved))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::package;
+
+    #[test]
+    fn test_interpolate_url_version() {
+        let p = package("foo", "1.2.3", "https://example.com", "");
+        let url = interpolate_url(
+            "https://example.com/${name}/${name}-${version}.tar.gz",
+            &p,
+            None,
+        );
+        assert_eq!(
+            url.unwrap().as_str(),
+            "https://example.com/foo/foo-1.2.3.tar.gz"
+        );
+
+        let url = interpolate_url("https://example.com/v${version}/src.tar.gz", &p, None);
+        assert_eq!(
+            url.unwrap().as_str(),
+            "https://example.com/v1.2.3/src.tar.gz"
+        );
+    }
+
+    #[test]
+    fn test_interpolate_url_mirror_base() {
+        let p = package("foo", "1", "https://example.com", "");
+        let url = interpolate_url(
+            "${mirror_base}/foo-${version}.tar.gz",
+            &p,
+            Some("https://mirror.example.com/pub"),
+        );
+        assert_eq!(
+            url.unwrap().as_str(),
+            "https://mirror.example.com/pub/foo-1.tar.gz"
+        );
+
+        assert!(interpolate_url("${mirror_base}/foo-${version}.tar.gz", &p, None).is_err());
+    }
+
+    #[test]
+    fn test_interpolate_url_fails_loudly() {
+        let p = package("foo", "1", "https://example.com", "");
+        assert!(interpolate_url("https://example.com/${vresion}.tar.gz", &p, None).is_err());
+        assert!(interpolate_url("https://example.com/${version.tar.gz", &p, None).is_err());
+
+        let url = interpolate_url("https://example.com/$version/a.tar.gz", &p, None);
+        assert_eq!(
+            url.unwrap().as_str(),
+            "https://example.com/$version/a.tar.gz"
+        );
+    }
+}
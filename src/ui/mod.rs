@@ -23,13 +23,17 @@ use crate::package::Script;
 mod package;
 pub use crate::ui::package::*;
 
+/// Get the `script` for printing it
+///
+/// The script is only highlighted if colors are enabled (see `--color`), as the highlighting
+/// consists of ANSI escape codes.
 pub fn script_to_printable(
     script: &Script,
     highlight: bool,
     highlight_theme: &str,
     line_numbers: bool,
 ) -> Result<String> {
-    let script = if highlight {
+    let script = if highlight && colored::control::SHOULD_COLORIZE.should_colorize() {
         let script = script.highlighted(highlight_theme);
         if line_numbers {
             script
@@ -34,6 +34,7 @@ pub struct PackagePrintFlags {
     pub print_denied_images: bool,
     pub print_phases: bool,
     pub print_script: bool,
+    pub print_field_origins: bool,
     pub script_line_numbers: bool,
     pub script_highlighting: bool,
 }
@@ -55,6 +56,7 @@ impl PackagePrintFlags {
                 || self.print_denied_images
                 || self.print_phases
                 || self.print_script
+                || self.print_field_origins
         }
     }
 }
@@ -183,6 +185,14 @@ impl<P: Borrow<Package>> PreparePrintPackage<'_, P> {
             "print_script",
             serde_json::Value::Bool(self.flags.print_all || self.flags.print_script),
         );
+        data.insert(
+            "print_field_origins",
+            serde_json::Value::Bool(self.flags.print_field_origins),
+        );
+        data.insert(
+            "field_origins",
+            serde_json::to_value(self.package.borrow().field_origins())?,
+        );
 
         let string = self.handlebars.render("package", &data)?;
         Ok(PrintablePackage { string })
@@ -50,11 +50,20 @@ impl AsRef<str> for ImageName {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct ContainerImage {
     pub name: ImageName,
     pub short_name: ImageName,
+
+    /// The shebang for the package scripts that are run in this image, overrides the global one
+    #[serde(default)]
+    pub shebang: Option<String>,
+
+    /// The shell the containers of this image are started with and that runs the package scripts
+    /// (`/bin/bash` if not set)
+    #[serde(default)]
+    pub shell: Option<String>,
 }
 
 pub struct ImageNameLookup {
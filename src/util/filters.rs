@@ -91,8 +91,8 @@ pub fn build_package_filter_by_version(
 
 pub fn build_package_filter_by_version_constraint(
     version_constraint: Option<PackageVersionConstraint>,
-) -> impl filters::filter::Filter<Package> {
-    move |p: &Package| {
+) -> impl filters::failable::filter::FailableFilter<Package, Error = Error> {
+    move |p: &Package| -> Result<bool> {
         trace!(
             "Checking {:?} -> version matches constraint: {:?}",
             p,
@@ -101,7 +101,7 @@ pub fn build_package_filter_by_version_constraint(
         version_constraint
             .as_ref()
             .map(|v| v.matches(p.version()))
-            .unwrap_or(true)
+            .unwrap_or(Ok(true))
     }
 }
 
@@ -8,6 +8,9 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::path::Path;
+use std::path::PathBuf;
+
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
@@ -26,3 +29,33 @@ pub fn get_repo_head_commit_hash(r: &Repository) -> Result<String> {
     trace!("Found git commit hash = {}", s);
     Ok(s)
 }
+
+/// Get the paths of the files that differ between the revision `rev` and the working directory
+///
+/// Changes in the index and untracked files are included. For renamed files, both the old and the
+/// new path are returned. The paths are relative to the working directory.
+pub fn get_changed_files_since(r: &Repository, rev: &str) -> Result<Vec<PathBuf>> {
+    let tree = r
+        .revparse_single(rev)
+        .with_context(|| anyhow!("Cannot find the git revision: {}", rev))?
+        .peel_to_tree()
+        .with_context(|| anyhow!("Cannot get the tree of the git revision: {}", rev))?;
+
+    let mut options = git2::DiffOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    let diff = r
+        .diff_tree_to_workdir_with_index(Some(&tree), Some(&mut options))
+        .with_context(|| anyhow!("Comparing {} with the working directory", rev))?;
+
+    let mut files = diff
+        .deltas()
+        .flat_map(|delta| [delta.old_file().path(), delta.new_file().path()])
+        .flatten()
+        .map(Path::to_path_buf)
+        .collect::<Vec<_>>();
+    files.sort();
+    files.dedup();
+
+    trace!("Files changed since {}: {:?}", rev, files);
+    Ok(files)
+}